@@ -101,15 +101,15 @@ fn main() {
                     let obj = host_object::FunctionWithStringArgument {
                         sender: sender.clone(),
                     };
-                    let message_obj = Box::new(host_object::Variant::from(ManuallyDrop::new(
-                        Some(IDispatch::from(obj)),
-                    )));
+                    let message_obj =
+                        host_object::Variant::from(ManuallyDrop::new(Some(IDispatch::from(obj))));
 
                     host_object::ensure_bind(
                         w.clone(),
                         "functioncall".to_owned(),
                         message_obj,
-                        move |w| {
+                        move |w, res| {
+                            res.expect("bind");
                             run_script0(&w);
                             w.navigate("https://wikipedia.com").expect("navigate");
                         },
@@ -0,0 +1,933 @@
+//! Serving app content from bundles compiled into the binary.
+//!
+//! A `Bundle` maps paths to contents, typically from `include_bytes!`, with
+//! optional gzip or brotli compressed variants of each file. An `AssetServer`
+//! answers requests to one origin from a bundle, or from any other
+//! `Provider` such as the archives in `archive`. `AssetServer::resolve` is
+//! the whole of it, a pure function from a `Request` to a `Response`. On
+//! Windows, `AssetServer::attach` hands it the requests a webview makes to
+//! that origin through `add_web_resource_requested`, so nothing is read from
+//! disk and nothing can be edited next to the executable.
+//!
+//! ```
+//! use webview2::assets::{AssetServer, Bundle, Request};
+//!
+//! let bundle = Bundle::new()
+//!     .with("index.html", b"<script src=app.js></script>")
+//!     .with("app.js", b"console.log('hi')");
+//! let server = AssetServer::new("https://app.local", bundle).with_fallback("index.html");
+//! let request = Request::get("https://app.local/settings/profile").with_header("Accept", "text/html");
+//! let response = server.resolve(&request).unwrap();
+//! assert_eq!(response.status, 200);
+//! assert_eq!(response.header("Content-Type"), Some("text/html; charset=utf-8"));
+//! ```
+//!
+//! Responses carry an `ETag` and honour `If-None-Match`, single byte
+//! `Range`s with `If-Range`, and `Accept-Encoding` when compressed variants
+//! exist.
+
+use crate::util::uri::percent_decode;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::rc::Rc;
+
+pub mod archive;
+
+pub use self::archive::{Archive, IntegrityError, Layers, Manifest};
+
+/// How a stored variant of a file is encoded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// The `Content-Encoding` token.
+    pub fn token(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Stored {
+    encoding: Encoding,
+    content: Cow<'static, [u8]>,
+    etag: String,
+}
+
+/// Files by path, without a leading `/`.
+#[derive(Debug, Clone, Default)]
+pub struct Bundle {
+    files: HashMap<String, Vec<Stored>>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(self, path: &str, content: &'static [u8]) -> Self {
+        self.with_encoded(path, Encoding::Identity, content)
+    }
+
+    /// Add a precompressed variant of `path`, e.g. the contents of
+    /// `app.js.br` as the `Encoding::Brotli` variant of `app.js`.
+    pub fn with_encoded(mut self, path: &str, encoding: Encoding, content: &'static [u8]) -> Self {
+        self.insert(path, encoding, Cow::Borrowed(content));
+        self
+    }
+
+    /// Add or replace the `encoding` variant of `path`.
+    pub fn insert(&mut self, path: &str, encoding: Encoding, content: Cow<'static, [u8]>) {
+        let etag = etag(&content);
+        let variants = self
+            .files
+            .entry(path.trim_start_matches('/').to_owned())
+            .or_default();
+        variants.retain(|v| v.encoding != encoding);
+        variants.push(Stored {
+            encoding,
+            content,
+            etag,
+        });
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path.trim_start_matches('/'))
+    }
+
+    /// The identity encoded content of `path`.
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        self.files
+            .get(path.trim_start_matches('/'))?
+            .iter()
+            .find(|v| v.encoding == Encoding::Identity)
+            .map(|v| &*v.content)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Where an `AssetServer` gets its files. Paths have no leading `/`.
+pub trait Provider {
+    /// The encodings `path` is stored with and their sizes, none if it does
+    /// not exist.
+    fn variants(&self, path: &str) -> Vec<(Encoding, u64)>;
+
+    /// `path` as stored with `encoding`.
+    fn read(&self, path: &str, encoding: Encoding) -> io::Result<Asset<'_>>;
+}
+
+/// One stored variant of a file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Asset<'a> {
+    pub content: Cow<'a, [u8]>,
+    /// A quoted strong entity tag, different for different contents.
+    pub etag: String,
+}
+
+impl Provider for Bundle {
+    fn variants(&self, path: &str) -> Vec<(Encoding, u64)> {
+        match self.files.get(path) {
+            Some(variants) => variants
+                .iter()
+                .map(|v| (v.encoding, v.content.len() as u64))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn read(&self, path: &str, encoding: Encoding) -> io::Result<Asset<'_>> {
+        self.files
+            .get(path)
+            .and_then(|variants| variants.iter().find(|v| v.encoding == encoding))
+            .map(|v| Asset {
+                content: Cow::Borrowed(&*v.content),
+                etag: v.etag.clone(),
+            })
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+impl<P: Provider + ?Sized> Provider for Box<P> {
+    fn variants(&self, path: &str) -> Vec<(Encoding, u64)> {
+        (**self).variants(path)
+    }
+
+    fn read(&self, path: &str, encoding: Encoding) -> io::Result<Asset<'_>> {
+        (**self).read(path, encoding)
+    }
+}
+
+impl<P: Provider + ?Sized> Provider for Rc<P> {
+    fn variants(&self, path: &str) -> Vec<(Encoding, u64)> {
+        (**self).variants(path)
+    }
+
+    fn read(&self, path: &str, encoding: Encoding) -> io::Result<Asset<'_>> {
+        (**self).read(path, encoding)
+    }
+}
+
+/// A quoted entity tag for `content`.
+pub fn etag(content: &[u8]) -> String {
+    format!("\"{:016x}\"", fnv1a(content))
+}
+
+/// 64 bit FNV-1a, good enough to tell contents apart in an `ETag`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The `Content-Type` for a path, by its extension.
+pub fn mime_type(path: &str) -> &'static str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = match name.rfind('.') {
+        Some(i) => name[i + 1..].to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+/// What `AssetServer::resolve` needs to know about a request.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn get(uri: &str) -> Self {
+        Request {
+            method: "GET".to_owned(),
+            uri: uri.to_owned(),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn with_method(mut self, method: &str) -> Self {
+        self.method = method.to_owned();
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// The first value of a header, by case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// A response, with a body borrowed from the provider where it can be.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Response<'a> {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Cow<'a, [u8]>,
+}
+
+impl<'a> Response<'a> {
+    fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Cow::Borrowed(&[]),
+        }
+    }
+
+    fn text(status: u16, body: &'static str) -> Self {
+        Self::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_header("Content-Length", &body.len().to_string())
+            .with_body(Cow::Borrowed(body.as_bytes()))
+    }
+
+    fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    fn with_body(mut self, body: Cow<'a, [u8]>) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            206 => "Partial Content",
+            301 => "Moved Permanently",
+            304 => "Not Modified",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            416 => "Range Not Satisfiable",
+            500 => "Internal Server Error",
+            _ => "",
+        }
+    }
+
+    /// The headers as `create_web_resource_response` takes them, one
+    /// `Name: value` per line.
+    pub fn header_block(&self) -> String {
+        let mut block = String::new();
+        for (i, (name, value)) in self.headers.iter().enumerate() {
+            if i > 0 {
+                block.push_str("\r\n");
+            }
+            let _ = write!(block, "{}: {}", name, value);
+        }
+        block
+    }
+}
+
+/// Serves an origin, like `https://app.local`, from a `Bundle` or another
+/// `Provider`.
+#[derive(Debug, Clone)]
+pub struct AssetServer<P = Bundle> {
+    origin: String,
+    provider: P,
+    index: String,
+    fallback: Option<String>,
+    cache_control: String,
+}
+
+impl<P: Provider> AssetServer<P> {
+    pub fn new(origin: &str, provider: P) -> Self {
+        AssetServer {
+            origin: origin.trim_end_matches('/').to_ascii_lowercase(),
+            provider,
+            index: "index.html".to_owned(),
+            fallback: None,
+            cache_control: "no-cache".to_owned(),
+        }
+    }
+
+    /// The file served for directories, `index.html` by default.
+    pub fn with_index(mut self, index: &str) -> Self {
+        self.index = index.to_owned();
+        self
+    }
+
+    /// Serve `path` for pages that do not exist, so that routes of a single
+    /// page app can be loaded directly. Only requests that accept
+    /// `text/html` get it; missing scripts or images are still not found.
+    pub fn with_fallback(mut self, path: &str) -> Self {
+        self.fallback = Some(path.trim_start_matches('/').to_owned());
+        self
+    }
+
+    /// The `Cache-Control` of every file, `no-cache` by default so that the
+    /// browser revalidates with `If-None-Match`.
+    pub fn with_cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = cache_control.to_owned();
+        self
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// The URI filter for `add_web_resource_requested_filter`.
+    pub fn filter(&self) -> String {
+        format!("{}/*", self.origin)
+    }
+
+    /// The response to `request`, or `None` if it is not for this origin.
+    pub fn resolve(&self, request: &Request) -> Option<Response<'_>> {
+        let path = self.path_of(&request.uri)?;
+        let method = request.method.to_ascii_uppercase();
+        if method != "GET" && method != "HEAD" {
+            return Some(
+                Response::text(405, "Method Not Allowed").with_header("Allow", "GET, HEAD"),
+            );
+        }
+        let path = match path.and_then(|p| normalize(&p)) {
+            Some(path) => path,
+            None => return Some(Response::text(400, "Bad Request")),
+        };
+        let mut response = match self.lookup(&path, request) {
+            Lookup::Found(file, variants) => self.serve(&file, &variants, request),
+            Lookup::Redirect(location) => Response::new(301)
+                .with_header("Location", &location)
+                .with_header("Content-Length", "0"),
+            Lookup::NotFound => Response::text(404, "Not Found"),
+        };
+        if method == "HEAD" {
+            response.body = Cow::Borrowed(&[]);
+        }
+        Some(response)
+    }
+
+    /// The percent encoded path of a URI on this origin, without the
+    /// leading `/`, query and fragment. `Some(None)` if it is malformed.
+    fn path_of(&self, uri: &str) -> Option<Option<String>> {
+        let prefix = uri.get(..self.origin.len())?;
+        if !prefix.eq_ignore_ascii_case(&self.origin) {
+            return None;
+        }
+        let rest = &uri[self.origin.len()..];
+        let end = rest.find(['?', '#']).unwrap_or(rest.len());
+        let path = &rest[..end];
+        if !path.is_empty() && !path.starts_with('/') {
+            // `https://app.localhost` is not on `https://app.local`.
+            return None;
+        }
+        Some(percent_decode(path.trim_start_matches('/')))
+    }
+
+    fn lookup(&self, path: &str, request: &Request) -> Lookup {
+        let file = if path.is_empty() || path.ends_with('/') {
+            format!("{}{}", path, self.index)
+        } else {
+            path.to_owned()
+        };
+        let variants = self.provider.variants(&file);
+        if !variants.is_empty() {
+            return Lookup::Found(file, variants);
+        }
+        let index = format!("{}/{}", file, self.index);
+        if !file.ends_with('/') && !self.provider.variants(&index).is_empty() {
+            return Lookup::Redirect(format!("{}/{}/", self.origin, file));
+        }
+        let wants_html = match request.header("Accept") {
+            Some(accept) => accept.contains("text/html"),
+            None => false,
+        };
+        match &self.fallback {
+            Some(fallback) if wants_html => {
+                let variants = self.provider.variants(fallback);
+                if variants.is_empty() {
+                    Lookup::NotFound
+                } else {
+                    Lookup::Found(fallback.clone(), variants)
+                }
+            }
+            _ => Lookup::NotFound,
+        }
+    }
+
+    fn serve(&self, file: &str, variants: &[(Encoding, u64)], request: &Request) -> Response<'_> {
+        let encoded = variants.iter().any(|&(e, _)| e != Encoding::Identity);
+        let encoding = match negotiate(variants, request.header("Accept-Encoding")) {
+            Some(encoding) => encoding,
+            None => return Response::text(406, "Not Acceptable"),
+        };
+        let asset = match self.provider.read(file, encoding) {
+            Ok(asset) => asset,
+            Err(_) => return Response::text(500, "Internal Server Error"),
+        };
+        let mut response = Response::new(200)
+            .with_header("Content-Type", mime_type(file))
+            .with_header("ETag", &asset.etag)
+            .with_header("Cache-Control", &self.cache_control);
+        if encoded {
+            response = response.with_header("Vary", "Accept-Encoding");
+        }
+        if encoding != Encoding::Identity {
+            response = response.with_header("Content-Encoding", encoding.token());
+        }
+        if let Some(tags) = request.header("If-None-Match") {
+            if etag_matches(tags, &asset.etag) {
+                response.status = 304;
+                return response;
+            }
+        }
+        response = response.with_header("Accept-Ranges", "bytes");
+        let len = asset.content.len();
+        let if_range = match request.header("If-Range") {
+            Some(tag) => tag.trim() == asset.etag,
+            None => true,
+        };
+        let range = match request.header("Range") {
+            Some(range) if if_range => parse_range(range, len as u64),
+            _ => None,
+        };
+        match range {
+            None => response
+                .with_header("Content-Length", &len.to_string())
+                .with_body(asset.content),
+            Some(Ok((start, end))) => {
+                response.status = 206;
+                let (start, end) = (start as usize, end as usize);
+                let body = match asset.content {
+                    Cow::Borrowed(content) => Cow::Borrowed(&content[start..=end]),
+                    Cow::Owned(mut content) => {
+                        content.truncate(end + 1);
+                        content.drain(..start);
+                        Cow::Owned(content)
+                    }
+                };
+                response
+                    .with_header("Content-Length", &body.len().to_string())
+                    .with_header("Content-Range", &format!("bytes {}-{}/{}", start, end, len))
+                    .with_body(body)
+            }
+            Some(Err(())) => Response::text(416, "Range Not Satisfiable")
+                .with_header("Content-Range", &format!("bytes */{}", len)),
+        }
+    }
+}
+
+enum Lookup {
+    /// The file served, which is not the one asked for with a fallback.
+    Found(String, Vec<(Encoding, u64)>),
+    Redirect(String),
+    NotFound,
+}
+
+/// Resolve `.` and `..` segments; `None` if `..` climbs out of the root.
+fn normalize(path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = Vec::new();
+    let parts: Vec<&str> = path.split('/').collect();
+    for (i, segment) in parts.iter().enumerate() {
+        match *segment {
+            "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            "" if i + 1 < parts.len() => {}
+            s => segments.push(s),
+        }
+    }
+    let mut normalized = segments.join("/");
+    if matches!(parts.last(), Some(&".") | Some(&"..")) && !normalized.is_empty() {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// The quality of each coding listed in an `Accept-Encoding`.
+fn parse_accept_encoding(header: &str) -> Vec<(String, f32)> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let q = parts
+                .filter_map(|p| {
+                    let p = p.trim();
+                    p.strip_prefix("q=").or_else(|| p.strip_prefix("Q="))
+                })
+                .next()
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            Some((coding, q))
+        })
+        .collect()
+}
+
+/// The variant to send, preferring the most wanted encoding and then the
+/// smallest. Identity is acceptable unless refused outright.
+fn negotiate(variants: &[(Encoding, u64)], accept: Option<&str>) -> Option<Encoding> {
+    let accepted = accept.map(parse_accept_encoding).unwrap_or_default();
+    let quality = |encoding: Encoding| {
+        let listed = |coding: &str| accepted.iter().find(|(c, _)| c == coding).map(|&(_, q)| q);
+        match (listed(encoding.token()), listed("*"), encoding) {
+            (Some(q), _, _) => q,
+            (None, Some(q), _) => q,
+            (None, None, Encoding::Identity) => 1.0,
+            (None, None, _) => 0.0,
+        }
+    };
+    variants
+        .iter()
+        .map(|&(encoding, size)| (quality(encoding), size, encoding))
+        .filter(|&(q, _, _)| q > 0.0)
+        .fold(
+            None,
+            |best: Option<(f32, u64, Encoding)>, (q, size, e)| match best {
+                Some((bq, bsize, _)) if bq > q || (bq == q && bsize <= size) => best,
+                _ => Some((q, size, e)),
+            },
+        )
+        .map(|(_, _, e)| e)
+}
+
+/// Weak comparison against a list of entity tags, or `*`.
+fn etag_matches(tags: &str, etag: &str) -> bool {
+    let strip = |t: &str| t.trim().trim_start_matches("W/").to_owned();
+    tags.split(',')
+        .any(|t| t.trim() == "*" || strip(t) == strip(etag))
+}
+
+/// The inclusive bounds of a single byte range, or `Err` if it cannot be
+/// satisfied. `None` for anything else, which is served whole.
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let dash = spec.find('-')?;
+    let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+    let range = if first.is_empty() {
+        let suffix: u64 = last.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = first.parse().ok()?;
+        let end = if last.is_empty() {
+            u64::MAX
+        } else {
+            last.parse().ok()?
+        };
+        if end < start {
+            return None;
+        }
+        if start >= len {
+            return Some(Err(()));
+        }
+        (start, end.min(len - 1))
+    };
+    Some(Ok(range))
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::{Environment, EventRegistrationToken, Result, Stream, WebResourceContext, WebView};
+
+    impl<P: Provider + 'static> AssetServer<P> {
+        /// Answer the requests `webview` makes to the origin, until the
+        /// returned token is passed to `remove_web_resource_requested`.
+        pub fn attach(
+            self,
+            webview: &WebView,
+            environment: Environment,
+        ) -> Result<EventRegistrationToken> {
+            webview.add_web_resource_requested_filter(&self.filter(), WebResourceContext::All)?;
+            webview.add_web_resource_requested(move |_, args| {
+                let request = args.get_request()?;
+                let request = Request {
+                    method: request.get_method()?,
+                    uri: request.get_uri()?,
+                    headers: request.get_headers()?.get_iterator()?.collect(),
+                };
+                if let Some(response) = self.resolve(&request) {
+                    let response = environment.create_web_resource_response(
+                        Stream::from_bytes(&response.body),
+                        response.status as i32,
+                        response.reason(),
+                        &response.header_block(),
+                    )?;
+                    args.put_response(response)?;
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: &str = "https://app.local";
+
+    fn server() -> AssetServer {
+        let bundle = Bundle::new()
+            .with("index.html", b"<h1>home</h1>")
+            .with("app.js", b"console.log('app')")
+            .with_encoded("app.js", Encoding::Gzip, b"gz")
+            .with_encoded("app.js", Encoding::Brotli, b"b")
+            .with("docs/index.html", b"docs")
+            .with("a b.txt", b"space")
+            .with("video.mp4", b"0123456789")
+            .with_encoded("only.css", Encoding::Gzip, b"gz");
+        AssetServer::new(ORIGIN, bundle)
+    }
+
+    fn get<'a>(server: &'a AssetServer, path: &str) -> Response<'a> {
+        let uri = format!("{}{}", ORIGIN, path);
+        server.resolve(&Request::get(&uri)).unwrap()
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(mime_type("a/b.min.JS"), "text/javascript; charset=utf-8");
+        assert_eq!(mime_type("x.wasm"), "application/wasm");
+        assert_eq!(mime_type("dir.d/README"), "application/octet-stream");
+        assert_eq!(mime_type("font.woff2"), "font/woff2");
+    }
+
+    #[test]
+    fn test_paths() {
+        let server = server();
+        assert_eq!(server.filter(), "https://app.local/*");
+        assert!(server
+            .resolve(&Request::get("https://other.local/"))
+            .is_none());
+        assert!(server
+            .resolve(&Request::get("https://app.localhost/index.html"))
+            .is_none());
+
+        for path in &["", "/", "/index.html", "/?x=1", "/#top", "/./", "/docs/../"] {
+            let response = get(&server, path);
+            assert_eq!(response.status, 200, "{:?}", path);
+            assert_eq!(response.body, &b"<h1>home</h1>"[..]);
+        }
+        assert_eq!(
+            server
+                .resolve(&Request::get("HTTPS://APP.LOCAL/index.html"))
+                .unwrap()
+                .status,
+            200
+        );
+        assert_eq!(get(&server, "/a%20b.txt").body, &b"space"[..]);
+        assert_eq!(get(&server, "/docs/").body, &b"docs"[..]);
+
+        let response = get(&server, "/docs");
+        assert_eq!(response.status, 301);
+        assert_eq!(response.header("location"), Some("https://app.local/docs/"));
+
+        assert_eq!(get(&server, "/../index.html").status, 400);
+        assert_eq!(get(&server, "/%zz").status, 400);
+        assert_eq!(get(&server, "/%ff").status, 400);
+        assert_eq!(get(&server, "/missing.js").status, 404);
+
+        let response = server
+            .resolve(&Request::get("https://app.local/").with_method("POST"))
+            .unwrap();
+        assert_eq!(response.status, 405);
+        assert_eq!(response.header("Allow"), Some("GET, HEAD"));
+    }
+
+    #[test]
+    fn test_fallback() {
+        let server = server().with_fallback("/index.html");
+        let page = Request::get("https://app.local/users/7?tab=1")
+            .with_header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8");
+        let response = server.resolve(&page).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, &b"<h1>home</h1>"[..]);
+        assert_eq!(
+            response.header("content-type"),
+            Some("text/html; charset=utf-8")
+        );
+        // Not a navigation.
+        assert_eq!(get(&server, "/users/7").status, 404);
+        let script = Request::get("https://app.local/missing.js").with_header("Accept", "*/*");
+        assert_eq!(server.resolve(&script).unwrap().status, 404);
+    }
+
+    #[test]
+    fn test_headers_and_validation() {
+        let server = server().with_cache_control("max-age=60");
+        let response = get(&server, "/index.html");
+        assert_eq!(
+            response.header_block(),
+            format!(
+                "Content-Type: text/html; charset=utf-8\r\nETag: {}\r\nCache-Control: max-age=60\r\nAccept-Ranges: bytes\r\nContent-Length: 13",
+                response.header("ETag").unwrap()
+            )
+        );
+        assert_eq!(response.reason(), "OK");
+        let etag = response.header("ETag").unwrap().to_owned();
+        assert_ne!(etag, get(&server, "/docs/").header("ETag").unwrap());
+
+        for tags in &[
+            etag.clone(),
+            format!("W/{}", etag),
+            format!("\"x\", {}", etag),
+            "*".to_owned(),
+        ] {
+            let request = Request::get("https://app.local/").with_header("If-None-Match", tags);
+            let response = server.resolve(&request).unwrap();
+            assert_eq!(response.status, 304, "{}", tags);
+            assert!(response.body.is_empty());
+            assert_eq!(response.header("ETag"), Some(etag.as_str()));
+        }
+        let request = Request::get("https://app.local/").with_header("If-None-Match", "\"x\"");
+        assert_eq!(server.resolve(&request).unwrap().status, 200);
+
+        let head = Request::get("https://app.local/").with_method("HEAD");
+        let response = server.resolve(&head).unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+        assert_eq!(response.header("Content-Length"), Some("13"));
+    }
+
+    #[test]
+    fn test_encodings() {
+        let server = server();
+        let fetch = |accept: &str| {
+            let request =
+                Request::get("https://app.local/app.js").with_header("Accept-Encoding", accept);
+            let response = server.resolve(&request).unwrap();
+            (
+                response.header("Content-Encoding").map(str::to_owned),
+                response.body.to_vec(),
+            )
+        };
+        assert_eq!(
+            fetch("gzip, deflate, br"),
+            (Some("br".to_owned()), b"b".to_vec())
+        );
+        assert_eq!(fetch("gzip"), (Some("gzip".to_owned()), b"gz".to_vec()));
+        assert_eq!(
+            fetch("br;q=0.5, gzip"),
+            (Some("gzip".to_owned()), b"gz".to_vec())
+        );
+        assert_eq!(fetch("*"), (Some("br".to_owned()), b"b".to_vec()));
+        assert_eq!(
+            fetch("br;q=0, gzip;q=0"),
+            (None, b"console.log('app')".to_vec())
+        );
+        assert_eq!(fetch(""), (None, b"console.log('app')".to_vec()));
+
+        let response = get(&server, "/app.js");
+        assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+        let gzip = Request::get("https://app.local/app.js").with_header("Accept-Encoding", "gzip");
+        assert_ne!(
+            server.resolve(&gzip).unwrap().header("ETag"),
+            response.header("ETag")
+        );
+        assert_eq!(get(&server, "/index.html").header("Vary"), None);
+
+        assert_eq!(get(&server, "/only.css").status, 406);
+        let gzip =
+            Request::get("https://app.local/only.css").with_header("Accept-Encoding", "gzip");
+        assert_eq!(server.resolve(&gzip).unwrap().status, 200);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let server = server();
+        let range = |range: &str| {
+            let request = Request::get("https://app.local/video.mp4").with_header("Range", range);
+            server.resolve(&request).unwrap()
+        };
+        let response = range("bytes=2-4");
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body, &b"234"[..]);
+        assert_eq!(response.header("Content-Range"), Some("bytes 2-4/10"));
+        assert_eq!(response.header("Content-Length"), Some("3"));
+        assert_eq!(response.reason(), "Partial Content");
+        assert_eq!(range("bytes=7-").body, &b"789"[..]);
+        assert_eq!(range("bytes=-3").body, &b"789"[..]);
+        assert_eq!(range("bytes=-30").body, &b"0123456789"[..]);
+        assert_eq!(range("bytes=8-100").body, &b"89"[..]);
+
+        let response = range("bytes=10-");
+        assert_eq!(response.status, 416);
+        assert_eq!(response.header("Content-Range"), Some("bytes */10"));
+        assert_eq!(range("bytes=-0").status, 416);
+        // Served whole.
+        for spec in &["bytes=0-1,4-5", "bytes=5-2", "items=0-1", "bytes=x-"] {
+            assert_eq!(range(spec).status, 200, "{}", spec);
+        }
+
+        let etag = get(&server, "/video.mp4")
+            .header("ETag")
+            .unwrap()
+            .to_owned();
+        let request = |if_range: &str| {
+            Request::get("https://app.local/video.mp4")
+                .with_header("Range", "bytes=0-0")
+                .with_header("If-Range", if_range)
+        };
+        assert_eq!(server.resolve(&request(&etag)).unwrap().status, 206);
+        assert_eq!(server.resolve(&request("\"old\"")).unwrap().status, 200);
+    }
+
+    /// The edges of tiny files: nothing in an empty one can be asked for,
+    /// and a one byte file's only range is that byte.
+    #[test]
+    fn test_edge_ranges() {
+        let server = AssetServer::new(ORIGIN, Bundle::new().with("empty", b"").with("one", b"1"));
+        let range = |path: &str, range: &str| {
+            let request = Request::get(&format!("{}{}", ORIGIN, path)).with_header("Range", range);
+            let response = server.resolve(&request).unwrap();
+            let content_range = response.header("Content-Range").map(str::to_owned);
+            (response.status, content_range, response.body.to_vec())
+        };
+        for spec in &["bytes=0-", "bytes=0-0", "bytes=-1"] {
+            let (status, content_range, _) = range("/empty", spec);
+            assert_eq!(status, 416, "{}", spec);
+            assert_eq!(content_range.as_deref(), Some("bytes */0"));
+        }
+        for spec in &["bytes=0-0", "bytes=0-", "bytes=-1", "bytes=-5", "bytes=0-9"] {
+            assert_eq!(
+                range("/one", spec),
+                (206, Some("bytes 0-0/1".to_owned()), b"1".to_vec()),
+                "{}",
+                spec
+            );
+        }
+        assert_eq!(range("/one", "bytes=1-").0, 416);
+    }
+}
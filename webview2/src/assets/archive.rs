@@ -0,0 +1,806 @@
+//! Web content from zip and pak archives.
+//!
+//! An `Archive` reads its index when opened and each file only when it is
+//! asked for, so a large archive costs little until it is used. Given a
+//! `Manifest` of SHA-256 digests, it checks every file it reads against it
+//! and refuses those that differ, and does not serve files the manifest
+//! does not list. The manifest is best compiled into the binary, or shipped
+//! signed: an archive next to the executable is as easy to edit as a folder.
+//!
+//! Precompressed variants are stored beside the file they compress, as
+//! `app.js.gz` and `app.js.br` for `app.js`.
+//!
+//! `Layers` stacks archives, or any providers, like a patch over the base
+//! archive:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! # const MANIFEST: &str = "";
+//! use std::fs::File;
+//! use webview2::assets::{Archive, AssetServer, Layers, Manifest};
+//!
+//! // What `sha256sum` printed for the files when the archives were built.
+//! let manifest = Manifest::parse(MANIFEST).expect("manifest");
+//! let base = Archive::open(File::open("app.pak")?)?.with_manifest(manifest.clone());
+//! let patch = Archive::open(File::open("patch.zip")?)?.with_manifest(manifest);
+//! let server = AssetServer::new("https://app.local", Layers::new(base).with_overlay(patch));
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{etag, Asset, Encoding, Provider};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use zip::ZipArchive;
+
+/// The first bytes of a pak archive: a name and a format version.
+pub const PAK_MAGIC: &[u8; 8] = b"WV2PAK\0\x01";
+
+/// The first bytes of a zip archive with at least one file.
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+/// The default limit on the length of a file that is read, in bytes.
+pub const DEFAULT_LIMIT: u64 = 64 << 20;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum IntegrityError {
+    /// A line of a manifest is not `<digest>  <path>`.
+    Manifest { line: usize },
+    /// A file that the manifest does not list.
+    Unlisted { path: String },
+    /// A file whose digest is not the one in the manifest.
+    Mismatch { path: String },
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntegrityError::Manifest { line } => write!(f, "malformed manifest line {}", line),
+            IntegrityError::Unlisted { path } => write!(f, "{} is not in the manifest", path),
+            IntegrityError::Mismatch { path } => {
+                write!(f, "{} does not match its digest in the manifest", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+impl From<IntegrityError> for io::Error {
+    fn from(e: IntegrityError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// SHA-256 digests by path, in the format of `sha256sum`: one
+/// `<hex digest>  <path>` per line.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Manifest {
+    digests: HashMap<String, [u8; 32]>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse `sha256sum` output. Blank lines are skipped, and the `*` that
+    /// marks binary mode before a path is allowed.
+    pub fn parse(text: &str) -> Result<Self, IntegrityError> {
+        let mut manifest = Manifest::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            let malformed = IntegrityError::Manifest { line: i + 1 };
+            let (hex, path) = match (line.get(..64), line.get(64..)) {
+                (Some(hex), Some(rest)) if rest.starts_with(' ') => (hex, &rest[1..]),
+                _ => return Err(malformed),
+            };
+            let path = path.strip_prefix(|c| c == ' ' || c == '*').unwrap_or(path);
+            let digest = parse_digest(hex).ok_or_else(|| malformed.clone())?;
+            if path.is_empty() {
+                return Err(malformed);
+            }
+            manifest.digests.insert(normalize(path).to_owned(), digest);
+        }
+        Ok(manifest)
+    }
+
+    /// Record the digest of `content` as that of `path`.
+    pub fn insert(&mut self, path: &str, content: &[u8]) {
+        self.digests
+            .insert(normalize(path).to_owned(), Sha256::digest(content).into());
+    }
+
+    pub fn digest(&self, path: &str) -> Option<&[u8; 32]> {
+        self.digests.get(normalize(path))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.digests.contains_key(normalize(path))
+    }
+
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// Whether `content` is what the manifest lists for `path`.
+    pub fn check(&self, path: &str, content: &[u8]) -> Result<(), IntegrityError> {
+        let expected = self.digest(path).ok_or_else(|| IntegrityError::Unlisted {
+            path: path.to_owned(),
+        })?;
+        if Sha256::digest(content)[..] == expected[..] {
+            Ok(())
+        } else {
+            Err(IntegrityError::Mismatch {
+                path: path.to_owned(),
+            })
+        }
+    }
+}
+
+/// Sorted by path, so the same files always give the same manifest.
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut paths = self.digests.keys().collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            for b in &self.digests[path] {
+                write!(f, "{:02x}", b)?;
+            }
+            writeln!(f, "  {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_digest(hex: &str) -> Option<[u8; 32]> {
+    let mut digest = [0; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        let pair = hex.get(2 * i..2 * i + 2)?;
+        if !pair.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+enum Source<R> {
+    Zip(ZipArchive<R>),
+    Pak(R),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    /// The index of a zip entry.
+    Zip(usize),
+    /// Where a pak file's content starts.
+    Pak(u64),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    location: Location,
+    size: u64,
+}
+
+/// A zip or pak archive.
+pub struct Archive<R> {
+    source: RefCell<Source<R>>,
+    entries: HashMap<String, Entry>,
+    manifest: Option<Manifest>,
+    /// The archive's size in bytes, which bounds what is allocated up front
+    /// for a file: sizes come from headers anyone can edit.
+    len: u64,
+    limit: u64,
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Open a zip or pak archive, whichever it is.
+    pub fn open(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        let n = read_up_to(&mut reader, &mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+        if &magic[..n] == PAK_MAGIC {
+            Self::pak(reader)
+        } else if magic[..n].starts_with(ZIP_MAGIC) {
+            Self::zip(reader)
+        } else {
+            Err(invalid("not a zip or pak archive"))
+        }
+    }
+
+    pub fn zip(mut reader: R) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let mut zip = ZipArchive::new(reader)?;
+        let mut entries = HashMap::new();
+        for i in 0..zip.len() {
+            let file = zip.by_index(i)?;
+            if !file.is_dir() {
+                let entry = Entry {
+                    location: Location::Zip(i),
+                    size: file.size(),
+                };
+                entries.insert(normalize(file.name()).to_owned(), entry);
+            }
+        }
+        Ok(Self::new(Source::Zip(zip), entries, len))
+    }
+
+    /// Open an archive in the format `write_pak` writes.
+    pub fn pak(mut reader: R) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != PAK_MAGIC {
+            return Err(invalid("not a pak archive"));
+        }
+        let count = read_u32(&mut reader)?;
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let mut path = vec![0; read_u16(&mut reader)? as usize];
+            reader.read_exact(&mut path)?;
+            let path = String::from_utf8(path).map_err(|_| invalid("path is not UTF-8"))?;
+            let offset = read_u64(&mut reader)?;
+            let size = read_u64(&mut reader)?;
+            match offset.checked_add(size) {
+                Some(end) if end <= len => {}
+                _ => return Err(invalid("file is out of bounds")),
+            }
+            let entry = Entry {
+                location: Location::Pak(offset),
+                size,
+            };
+            entries.insert(normalize(&path).to_owned(), entry);
+        }
+        Ok(Self::new(Source::Pak(reader), entries, len))
+    }
+
+    fn new(source: Source<R>, entries: HashMap<String, Entry>, len: u64) -> Self {
+        Archive {
+            source: RefCell::new(source),
+            entries,
+            manifest: None,
+            len,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+
+    /// Only serve files listed in `manifest`, and only if they match it.
+    pub fn with_manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    /// Refuse files longer than `limit` bytes once decompressed, whatever
+    /// their header says.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Every path in the archive, listed in the manifest or not.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// The content of the file at `path`, exactly as stored. Checked
+    /// against the manifest if there is one.
+    ///
+    /// Fails with `InvalidData` if the file is shorter or longer than its
+    /// header says, or longer than the limit.
+    pub fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let path = normalize(path);
+        let entry = self.entries.get(path).ok_or(io::ErrorKind::NotFound)?;
+        if entry.size > self.limit {
+            return Err(invalid("file is longer than the limit"));
+        }
+        let mut content = Vec::with_capacity(entry.size.min(self.len) as usize);
+        match (&mut *self.source.borrow_mut(), entry.location) {
+            (Source::Zip(zip), Location::Zip(i)) => {
+                // One byte more than the header says, to tell if there is
+                // more, and never more than the limit whatever it says.
+                let limit = entry.size.min(self.limit).saturating_add(1);
+                zip.by_index(i)?.take(limit).read_to_end(&mut content)?;
+            }
+            (Source::Pak(reader), Location::Pak(offset)) => {
+                reader.seek(SeekFrom::Start(offset))?;
+                reader.take(entry.size).read_to_end(&mut content)?;
+            }
+            _ => return Err(invalid("entry is not from this archive's format")),
+        }
+        match (content.len() as u64).cmp(&entry.size) {
+            Ordering::Less => return Err(invalid("file is truncated")),
+            Ordering::Greater => return Err(invalid("file is longer than its header says")),
+            Ordering::Equal => {}
+        }
+        if let Some(manifest) = &self.manifest {
+            manifest.check(path, &content)?;
+        }
+        Ok(content)
+    }
+
+    /// Read every file the manifest lists, failing on the first that is
+    /// missing or differs, to refuse a tampered archive up front.
+    pub fn verify(&self) -> io::Result<()> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Ok(()),
+        };
+        let mut paths = manifest.digests.keys().collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            self.read_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the file at `path` is there and may be served.
+    fn servable(&self, path: &str) -> Option<&Entry> {
+        let entry = self.entries.get(path)?;
+        match &self.manifest {
+            Some(manifest) if !manifest.contains(path) => None,
+            _ => Some(entry),
+        }
+    }
+}
+
+impl<R: Read + Seek> Provider for Archive<R> {
+    fn variants(&self, path: &str) -> Vec<(Encoding, u64)> {
+        [Encoding::Identity, Encoding::Gzip, Encoding::Brotli]
+            .iter()
+            .filter_map(|&encoding| {
+                let entry = self.servable(&stored_path(path, encoding))?;
+                Some((encoding, entry.size))
+            })
+            .collect()
+    }
+
+    fn read(&self, path: &str, encoding: Encoding) -> io::Result<Asset<'_>> {
+        let path = stored_path(path, encoding);
+        if self.servable(&path).is_none() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        let content = self.read_file(&path)?;
+        Ok(Asset {
+            etag: etag(&content),
+            content: Cow::Owned(content),
+        })
+    }
+}
+
+impl<R> fmt::Debug for Archive<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match &*self.source.borrow() {
+            Source::Zip(_) => "zip",
+            Source::Pak(_) => "pak",
+        };
+        f.debug_struct("Archive")
+            .field("format", &format)
+            .field("files", &self.entries.len())
+            .field("manifest", &self.manifest.is_some())
+            .finish()
+    }
+}
+
+/// Where the `encoding` variant of `path` is stored.
+fn stored_path(path: &str, encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Identity => path.to_owned(),
+        Encoding::Gzip => format!("{}.gz", path),
+        Encoding::Brotli => format!("{}.br", path),
+    }
+}
+
+/// Write a pak archive of `files`: `PAK_MAGIC`, the number of files, then
+/// for each its path's length and UTF-8 bytes, the offset of its content
+/// and its size, and finally the contents. Integers are little endian, of
+/// 32 bits for the count, 16 for path lengths and 64 for the rest.
+pub fn write_pak<W: Write>(mut out: W, files: &[(&str, &[u8])]) -> io::Result<()> {
+    let index_len: usize = files.iter().map(|(path, _)| 2 + path.len() + 16).sum();
+    let mut offset = (PAK_MAGIC.len() + 4 + index_len) as u64;
+    out.write_all(PAK_MAGIC)?;
+    out.write_all(&(files.len() as u32).to_le_bytes())?;
+    for (path, content) in files {
+        if path.len() > u16::MAX as usize {
+            return Err(invalid("path is too long"));
+        }
+        out.write_all(&(path.len() as u16).to_le_bytes())?;
+        out.write_all(path.as_bytes())?;
+        out.write_all(&offset.to_le_bytes())?;
+        out.write_all(&(content.len() as u64).to_le_bytes())?;
+        offset += content.len() as u64;
+    }
+    for (_, content) in files {
+        out.write_all(content)?;
+    }
+    out.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Providers stacked over each other, like a patch archive over the base
+/// one. A file comes whole from the topmost layer that has it, so a patched
+/// `app.js` also hides the compressed variants of the base one.
+#[derive(Default)]
+pub struct Layers {
+    /// Topmost first.
+    layers: Vec<Box<dyn Provider>>,
+}
+
+impl Layers {
+    pub fn new(base: impl Provider + 'static) -> Self {
+        Layers {
+            layers: vec![Box::new(base)],
+        }
+    }
+
+    /// Put `overlay` over the layers so far.
+    pub fn with_overlay(mut self, overlay: impl Provider + 'static) -> Self {
+        self.layers.insert(0, Box::new(overlay));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    fn layer_of(&self, path: &str) -> Option<(&dyn Provider, Vec<(Encoding, u64)>)> {
+        self.layers.iter().find_map(|layer| {
+            let variants = layer.variants(path);
+            if variants.is_empty() {
+                None
+            } else {
+                Some((&**layer, variants))
+            }
+        })
+    }
+}
+
+impl Provider for Layers {
+    fn variants(&self, path: &str) -> Vec<(Encoding, u64)> {
+        self.layer_of(path).map(|(_, v)| v).unwrap_or_default()
+    }
+
+    fn read(&self, path: &str, encoding: Encoding) -> io::Result<Asset<'_>> {
+        match self.layer_of(path) {
+            Some((layer, _)) => layer.read(path, encoding),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+impl fmt::Debug for Layers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Layers")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::{AssetServer, Bundle, Request};
+    use std::io::Cursor;
+    use zip::write::{FileOptions, ZipWriter};
+    use zip::CompressionMethod;
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.add_directory("static/", FileOptions::default())
+            .unwrap();
+        for (i, (path, content)) in files.iter().enumerate() {
+            let method = if i % 2 == 0 {
+                CompressionMethod::Deflated
+            } else {
+                CompressionMethod::Stored
+            };
+            zip.start_file(*path, FileOptions::default().compression_method(method))
+                .unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn pak(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut pak = Vec::new();
+        write_pak(&mut pak, files).unwrap();
+        pak
+    }
+
+    fn manifest(files: &[(&str, &[u8])]) -> Manifest {
+        let mut manifest = Manifest::new();
+        for (path, content) in files {
+            manifest.insert(path, content);
+        }
+        manifest
+    }
+
+    const FILES: &[(&str, &[u8])] = &[
+        ("index.html", b"<h1>app</h1>"),
+        ("static/app.js", b"console.log('app')"),
+        ("static/app.js.gz", b"gzipped"),
+        ("empty.txt", b""),
+    ];
+
+    #[test]
+    fn test_formats() {
+        for bytes in &[zip(FILES), pak(FILES)] {
+            let archive = Archive::open(Cursor::new(bytes.clone())).unwrap();
+            let mut paths = archive.paths().collect::<Vec<_>>();
+            paths.sort();
+            assert_eq!(
+                paths,
+                [
+                    "empty.txt",
+                    "index.html",
+                    "static/app.js",
+                    "static/app.js.gz"
+                ]
+            );
+            for (path, content) in FILES {
+                assert_eq!(archive.read_file(path).unwrap(), *content);
+            }
+            assert_eq!(
+                archive.read_file("missing").unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+            assert_eq!(
+                archive.variants("static/app.js"),
+                vec![(Encoding::Identity, 18), (Encoding::Gzip, 7)]
+            );
+            assert_eq!(archive.variants("static"), vec![]);
+            let asset = archive.read("static/app.js", Encoding::Gzip).unwrap();
+            assert_eq!(asset.content, &b"gzipped"[..]);
+            assert_eq!(asset.etag, etag(b"gzipped"));
+        }
+
+        assert!(Archive::open(Cursor::new(b"<html>".to_vec())).is_err());
+        let mut truncated = pak(FILES);
+        truncated.pop();
+        assert!(Archive::pak(Cursor::new(truncated)).is_err());
+        assert!(Archive::pak(Cursor::new(PAK_MAGIC.to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = manifest(FILES);
+        let text = manifest.to_string();
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.starts_with(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  empty.txt\n"
+        ));
+        assert_eq!(Manifest::parse(&text).unwrap(), manifest);
+
+        // `sha256sum -b` output, with CRLF line ends.
+        let binary = text.replace("  ", " *").replace('\n', "\r\n");
+        assert_eq!(Manifest::parse(&binary).unwrap(), manifest);
+        assert_eq!(Manifest::parse("\n\n").unwrap(), Manifest::new());
+        for (text, line) in &[
+            ("abc  x", 1),
+            (
+                "\nzz3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  x",
+                2,
+            ),
+            (
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855x",
+                1,
+            ),
+            (
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  ",
+                1,
+            ),
+        ] {
+            assert_eq!(
+                Manifest::parse(text),
+                Err(IntegrityError::Manifest { line: *line }),
+                "{:?}",
+                text
+            );
+        }
+
+        assert_eq!(manifest.check("empty.txt", b""), Ok(()));
+        assert_eq!(
+            manifest.check("/index.html", b"<h1>evil</h1>"),
+            Err(IntegrityError::Mismatch {
+                path: "/index.html".to_owned()
+            })
+        );
+        assert_eq!(
+            manifest.check("new.js", b""),
+            Err(IntegrityError::Unlisted {
+                path: "new.js".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_tampering() {
+        let manifest = manifest(FILES);
+        let mut tampered = FILES.to_vec();
+        tampered[1].1 = b"console.log('evil')";
+        tampered.push(("extra.js", b"evil()"));
+        for bytes in &[zip(&tampered), pak(&tampered)] {
+            let archive = Archive::open(Cursor::new(bytes.clone()))
+                .unwrap()
+                .with_manifest(manifest.clone());
+            assert_eq!(archive.read_file("index.html").unwrap(), b"<h1>app</h1>");
+            let e = archive.read_file("static/app.js").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                e.get_ref().unwrap().to_string(),
+                "static/app.js does not match its digest in the manifest"
+            );
+            assert!(archive.verify().is_err());
+            // Not listed, so not there as far as the server is concerned.
+            assert_eq!(archive.variants("extra.js"), vec![]);
+
+            let server = AssetServer::new("https://app.local", archive);
+            let get = |path: &str| {
+                let request = Request::get(&format!("https://app.local/{}", path));
+                server.resolve(&request).unwrap().status
+            };
+            assert_eq!(get("index.html"), 200);
+            assert_eq!(get("static/app.js"), 500);
+            assert_eq!(get("extra.js"), 404);
+        }
+
+        let archive = Archive::open(Cursor::new(pak(FILES)))
+            .unwrap()
+            .with_manifest(manifest);
+        archive.verify().unwrap();
+    }
+
+    /// Sizes in zip headers are not trusted, neither to allocate nor to
+    /// know when a file ends.
+    #[test]
+    fn test_lying_sizes() {
+        let bytes = zip(&[("a.txt", b"abc")]);
+        let central = (0..bytes.len() - 4)
+            .rev()
+            .find(|&i| bytes[i..].starts_with(b"PK\x01\x02"))
+            .unwrap();
+        let with_size = |size: u32| {
+            let mut bytes = bytes.clone();
+            bytes[central + 24..central + 28].copy_from_slice(&size.to_le_bytes());
+            Archive::open(Cursor::new(bytes)).unwrap()
+        };
+        for (size, message) in &[
+            (0x03ff_fff0, "file is truncated"),
+            (0x7fff_fff0, "file is longer than the limit"),
+            (2, "file is longer than its header says"),
+        ] {
+            let e = with_size(*size).read_file("a.txt").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(e.to_string(), *message);
+        }
+        assert_eq!(with_size(3).read_file("a.txt").unwrap(), b"abc");
+    }
+
+    /// A small header over a file that inflates to far more is refused
+    /// after reading one byte past it, and so is a file over the limit.
+    #[test]
+    fn test_limit() {
+        let big = vec![0; 1 << 20];
+        let bytes = zip(&[("big.bin", &big)]);
+        let central = (0..bytes.len() - 4)
+            .rev()
+            .find(|&i| bytes[i..].starts_with(b"PK\x01\x02"))
+            .unwrap();
+        let mut lying = bytes.clone();
+        lying[central + 24..central + 28].copy_from_slice(&10u32.to_le_bytes());
+        let e = Archive::open(Cursor::new(lying))
+            .unwrap()
+            .read_file("big.bin")
+            .unwrap_err();
+        assert_eq!(e.to_string(), "file is longer than its header says");
+
+        let archive = Archive::open(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.read_file("big.bin").unwrap(), big);
+        let e = archive.with_limit(1000).read_file("big.bin").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "file is longer than the limit");
+        let pak = Archive::open(Cursor::new(pak(&[("big.bin", &big)]))).unwrap();
+        let e = pak.with_limit(1000).read_file("big.bin").unwrap_err();
+        assert_eq!(e.to_string(), "file is longer than the limit");
+    }
+
+    #[test]
+    fn test_layers() {
+        let base = Archive::open(Cursor::new(zip(FILES))).unwrap();
+        let patch = pak(&[("static/app.js", b"patched"), ("new.html", b"new")]);
+        let patch = Archive::open(Cursor::new(patch)).unwrap();
+        let top = Bundle::new().with("index.html", b"top");
+        let layers = Layers::new(base).with_overlay(patch).with_overlay(top);
+        assert_eq!(layers.len(), 3);
+
+        let server = AssetServer::new("https://app.local", layers);
+        let get = |path: &str| {
+            let request = Request::get(&format!("https://app.local/{}", path))
+                .with_header("Accept-Encoding", "gzip");
+            let response = server.resolve(&request).unwrap();
+            (response.status, response.body.into_owned())
+        };
+        assert_eq!(get(""), (200, b"top".to_vec()));
+        // The base archive's gzip variant is stale, so it is not used.
+        assert_eq!(get("static/app.js"), (200, b"patched".to_vec()));
+        assert_eq!(get("new.html"), (200, b"new".to_vec()));
+        assert_eq!(get("static/app.js.gz"), (200, b"gzipped".to_vec()));
+        assert_eq!(get("missing").0, 404);
+    }
+
+    /// Every byte value, and files spanning many deflate blocks, read back
+    /// as written and verified in both formats.
+    #[test]
+    fn test_binary_files() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let large: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let files: &[(&str, &[u8])] = &[("bytes.bin", &bytes), ("d/large.bin", &large)];
+        for archive in &[zip(files), pak(files)] {
+            let archive = Archive::open(Cursor::new(archive.clone()))
+                .unwrap()
+                .with_manifest(manifest(files));
+            archive.verify().unwrap();
+            for (path, content) in files {
+                assert_eq!(archive.read_file(path).unwrap(), *content);
+            }
+        }
+    }
+}
@@ -0,0 +1,1319 @@
+//! Blocking ads and trackers with AdBlock Plus / EasyList filter lists.
+//!
+//! A `Blocker` compiles the network filters of lists like EasyList, such as
+//! `||ads.example^$third-party,script` and `@@||ads.example/ok.js`, into a
+//! matcher indexed by the tokens of the filters, so that a request is only
+//! compared with filters that share a token with its URL:
+//!
+//! ```
+//! use webview2::blocker::{Blocker, Request, ResourceType};
+//!
+//! let list = "! A comment\n||ads.example^$third-party\n@@||ads.example/allowed.js\n##.banner";
+//! let blocker = Blocker::new().with_list(list);
+//! let page = Some("https://news.example/article");
+//! let request = Request::new("https://ads.example/ad.js", page, ResourceType::Script);
+//! assert!(blocker.check(&request).is_blocked());
+//! let request = Request::new("https://ads.example/allowed.js", page, ResourceType::Script);
+//! assert!(!blocker.check(&request).is_blocked());
+//! assert_eq!(blocker.hidden_selectors("news.example"), [".banner"]);
+//! ```
+//!
+//! Element hiding filters, `##` and the `#@#` exceptions, become a style
+//! sheet that `document_script` adds to the pages of a host. Filters the blocker does not
+//! support, like regular expressions, `$popup` or `#?#`, are skipped and can
+//! be listed with `skipped`.
+//!
+//! Whether a request is third party is decided by comparing the last two
+//! labels of the host names, without a public suffix list. Hosts under a
+//! suffix like `co.uk` are therefore all the same party, and `$third-party`
+//! filters do not apply between `a.co.uk` and `b.co.uk`.
+//!
+//! On Windows `Blocker::attach` answers blocked requests with an empty
+//! `204 No Content` response.
+
+use crate::policy::ResourceContext;
+use crate::util::to_js;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The request types filter options like `$script` refer to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResourceType {
+    /// The page itself, for `$document` exceptions.
+    Document,
+    Subdocument,
+    Script,
+    Image,
+    Stylesheet,
+    Font,
+    Media,
+    XmlHttpRequest,
+    WebSocket,
+    Ping,
+    Other,
+}
+
+impl ResourceType {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    fn from_option(name: &str) -> Option<Self> {
+        Some(match name {
+            "document" | "doc" => ResourceType::Document,
+            "subdocument" | "frame" => ResourceType::Subdocument,
+            "script" => ResourceType::Script,
+            "image" => ResourceType::Image,
+            "stylesheet" | "css" => ResourceType::Stylesheet,
+            "font" => ResourceType::Font,
+            "media" => ResourceType::Media,
+            "xmlhttprequest" | "xhr" => ResourceType::XmlHttpRequest,
+            "websocket" => ResourceType::WebSocket,
+            "ping" => ResourceType::Ping,
+            "object" | "other" => ResourceType::Other,
+            _ => return None,
+        })
+    }
+}
+
+/// WebView2 does not tell documents in frames from top level ones, so
+/// documents are `Subdocument`.
+impl From<ResourceContext> for ResourceType {
+    fn from(context: ResourceContext) -> Self {
+        match context {
+            ResourceContext::Document => ResourceType::Subdocument,
+            ResourceContext::Stylesheet => ResourceType::Stylesheet,
+            ResourceContext::Image => ResourceType::Image,
+            ResourceContext::Media | ResourceContext::TextTrack => ResourceType::Media,
+            ResourceContext::Font => ResourceType::Font,
+            ResourceContext::Script => ResourceType::Script,
+            ResourceContext::XmlHttpRequest
+            | ResourceContext::Fetch
+            | ResourceContext::EventSource => ResourceType::XmlHttpRequest,
+            ResourceContext::Websocket => ResourceType::WebSocket,
+            ResourceContext::Ping => ResourceType::Ping,
+            _ => ResourceType::Other,
+        }
+    }
+}
+
+/// Filters without type options apply to every type but documents.
+const DEFAULT_TYPES: u32 = !(1 << ResourceType::Document as u32);
+
+/// Why a filter was skipped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FilterError {
+    /// A regular expression, `/.../`.
+    Regex,
+    /// An option the blocker does not support, like `popup` or `csp`.
+    Option(String),
+    /// A cosmetic filter other than `##` and `#@#`, like `#?#` or `#$#`.
+    Cosmetic(String),
+    /// An empty selector or an empty `domain=`.
+    Empty,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterError::Regex => write!(f, "regular expressions are not supported"),
+            FilterError::Option(name) => write!(f, "option {} is not supported", name),
+            FilterError::Cosmetic(marker) => write!(f, "{} filters are not supported", marker),
+            FilterError::Empty => write!(f, "empty filter"),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Anchor {
+    None,
+    /// `|`, the start of the URL.
+    Start,
+    /// `||`, the start of the host or of one of its labels.
+    Host,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Part {
+    Byte(u8),
+    /// `*`
+    Any,
+    /// `^`, a byte that is not a letter, a digit or one of `_-.%`, or the
+    /// end of the URL.
+    Separator,
+}
+
+/// A filter on requests, like `||ads.example^$script`, or an exception to
+/// such filters, like `@@||ads.example/ok.js`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NetworkFilter {
+    text: String,
+    exception: bool,
+    anchor: Anchor,
+    end: bool,
+    parts: Vec<Part>,
+    match_case: bool,
+    third_party: Option<bool>,
+    types: u32,
+    domains: Vec<String>,
+    not_domains: Vec<String>,
+}
+
+/// An element hiding filter, like `example.com##.ad`, or an exception to
+/// such filters, like `example.com#@#.ad`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CosmeticFilter {
+    exception: bool,
+    selector: String,
+    domains: Vec<String>,
+    not_domains: Vec<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Filter {
+    Network(NetworkFilter),
+    Cosmetic(CosmeticFilter),
+}
+
+/// Parse one line of a filter list. Empty lines, comments and the
+/// `[Adblock Plus 2.0]` header are `None`.
+pub fn parse_filter(line: &str) -> Result<Option<Filter>, FilterError> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+        return Ok(None);
+    }
+    let filter = match cosmetic_marker(line) {
+        Some((i, "##")) => Filter::Cosmetic(parse_cosmetic(&line[..i], &line[i + 2..], false)?),
+        Some((i, "#@#")) => Filter::Cosmetic(parse_cosmetic(&line[..i], &line[i + 3..], true)?),
+        Some((_, marker)) => return Err(FilterError::Cosmetic(marker.to_owned())),
+        None => Filter::Network(parse_network(line)?),
+    };
+    Ok(Some(filter))
+}
+
+fn cosmetic_marker(line: &str) -> Option<(usize, &'static str)> {
+    const MARKERS: &[&str] = &["#@$#", "#@?#", "#@#", "#$#", "#?#", "##"];
+    line.match_indices('#').find_map(|(i, _)| {
+        MARKERS
+            .iter()
+            .find(|m| line[i..].starts_with(**m))
+            .map(|m| (i, *m))
+    })
+}
+
+fn parse_domains(list: &str, separator: char) -> (Vec<String>, Vec<String>) {
+    let mut domains = Vec::new();
+    let mut not_domains = Vec::new();
+    for domain in list
+        .split(separator)
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+    {
+        match domain.strip_prefix('~') {
+            Some(d) => not_domains.push(d.to_ascii_lowercase()),
+            None => domains.push(domain.to_ascii_lowercase()),
+        }
+    }
+    (domains, not_domains)
+}
+
+fn parse_cosmetic(
+    domains: &str,
+    selector: &str,
+    exception: bool,
+) -> Result<CosmeticFilter, FilterError> {
+    let selector = selector.trim();
+    if selector.is_empty() {
+        return Err(FilterError::Empty);
+    }
+    let (domains, not_domains) = parse_domains(domains, ',');
+    Ok(CosmeticFilter {
+        exception,
+        selector: selector.to_owned(),
+        domains,
+        not_domains,
+    })
+}
+
+fn parse_network(line: &str) -> Result<NetworkFilter, FilterError> {
+    let (exception, rest) = match line.strip_prefix("@@") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (pattern, options) = match rest.rfind('$') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+        return Err(FilterError::Regex);
+    }
+
+    let mut filter = NetworkFilter {
+        text: line.to_owned(),
+        exception,
+        anchor: Anchor::None,
+        end: false,
+        parts: Vec::new(),
+        match_case: false,
+        third_party: None,
+        types: DEFAULT_TYPES,
+        domains: Vec::new(),
+        not_domains: Vec::new(),
+    };
+    let (mut types, mut not_types) = (0, 0);
+    for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        let (negated, option) = match option.strip_prefix('~') {
+            Some(option) => (true, option),
+            None => (false, option),
+        };
+        let (name, value) = match option.find('=') {
+            Some(i) => (&option[..i], &option[i + 1..]),
+            None => (option, ""),
+        };
+        match name {
+            "third-party" | "3p" => filter.third_party = Some(!negated),
+            "first-party" | "1p" => filter.third_party = Some(negated),
+            "match-case" => filter.match_case = true,
+            "domain" => {
+                let (domains, not_domains) = parse_domains(value, '|');
+                if domains.is_empty() && not_domains.is_empty() {
+                    return Err(FilterError::Empty);
+                }
+                filter.domains = domains;
+                filter.not_domains = not_domains;
+            }
+            _ => match ResourceType::from_option(name) {
+                Some(t) if negated => not_types |= t.bit(),
+                Some(t) => types |= t.bit(),
+                None => return Err(FilterError::Option(name.to_owned())),
+            },
+        }
+    }
+    if types != 0 {
+        filter.types = types;
+    }
+    filter.types &= !not_types;
+
+    let mut pattern = pattern;
+    if let Some(p) = pattern.strip_prefix("||") {
+        filter.anchor = Anchor::Host;
+        pattern = p;
+    } else if let Some(p) = pattern.strip_prefix('|') {
+        filter.anchor = Anchor::Start;
+        pattern = p;
+    }
+    if let Some(p) = pattern.strip_suffix('|') {
+        filter.end = true;
+        pattern = p;
+    }
+    for b in pattern.bytes() {
+        let part = match b {
+            b'*' if filter.parts.last() == Some(&Part::Any) => continue,
+            b'*' => Part::Any,
+            b'^' => Part::Separator,
+            b if filter.match_case => Part::Byte(b),
+            b => Part::Byte(b.to_ascii_lowercase()),
+        };
+        filter.parts.push(part);
+    }
+    Ok(filter)
+}
+
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'%'
+}
+
+fn is_separator(b: u8) -> bool {
+    !(b.is_ascii_alphanumeric() || b"_-.%".contains(&b))
+}
+
+/// Whether `parts` match at the start of `text`, and up to its end if `end`.
+fn match_here(parts: &[Part], text: &[u8], end: bool) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // After the last `*`, and where it stopped taking bytes.
+    let mut star = None;
+    loop {
+        if p == parts.len() && (!end || t == text.len()) {
+            return true;
+        }
+        let matched = match parts.get(p) {
+            Some(Part::Any) => {
+                star = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some(Part::Separator) if t == text.len() => {
+                p += 1;
+                continue;
+            }
+            Some(Part::Separator) => is_separator(text[t]),
+            Some(Part::Byte(b)) => t < text.len() && text[t] == *b,
+            None => false,
+        };
+        if matched {
+            p += 1;
+            t += 1;
+            continue;
+        }
+        match star {
+            Some((after, from)) if from < text.len() => {
+                star = Some((after, from + 1));
+                p = after;
+                t = from + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn on_domain(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+fn applies(domains: &[String], not_domains: &[String], host: &str) -> bool {
+    (domains.is_empty() || domains.iter().any(|d| on_domain(host, d)))
+        && !not_domains.iter().any(|d| on_domain(host, d))
+}
+
+impl NetworkFilter {
+    /// The filter as it was written.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_exception(&self) -> bool {
+        self.exception
+    }
+
+    /// The longest run of letters and digits that a URL matching the filter
+    /// has as a whole token, to index the filter by.
+    fn token(&self) -> Option<String> {
+        let parts = &self.parts;
+        let is_bound = |i: usize| match parts.get(i) {
+            Some(Part::Byte(b)) => !is_token(*b),
+            Some(Part::Separator) => true,
+            _ => false,
+        };
+        let mut best: Option<&[Part]> = None;
+        let mut i = 0;
+        while i < parts.len() {
+            let start = i;
+            while let Some(Part::Byte(b)) = parts.get(i) {
+                if !is_token(*b) {
+                    break;
+                }
+                i += 1;
+            }
+            if i == start {
+                i += 1;
+                continue;
+            }
+            let left = if start == 0 {
+                self.anchor != Anchor::None
+            } else {
+                is_bound(start - 1)
+            };
+            let right = if i == parts.len() {
+                self.end
+            } else {
+                is_bound(i)
+            };
+            let longer = match best {
+                Some(b) => b.len() < i - start,
+                None => true,
+            };
+            if left && right && longer {
+                best = Some(&parts[start..i]);
+            }
+        }
+        let token = best?.iter().map(|p| match p {
+            Part::Byte(b) => b.to_ascii_lowercase() as char,
+            _ => unreachable!(),
+        });
+        Some(token.collect())
+    }
+
+    fn matches(&self, request: &Request) -> bool {
+        if self.types & request.kind.bit() == 0 {
+            return false;
+        }
+        if self.third_party.is_some() && self.third_party != request.third_party {
+            return false;
+        }
+        if !self.domains.is_empty() || !self.not_domains.is_empty() {
+            match &request.source_host {
+                Some(host) if applies(&self.domains, &self.not_domains, host) => {}
+                None if self.domains.is_empty() => {}
+                _ => return false,
+            }
+        }
+        let text = if self.match_case {
+            request.url.as_bytes()
+        } else {
+            request.lower.as_bytes()
+        };
+        let at = |start: usize| match_here(&self.parts, &text[start..], self.end);
+        match self.anchor {
+            Anchor::Start => at(0),
+            Anchor::Host => {
+                let (start, end) = request.host;
+                at(start) || (start + 1..end).any(|i| text[i - 1] == b'.' && at(i))
+            }
+            Anchor::None => match self.parts.first() {
+                Some(Part::Byte(b)) => (0..text.len()).any(|i| text[i] == *b && at(i)),
+                _ => (0..=text.len()).any(at),
+            },
+        }
+    }
+}
+
+impl CosmeticFilter {
+    pub fn selector(&self) -> &str {
+        &self.selector
+    }
+
+    pub fn is_exception(&self) -> bool {
+        self.exception
+    }
+}
+
+/// A request to check.
+#[derive(Debug, Clone)]
+pub struct Request<'a> {
+    url: &'a str,
+    source: Option<&'a str>,
+    lower: String,
+    /// Where the host is in `url`.
+    host: (usize, usize),
+    source_host: Option<String>,
+    third_party: Option<bool>,
+    kind: ResourceType,
+}
+
+impl<'a> Request<'a> {
+    /// A request for `url` of `kind`, made by the page at `source`, if it is
+    /// known.
+    pub fn new(url: &'a str, source: Option<&'a str>, kind: ResourceType) -> Self {
+        let lower = url.to_ascii_lowercase();
+        let host = host_range(&lower);
+        let source_host = source.map(|s| {
+            let s = s.to_ascii_lowercase();
+            let (start, end) = host_range(&s);
+            s[start..end].to_owned()
+        });
+        let third_party = source_host
+            .as_deref()
+            .filter(|s| !s.is_empty())
+            .map(|s| base_domain(s) != base_domain(&lower[host.0..host.1]));
+        Request {
+            url,
+            source,
+            lower,
+            host,
+            source_host,
+            third_party,
+            kind,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        self.url
+    }
+
+    pub fn host(&self) -> &str {
+        &self.lower[self.host.0..self.host.1]
+    }
+
+    pub fn is_third_party(&self) -> Option<bool> {
+        self.third_party
+    }
+
+    fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.lower
+            .split(|c: char| !(c.is_ascii() && is_token(c as u8)))
+            .filter(|t| !t.is_empty())
+    }
+}
+
+/// Where the host of `url` is, without user info and port.
+fn host_range(url: &str) -> (usize, usize) {
+    let start = match url.find("://") {
+        Some(i) => i + 3,
+        None => return (0, 0),
+    };
+    let end = url[start..]
+        .find(&['/', '?', '#'][..])
+        .map_or(url.len(), |i| start + i);
+    let start = url[start..end].rfind('@').map_or(start, |i| start + i + 1);
+    let end = if url[start..].starts_with('[') {
+        url[start..end].find(']').map_or(end, |i| start + i + 1)
+    } else {
+        url[start..end].find(':').map_or(end, |i| start + i)
+    };
+    (start, end)
+}
+
+/// The last two labels of `host`, or all of an IP address. Not the
+/// registrable domain under suffixes of more than one label, like `co.uk`.
+fn base_domain(host: &str) -> &str {
+    if host.starts_with('[') || host.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return host;
+    }
+    match host.rmatch_indices('.').nth(1) {
+        Some((i, _)) => &host[i + 1..],
+        None => host,
+    }
+}
+
+/// What a `Blocker` decided, with the filters that decided it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Verdict<'a> {
+    Allowed,
+    Blocked {
+        filter: &'a str,
+    },
+    /// A filter matched, but so did an exception.
+    Excepted {
+        filter: &'a str,
+        exception: &'a str,
+    },
+}
+
+impl<'a> Verdict<'a> {
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, Verdict::Blocked { .. })
+    }
+}
+
+/// Network filters, indexed by token.
+#[derive(Debug, Default)]
+struct Index {
+    filters: Vec<NetworkFilter>,
+    by_token: HashMap<String, Vec<usize>>,
+    /// Filters without a token, compared with every request.
+    generic: Vec<usize>,
+}
+
+impl Index {
+    fn insert(&mut self, filter: NetworkFilter) {
+        let i = self.filters.len();
+        match filter.token() {
+            Some(token) => self.by_token.entry(token).or_default().push(i),
+            None => self.generic.push(i),
+        }
+        self.filters.push(filter);
+    }
+
+    fn find(&self, request: &Request) -> Option<&NetworkFilter> {
+        request
+            .tokens()
+            .filter_map(|t| self.by_token.get(t))
+            .flatten()
+            .chain(&self.generic)
+            .map(|&i| &self.filters[i])
+            .find(|f| f.matches(request))
+    }
+}
+
+/// Compiled filter lists.
+#[derive(Debug, Default)]
+pub struct Blocker {
+    blocks: Index,
+    exceptions: Index,
+    hide: Vec<CosmeticFilter>,
+    unhide: Vec<CosmeticFilter>,
+    skipped: Vec<(String, FilterError)>,
+}
+
+impl Blocker {
+    pub fn new() -> Self {
+        Blocker::default()
+    }
+
+    /// Add the filters of a list, one per line.
+    pub fn with_list(mut self, list: &str) -> Self {
+        for line in list.lines() {
+            match parse_filter(line) {
+                Ok(Some(filter)) => self.add(filter),
+                Ok(None) => {}
+                Err(e) => self.skipped.push((line.trim().to_owned(), e)),
+            }
+        }
+        self
+    }
+
+    pub fn add(&mut self, filter: Filter) {
+        match filter {
+            Filter::Network(f) if f.exception => self.exceptions.insert(f),
+            Filter::Network(f) => self.blocks.insert(f),
+            Filter::Cosmetic(f) if f.exception => self.unhide.push(f),
+            Filter::Cosmetic(f) => self.hide.push(f),
+        }
+    }
+
+    /// The number of filters in use.
+    pub fn len(&self) -> usize {
+        self.blocks.filters.len()
+            + self.exceptions.filters.len()
+            + self.hide.len()
+            + self.unhide.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The lines that were skipped, and why.
+    pub fn skipped(&self) -> &[(String, FilterError)] {
+        &self.skipped
+    }
+
+    pub fn check(&self, request: &Request) -> Verdict<'_> {
+        let filter = match self.blocks.find(request) {
+            Some(f) => &f.text,
+            None => return Verdict::Allowed,
+        };
+        let exception = self.exceptions.find(request).or_else(|| {
+            // `$document` exceptions match the page rather than the request.
+            let page = Request::new(request.source?, None, ResourceType::Document);
+            self.exceptions.find(&page)
+        });
+        match exception {
+            Some(e) => Verdict::Excepted {
+                filter,
+                exception: &e.text,
+            },
+            None => Verdict::Blocked { filter },
+        }
+    }
+
+    /// The selectors to hide on pages of `host`, in list order.
+    pub fn hidden_selectors(&self, host: &str) -> Vec<&str> {
+        let host = host.to_ascii_lowercase();
+        let mut selectors: Vec<&str> = Vec::new();
+        for f in &self.hide {
+            if applies(&f.domains, &f.not_domains, &host)
+                && !self
+                    .unhide
+                    .iter()
+                    .any(|u| u.selector == f.selector && applies(&u.domains, &u.not_domains, &host))
+                && !selectors.contains(&f.selector.as_str())
+            {
+                selectors.push(&f.selector);
+            }
+        }
+        selectors
+    }
+
+    /// A style sheet hiding `hidden_selectors`. Every selector is a rule of
+    /// its own, so that the ones a browser does not support only drop
+    /// themselves.
+    pub fn stylesheet(&self, host: &str) -> String {
+        self.hidden_selectors(host)
+            .iter()
+            .map(|s| format!("{} {{ display: none !important; }}\n", s))
+            .collect()
+    }
+
+    /// Adds the style sheet for its host to a page, for
+    /// `add_script_to_execute_on_document_created`. The script carries the
+    /// generic element hiding filters and those for `host`, and does what
+    /// `stylesheet` does in the page, so that frames from other hosts still
+    /// get the generic ones.
+    pub fn document_script(&self, host: &str) -> String {
+        let host = host.to_ascii_lowercase();
+        let rules = |filters: &[CosmeticFilter]| {
+            filters
+                .iter()
+                .filter(|f| f.domains.is_empty() || f.domains.iter().any(|d| on_domain(&host, d)))
+                .map(|f| json!({ "s": f.selector, "i": f.domains, "x": f.not_domains }))
+                .collect::<Vec<_>>()
+        };
+        let filters = json!({ "hide": rules(&self.hide), "unhide": rules(&self.unhide) });
+        format!(
+            r#"(function () {{
+    var filters = {};
+    var host = location.hostname.toLowerCase();
+    function on(domain) {{
+        return host === domain || host.slice(-domain.length - 1) === '.' + domain;
+    }}
+    function applies(f) {{
+        return (!f.i.length || f.i.some(on)) && !f.x.some(on);
+    }}
+    var seen = {{}};
+    var css = '';
+    filters.hide.forEach(function (f) {{
+        if (seen[f.s] || !applies(f)) return;
+        if (filters.unhide.some(function (u) {{ return u.s === f.s && applies(u); }})) return;
+        seen[f.s] = true;
+        css += f.s + ' {{ display: none !important; }}\n';
+    }});
+    if (!css) return;
+    function insert() {{
+        var style = document.createElement('style');
+        style.textContent = css;
+        (document.head || document.documentElement).appendChild(style);
+    }}
+    if (document.documentElement) {{
+        insert();
+    }} else {{
+        new MutationObserver(function (_, observer) {{
+            if (document.documentElement) {{
+                observer.disconnect();
+                insert();
+            }}
+        }}).observe(document, {{ childList: true }});
+    }}
+}})();"#,
+            to_js(&filters)
+        )
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::util::script::Slot;
+    use crate::{Environment, EventRegistrationToken, Result, Stream, WebResourceContext, WebView};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// The event handlers and script `Blocker::attach` added.
+    #[derive(Debug)]
+    pub struct Attached {
+        token: EventRegistrationToken,
+        navigation: Option<EventRegistrationToken>,
+        script: Rc<RefCell<Option<Slot>>>,
+    }
+
+    impl Attached {
+        /// Stop blocking. The web resource filter stays.
+        pub fn detach(self, webview: &WebView) -> Result<()> {
+            webview.remove_web_resource_requested(self.token)?;
+            if let Some(token) = self.navigation {
+                webview.remove_navigation_starting(token)?;
+            }
+            match &*self.script.borrow() {
+                Some(script) => script.remove(webview),
+                None => Ok(()),
+            }
+        }
+    }
+
+    /// The host of `url`, lowercase.
+    fn host_of(url: &str) -> String {
+        let (start, end) = host_range(url);
+        url[start..end].to_ascii_lowercase()
+    }
+
+    impl Blocker {
+        /// Answer the requests `webview` makes that the blocker blocks with
+        /// `204 No Content`, and hide elements in its pages.
+        ///
+        /// Requests are checked as made by the page the webview shows. The
+        /// document created script hiding elements is replaced on every
+        /// navigation with one for the host navigated to.
+        pub fn attach(self, webview: &WebView, environment: Environment) -> Result<Attached> {
+            let blocker = Rc::new(self);
+            let script = Rc::new(RefCell::new(None));
+            let navigation = if blocker.hide.is_empty() {
+                None
+            } else {
+                let host = host_of(&webview.get_source()?);
+                *script.borrow_mut() = Some(Slot::add(webview, &blocker.document_script(&host))?);
+                let blocker = blocker.clone();
+                let script = script.clone();
+                Some(webview.add_navigation_starting(move |w, args| {
+                    let host = host_of(&args.get_uri()?);
+                    let slot = Slot::add(&w, &blocker.document_script(&host))?;
+                    match script.borrow_mut().replace(slot) {
+                        Some(old) => old.remove(&w),
+                        None => Ok(()),
+                    }
+                })?)
+            };
+            webview.add_web_resource_requested_filter("*", WebResourceContext::All)?;
+            let token = webview.add_web_resource_requested(move |w, args| {
+                let url = args.get_request()?.get_uri()?;
+                let source = w.get_source()?;
+                let kind = ResourceContext::from(args.get_resource_context()?).into();
+                let request = Request::new(&url, Some(&source), kind);
+                if blocker.check(&request).is_blocked() {
+                    let response = environment.create_web_resource_response(
+                        Stream::from_bytes(&[]),
+                        204,
+                        "No Content",
+                        "",
+                    )?;
+                    args.put_response(response)?;
+                }
+                Ok(())
+            })?;
+            Ok(Attached {
+                token,
+                navigation,
+                script,
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::Attached;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASYLIST: &str = include_str!("blocker/fixtures/easylist.txt");
+    const EASYPRIVACY: &str = include_str!("blocker/fixtures/easyprivacy.txt");
+
+    fn blocker() -> Blocker {
+        Blocker::new().with_list(EASYLIST).with_list(EASYPRIVACY)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_filter("  ! comment"), Ok(None));
+        assert_eq!(parse_filter("[Adblock Plus 2.0]"), Ok(None));
+        assert_eq!(parse_filter("/ads[0-9]+/"), Err(FilterError::Regex));
+        assert_eq!(
+            parse_filter("||a.example^$popup"),
+            Err(FilterError::Option("popup".to_owned()))
+        );
+        assert_eq!(
+            parse_filter("a.example#?#div:-abp-has(.ad)"),
+            Err(FilterError::Cosmetic("#?#".to_owned()))
+        );
+        assert_eq!(parse_filter("a.example##"), Err(FilterError::Empty));
+        assert_eq!(parse_filter("||a^$domain="), Err(FilterError::Empty));
+
+        let filter = match parse_filter("@@|https://a.example/*.js|$script,~third-party") {
+            Ok(Some(Filter::Network(f))) => f,
+            other => panic!("{:?}", other),
+        };
+        assert!(filter.is_exception());
+        assert_eq!(filter.anchor, Anchor::Start);
+        assert!(filter.end);
+        assert_eq!(filter.third_party, Some(false));
+        assert_eq!(filter.types, ResourceType::Script.bit());
+        assert_eq!(filter.parts[0], Part::Byte(b'h'));
+        assert_eq!(filter.parts[18], Part::Any);
+
+        let filter = match parse_filter("||a.example^$~image,domain=b.example|~c.b.example") {
+            Ok(Some(Filter::Network(f))) => f,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(filter.types, DEFAULT_TYPES & !ResourceType::Image.bit());
+        assert_eq!(filter.domains, ["b.example"]);
+        assert_eq!(filter.not_domains, ["c.b.example"]);
+
+        let filter = match parse_filter("Example.com,~Sub.Example.com##.ad > div") {
+            Ok(Some(Filter::Cosmetic(f))) => f,
+            other => panic!("{:?}", other),
+        };
+        assert!(!filter.is_exception());
+        assert_eq!(filter.selector(), ".ad > div");
+        assert_eq!(filter.domains, ["example.com"]);
+        assert_eq!(filter.not_domains, ["sub.example.com"]);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let token = |line: &str| match parse_filter(line) {
+            Ok(Some(Filter::Network(f))) => f.token(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(token("||doubleclick.net^").as_deref(), Some("doubleclick"));
+        assert_eq!(token("||ads.example.org^").as_deref(), Some("example"));
+        // Could be the end of a longer word.
+        assert_eq!(token("banner/").as_deref(), None);
+        assert_eq!(token("/banner/*/img^").as_deref(), Some("banner"));
+        assert_eq!(token("/ad*s^").as_deref(), None);
+        assert_eq!(token("|https://track.").as_deref(), Some("https"));
+        assert_eq!(token("$websocket").as_deref(), None);
+    }
+
+    #[test]
+    fn test_hosts() {
+        assert_eq!(host_range("https://user:pw@A.example:8080/x"), (16, 25));
+        assert_eq!(host_range("http://[::1]:80/"), (7, 12));
+        assert_eq!(host_range("about:blank"), (0, 0));
+        assert_eq!(base_domain("a.b.example.com"), "example.com");
+        assert_eq!(base_domain("localhost"), "localhost");
+        assert_eq!(base_domain("192.168.0.1"), "192.168.0.1");
+        let request = Request::new(
+            "https://CDN.Example.com/a",
+            Some("https://www.example.com/"),
+            ResourceType::Image,
+        );
+        assert_eq!(request.host(), "cdn.example.com");
+        assert_eq!(request.is_third_party(), Some(false));
+        let request = Request::new(
+            "https://cdn.other.com/a",
+            Some("https://www.example.com/"),
+            ResourceType::Image,
+        );
+        assert_eq!(request.is_third_party(), Some(true));
+        let request = Request::new("https://cdn.other.com/a", None, ResourceType::Image);
+        assert_eq!(request.is_third_party(), None);
+    }
+
+    /// Without a public suffix list, sites under `co.uk` are one party.
+    #[test]
+    fn test_multi_label_suffix() {
+        assert_eq!(base_domain("www.a.co.uk"), "co.uk");
+        let request = Request::new(
+            "https://b.co.uk/a",
+            Some("https://a.co.uk/"),
+            ResourceType::Image,
+        );
+        assert_eq!(request.is_third_party(), Some(false));
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let blocker = blocker();
+        let skipped: Vec<_> = blocker
+            .skipped()
+            .iter()
+            .map(|(line, e)| (line.as_str(), e.clone()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (
+                    "||popads.example^$popup",
+                    FilterError::Option("popup".to_owned())
+                ),
+                (
+                    "example.com#?#div:-abp-has(> .ad)",
+                    FilterError::Cosmetic("#?#".to_owned())
+                ),
+                ("/ads\\d+\\.js/", FilterError::Regex),
+                (
+                    "||beacon.example^$csp=script-src 'none'",
+                    FilterError::Option("csp".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(blocker.len(), 28);
+
+        let page = "https://news.example/story";
+        let check = |url: &str, source: Option<&str>, kind: ResourceType| match blocker
+            .check(&Request::new(url, source, kind))
+        {
+            Verdict::Allowed => "allowed".to_owned(),
+            Verdict::Blocked { filter } => filter.to_owned(),
+            Verdict::Excepted { exception, .. } => exception.to_owned(),
+        };
+        use ResourceType::*;
+        for &(url, source, kind, expected) in &[
+            // Plain patterns.
+            (
+                "https://a.example/x-ad-banner.png",
+                Some(page),
+                Image,
+                "-ad-banner.",
+            ),
+            (
+                "https://a.example/adframe.html",
+                Some(page),
+                Subdocument,
+                "/adframe.",
+            ),
+            (
+                "https://a.example/q?x=1&ad_type=2",
+                Some(page),
+                XmlHttpRequest,
+                "&ad_type=",
+            ),
+            (
+                "https://a.example/bad-ad-banner",
+                Some(page),
+                Image,
+                "allowed",
+            ),
+            (
+                "https://a.com/ads/1.png",
+                Some(page),
+                Image,
+                ".com/ads/$image,script",
+            ),
+            ("https://a.com/ads/1.css", Some(page), Stylesheet, "allowed"),
+            (
+                "https://a.example/banner/x/img?1",
+                Some(page),
+                Image,
+                "/banner/*/img^",
+            ),
+            (
+                "https://a.example/banner/x/img",
+                Some(page),
+                Image,
+                "/banner/*/img^",
+            ),
+            (
+                "https://a.example/banner/x/imgs",
+                Some(page),
+                Image,
+                "allowed",
+            ),
+            (
+                "https://track.example/p",
+                Some(page),
+                Image,
+                "|https://track.",
+            ),
+            ("http://track.example/p", Some(page), Image, "allowed"),
+            (
+                "https://x.example/?u=https://track.",
+                Some(page),
+                Image,
+                "allowed",
+            ),
+            // Host anchors.
+            (
+                "https://doubleclick.net/x.js",
+                Some(page),
+                Script,
+                "||doubleclick.net^",
+            ),
+            (
+                "https://ad.g.doubleclick.net/x",
+                Some(page),
+                Image,
+                "||doubleclick.net^",
+            ),
+            (
+                "https://DoubleClick.NET:443/x",
+                Some(page),
+                Image,
+                "||doubleclick.net^",
+            ),
+            ("https://notdoubleclick.net/x", Some(page), Image, "allowed"),
+            (
+                "https://doubleclick.network/x",
+                Some(page),
+                Image,
+                "allowed",
+            ),
+            (
+                "https://a.example/?r=doubleclick.net",
+                Some(page),
+                Image,
+                "allowed",
+            ),
+            (
+                "https://cdn.example.net/ads/a/b.js",
+                Some(page),
+                Script,
+                "||cdn.example.net/ads/*.js|",
+            ),
+            (
+                "https://cdn.example.net/ads/a/b.js?v=1",
+                Some(page),
+                Script,
+                "allowed",
+            ),
+            // Types.
+            (
+                "https://adserver.example/a",
+                Some(page),
+                Script,
+                "||adserver.example^$script,image",
+            ),
+            ("https://adserver.example/a", Some(page), Font, "allowed"),
+            (
+                "https://ads.example.org/a",
+                Some(page),
+                Image,
+                "||ads.example.org^$~script",
+            ),
+            ("https://ads.example.org/a", Some(page), Script, "allowed"),
+            (
+                "wss://chat.example/socket",
+                Some("https://chat.example/"),
+                WebSocket,
+                "$websocket,domain=chat.example",
+            ),
+            (
+                "wss://chat.example/socket",
+                Some(page),
+                WebSocket,
+                "allowed",
+            ),
+            // Third party.
+            (
+                "https://googlesyndication.com/a.js",
+                Some(page),
+                Script,
+                "||googlesyndication.com^$third-party",
+            ),
+            (
+                "https://googlesyndication.com/a.js",
+                Some("https://www.googlesyndication.com/"),
+                Script,
+                "allowed",
+            ),
+            (
+                "https://googlesyndication.com/a.js",
+                None,
+                Script,
+                "allowed",
+            ),
+            (
+                "https://cdn.example/pixel.gif?id=1",
+                Some(page),
+                Image,
+                "/pixel.gif?$image,third-party",
+            ),
+            (
+                "https://news.example/pixel.gif?id=1",
+                Some(page),
+                Image,
+                "allowed",
+            ),
+            // Domain options.
+            (
+                "https://example.com/ad-frame/1",
+                Some(page),
+                Subdocument,
+                "||example.com/ad-frame^$subdocument,domain=news.example|~sports.news.example",
+            ),
+            (
+                "https://example.com/ad-frame/1",
+                Some("https://sports.news.example/"),
+                Subdocument,
+                "allowed",
+            ),
+            (
+                "https://example.com/ad-frame/1",
+                Some("https://other.example/"),
+                Subdocument,
+                "allowed",
+            ),
+            (
+                "https://metrics.example/m",
+                Some("https://www.shop.example/"),
+                XmlHttpRequest,
+                "||metrics.example^$domain=shop.example",
+            ),
+            ("https://metrics.example/m", None, XmlHttpRequest, "allowed"),
+            // Match case.
+            (
+                "https://collect.example/v1/event",
+                Some(page),
+                Ping,
+                "||collect.example/*/event$match-case",
+            ),
+            (
+                "https://collect.example/v1/EVENT",
+                Some(page),
+                Ping,
+                "allowed",
+            ),
+            // Exceptions.
+            (
+                "https://doubleclick.net/ads/allowed.js",
+                Some(page),
+                Script,
+                "@@||doubleclick.net/ads/allowed.js",
+            ),
+            (
+                "https://googlesyndication.com/a.js",
+                Some("https://partner.example/"),
+                Script,
+                "@@||googlesyndication.com^$domain=partner.example",
+            ),
+            (
+                "https://adserver.example/a.png",
+                Some(page),
+                Image,
+                "@@||adserver.example^$image",
+            ),
+            (
+                "https://adserver.example/a.js",
+                Some("https://trusted.example/"),
+                Script,
+                "@@||trusted.example^$document",
+            ),
+            // `$document` exceptions only match pages.
+            (
+                "https://trusted.example/x-ad-banner.png",
+                Some(page),
+                Image,
+                "-ad-banner.",
+            ),
+            (
+                "https://www.google-analytics.com/analytics.js",
+                Some("https://stats.example/"),
+                Script,
+                "@@||google-analytics.com/analytics.js$domain=stats.example",
+            ),
+            (
+                "https://www.google-analytics.com/analytics.js",
+                Some(page),
+                Script,
+                "||google-analytics.com^$third-party",
+            ),
+            (
+                "https://tracker.example/collect",
+                Some(page),
+                XmlHttpRequest,
+                "||tracker.example^$ping,xmlhttprequest",
+            ),
+            (
+                "https://tracker.example/collect",
+                Some(page),
+                Script,
+                "allowed",
+            ),
+        ] {
+            assert_eq!(
+                check(url, source, kind),
+                expected,
+                "{} from {:?}",
+                url,
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_cosmetic() {
+        let blocker = blocker();
+        let generic = [
+            "##.ad-banner",
+            "###sidebar-ads",
+            "##div[id^=\"google_ads_\"]",
+        ];
+        let generic: Vec<&str> = generic.iter().map(|s| s.trim_start_matches("##")).collect();
+        assert_eq!(blocker.hidden_selectors("a.example"), generic);
+        let mut news = generic.clone();
+        news.push(".sponsored");
+        assert_eq!(blocker.hidden_selectors("www.News.example"), news);
+        assert_eq!(blocker.hidden_selectors("sports.news.example"), generic);
+        assert_eq!(
+            blocker.hidden_selectors("example.org"),
+            ["#sidebar-ads", "div[id^=\"google_ads_\"]"]
+        );
+        assert_eq!(
+            blocker.stylesheet("example.org"),
+            "#sidebar-ads { display: none !important; }\n\
+             div[id^=\"google_ads_\"] { display: none !important; }\n"
+        );
+        let sponsored = r#"{"i":["news.example"],"s":".sponsored","x":["sports.news.example"]}"#;
+        let unhide = r#""unhide":[{"i":["example.org"],"s":".ad-banner","x":[]}]"#;
+        let script = blocker.document_script("www.news.example");
+        assert!(script.contains(sponsored));
+        assert!(script.contains(r#""unhide":[]"#));
+        assert!(script.contains(".ad-banner"));
+        let script = blocker.document_script("Example.org");
+        assert!(!script.contains(sponsored));
+        assert!(script.contains(unhide));
+    }
+}
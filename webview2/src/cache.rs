@@ -0,0 +1,1180 @@
+//! An offline cache for remote app pages, like a service worker would keep.
+//!
+//! An app that loads its UI from a remote origin shows an error page when
+//! the network is down. An `OfflineCache` keeps the last successful response
+//! to each `GET` on disk, typically in the user data folder, and serves it
+//! when the network fails or the cache is forced offline. The network always
+//! comes first otherwise.
+//!
+//! How long an entry may be served follows its `Cache-Control`:
+//!
+//! * `no-store` responses are not kept;
+//! * a response is fresh for its `max-age`, or from its `Date` to its
+//!   `Expires`, and `no-cache` makes it stale at once;
+//! * after that, a stale response is still served when the network fails,
+//!   for its `stale-if-error` if it has one, never with `must-revalidate`,
+//!   and for `OfflineCache::with_max_stale` otherwise.
+//!
+//! The cache is versioned: opening it with another version than it was
+//! stored with drops every entry, so a new release does not mix with pages
+//! of the old one.
+//!
+//! The manifest lists every entry, so writing it costs as much as the cache
+//! is large. It is written every `SAVE_EVERY` changes, by
+//! `OfflineCache::flush` and when the cache is dropped, rather than for each
+//! stored response. A crash loses the changes since; the bodies they wrote
+//! are removed the next time the cache is opened.
+//!
+//! `OfflineCache::fetch` is the whole policy for a `Source`, e.g. an HTTP
+//! client, and takes the current time so that it can be tested without a
+//! network or a clock:
+//!
+//! ```
+//! use std::io;
+//! use std::time::{Duration, SystemTime};
+//! use webview2::cache::OfflineCache;
+//! use webview2::web_resource::http::{Request, Response};
+//!
+//! let dir = std::env::temp_dir().join(format!("webview2-cache-doc-{}", std::process::id()));
+//! let mut cache = OfflineCache::open(&dir, "1.0").unwrap();
+//! let request = Request::get("https://app.example.com/index.html").body(Vec::new()).unwrap();
+//! let online = |_: &Request<Vec<u8>>| {
+//!     Ok(Some(Response::builder()
+//!         .header("cache-control", "max-age=60")
+//!         .body(b"<h1>hi</h1>".to_vec())
+//!         .unwrap()))
+//! };
+//! let offline = |_: &Request<Vec<u8>>| Err(io::Error::new(io::ErrorKind::NotConnected, "offline"));
+//!
+//! let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+//! cache.fetch(&online, &request, now).unwrap();
+//! let later = now + Duration::from_secs(3600);
+//! let response = cache.fetch(&offline, &request, later).unwrap().unwrap();
+//! assert_eq!(response.body(), b"<h1>hi</h1>");
+//! assert_eq!(response.headers()["age"], "3600");
+//! assert_eq!(response.headers()["x-webview2-cache"], "stale");
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+//!
+//! On Windows, `OfflineCache::attach` stores the responses a webview
+//! receives from `WebResourceResponseReceived`, and reloads a page whose
+//! navigation failed for lack of network from the cache.
+
+use crate::rewrite::Source;
+use crate::util::uri::normalize_uri;
+use crate::web_resource::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use crate::web_resource::http::{Method, Request, Response, StatusCode};
+use crate::web_resource::Body;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The directory `OfflineCache::in_user_data_folder` keeps the cache in.
+pub const DIR_NAME: &str = "OfflineCache";
+
+/// Set on responses served from the cache, to `fresh` or `stale`. Responses
+/// with it are not stored again.
+pub const CACHE_HEADER: &str = "x-webview2-cache";
+
+const MANIFEST: &str = "manifest.json";
+
+/// How many changes are kept in memory before the manifest is written.
+pub const SAVE_EVERY: usize = 16;
+
+/// Bumped when the layout of the manifest changes.
+const FORMAT: u32 = 1;
+
+/// Headers that describe a connection or the client rather than the
+/// response, which are not stored.
+const UNSTORED_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "set-cookie",
+    "transfer-encoding",
+];
+
+/// The `Cache-Control` directives the cache honours.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub must_revalidate: bool,
+    pub max_age: Option<u64>,
+    pub stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+    /// Parse the directives of all the `Cache-Control` headers. Unknown
+    /// directives are ignored, and so are invalid numbers.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut control = CacheControl::default();
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            for directive in value.split(',') {
+                let (name, argument) = match directive.find('=') {
+                    Some(i) => (
+                        &directive[..i],
+                        Some(directive[i + 1..].trim().trim_matches('"')),
+                    ),
+                    None => (directive, None),
+                };
+                let seconds = argument.and_then(|a| a.parse::<u64>().ok());
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "no-store" => control.no_store = true,
+                    "no-cache" => control.no_cache = true,
+                    "must-revalidate" => control.must_revalidate = true,
+                    "max-age" => control.max_age = seconds.or(control.max_age),
+                    "stale-if-error" => control.stale_if_error = seconds.or(control.stale_if_error),
+                    _ => {}
+                }
+            }
+        }
+        control
+    }
+}
+
+/// Seconds since the Unix epoch of an HTTP date in the preferred
+/// `Sun, 06 Nov 1994 08:49:37 GMT` format.
+pub fn parse_http_date(date: &str) -> Option<u64> {
+    let date = date.trim();
+    let rest = &date[date.find(", ")? + 2..];
+    let parts: Vec<&str> = rest.split(' ').collect();
+    if parts.len() != 5 || parts[4] != "GMT" || parts[0].len() != 2 || parts[2].len() != 4 {
+        return None;
+    }
+    let day: u64 = parts[0].parse().ok()?;
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ]
+    .iter()
+    .position(|m| *m == parts[1])? as u64
+        + 1;
+    let year: u64 = parts[2].parse().ok()?;
+    let time: Vec<u64> = parts[3]
+        .split(':')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    if year < 1970
+        || day == 0
+        || day > 31
+        || time.len() != 3
+        || time[0] > 23
+        || time[1] > 59
+        || time[2] > 60
+    {
+        return None;
+    }
+    // Days from 1970-01-01 to the date, counting years from March so that
+    // the leap day comes last.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let year_of_era = y % 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+    Some(days * 86400 + time[0] * 3600 + time[1] * 60 + time[2])
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn header_date(headers: &HeaderMap, name: HeaderName) -> Option<u64> {
+    parse_http_date(headers.get(name)?.to_str().ok()?)
+}
+
+/// How long a response received at `now` stays fresh, in seconds.
+fn freshness_lifetime(headers: &HeaderMap, control: &CacheControl, now: u64) -> u64 {
+    if control.no_cache {
+        return 0;
+    }
+    let age = headers
+        .get(header::AGE)
+        .and_then(|age| age.to_str().ok()?.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    let date = header_date(headers, header::DATE).unwrap_or(now);
+    let lifetime = match control.max_age {
+        Some(max_age) => max_age,
+        None => match headers.get(header::EXPIRES) {
+            // An invalid date, like `0`, means already expired.
+            Some(_) => header_date(headers, header::EXPIRES)
+                .map(|expires| expires.saturating_sub(date))
+                .unwrap_or(0),
+            // The usual heuristic: a tenth of the time since it changed, up
+            // to a day.
+            None => header_date(headers, header::LAST_MODIFIED)
+                .map(|modified| (date.saturating_sub(modified) / 10).min(86400))
+                .unwrap_or(0),
+        },
+    };
+    lifetime.saturating_sub(age)
+}
+
+/// How an entry may be served at some time.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Freshness {
+    /// Within its freshness lifetime.
+    Fresh,
+    /// Past it, but still good when the network fails.
+    Stale,
+    /// Not to be served any more.
+    Expired,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Seconds since the Unix epoch.
+    stored: u64,
+    /// Seconds it is fresh for after `stored`.
+    fresh_for: u64,
+    /// Seconds it can be served stale for after that, or `None` for the
+    /// cache's own limit.
+    stale_for: Option<u64>,
+    /// The file with the body, in the cache directory.
+    file: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    version: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Responses kept on disk for when the network fails. See the module
+/// documentation.
+#[derive(Debug)]
+pub struct OfflineCache {
+    dir: PathBuf,
+    version: String,
+    entries: BTreeMap<String, Entry>,
+    max_stale: Option<Duration>,
+    offline: bool,
+    /// Changes not in the manifest on disk yet.
+    unsaved: usize,
+}
+
+impl OfflineCache {
+    /// Open the cache in `dir`, creating it if needed. Entries stored with
+    /// another `version`, or by another layout of the cache, are dropped.
+    pub fn open(dir: impl Into<PathBuf>, version: &str) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let manifest = fs::read(dir.join(MANIFEST))
+            .ok()
+            .and_then(|json| serde_json::from_slice::<Manifest>(&json).ok());
+        let mut cache = OfflineCache {
+            dir,
+            version: version.to_owned(),
+            entries: BTreeMap::new(),
+            max_stale: None,
+            offline: false,
+            unsaved: 0,
+        };
+        match manifest {
+            Some(manifest) if manifest.format == FORMAT && manifest.version == version => {
+                cache.entries = manifest.entries;
+                cache.remove_unlisted()?;
+            }
+            _ => cache.clear()?,
+        }
+        Ok(cache)
+    }
+
+    /// Remove the bodies the manifest does not list, which changes lost in
+    /// a crash left behind.
+    fn remove_unlisted(&self) -> io::Result<()> {
+        for file in fs::read_dir(&self.dir)? {
+            let file = file?;
+            let name = file.file_name();
+            let listed = self.entries.values().any(|entry| *entry.file == *name);
+            if !listed && file.path().extension() == Some("body".as_ref()) {
+                remove_file(&file.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the cache in `DIR_NAME` in the user data folder of the webview
+    /// environment.
+    pub fn in_user_data_folder(user_data_folder: &Path, version: &str) -> io::Result<Self> {
+        Self::open(user_data_folder.join(DIR_NAME), version)
+    }
+
+    /// Serve stale responses without a `stale-if-error` for at most
+    /// `max_stale` past their freshness. Without it, they are served for as
+    /// long as they are kept.
+    pub fn with_max_stale(mut self, max_stale: Duration) -> Self {
+        self.max_stale = Some(max_stale);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The URIs of the entries.
+    pub fn uris(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Serve from the cache only, without trying the network.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// How the entry for `uri` may be served at `now`, if there is one.
+    pub fn freshness(&self, uri: &str, now: SystemTime) -> Option<Freshness> {
+        let entry = self.entries.get(&normalize_uri(uri))?;
+        Some(self.entry_freshness(entry, seconds(now)))
+    }
+
+    fn entry_freshness(&self, entry: &Entry, now: u64) -> Freshness {
+        let age = now.saturating_sub(entry.stored);
+        if age < entry.fresh_for {
+            return Freshness::Fresh;
+        }
+        let stale_for = entry
+            .stale_for
+            .or_else(|| self.max_stale.map(|max| max.as_secs()));
+        match stale_for {
+            Some(stale_for) if age - entry.fresh_for >= stale_for => Freshness::Expired,
+            _ => Freshness::Stale,
+        }
+    }
+
+    /// Keep `response` to a `GET` of `uri` received at `now`. Returns
+    /// whether it was stored: only `200 OK` responses are, and not those
+    /// with `no-store`, `Vary: *` or from the cache itself. A `no-store`
+    /// response also drops what was kept for `uri` before.
+    pub fn store(
+        &mut self,
+        uri: &str,
+        response: &Response<Body>,
+        now: SystemTime,
+    ) -> io::Result<bool> {
+        let headers = response.headers();
+        let control = CacheControl::from_headers(headers);
+        if control.no_store {
+            self.remove(uri)?;
+            return Ok(false);
+        }
+        let vary_all = headers
+            .get_all(header::VARY)
+            .iter()
+            .any(|v| v.to_str().map(|v| v.contains('*')).unwrap_or(false));
+        if response.status() != StatusCode::OK || vary_all || headers.contains_key(CACHE_HEADER) {
+            return Ok(false);
+        }
+
+        let uri = normalize_uri(uri);
+        let now = seconds(now);
+        // A new file for each response, so that the manifest on disk never
+        // lists the body of another one.
+        let file = format!("{:x}-{}.body", Sha256::digest(uri.as_bytes()), now);
+        write_file(&self.dir.join(&file), response.body())?;
+        let entry = Entry {
+            status: response.status().as_u16(),
+            headers: headers
+                .iter()
+                .filter(|(name, _)| !UNSTORED_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| {
+                    Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            stored: now,
+            fresh_for: freshness_lifetime(headers, &control, now),
+            stale_for: if control.must_revalidate {
+                Some(0)
+            } else {
+                control.stale_if_error
+            },
+            file: file.clone(),
+        };
+        if let Some(old) = self.entries.insert(uri, entry) {
+            if old.file != file {
+                remove_file(&self.dir.join(old.file))?;
+            }
+        }
+        self.changed()?;
+        Ok(true)
+    }
+
+    /// The response kept for `uri`, unless it expired at `now`. It has an
+    /// `Age` and `CACHE_HEADER`.
+    pub fn response(&self, uri: &str, now: SystemTime) -> io::Result<Option<Response<Body>>> {
+        let entry = match self.entries.get(&normalize_uri(uri)) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let now = seconds(now);
+        let freshness = match self.entry_freshness(entry, now) {
+            Freshness::Fresh => "fresh",
+            Freshness::Stale => "stale",
+            Freshness::Expired => return Ok(None),
+        };
+        let body = match fs::read(self.dir.join(&entry.file)) {
+            Ok(body) => body,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut response = Response::new(body);
+        *response.status_mut() = StatusCode::from_u16(entry.status).unwrap_or(StatusCode::OK);
+        let headers = response.headers_mut();
+        for (name, value) in &entry.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        headers.insert(
+            header::AGE,
+            HeaderValue::from(now.saturating_sub(entry.stored)),
+        );
+        headers.insert(CACHE_HEADER, HeaderValue::from_static(freshness));
+        Ok(Some(response))
+    }
+
+    /// Online first: `request` from `source`, keeping the response of a
+    /// `GET`, or the kept response if the source fails. Forced offline, only
+    /// the kept response. Errors of the source are returned when nothing
+    /// is kept for the request.
+    pub fn fetch(
+        &mut self,
+        source: &dyn Source,
+        request: &Request<Body>,
+        now: SystemTime,
+    ) -> io::Result<Option<Response<Body>>> {
+        let uri = request.uri().to_string();
+        let get = request.method() == Method::GET;
+        if self.offline {
+            return if get {
+                self.response(&uri, now)
+            } else {
+                Ok(None)
+            };
+        }
+        match source.fetch(request) {
+            Ok(Some(response)) => {
+                if get {
+                    self.store(&uri, &response, now)?;
+                }
+                Ok(Some(response))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => match self.response(&uri, now)? {
+                Some(response) if get => Ok(Some(response)),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Drop the entry for `uri`. Returns whether there was one.
+    pub fn remove(&mut self, uri: &str) -> io::Result<bool> {
+        match self.entries.remove(&normalize_uri(uri)) {
+            Some(entry) => {
+                remove_file(&self.dir.join(entry.file))?;
+                self.changed()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Drop the entries that expired at `now`. Returns how many.
+    pub fn purge(&mut self, now: SystemTime) -> io::Result<usize> {
+        let now = seconds(now);
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| self.entry_freshness(entry, now) == Freshness::Expired)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in &expired {
+            if let Some(entry) = self.entries.remove(uri) {
+                remove_file(&self.dir.join(entry.file))?;
+                self.changed()?;
+            }
+        }
+        Ok(expired.len())
+    }
+
+    /// Drop every entry.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension() == Some("body".as_ref()) {
+                remove_file(&path)?;
+            }
+        }
+        self.save()
+    }
+
+    /// Write the changes not in the manifest on disk yet.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.unsaved > 0 {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn changed(&mut self) -> io::Result<()> {
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_EVERY {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&mut self) -> io::Result<()> {
+        let manifest = serde_json::json!({
+            "format": FORMAT,
+            "version": self.version,
+            "entries": self.entries,
+        });
+        write_file(&self.dir.join(MANIFEST), manifest.to_string().as_bytes())?;
+        self.unsaved = 0;
+        Ok(())
+    }
+}
+
+impl Drop for OfflineCache {
+    fn drop(&mut self) {
+        self.flush().ok();
+    }
+}
+
+/// Write to a temporary file first, so that a crash does not leave half a
+/// file behind.
+fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::policy::Wildcard;
+    use crate::{
+        Environment, EventRegistrationToken, Result, WebErrorStatus, WebResourceContext, WebView,
+    };
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    /// The event handlers `OfflineCache::attach` added.
+    #[derive(Debug)]
+    pub struct Attached {
+        requested: EventRegistrationToken,
+        received: EventRegistrationToken,
+        starting: EventRegistrationToken,
+        completed: EventRegistrationToken,
+    }
+
+    impl Attached {
+        /// Stop caching. The web resource filter stays.
+        pub fn detach(self, webview: &WebView) -> Result<()> {
+            webview.remove_web_resource_requested(self.requested)?;
+            webview
+                .get_webview_2()?
+                .remove_web_resource_response_received(self.received)?;
+            webview.remove_navigation_starting(self.starting)?;
+            webview.remove_navigation_completed(self.completed)
+        }
+    }
+
+    /// Whether a navigation failed for lack of network, rather than e.g. a
+    /// bad certificate.
+    fn is_network_error(status: WebErrorStatus) -> bool {
+        matches!(
+            status,
+            WebErrorStatus::ServerUnreachable
+                | WebErrorStatus::Timeout
+                | WebErrorStatus::ConnectionAborted
+                | WebErrorStatus::ConnectionReset
+                | WebErrorStatus::Disconnected
+                | WebErrorStatus::CannotConnect
+                | WebErrorStatus::HostNameNotResolved
+        )
+    }
+
+    #[derive(Debug, Default)]
+    struct State {
+        /// Serving from the cache after a navigation failed.
+        fallback: Cell<bool>,
+        /// The next navigation is the reload into the fallback.
+        reloading: Cell<bool>,
+    }
+
+    impl OfflineCache {
+        /// Keep the successful responses `webview` receives from URIs
+        /// matching `filter`, a WebView2 wildcard pattern, and serve them
+        /// when it is offline.
+        ///
+        /// A navigation that fails for lack of network is reloaded from the
+        /// cache, if the cache has the page. The next navigation tries the
+        /// network again. Other requests only fall back to the cache during
+        /// such a reload or when the cache is forced offline, where requests
+        /// it does not have get `504 Gateway Timeout`.
+        ///
+        /// The cache is shared so that it can be forced offline later.
+        pub fn attach(
+            cache: &Rc<RefCell<OfflineCache>>,
+            webview: &WebView,
+            environment: Environment,
+            filter: &str,
+        ) -> Result<Attached> {
+            let state = Rc::new(State::default());
+
+            webview.add_web_resource_requested_filter(filter, WebResourceContext::All)?;
+            let (c, s) = (cache.clone(), state.clone());
+            let requested = webview.add_web_resource_requested(move |_, args| {
+                let cache = c.borrow();
+                if !cache.is_offline() && !s.fallback.get() {
+                    return Ok(());
+                }
+                let request = args.get_request()?;
+                if !request.get_method()?.eq_ignore_ascii_case("GET") {
+                    return Ok(());
+                }
+                let response = match cache.response(&request.get_uri()?, SystemTime::now())? {
+                    Some(response) => response,
+                    None if cache.is_offline() => {
+                        let mut response = Response::new(b"Offline".to_vec());
+                        *response.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+                        response
+                    }
+                    None => return Ok(()),
+                };
+                args.put_response(environment.create_http_response(response)?)
+            })?;
+
+            let filter = Wildcard::new(filter);
+            let (c, s) = (cache.clone(), state.clone());
+            let received = webview
+                .get_webview_2()?
+                .add_web_resource_response_received(move |_, args| {
+                    if c.borrow().is_offline() || s.fallback.get() {
+                        return Ok(());
+                    }
+                    let request = args.get_request()?;
+                    let uri = request.get_uri()?;
+                    if !filter.matches(&uri) || !request.get_method()?.eq_ignore_ascii_case("GET") {
+                        return Ok(());
+                    }
+                    let view = args.get_response()?;
+                    if view.get_status_code()? != 200 {
+                        return Ok(());
+                    }
+                    let c = c.clone();
+                    view.to_http(move |response| {
+                        c.borrow_mut().store(&uri, &response?, SystemTime::now())?;
+                        Ok(())
+                    })
+                })?;
+
+            let s = state.clone();
+            let starting = webview.add_navigation_starting(move |_, _| {
+                if !s.reloading.replace(false) {
+                    s.fallback.set(false);
+                }
+                Ok(())
+            })?;
+
+            let c = cache.clone();
+            let completed = webview.add_navigation_completed(move |w, args| {
+                if state.fallback.get()
+                    || args.get_is_success()?
+                    || !is_network_error(args.get_web_error_status()?)
+                {
+                    return Ok(());
+                }
+                let uri = w.get_source()?;
+                let cached = c.borrow().freshness(&uri, SystemTime::now());
+                if let Some(Freshness::Fresh) | Some(Freshness::Stale) = cached {
+                    state.fallback.set(true);
+                    state.reloading.set(true);
+                    w.reload()?;
+                }
+                Ok(())
+            })?;
+
+            Ok(Attached {
+                requested,
+                received,
+                starting,
+                completed,
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::Attached;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::{request, response, temp_path};
+    use std::cell::Cell;
+
+    /// A fresh directory for one test.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = temp_path(&format!("cache-{}", name));
+            fs::remove_dir_all(&dir).ok();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    const T: u64 = 1_600_000_000;
+    const URI: &str = "https://app.example.com/index.html";
+
+    #[test]
+    fn test_http_date() {
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2000 23:59:59 GMT"),
+            Some(951_868_799)
+        );
+        assert_eq!(parse_http_date("Sun, 13 Sep 2020 12:26:40 GMT"), Some(T));
+        for invalid in &[
+            "0",
+            "",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+        ] {
+            assert_eq!(parse_http_date(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let headers = response(
+            &[
+                ("cache-control", "public, Max-Age=\"60\", no-cache"),
+                (
+                    "cache-control",
+                    "must-revalidate,stale-if-error=300, max-age=x",
+                ),
+            ],
+            b"",
+        )
+        .headers()
+        .clone();
+        assert_eq!(
+            CacheControl::from_headers(&headers),
+            CacheControl {
+                no_store: false,
+                no_cache: true,
+                must_revalidate: true,
+                max_age: Some(60),
+                stale_if_error: Some(300),
+            }
+        );
+        assert_eq!(
+            CacheControl::from_headers(&HeaderMap::new()),
+            CacheControl::default()
+        );
+    }
+
+    #[test]
+    fn test_freshness_lifetime() {
+        let lifetime = |headers: &[(&str, &str)]| {
+            let response = response(headers, b"");
+            let control = CacheControl::from_headers(response.headers());
+            freshness_lifetime(response.headers(), &control, T)
+        };
+        assert_eq!(lifetime(&[("cache-control", "max-age=60")]), 60);
+        assert_eq!(
+            lifetime(&[("cache-control", "max-age=60"), ("age", "50")]),
+            10
+        );
+        assert_eq!(
+            lifetime(&[("cache-control", "max-age=60"), ("age", "70")]),
+            0
+        );
+        assert_eq!(lifetime(&[("cache-control", "max-age=60, no-cache")]), 0);
+        // `max-age` wins over `Expires`.
+        assert_eq!(
+            lifetime(&[
+                ("cache-control", "max-age=60"),
+                ("expires", "Sun, 13 Sep 2020 13:26:40 GMT")
+            ]),
+            60
+        );
+        assert_eq!(
+            lifetime(&[
+                ("date", "Sun, 13 Sep 2020 12:26:40 GMT"),
+                ("expires", "Sun, 13 Sep 2020 13:26:40 GMT")
+            ]),
+            3600
+        );
+        // Without a `Date`, `Expires` counts from now.
+        assert_eq!(
+            lifetime(&[("expires", "Sun, 13 Sep 2020 12:36:40 GMT")]),
+            600
+        );
+        assert_eq!(lifetime(&[("expires", "0")]), 0);
+        assert_eq!(
+            lifetime(&[
+                ("date", "Sun, 13 Sep 2020 12:26:40 GMT"),
+                ("last-modified", "Sun, 13 Sep 2020 12:00:00 GMT")
+            ]),
+            160
+        );
+        assert_eq!(
+            lifetime(&[("last-modified", "Thu, 01 Jan 1970 00:00:00 GMT")]),
+            86400
+        );
+        assert_eq!(lifetime(&[]), 0);
+    }
+
+    #[test]
+    fn test_store_and_serve() {
+        let dir = TempDir::new("store");
+        let mut cache = OfflineCache::open(&dir.0, "1").unwrap();
+        let stored = response(
+            &[
+                ("content-type", "text/html"),
+                ("cache-control", "max-age=60"),
+                ("set-cookie", "session=1"),
+                ("x-custom", "a"),
+                ("x-custom", "b"),
+            ],
+            b"<h1>hi</h1>",
+        );
+        assert!(cache.store(URI, &stored, at(T)).unwrap());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.uris().collect::<Vec<_>>(), vec![URI]);
+
+        // The key is normalized like WebView2 does.
+        let served = cache
+            .response("HTTPS://App.Example.com:443/index.html#top", at(T + 10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(served.status(), StatusCode::OK);
+        assert_eq!(served.body(), b"<h1>hi</h1>");
+        let headers = served.headers();
+        assert_eq!(headers["content-type"], "text/html");
+        assert_eq!(headers.get_all("x-custom").iter().count(), 2);
+        assert!(!headers.contains_key("set-cookie"));
+        assert_eq!(headers["age"], "10");
+        assert_eq!(headers[CACHE_HEADER], "fresh");
+
+        // Responses from the cache are not stored again.
+        assert!(!cache.store(URI, &served, at(T + 20)).unwrap());
+        let served = cache.response(URI, at(T + 60)).unwrap().unwrap();
+        assert_eq!(served.headers()[CACHE_HEADER], "stale");
+        assert_eq!(cache.freshness(URI, at(T + 59)), Some(Freshness::Fresh));
+        assert_eq!(cache.freshness(URI, at(T + 60)), Some(Freshness::Stale));
+
+        for unstored in &[
+            response(&[("cache-control", "no-store")], b""),
+            response(&[("vary", "*")], b""),
+            Response::builder().status(404).body(Vec::new()).unwrap(),
+        ] {
+            assert!(!cache
+                .store("https://app.example.com/other", unstored, at(T))
+                .unwrap());
+        }
+        assert_eq!(cache.len(), 1);
+
+        // `no-store` also drops what was there.
+        let no_store = response(&[("cache-control", "no-store")], b"");
+        assert!(!cache.store(URI, &no_store, at(T)).unwrap());
+        assert!(cache.is_empty());
+        assert!(cache.response(URI, at(T)).unwrap().is_none());
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let dir = TempDir::new("expiry");
+        let mut cache = OfflineCache::open(&dir.0, "1")
+            .unwrap()
+            .with_max_stale(Duration::from_secs(100));
+        let entries = [
+            ("https://a/default", "max-age=10"),
+            ("https://a/revalidate", "max-age=10, must-revalidate"),
+            (
+                "https://a/stale-if-error",
+                "max-age=10, stale-if-error=1000",
+            ),
+            ("https://a/no-cache", "no-cache"),
+        ];
+        for (uri, control) in &entries {
+            let stored = response(&[("cache-control", control)], b"x");
+            assert!(cache.store(uri, &stored, at(T)).unwrap());
+        }
+        let freshness = |cache: &OfflineCache, secs: u64| -> Vec<Freshness> {
+            entries
+                .iter()
+                .map(|(uri, _)| cache.freshness(uri, at(T + secs)).unwrap())
+                .collect()
+        };
+        use Freshness::*;
+        assert_eq!(freshness(&cache, 5), vec![Fresh, Fresh, Fresh, Stale]);
+        assert_eq!(freshness(&cache, 10), vec![Stale, Expired, Stale, Stale]);
+        assert_eq!(freshness(&cache, 109), vec![Stale, Expired, Stale, Expired]);
+        assert_eq!(
+            freshness(&cache, 110),
+            vec![Expired, Expired, Stale, Expired]
+        );
+        assert_eq!(
+            freshness(&cache, 1010),
+            vec![Expired, Expired, Expired, Expired]
+        );
+        // A clock that went back does not make anything older.
+        assert_eq!(freshness(&cache, 0)[0], Fresh);
+        assert_eq!(
+            cache.freshness(entries[0].0, at(T - 100)),
+            Some(Freshness::Fresh)
+        );
+
+        assert!(cache.response(entries[1].0, at(T + 10)).unwrap().is_none());
+        assert_eq!(cache.purge(at(T + 110)).unwrap(), 3);
+        assert_eq!(cache.uris().collect::<Vec<_>>(), vec![entries[2].0]);
+        // Only the kept body and the manifest are left.
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 2);
+        drop(cache);
+
+        // Without a limit, stale entries are kept until they are replaced.
+        let cache = OfflineCache::open(&dir.0, "1").unwrap();
+        assert_eq!(
+            cache.freshness(entries[2].0, at(T + 1_000_000)),
+            Some(Freshness::Expired)
+        );
+        let mut cache = cache;
+        let stored = response(&[("cache-control", "max-age=10")], b"x");
+        cache.store(entries[0].0, &stored, at(T)).unwrap();
+        assert_eq!(
+            cache.freshness(entries[0].0, at(T + 1_000_000)),
+            Some(Freshness::Stale)
+        );
+    }
+
+    #[test]
+    fn test_versions() {
+        let dir = TempDir::new("versions");
+        let mut cache = OfflineCache::in_user_data_folder(&dir.0, "1").unwrap();
+        assert_eq!(cache.dir(), dir.0.join(DIR_NAME));
+        cache
+            .store(
+                URI,
+                &response(&[("cache-control", "max-age=60")], b"v1"),
+                at(T),
+            )
+            .unwrap();
+        drop(cache);
+
+        let cache = OfflineCache::in_user_data_folder(&dir.0, "1").unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.version(), "1");
+        let served = cache.response(URI, at(T + 1)).unwrap().unwrap();
+        assert_eq!(served.body(), b"v1");
+        drop(cache);
+
+        let cache = OfflineCache::in_user_data_folder(&dir.0, "2").unwrap();
+        assert!(cache.is_empty());
+        assert!(cache.response(URI, at(T + 1)).unwrap().is_none());
+        assert_eq!(fs::read_dir(cache.dir()).unwrap().count(), 1);
+
+        // A broken manifest is the same as none.
+        fs::write(cache.dir().join(MANIFEST), b"{").unwrap();
+        assert!(OfflineCache::in_user_data_folder(&dir.0, "2")
+            .unwrap()
+            .is_empty());
+    }
+
+    /// The manifest is written in batches, and what a crash loses of it is
+    /// cleaned up.
+    #[test]
+    fn test_saving() {
+        let dir = TempDir::new("saving");
+        let saved = || {
+            let json = fs::read(dir.0.join(MANIFEST)).unwrap();
+            serde_json::from_slice::<Manifest>(&json)
+                .unwrap()
+                .entries
+                .len()
+        };
+        let mut cache = OfflineCache::open(&dir.0, "1").unwrap();
+        let stored = response(&[("cache-control", "max-age=60")], b"x");
+        let uri = |i: usize| format!("https://a/{}", i);
+        for i in 0..SAVE_EVERY - 1 {
+            cache.store(&uri(i), &stored, at(T)).unwrap();
+        }
+        assert_eq!(saved(), 0);
+        cache.store(&uri(SAVE_EVERY), &stored, at(T)).unwrap();
+        assert_eq!(saved(), SAVE_EVERY);
+
+        // Storing again replaces the body.
+        cache.store(&uri(0), &stored, at(T + 1)).unwrap();
+        cache.flush().unwrap();
+        assert_eq!(saved(), SAVE_EVERY);
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), SAVE_EVERY + 1);
+
+        // A crash before the manifest is written.
+        cache.store("https://a/lost", &stored, at(T)).unwrap();
+        cache.remove(&uri(1)).unwrap();
+        std::mem::forget(cache);
+        let cache = OfflineCache::open(&dir.0, "1").unwrap();
+        assert_eq!(cache.len(), SAVE_EVERY);
+        assert!(cache.response("https://a/lost", at(T)).unwrap().is_none());
+        assert!(cache.response(&uri(1), at(T)).unwrap().is_none());
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), SAVE_EVERY);
+    }
+
+    #[test]
+    fn test_fetch() {
+        let dir = TempDir::new("fetch");
+        let mut cache = OfflineCache::open(&dir.0, "1").unwrap();
+        let calls = Cell::new(0);
+        let online = |request: &Request<Body>| {
+            calls.set(calls.get() + 1);
+            Ok(Some(response(
+                &[("cache-control", "max-age=60")],
+                request.uri().to_string().as_bytes(),
+            )))
+        };
+        let offline = |_: &Request<Body>| -> io::Result<Option<Response<Body>>> {
+            calls.set(calls.get() + 1);
+            Err(io::Error::new(io::ErrorKind::NotConnected, "offline"))
+        };
+        let pass = |_: &Request<Body>| Ok(None);
+
+        // Online first, even when the entry is fresh.
+        let response = cache
+            .fetch(&online, &request("GET", URI), at(T))
+            .unwrap()
+            .unwrap();
+        assert!(!response.headers().contains_key(CACHE_HEADER));
+        let response = cache
+            .fetch(&online, &request("GET", URI), at(T + 1))
+            .unwrap()
+            .unwrap();
+        assert!(!response.headers().contains_key(CACHE_HEADER));
+        assert_eq!(calls.get(), 2);
+
+        let response = cache
+            .fetch(&offline, &request("GET", URI), at(T + 2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.body(), URI.as_bytes());
+        assert_eq!(response.headers()["age"], "1");
+        let error = cache
+            .fetch(
+                &offline,
+                &request("GET", "https://app.example.com/missing"),
+                at(T),
+            )
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+        assert!(cache
+            .fetch(&pass, &request("GET", URI), at(T))
+            .unwrap()
+            .is_none());
+
+        // Only `GET`s are kept and served.
+        let post = Request::post(URI).body(Vec::new()).unwrap();
+        let other = "https://app.example.com/post";
+        let post_other = Request::post(other).body(Vec::new()).unwrap();
+        cache.fetch(&online, &post_other, at(T)).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(cache.fetch(&offline, &post, at(T)).is_err());
+
+        cache.set_offline(true);
+        assert!(cache.is_offline());
+        let calls_before = calls.get();
+        let response = cache
+            .fetch(&online, &request("GET", URI), at(T + 3))
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.headers()[CACHE_HEADER], "fresh");
+        assert!(cache
+            .fetch(&online, &request("GET", other), at(T))
+            .unwrap()
+            .is_none());
+        assert!(cache.fetch(&online, &post, at(T)).unwrap().is_none());
+        assert_eq!(calls.get(), calls_before);
+    }
+
+    #[test]
+    fn test_http_date_boundaries() {
+        // The ends of years and months, leap days, and a century that is
+        // not a leap year.
+        for (date, secs) in &[
+            ("Thu, 31 Dec 1970 23:59:59 GMT", 31_535_999),
+            ("Fri, 01 Jan 1971 00:00:00 GMT", 31_536_000),
+            ("Tue, 29 Feb 1972 00:00:00 GMT", 68_169_600),
+            ("Wed, 01 Mar 1972 00:00:00 GMT", 68_256_000),
+            ("Tue, 19 Jan 2038 03:14:08 GMT", 2_147_483_648),
+            ("Sun, 28 Feb 2100 00:00:00 GMT", 4_107_456_000),
+            ("Mon, 01 Mar 2100 00:00:00 GMT", 4_107_542_400),
+        ] {
+            assert_eq!(parse_http_date(date), Some(*secs), "{}", date);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Configuration handed to the page before any of its own scripts run.
+//!
+//! A `PageConfig` is a JSON object that `document_script` installs as a
+//! frozen global, `window.webview2Config` unless renamed with `with_global`.
+//! Adding that script with `add_script_to_execute_on_document_created` makes
+//! it part of every document without a round trip per value. On Windows,
+//! `ConfigInjector` does that and also pushes later changes to the current
+//! document, where they fire a `webview2configchange` event on `window`:
+//!
+//! ```js
+//! if (window.webview2Config.debug) { ... }
+//! window.addEventListener('webview2configchange', e => render(e.detail.config));
+//! ```
+
+use crate::util::to_js;
+use crate::util::uri::normalize_origin;
+use serde_json::{Map, Value as Json};
+
+/// The global the configuration is installed as by default.
+pub const DEFAULT_GLOBAL: &str = "webview2Config";
+
+/// The event fired on `window` when `update_script` replaces the
+/// configuration. Its `detail` has the new `config` and the `previous` one.
+pub const CHANGE_EVENT: &str = "webview2configchange";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageConfig {
+    global: String,
+    origins: Option<Vec<String>>,
+    values: Map<String, Json>,
+}
+
+impl Default for PageConfig {
+    fn default() -> Self {
+        PageConfig {
+            global: DEFAULT_GLOBAL.to_owned(),
+            origins: None,
+            values: Map::new(),
+        }
+    }
+}
+
+impl PageConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One `true` flag for each name in a `;` separated list, e.g. `"A;B"`.
+    pub fn from_defines(defines: &str) -> Self {
+        defines
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .fold(Self::new(), |config, name| config.with_flag(name))
+    }
+
+    pub fn with_global(mut self, global: &str) -> Self {
+        self.global = global.to_owned();
+        self
+    }
+
+    /// Only install the configuration in documents from these origins, like
+    /// `https://example.com` or `http://localhost:8080`. Origins that are not
+    /// `scheme://host[:port]` match no document and are left out.
+    pub fn with_origins<I, S>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.origins = Some(
+            origins
+                .into_iter()
+                .filter_map(|o| normalize_origin(o.as_ref()))
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_flag(self, name: &str) -> Self {
+        self.with_value(name, true)
+    }
+
+    pub fn with_value(mut self, key: &str, value: impl Into<Json>) -> Self {
+        self.set(key, value);
+        self
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<Json>) -> Option<Json> {
+        self.values.insert(key.to_owned(), value.into())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Json> {
+        self.values.remove(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        self.values.get(key)
+    }
+
+    pub fn global(&self) -> &str {
+        &self.global
+    }
+
+    pub fn origins(&self) -> Option<&[String]> {
+        self.origins.as_deref()
+    }
+
+    pub fn values(&self) -> &Map<String, Json> {
+        &self.values
+    }
+
+    /// Whether a document from `origin` gets the configuration.
+    pub fn applies_to(&self, origin: &str) -> bool {
+        match &self.origins {
+            Some(origins) => matches!(normalize_origin(origin), Some(o) if origins.contains(&o)),
+            None => true,
+        }
+    }
+
+    /// Installs the configuration, for `add_script_to_execute_on_document_created`.
+    pub fn document_script(&self) -> String {
+        format!(
+            "(function () {{\n{}    install({});\n}})();",
+            self.prelude(),
+            to_js(&Json::Object(self.values.clone()))
+        )
+    }
+
+    /// Replaces the configuration of the current document, for
+    /// `execute_script`, and fires `CHANGE_EVENT` if it was installed before.
+    pub fn update_script(&self) -> String {
+        format!(
+            r#"(function () {{
+{}    var previous = window[global];
+    var config = install({});
+    if (previous !== undefined) {{
+        var detail = {{ config: config, previous: previous }};
+        window.dispatchEvent(new CustomEvent({}, {{ detail: detail }}));
+    }}
+}})();"#,
+            self.prelude(),
+            to_js(&Json::Object(self.values.clone())),
+            to_js(&Json::from(CHANGE_EVENT))
+        )
+    }
+
+    /// The origin check and the `install` function shared by both scripts.
+    fn prelude(&self) -> String {
+        let origins = match &self.origins {
+            Some(origins) => to_js(&Json::from(origins.clone())),
+            None => "null".to_owned(),
+        };
+        format!(
+            r#"    var global = {};
+    var origins = {};
+    if (origins && origins.indexOf(window.location.origin.toLowerCase()) < 0) {{
+        return;
+    }}
+    function freeze(value) {{
+        if (value && typeof value === 'object') {{
+            Object.keys(value).forEach(function (key) {{ freeze(value[key]); }});
+            Object.freeze(value);
+        }}
+        return value;
+    }}
+    function install(config) {{
+        Object.defineProperty(window, global, {{
+            value: freeze(config),
+            configurable: true,
+            enumerable: false,
+            writable: false
+        }});
+        return config;
+    }}
+"#,
+            to_js(&Json::from(self.global.as_str())),
+            origins
+        )
+    }
+}
+
+#[cfg(windows)]
+pub use self::injector::*;
+
+#[cfg(windows)]
+mod injector {
+    use super::*;
+    use crate::util::script::Slot;
+    use crate::{Result, WebView};
+
+    /// Keeps a `PageConfig` installed in a webview's documents.
+    pub struct ConfigInjector {
+        webview: WebView,
+        config: PageConfig,
+        script: Slot,
+    }
+
+    impl ConfigInjector {
+        /// Install `config` in every document `webview` loads from now on.
+        pub fn new(webview: WebView, config: PageConfig) -> Result<Self> {
+            let script = Slot::add(&webview, &config.document_script())?;
+            Ok(ConfigInjector {
+                webview,
+                config,
+                script,
+            })
+        }
+
+        pub fn config(&self) -> &PageConfig {
+            &self.config
+        }
+
+        /// Replace the configuration in later documents and in the current
+        /// one.
+        pub fn update(&mut self, config: PageConfig) -> Result<()> {
+            let script = Slot::add(&self.webview, &config.document_script())?;
+            std::mem::replace(&mut self.script, script).remove(&self.webview)?;
+            self.webview
+                .execute_script(&config.update_script(), |_| Ok(()))?;
+            self.config = config;
+            Ok(())
+        }
+
+        /// `update` with a change made to the current configuration.
+        pub fn modify(&mut self, f: impl FnOnce(&mut PageConfig)) -> Result<()> {
+            let mut config = self.config.clone();
+            f(&mut config);
+            self.update(config)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_builder() {
+        let config = PageConfig::from_defines("A;;B;")
+            .with_value("name", "x")
+            .with_value("nested", json!({ "n": [1, 2.5, null] }))
+            .with_origins(vec!["HTTPS://Example.com/", "http://localhost:8080"]);
+        assert_eq!(
+            Json::Object(config.values().clone()),
+            json!({ "A": true, "B": true, "name": "x", "nested": { "n": [1, 2.5, null] } })
+        );
+        assert_eq!(
+            config.origins(),
+            Some(
+                &[
+                    "https://example.com".to_owned(),
+                    "http://localhost:8080".to_owned()
+                ][..]
+            )
+        );
+        assert!(config.applies_to("https://EXAMPLE.com"));
+        assert!(config.applies_to("https://example.com:443"));
+        assert!(!config.applies_to("https://example.org"));
+        assert!(!config.applies_to("null"));
+        assert!(PageConfig::new().applies_to("null"));
+
+        let config = PageConfig::new().with_origins(vec!["https://a.com:443", "a.com"]);
+        assert_eq!(config.origins(), Some(&["https://a.com".to_owned()][..]));
+        assert!(config.applies_to("https://a.com"));
+        assert_eq!(config.global(), DEFAULT_GLOBAL);
+    }
+
+    #[test]
+    fn test_scripts() {
+        let config = PageConfig::new()
+            .with_global("my\"Config")
+            .with_flag("debug")
+            .with_origins(vec!["https://example.com"]);
+        let script = config.document_script();
+        assert!(script.starts_with("(function () {\n    var global = \"my\\\"Config\";\n"));
+        assert!(script.contains("var origins = [\"https://example.com\"];"));
+        assert!(script.ends_with("    install({\"debug\":true});\n})();"));
+        assert!(!script.contains(CHANGE_EVENT));
+
+        let script = config.update_script();
+        assert!(script.contains("var config = install({\"debug\":true});"));
+        assert!(script.contains("new CustomEvent(\"webview2configchange\""));
+
+        assert!(PageConfig::new()
+            .document_script()
+            .contains("var origins = null;"));
+    }
+
+    /// Quotes, line terminators and `</script>` in keys and values come
+    /// back as the same JSON and leave no raw line terminators in the
+    /// script.
+    #[test]
+    fn test_escaping() {
+        let text = "a\"b'\\c\n\r\u{0}\u{1f}\u{2028}\u{2029}</script>\u{7f}\u{ffff}😀";
+        let config = PageConfig::new()
+            .with_global(text)
+            .with_value(text, json!({ text: [text, -1, true] }));
+        let values = Json::Object(config.values().clone());
+        let literal = to_js(&values);
+        assert_eq!(serde_json::from_str::<Json>(&literal).unwrap(), values);
+        let script = config.document_script();
+        for c in ['\u{0}', '\r', '\u{2028}', '\u{2029}'].iter() {
+            assert!(!script.contains(*c), "{:?} in {:?}", c, script);
+        }
+        assert!(script.contains(&format!("    install({});", literal)));
+    }
+}
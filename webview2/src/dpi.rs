@@ -0,0 +1,364 @@
+//! DPI and text scale tracking.
+//!
+//! The page is rendered at the controller's rasterization scale, which
+//! WebView2 derives from the monitor DPI and the user's text scale factor. To
+//! fill a given number of device pixels the zoom factor has to divide that
+//! scale out again. `DpiState` holds the inputs and the rules for combining
+//! them. On Windows, `DpiManager` keeps a `DpiState` up to date for one
+//! controller and re-applies its layout whenever the effective scale changes.
+
+use crate::geometry::Scale;
+
+/// Changes below this are rounding noise.
+const EPSILON: f64 = 1e-6;
+
+/// One input to the effective scale.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DpiChange {
+    /// Effective DPI of the monitor the parent window is on.
+    MonitorDpi(u32),
+    /// The "Make text bigger" accessibility setting, in percent.
+    TextScale(u32),
+    /// A controller's `RasterizationScale`.
+    RasterizationScale(f64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DpiState {
+    monitor_dpi: u32,
+    text_scale: u32,
+    rasterization_scale: Option<f64>,
+}
+
+impl Default for DpiState {
+    fn default() -> Self {
+        Self {
+            monitor_dpi: 96,
+            text_scale: 100,
+            rasterization_scale: None,
+        }
+    }
+}
+
+impl DpiState {
+    pub fn new(monitor_dpi: u32, text_scale: u32) -> Self {
+        let mut state = Self::default();
+        state.apply(&[
+            DpiChange::MonitorDpi(monitor_dpi),
+            DpiChange::TextScale(text_scale),
+        ]);
+        state
+    }
+
+    pub fn monitor_dpi(&self) -> u32 {
+        self.monitor_dpi
+    }
+
+    pub fn text_scale(&self) -> u32 {
+        self.text_scale
+    }
+
+    pub fn rasterization_scale(&self) -> Option<f64> {
+        self.rasterization_scale
+    }
+
+    /// The scale the page is rendered at.
+    ///
+    /// A rasterization scale reported by the controller already combines the
+    /// monitor DPI and the text scale, so it wins. Without one (older
+    /// runtimes), the monitor scale is multiplied by the text scale.
+    pub fn effective_scale(&self) -> Scale {
+        match self.rasterization_scale {
+            Some(scale) => Scale(scale),
+            None => Scale(self.monitor_dpi as f64 / 96.0 * self.text_scale as f64 / 100.0),
+        }
+    }
+
+    /// Apply `changes` and return the new effective scale if it changed.
+    ///
+    /// Invalid values are ignored: a DPI of 0, a non positive or non finite
+    /// rasterization scale. The text scale is clamped to the 100% to 225%
+    /// Windows allows.
+    pub fn apply(&mut self, changes: &[DpiChange]) -> Option<Scale> {
+        let before = self.effective_scale();
+        for change in changes {
+            match *change {
+                DpiChange::MonitorDpi(dpi) if dpi > 0 => self.monitor_dpi = dpi,
+                DpiChange::TextScale(percent) => self.text_scale = percent.clamp(100, 225),
+                DpiChange::RasterizationScale(scale) if scale.is_finite() && scale > 0.0 => {
+                    self.rasterization_scale = Some(scale)
+                }
+                _ => {}
+            }
+        }
+        let after = self.effective_scale();
+        if (after.0 - before.0).abs() > EPSILON {
+            Some(after)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::manager::DpiManager;
+
+#[cfg(windows)]
+mod manager {
+    use super::*;
+    use crate::geometry::Rect;
+    use crate::util::layout::{Layout, Placement};
+    use crate::{Controller, Controller3, EventRegistrationToken, Result};
+    use std::cell::{Cell, RefCell};
+    use std::ptr;
+    use std::rc::Rc;
+    use widestring::WideCString;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::shared::windef::HWND;
+    use winapi::shared::winerror::{ERROR_SUCCESS, S_OK};
+    use winapi::um::shellscalingapi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
+    use winapi::um::winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+    use winapi::um::winuser::{MonitorFromWindow, MONITOR_DEFAULTTONEAREST};
+
+    type Apply = Box<dyn Fn(&Placement) -> Result<()>>;
+
+    struct Inner {
+        controller: Controller,
+        controller3: Option<Controller3>,
+        token: Cell<Option<EventRegistrationToken>>,
+        state: Cell<DpiState>,
+        layout: Cell<Option<(Layout, Rect)>>,
+        placement: Cell<Option<Placement>>,
+        apply: RefCell<Option<Apply>>,
+        handlers: RefCell<Vec<Rc<dyn Fn(Scale)>>>,
+    }
+
+    impl Drop for Inner {
+        fn drop(&mut self) {
+            if let (Some(controller3), Some(token)) = (&self.controller3, self.token.get()) {
+                let _ = controller3.remove_rasterization_scale_changed(token);
+            }
+        }
+    }
+
+    /// Tracks the effective scale of one controller and keeps its bounds and
+    /// zoom factor in sync with it.
+    ///
+    /// Rasterization scale changes are picked up automatically. Monitor DPI
+    /// and text scale changes are picked up by `refresh`, which should be
+    /// called on `WM_DPICHANGED`, `WM_SETTINGCHANGE` and when the parent
+    /// window moves.
+    #[derive(Clone)]
+    pub struct DpiManager {
+        inner: Rc<Inner>,
+    }
+
+    impl DpiManager {
+        pub fn new(controller: Controller) -> Result<Self> {
+            let controller3 = controller.get_controller3().ok();
+            let mut state = DpiState::new(
+                monitor_dpi(controller.get_parent_window()?),
+                text_scale_factor(),
+            );
+            if let Some(ref controller3) = controller3 {
+                let scale = controller3.get_rasterization_scale()?;
+                state.apply(&[DpiChange::RasterizationScale(scale)]);
+            }
+
+            let inner = Rc::new(Inner {
+                controller,
+                controller3,
+                token: Cell::new(None),
+                state: Cell::new(state),
+                layout: Cell::new(None),
+                placement: Cell::new(None),
+                apply: RefCell::new(None),
+                handlers: RefCell::new(Vec::new()),
+            });
+
+            if let Some(ref controller3) = inner.controller3 {
+                let weak = Rc::downgrade(&inner);
+                let token = controller3.add_rasterization_scale_changed(move |controller| {
+                    if let Some(inner) = weak.upgrade() {
+                        let scale = controller.get_controller3()?.get_rasterization_scale()?;
+                        DpiManager { inner }.update(&[DpiChange::RasterizationScale(scale)])?;
+                    }
+                    Ok(())
+                })?;
+                inner.token.set(Some(token));
+            }
+
+            Ok(DpiManager { inner })
+        }
+
+        pub fn state(&self) -> DpiState {
+            self.inner.state.get()
+        }
+
+        pub fn effective_scale(&self) -> Scale {
+            self.state().effective_scale()
+        }
+
+        /// Re-read the monitor DPI and the text scale factor.
+        pub fn refresh(&self) -> Result<()> {
+            let hwnd = self.inner.controller.get_parent_window()?;
+            self.update(&[
+                DpiChange::MonitorDpi(monitor_dpi(hwnd)),
+                DpiChange::TextScale(text_scale_factor()),
+            ])
+        }
+
+        /// Apply `changes`. If the effective scale changes, the layout is
+        /// re-applied and the handlers are called.
+        pub fn update(&self, changes: &[DpiChange]) -> Result<()> {
+            let mut state = self.inner.state.get();
+            let changed = state.apply(changes);
+            self.inner.state.set(state);
+
+            if let Some(scale) = changed {
+                if let Some((layout, area)) = self.inner.layout.get() {
+                    self.place(layout, area)?;
+                }
+                let handlers = self.inner.handlers.borrow().clone();
+                for handler in handlers {
+                    handler(scale);
+                }
+            }
+            Ok(())
+        }
+
+        /// Place the webview in `area` with `layout` at the effective scale,
+        /// now and whenever the scale changes.
+        pub fn set_layout(&self, layout: Layout, area: Rect) -> Result<Option<Placement>> {
+            self.inner.layout.set(Some((layout, area)));
+            self.place(layout, area)
+        }
+
+        /// Stop re-applying the layout, e.g. because the host sets the
+        /// bounds itself.
+        pub fn clear_layout(&self) {
+            self.inner.layout.set(None);
+            self.inner.placement.set(None);
+        }
+
+        /// The placement last applied by the layout.
+        pub fn placement(&self) -> Option<Placement> {
+            self.inner.placement.get()
+        }
+
+        /// Replace how placements are applied. By default they are passed to
+        /// `Controller::set_bounds_and_zoom_factor`.
+        pub fn set_apply(&self, apply: impl Fn(&Placement) -> Result<()> + 'static) {
+            *self.inner.apply.borrow_mut() = Some(Box::new(apply));
+        }
+
+        pub fn add_effective_scale_changed(&self, handler: impl Fn(Scale) + 'static) {
+            self.inner.handlers.borrow_mut().push(Rc::new(handler));
+        }
+
+        fn place(&self, layout: Layout, area: Rect) -> Result<Option<Placement>> {
+            let placement = match layout.place(area, self.effective_scale()) {
+                Some(placement) => placement,
+                None => return Ok(None),
+            };
+            match *self.inner.apply.borrow() {
+                Some(ref apply) => apply(&placement)?,
+                None => self
+                    .inner
+                    .controller
+                    .set_bounds_and_zoom_factor(placement.bounds.into(), placement.zoom)?,
+            }
+            self.inner.placement.set(Some(placement));
+            Ok(Some(placement))
+        }
+    }
+
+    fn monitor_dpi(hwnd: HWND) -> u32 {
+        let (mut dpi_x, mut dpi_y) = (0, 0);
+        let hr = unsafe {
+            let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+            GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y)
+        };
+        if hr == S_OK {
+            dpi_x
+        } else {
+            96
+        }
+    }
+
+    fn text_scale_factor() -> u32 {
+        let key = WideCString::from_str("Software\\Microsoft\\Accessibility").unwrap();
+        let value = WideCString::from_str("TextScaleFactor").unwrap();
+        let mut data: DWORD = 0;
+        let mut size = std::mem::size_of::<DWORD>() as DWORD;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_DWORD,
+                ptr::null_mut(),
+                &mut data as *mut DWORD as *mut _,
+                &mut size,
+            )
+        };
+        if status == ERROR_SUCCESS as i32 {
+            data
+        } else {
+            100
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_scale() {
+        assert_eq!(DpiState::default().effective_scale(), Scale(1.0));
+        assert_eq!(DpiState::new(144, 100).effective_scale(), Scale(1.5));
+        assert_eq!(DpiState::new(192, 150).effective_scale(), Scale(3.0));
+
+        // The rasterization scale already includes monitor and text scale.
+        let mut state = DpiState::new(192, 150);
+        assert_eq!(
+            state.apply(&[DpiChange::RasterizationScale(2.5)]),
+            Some(Scale(2.5))
+        );
+        assert_eq!(state.apply(&[DpiChange::MonitorDpi(96)]), None);
+        assert_eq!(state.effective_scale(), Scale(2.5));
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut state = DpiState::new(96, 100);
+        assert_eq!(state.apply(&[DpiChange::MonitorDpi(96)]), None);
+        assert_eq!(state.apply(&[DpiChange::TextScale(125)]), Some(Scale(1.25)));
+
+        // Several changes are reported once, by their combined result.
+        assert_eq!(
+            state.apply(&[DpiChange::MonitorDpi(192), DpiChange::TextScale(100)]),
+            Some(Scale(2.0))
+        );
+        assert_eq!(
+            state.apply(&[DpiChange::MonitorDpi(96), DpiChange::MonitorDpi(192)]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut state = DpiState::new(0, 50);
+        assert_eq!((state.monitor_dpi(), state.text_scale()), (96, 100));
+        assert_eq!(
+            state.apply(&[DpiChange::TextScale(1000)]),
+            Some(Scale(2.25))
+        );
+
+        for scale in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(state.apply(&[DpiChange::RasterizationScale(*scale)]), None);
+        }
+        assert_eq!(state.rasterization_scale(), None);
+    }
+}
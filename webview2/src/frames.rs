@@ -0,0 +1,1045 @@
+//! Iframes: which frames a page has, where they are, and talking to them.
+//!
+//! WebView2 hands out an `ICoreWebView2Frame` for each iframe of the main
+//! document when it is created, but nothing relates frames to each other or
+//! remembers what they show. `FrameTree` is that model. It is fed
+//! `FrameEvent`s, names frames by `FrameId`s in creation order with
+//! `FrameId::MAIN` for the main frame, and knows the document each frame
+//! shows, so that a message or a host object meant for one origin does not
+//! end up in another.
+//!
+//! A frame's `source` is its current document, which only changes when the
+//! new document commits. Until then, the navigation is `pending`, and
+//! `FrameTree::is_allowed` wants both on an `OriginAllowList`, as the frame
+//! can be either.
+//!
+//! ```
+//! use webview2::frames::{FrameEvent, FrameId, FrameTree, OriginAllowList};
+//!
+//! let mut tree = FrameTree::new();
+//! let payment = FrameId(1);
+//! let events = vec![
+//!     FrameEvent::Created { id: payment, parent: FrameId::MAIN, name: "payment".into() },
+//!     FrameEvent::NavigationStarting { id: payment, uri: "https://pay.example.com/card".into() },
+//!     FrameEvent::ContentLoading { id: payment },
+//! ];
+//! for event in &events {
+//!     tree.apply(event).unwrap();
+//! }
+//!
+//! let allowed = OriginAllowList::new(vec!["https://pay.example.com"]).unwrap();
+//! assert_eq!(tree.find_by_name("payment"), Some(payment));
+//! assert!(tree.is_allowed(payment, &allowed));
+//!
+//! tree.apply(&FrameEvent::NavigationStarting { id: payment, uri: "https://ads.example.net/".into() })
+//!     .unwrap();
+//! assert!(!tree.is_allowed(payment, &allowed));
+//! ```
+//!
+//! On Windows, `Frames` keeps a tree up to date from the events of a
+//! webview, and posts messages, runs scripts and adds host objects in a
+//! frame only for the origins it is given.
+
+use crate::util::uri::{normalize_origin, normalize_uri, origin_of};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A frame of a `FrameTree`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct FrameId(pub u32);
+
+impl FrameId {
+    /// The main frame, which every tree has. WebView2 never uses `0` for a
+    /// frame.
+    pub const MAIN: FrameId = FrameId(0);
+}
+
+impl fmt::Display for FrameId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FrameError {
+    /// No frame with this id, or no longer.
+    Unknown(FrameId),
+    /// A frame with this id was created before.
+    Duplicate(FrameId),
+    /// The main frame is not created or destroyed.
+    MainFrame,
+    /// An origin that is not `scheme://host[:port]`.
+    Origin(String),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Unknown(id) => write!(f, "unknown {}", id),
+            FrameError::Duplicate(id) => write!(f, "{} created twice", id),
+            FrameError::MainFrame => write!(f, "the main frame cannot be created or destroyed"),
+            FrameError::Origin(o) => write!(f, "invalid origin {:?}", o),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Something that happened to a frame, as WebView2 reports it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FrameEvent {
+    Created {
+        id: FrameId,
+        parent: FrameId,
+        name: String,
+    },
+    NameChanged {
+        id: FrameId,
+        name: String,
+    },
+    NavigationStarting {
+        id: FrameId,
+        uri: String,
+    },
+    /// The document of the navigation committed.
+    ContentLoading {
+        id: FrameId,
+    },
+    DomContentLoaded {
+        id: FrameId,
+    },
+    NavigationCompleted {
+        id: FrameId,
+        success: bool,
+    },
+    /// The frame is gone, and with it the frames inside it.
+    Destroyed {
+        id: FrameId,
+    },
+    /// The document of the frame posted a message.
+    WebMessageReceived {
+        id: FrameId,
+        source: String,
+        json: String,
+    },
+}
+
+impl FrameEvent {
+    pub fn id(&self) -> FrameId {
+        match self {
+            FrameEvent::Created { id, .. }
+            | FrameEvent::NameChanged { id, .. }
+            | FrameEvent::NavigationStarting { id, .. }
+            | FrameEvent::ContentLoading { id }
+            | FrameEvent::DomContentLoaded { id }
+            | FrameEvent::NavigationCompleted { id, .. }
+            | FrameEvent::Destroyed { id }
+            | FrameEvent::WebMessageReceived { id, .. } => *id,
+        }
+    }
+}
+
+/// Where a frame is in loading its document.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FrameState {
+    /// Created, nothing loaded yet.
+    New,
+    Navigating,
+    /// The document committed and is loading.
+    Loading,
+    /// The document was parsed, `DOMContentLoaded`.
+    Interactive,
+    Complete,
+    /// The navigation failed.
+    Failed,
+}
+
+/// A frame in a `FrameTree`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FrameNode {
+    id: FrameId,
+    parent: Option<FrameId>,
+    children: Vec<FrameId>,
+    name: String,
+    source: Option<String>,
+    pending: Option<String>,
+    state: FrameState,
+}
+
+impl FrameNode {
+    pub fn id(&self) -> FrameId {
+        self.id
+    }
+
+    /// `None` for the main frame only.
+    pub fn parent(&self) -> Option<FrameId> {
+        self.parent
+    }
+
+    /// In creation order.
+    pub fn children(&self) -> &[FrameId] {
+        &self.children
+    }
+
+    /// The `window.name` of the frame.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The URI of the current document, once one committed.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// The URI a navigation that has not committed yet is going to.
+    pub fn pending(&self) -> Option<&str> {
+        self.pending.as_deref()
+    }
+
+    pub fn state(&self) -> FrameState {
+        self.state
+    }
+}
+
+/// The frames of a webview and how they nest. See the module documentation.
+#[derive(Debug, Clone)]
+pub struct FrameTree {
+    nodes: BTreeMap<FrameId, FrameNode>,
+}
+
+impl Default for FrameTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameTree {
+    /// A tree with only the main frame.
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(FrameId::MAIN, FrameNode::new(FrameId::MAIN, None, ""));
+        FrameTree { nodes }
+    }
+
+    /// The number of frames, the main frame included.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Never, there is always the main frame.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn contains(&self, id: FrameId) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    pub fn get(&self, id: FrameId) -> Option<&FrameNode> {
+        self.nodes.get(&id)
+    }
+
+    fn node_mut(&mut self, id: FrameId) -> Result<&mut FrameNode, FrameError> {
+        self.nodes.get_mut(&id).ok_or(FrameError::Unknown(id))
+    }
+
+    /// Update the tree. Messages do not change it, but must come from a
+    /// frame in it.
+    pub fn apply(&mut self, event: &FrameEvent) -> Result<(), FrameError> {
+        match event {
+            FrameEvent::Created { id, parent, name } => {
+                self.insert(*id, *parent, name)?;
+            }
+            FrameEvent::NameChanged { id, name } => {
+                self.node_mut(*id)?.name = name.clone();
+            }
+            FrameEvent::NavigationStarting { id, uri } => {
+                let node = self.node_mut(*id)?;
+                node.pending = Some(uri.clone());
+                node.state = FrameState::Navigating;
+            }
+            FrameEvent::ContentLoading { id } => {
+                let node = self.node_mut(*id)?;
+                if let Some(uri) = node.pending.take() {
+                    node.source = Some(uri);
+                }
+                node.state = FrameState::Loading;
+            }
+            FrameEvent::DomContentLoaded { id } => {
+                self.node_mut(*id)?.state = FrameState::Interactive;
+            }
+            FrameEvent::NavigationCompleted { id, success } => {
+                let node = self.node_mut(*id)?;
+                node.pending = None;
+                node.state = if *success {
+                    FrameState::Complete
+                } else {
+                    FrameState::Failed
+                };
+            }
+            FrameEvent::Destroyed { id } => {
+                self.remove(*id)?;
+            }
+            FrameEvent::WebMessageReceived { id, .. } => {
+                self.node_mut(*id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add frame `id` as the last child of `parent`.
+    pub fn insert(&mut self, id: FrameId, parent: FrameId, name: &str) -> Result<(), FrameError> {
+        if id == FrameId::MAIN {
+            return Err(FrameError::MainFrame);
+        }
+        if self.contains(id) {
+            return Err(FrameError::Duplicate(id));
+        }
+        self.node_mut(parent)?.children.push(id);
+        self.nodes
+            .insert(id, FrameNode::new(id, Some(parent), name));
+        Ok(())
+    }
+
+    /// Remove frame `id` and the frames inside it. Returns their ids, `id`
+    /// first, like `descendants`.
+    pub fn remove(&mut self, id: FrameId) -> Result<Vec<FrameId>, FrameError> {
+        let parent = match self.get(id) {
+            Some(node) => node.parent.ok_or(FrameError::MainFrame)?,
+            None => return Err(FrameError::Unknown(id)),
+        };
+        let removed = self.descendants(id);
+        for id in &removed {
+            self.nodes.remove(id);
+        }
+        if let Some(parent) = self.nodes.get_mut(&parent) {
+            parent.children.retain(|child| *child != id);
+        }
+        Ok(removed)
+    }
+
+    pub fn parent(&self, id: FrameId) -> Option<FrameId> {
+        self.get(id)?.parent
+    }
+
+    pub fn children(&self, id: FrameId) -> &[FrameId] {
+        match self.get(id) {
+            Some(node) => &node.children,
+            None => &[],
+        }
+    }
+
+    /// The frames `id` is in, its parent first and the main frame last.
+    pub fn ancestors(&self, id: FrameId) -> Vec<FrameId> {
+        let mut ancestors = Vec::new();
+        let mut current = self.parent(id);
+        while let Some(id) = current {
+            ancestors.push(id);
+            current = self.parent(id);
+        }
+        ancestors
+    }
+
+    /// How deep `id` is nested, `0` for the main frame.
+    pub fn depth(&self, id: FrameId) -> usize {
+        self.ancestors(id).len()
+    }
+
+    /// `id` and the frames inside it, parents before their children and
+    /// siblings in creation order. Empty if there is no such frame.
+    pub fn descendants(&self, id: FrameId) -> Vec<FrameId> {
+        let mut descendants = Vec::new();
+        if !self.contains(id) {
+            return descendants;
+        }
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            descendants.push(id);
+            stack.extend(self.children(id).iter().rev());
+        }
+        descendants
+    }
+
+    /// Every frame, in the order of `descendants`.
+    pub fn iter(&self) -> impl Iterator<Item = &FrameNode> + '_ {
+        self.descendants(FrameId::MAIN)
+            .into_iter()
+            .map(move |id| &self.nodes[&id])
+    }
+
+    /// The first frame, in the order of `iter`, named `name`.
+    pub fn find_by_name(&self, name: &str) -> Option<FrameId> {
+        self.iter()
+            .find(|node| node.name == name)
+            .map(|node| node.id)
+    }
+
+    /// Whether the document in frame `id`, and the one it is navigating
+    /// to, are on `allowed` origins. Not before a document committed.
+    pub fn is_allowed(&self, id: FrameId, allowed: &OriginAllowList) -> bool {
+        let node = match self.get(id) {
+            Some(node) => node,
+            None => return false,
+        };
+        let source = match &node.source {
+            Some(source) => allowed.allows(source),
+            None => false,
+        };
+        let pending = match &node.pending {
+            Some(pending) => allowed.allows(pending),
+            None => true,
+        };
+        source && pending
+    }
+}
+
+impl FrameNode {
+    fn new(id: FrameId, parent: Option<FrameId>, name: &str) -> Self {
+        FrameNode {
+            id,
+            parent,
+            children: Vec::new(),
+            name: name.to_owned(),
+            source: None,
+            pending: None,
+            state: FrameState::New,
+        }
+    }
+}
+
+/// The origins a frame may be on, in the form of WebView2's
+/// `AddHostObjectToScriptWithOrigins`: `scheme://host[:port]`, `"*"` for any
+/// origin and `"file://"` for any file.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct OriginAllowList {
+    any: bool,
+    files: bool,
+    origins: Vec<String>,
+}
+
+impl OriginAllowList {
+    /// The list of `origins`. They are normalized like WebView2 does, so
+    /// `HTTPS://Example.com:443` is `https://example.com`.
+    pub fn new<I, S>(origins: I) -> Result<Self, FrameError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut list = OriginAllowList::default();
+        for origin in origins {
+            let origin = origin.as_ref();
+            if origin == "*" {
+                list.any = true;
+            } else if origin.eq_ignore_ascii_case("file://") {
+                list.files = true;
+            } else {
+                let origin = normalize_origin(origin)
+                    .ok_or_else(|| FrameError::Origin(origin.to_owned()))?;
+                if !list.origins.contains(&origin) {
+                    list.origins.push(origin);
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    /// Any origin at all.
+    pub fn any() -> Self {
+        OriginAllowList {
+            any: true,
+            ..OriginAllowList::default()
+        }
+    }
+
+    /// Whether the list has no origins, i.e. allows nothing.
+    pub fn is_empty(&self) -> bool {
+        !self.any && !self.files && self.origins.is_empty()
+    }
+
+    /// Whether the document at `uri` is on an allowed origin. Documents
+    /// without an origin, like `about:blank`, are only allowed by `"*"`.
+    pub fn allows(&self, uri: &str) -> bool {
+        if self.any {
+            return true;
+        }
+        let uri = normalize_uri(uri);
+        if uri.starts_with("file://") {
+            return self.files;
+        }
+        match origin_of(&uri) {
+            Some(origin) => self.origins.iter().any(|o| o == origin),
+            None => false,
+        }
+    }
+
+    /// The origins as WebView2 takes them.
+    pub fn origins(&self) -> Vec<&str> {
+        let mut origins = Vec::new();
+        if self.any {
+            origins.push("*");
+        }
+        if self.files {
+            origins.push("file://");
+        }
+        origins.extend(self.origins.iter().map(String::as_str));
+        origins
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::messaging::{encode, DEFAULT_LIMIT};
+    use crate::{Error, EventRegistrationToken, Frame, Result, WebView};
+    use serde::Serialize;
+    use std::cell::{Cell, Ref, RefCell};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use winapi::shared::winerror::{E_ACCESSDENIED, E_INVALIDARG};
+    use windows::Win32::System::Variant::VARIANT;
+
+    struct Inner {
+        tree: RefCell<FrameTree>,
+        frames: RefCell<HashMap<FrameId, Frame>>,
+        next_id: Cell<u32>,
+        on_event: Box<dyn Fn(&Frames, &FrameEvent) -> Result<()>>,
+        tokens: Cell<Option<Tokens>>,
+    }
+
+    #[derive(Clone, Copy)]
+    struct Tokens {
+        frame_created: EventRegistrationToken,
+        navigation_starting: EventRegistrationToken,
+        content_loading: EventRegistrationToken,
+        dom_content_loaded: EventRegistrationToken,
+        navigation_completed: EventRegistrationToken,
+    }
+
+    /// The frames of a webview, kept up to date from its events. Clones
+    /// share the frames.
+    #[derive(Clone)]
+    pub struct Frames {
+        inner: Rc<Inner>,
+    }
+
+    impl fmt::Debug for Frames {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Frames")
+                .field("tree", &self.inner.tree)
+                .finish()
+        }
+    }
+
+    impl Frames {
+        /// Track the frames of `webview`. `on_event` gets every event after
+        /// the tree was updated with it, messages from frames included.
+        ///
+        /// WebView2 only reports the iframes of the main document, so they
+        /// are all children of `FrameId::MAIN`.
+        pub fn attach(
+            webview: &WebView,
+            on_event: impl Fn(&Frames, &FrameEvent) -> Result<()> + 'static,
+        ) -> Result<Frames> {
+            let frames = Frames {
+                inner: Rc::new(Inner {
+                    tree: RefCell::new(FrameTree::new()),
+                    frames: RefCell::new(HashMap::new()),
+                    next_id: Cell::new(1),
+                    on_event: Box::new(on_event),
+                    tokens: Cell::new(None),
+                }),
+            };
+            let main = FrameId::MAIN;
+
+            let f = frames.clone();
+            let frame_created = webview
+                .get_webview_4()?
+                .add_frame_created(move |_, args| f.created(args.get_frame()?))?;
+            let f = frames.clone();
+            let navigation_starting = webview.add_navigation_starting(move |_, args| {
+                let uri = args.get_uri()?;
+                f.dispatch(FrameEvent::NavigationStarting { id: main, uri })
+            })?;
+            let f = frames.clone();
+            let content_loading = webview.add_content_loading(move |_, _| {
+                f.dispatch(FrameEvent::ContentLoading { id: main })
+            })?;
+            let f = frames.clone();
+            let dom_content_loaded =
+                webview
+                    .get_webview_2()?
+                    .add_domcontent_loaded(move |_, _| {
+                        f.dispatch(FrameEvent::DomContentLoaded { id: main })
+                    })?;
+            let f = frames.clone();
+            let navigation_completed = webview.add_navigation_completed(move |_, args| {
+                let success = args.get_is_success()?;
+                f.dispatch(FrameEvent::NavigationCompleted { id: main, success })
+            })?;
+
+            frames.inner.tokens.set(Some(Tokens {
+                frame_created,
+                navigation_starting,
+                content_loading,
+                dom_content_loaded,
+                navigation_completed,
+            }));
+            Ok(frames)
+        }
+
+        /// Stop tracking. The event handlers of the webview hold on to the
+        /// frames until then.
+        pub fn detach(&self, webview: &WebView) -> Result<()> {
+            if let Some(tokens) = self.inner.tokens.take() {
+                webview
+                    .get_webview_4()?
+                    .remove_frame_created(tokens.frame_created)?;
+                webview.remove_navigation_starting(tokens.navigation_starting)?;
+                webview.remove_content_loading(tokens.content_loading)?;
+                webview
+                    .get_webview_2()?
+                    .remove_domcontent_loaded(tokens.dom_content_loaded)?;
+                webview.remove_navigation_completed(tokens.navigation_completed)?;
+            }
+            self.inner.frames.borrow_mut().clear();
+            Ok(())
+        }
+
+        pub fn tree(&self) -> Ref<'_, FrameTree> {
+            self.inner.tree.borrow()
+        }
+
+        /// The WebView2 frame of `id`. `None` for the main frame, which is
+        /// the webview itself.
+        pub fn frame(&self, id: FrameId) -> Option<Frame> {
+            self.inner.frames.borrow().get(&id).cloned()
+        }
+
+        /// The frame of `id`, if its document is on `allowed` origins.
+        /// Fails with `E_INVALIDARG` for unknown frames and
+        /// `E_ACCESSDENIED` for other origins.
+        fn allowed_frame(&self, id: FrameId, allowed: &OriginAllowList) -> Result<Frame> {
+            let frame = self.frame(id).ok_or_else(|| Error::new(E_INVALIDARG))?;
+            if self.inner.tree.borrow().is_allowed(id, allowed) {
+                Ok(frame)
+            } else {
+                Err(Error::new(E_ACCESSDENIED))
+            }
+        }
+
+        /// Post `json` to frame `id`, if it is on `allowed` origins.
+        pub fn post_web_message_as_json(
+            &self,
+            id: FrameId,
+            json: &str,
+            allowed: &OriginAllowList,
+        ) -> Result<()> {
+            self.allowed_frame(id, allowed)?
+                .post_web_message_as_json(json)
+        }
+
+        /// Post `message` as JSON to frame `id`, if it is on `allowed`
+        /// origins. Fails with `E_INVALIDARG` if it cannot be serialized or
+        /// is longer than `DEFAULT_LIMIT`.
+        pub fn post_message<T: Serialize + ?Sized>(
+            &self,
+            id: FrameId,
+            message: &T,
+            allowed: &OriginAllowList,
+        ) -> Result<()> {
+            let json = encode(message, DEFAULT_LIMIT).map_err(|_| Error::new(E_INVALIDARG))?;
+            self.post_web_message_as_json(id, &json, allowed)
+        }
+
+        /// Run `script` in frame `id`, if it is on `allowed` origins.
+        pub fn execute_script(
+            &self,
+            id: FrameId,
+            script: &str,
+            allowed: &OriginAllowList,
+            callback: impl FnOnce(String) -> Result<()> + 'static,
+        ) -> Result<()> {
+            self.allowed_frame(id, allowed)?
+                .execute_script(script, callback)
+        }
+
+        /// Add `object` as `chrome.webview.hostObjects.{name}` in frame
+        /// `id`. WebView2 checks the origin when the document reaches for
+        /// it, so it is only there for documents on `allowed` origins, now
+        /// and after later navigations of the frame.
+        pub fn add_host_object_to_script(
+            &self,
+            id: FrameId,
+            name: &str,
+            object: *mut VARIANT,
+            allowed: &OriginAllowList,
+        ) -> Result<()> {
+            let frame = self.frame(id).ok_or_else(|| Error::new(E_INVALIDARG))?;
+            frame.add_host_object_to_script(name, object, &allowed.origins())
+        }
+
+        fn dispatch(&self, event: FrameEvent) -> Result<()> {
+            // Events of frames created before `attach` are not ours.
+            if self.inner.tree.borrow_mut().apply(&event).is_err() {
+                return Ok(());
+            }
+            if let FrameEvent::Destroyed { id } = event {
+                self.inner.frames.borrow_mut().remove(&id);
+            }
+            (self.inner.on_event)(self, &event)
+        }
+
+        fn created(&self, frame: Frame) -> Result<()> {
+            let id = FrameId(self.inner.next_id.get());
+            self.inner.next_id.set(id.0 + 1);
+            let name = frame.get_name()?;
+            self.inner.frames.borrow_mut().insert(id, frame.clone());
+
+            // The handlers go with the frame, and must not keep the frames
+            // alive after `detach`.
+            let weak = Rc::downgrade(&self.inner);
+            let on: Rc<dyn Fn(FrameEvent) -> Result<()>> =
+                Rc::new(move |event| match weak.upgrade() {
+                    Some(inner) if inner.tokens.get().is_some() => Frames { inner }.dispatch(event),
+                    _ => Ok(()),
+                });
+
+            let h = on.clone();
+            frame.add_name_changed(move |frame| {
+                h(FrameEvent::NameChanged {
+                    id,
+                    name: frame.get_name()?,
+                })
+            })?;
+            let h = on.clone();
+            frame.add_destroyed(move |_| h(FrameEvent::Destroyed { id }))?;
+            // Frames of older runtimes only have a name.
+            if let Ok(frame) = frame.get_frame_2() {
+                let h = on.clone();
+                frame.add_navigation_starting(move |_, args| {
+                    h(FrameEvent::NavigationStarting {
+                        id,
+                        uri: args.get_uri()?,
+                    })
+                })?;
+                let h = on.clone();
+                frame.add_content_loading(move |_, _| h(FrameEvent::ContentLoading { id }))?;
+                let h = on.clone();
+                frame.add_domcontent_loaded(move |_, _| h(FrameEvent::DomContentLoaded { id }))?;
+                let h = on.clone();
+                frame.add_navigation_completed(move |_, args| {
+                    h(FrameEvent::NavigationCompleted {
+                        id,
+                        success: args.get_is_success()?,
+                    })
+                })?;
+                let h = on.clone();
+                frame.add_web_message_received(move |_, args| {
+                    h(FrameEvent::WebMessageReceived {
+                        id,
+                        source: args.get_source()?,
+                        json: args.get_web_message_as_json()?,
+                    })
+                })?;
+            }
+
+            self.dispatch(FrameEvent::Created {
+                id,
+                parent: FrameId::MAIN,
+                name,
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::Frames;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn created(id: u32, parent: u32, name: &str) -> FrameEvent {
+        FrameEvent::Created {
+            id: FrameId(id),
+            parent: FrameId(parent),
+            name: name.to_owned(),
+        }
+    }
+
+    fn ids(ids: &[u32]) -> Vec<FrameId> {
+        ids.iter().map(|id| FrameId(*id)).collect()
+    }
+
+    /// 0 ─┬─ 1 ─┬─ 3
+    ///    │     └─ 4 ── 5
+    ///    └─ 2
+    fn sample() -> FrameTree {
+        let mut tree = FrameTree::new();
+        for event in &[
+            created(1, 0, "a"),
+            created(2, 0, "b"),
+            created(3, 1, "c"),
+            created(4, 1, "d"),
+            created(5, 4, "b"),
+        ] {
+            tree.apply(event).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn test_tree() {
+        let tree = FrameTree::new();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.parent(FrameId::MAIN), None);
+        assert!(tree.children(FrameId::MAIN).is_empty());
+
+        let tree = sample();
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.children(FrameId::MAIN), &ids(&[1, 2])[..]);
+        assert_eq!(tree.children(FrameId(1)), &ids(&[3, 4])[..]);
+        assert_eq!(tree.parent(FrameId(5)), Some(FrameId(4)));
+        assert_eq!(tree.ancestors(FrameId(5)), ids(&[4, 1, 0]));
+        assert_eq!(tree.depth(FrameId(5)), 3);
+        assert_eq!(tree.depth(FrameId::MAIN), 0);
+        assert_eq!(tree.descendants(FrameId::MAIN), ids(&[0, 1, 3, 4, 5, 2]));
+        assert_eq!(tree.descendants(FrameId(4)), ids(&[4, 5]));
+        assert!(tree.descendants(FrameId(9)).is_empty());
+        assert!(tree.ancestors(FrameId(9)).is_empty());
+        assert_eq!(
+            tree.iter().map(|node| node.id()).collect::<Vec<_>>(),
+            tree.descendants(FrameId::MAIN)
+        );
+        // The first in tree order, not the first created.
+        assert_eq!(tree.find_by_name("b"), Some(FrameId(5)));
+        assert_eq!(tree.find_by_name("e"), None);
+    }
+
+    #[test]
+    fn test_tree_errors() {
+        let mut tree = sample();
+        assert_eq!(tree.apply(&created(0, 1, "")), Err(FrameError::MainFrame));
+        assert_eq!(
+            tree.apply(&created(3, 2, "")),
+            Err(FrameError::Duplicate(FrameId(3)))
+        );
+        assert_eq!(
+            tree.apply(&created(6, 9, "")),
+            Err(FrameError::Unknown(FrameId(9)))
+        );
+        assert_eq!(
+            tree.apply(&FrameEvent::Destroyed { id: FrameId::MAIN }),
+            Err(FrameError::MainFrame)
+        );
+        assert_eq!(
+            tree.apply(&FrameEvent::ContentLoading { id: FrameId(9) }),
+            Err(FrameError::Unknown(FrameId(9)))
+        );
+        assert_eq!(
+            tree.apply(&FrameEvent::WebMessageReceived {
+                id: FrameId(9),
+                source: String::new(),
+                json: String::new(),
+            }),
+            Err(FrameError::Unknown(FrameId(9)))
+        );
+        assert_eq!(tree.len(), 6);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = sample();
+        assert_eq!(tree.remove(FrameId(1)), Ok(ids(&[1, 3, 4, 5])));
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.children(FrameId::MAIN), &ids(&[2])[..]);
+        assert!(!tree.contains(FrameId(5)));
+        assert_eq!(
+            tree.remove(FrameId(1)),
+            Err(FrameError::Unknown(FrameId(1)))
+        );
+
+        // Destroyed frames are gone for good, even if the id comes back.
+        tree.apply(&created(1, 2, "again")).unwrap();
+        assert_eq!(tree.ancestors(FrameId(1)), ids(&[2, 0]));
+        assert!(tree.children(FrameId(1)).is_empty());
+        tree.apply(&FrameEvent::Destroyed { id: FrameId(2) })
+            .unwrap();
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut tree = sample();
+        let id = FrameId(3);
+        let state = |tree: &FrameTree| tree.get(id).unwrap().state();
+        assert_eq!(state(&tree), FrameState::New);
+
+        tree.apply(&FrameEvent::NameChanged {
+            id,
+            name: "checkout".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(tree.find_by_name("checkout"), Some(id));
+        assert_eq!(tree.find_by_name("c"), None);
+
+        tree.apply(&FrameEvent::NavigationStarting {
+            id,
+            uri: "https://pay.example.com/".to_owned(),
+        })
+        .unwrap();
+        let node = tree.get(id).unwrap();
+        assert_eq!(node.source(), None);
+        assert_eq!(node.pending(), Some("https://pay.example.com/"));
+        assert_eq!(node.state(), FrameState::Navigating);
+
+        tree.apply(&FrameEvent::ContentLoading { id }).unwrap();
+        let node = tree.get(id).unwrap();
+        assert_eq!(node.source(), Some("https://pay.example.com/"));
+        assert_eq!(node.pending(), None);
+        assert_eq!(node.state(), FrameState::Loading);
+        tree.apply(&FrameEvent::DomContentLoaded { id }).unwrap();
+        assert_eq!(state(&tree), FrameState::Interactive);
+        tree.apply(&FrameEvent::NavigationCompleted { id, success: true })
+            .unwrap();
+        assert_eq!(state(&tree), FrameState::Complete);
+
+        // A navigation that fails before committing leaves the document.
+        tree.apply(&FrameEvent::NavigationStarting {
+            id,
+            uri: "https://down.example.com/".to_owned(),
+        })
+        .unwrap();
+        tree.apply(&FrameEvent::NavigationCompleted { id, success: false })
+            .unwrap();
+        let node = tree.get(id).unwrap();
+        assert_eq!(node.source(), Some("https://pay.example.com/"));
+        assert_eq!(node.pending(), None);
+        assert_eq!(node.state(), FrameState::Failed);
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let mut tree = sample();
+        let id = FrameId(2);
+        let allowed = OriginAllowList::new(vec!["https://pay.example.com"]).unwrap();
+        let navigate = |tree: &mut FrameTree, uri: &str| {
+            tree.apply(&FrameEvent::NavigationStarting {
+                id,
+                uri: uri.to_owned(),
+            })
+            .unwrap();
+        };
+
+        // Nothing committed yet.
+        assert!(!tree.is_allowed(id, &allowed));
+        navigate(&mut tree, "https://pay.example.com/card");
+        assert!(!tree.is_allowed(id, &allowed));
+        tree.apply(&FrameEvent::ContentLoading { id }).unwrap();
+        assert!(tree.is_allowed(id, &allowed));
+
+        // On the way somewhere else, either document can get a message.
+        navigate(&mut tree, "https://evil.example.com/");
+        assert!(!tree.is_allowed(id, &allowed));
+        tree.apply(&FrameEvent::NavigationCompleted { id, success: false })
+            .unwrap();
+        assert!(tree.is_allowed(id, &allowed));
+
+        navigate(&mut tree, "https://PAY.example.com:443/done#top");
+        assert!(tree.is_allowed(id, &allowed));
+        assert!(!tree.is_allowed(FrameId(9), &OriginAllowList::any()));
+        assert!(!tree.is_allowed(FrameId(1), &OriginAllowList::any()));
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let list = OriginAllowList::new(vec![
+            "HTTPS://Pay.Example.com:443",
+            "http://localhost:8080/",
+            "https://pay.example.com",
+        ])
+        .unwrap();
+        assert_eq!(
+            list.origins(),
+            vec!["https://pay.example.com", "http://localhost:8080"]
+        );
+        for allowed in &[
+            "https://pay.example.com",
+            "https://pay.example.com/card?x=1",
+            "HTTPS://PAY.EXAMPLE.COM:443/",
+            "http://localhost:8080/index.html",
+        ] {
+            assert!(list.allows(allowed), "{}", allowed);
+        }
+        for denied in &[
+            "http://pay.example.com/",
+            "https://pay.example.com:8443/",
+            "https://pay.example.com.evil.com/",
+            "https://evil.com/https://pay.example.com",
+            "http://localhost/",
+            "about:blank",
+            "file:///C:/app/index.html",
+            "",
+        ] {
+            assert!(!list.allows(denied), "{}", denied);
+        }
+
+        let files = OriginAllowList::new(vec!["file://"]).unwrap();
+        assert!(files.allows("file:///C:/app/index.html"));
+        assert!(!files.allows("https://pay.example.com/"));
+        assert_eq!(files.origins(), vec!["file://"]);
+
+        let any = OriginAllowList::new(vec!["*", "https://a.com"]).unwrap();
+        assert_eq!(any.origins(), vec!["*", "https://a.com"]);
+        assert!(any.allows("about:blank"));
+        assert_eq!(OriginAllowList::any().origins(), vec!["*"]);
+
+        let none = OriginAllowList::new(Vec::<String>::new()).unwrap();
+        assert!(none.is_empty());
+        assert!(!none.allows("https://pay.example.com/"));
+        assert!(!list.is_empty());
+
+        for invalid in &["pay.example.com", "https://", "https://a.com/path"] {
+            assert_eq!(
+                OriginAllowList::new(vec![*invalid]),
+                Err(FrameError::Origin(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    /// Parents and children stay in agreement as frames come and go, and
+    /// nothing is left behind a destroyed frame.
+    #[test]
+    fn test_consistency() {
+        let check = |tree: &FrameTree| {
+            for node in tree.iter() {
+                for child in node.children() {
+                    assert_eq!(tree.parent(*child), Some(node.id()));
+                }
+                if let Some(parent) = node.parent() {
+                    assert!(tree.children(parent).contains(&node.id()));
+                    assert_eq!(tree.depth(node.id()), tree.depth(parent) + 1);
+                }
+            }
+            assert_eq!(tree.iter().count(), tree.len());
+        };
+        let mut tree = sample();
+        for event in &[
+            created(6, 5, ""),
+            created(7, 3, ""),
+            FrameEvent::Destroyed { id: FrameId(4) },
+            created(8, 7, ""),
+            created(9, 2, ""),
+            FrameEvent::Destroyed { id: FrameId(1) },
+        ] {
+            tree.apply(event).unwrap();
+            check(&tree);
+        }
+        assert_eq!(tree.descendants(FrameId::MAIN), ids(&[0, 2, 9]));
+        for id in &[1, 3, 4, 5, 6, 7, 8] {
+            assert!(!tree.contains(FrameId(*id)));
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Geometry types for layout code that should not depend on `winapi`.
+//!
+//! On Windows they convert to and from `RECT`, `POINT` and `SIZE` with
+//! `From`/`Into`.
+
+/// A point in pixels.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+/// A size in pixels.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Size {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+}
+
+/// A rectangle in pixels, right and bottom exclusive. Same layout as `RECT`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    pub fn from_origin_size(origin: Point, size: Size) -> Self {
+        Self::new(
+            origin.x,
+            origin.y,
+            origin.x + size.width,
+            origin.y + size.height,
+        )
+    }
+
+    pub fn origin(&self) -> Point {
+        Point::new(self.left, self.top)
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+
+    pub fn width(&self) -> i32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> i32 {
+        self.bottom - self.top
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size().is_empty()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.left && point.x < self.right && point.y >= self.top && point.y < self.bottom
+    }
+
+    /// The overlapping part of both rectangles. Empty if they do not overlap.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        Rect {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        }
+    }
+
+    pub fn inset(&self, insets: &Insets) -> Rect {
+        Rect {
+            left: self.left + insets.left,
+            top: self.top + insets.top,
+            right: self.right - insets.right,
+            bottom: self.bottom - insets.bottom,
+        }
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::new(
+            self.left + dx,
+            self.top + dy,
+            self.right + dx,
+            self.bottom + dy,
+        )
+    }
+}
+
+/// Space to keep clear on each side of a rectangle, e.g. for a notch or
+/// overlapping UI.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Insets {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Insets {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    pub fn uniform(inset: i32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
+
+/// A DPI scale factor, 1.0 at 96 DPI.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Scale(pub f64);
+
+impl Scale {
+    pub const IDENTITY: Scale = Scale(1.0);
+
+    pub fn from_dpi(dpi: u32) -> Self {
+        Scale(dpi as f64 / 96.0)
+    }
+
+    pub fn dpi(self) -> u32 {
+        (self.0 * 96.0).round() as u32
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale::IDENTITY
+    }
+}
+
+#[cfg(windows)]
+mod win32 {
+    use super::*;
+    use winapi::shared::windef::{POINT, RECT, SIZE};
+
+    impl From<RECT> for Rect {
+        fn from(r: RECT) -> Self {
+            Rect::new(r.left, r.top, r.right, r.bottom)
+        }
+    }
+
+    impl From<Rect> for RECT {
+        fn from(r: Rect) -> Self {
+            RECT {
+                left: r.left,
+                top: r.top,
+                right: r.right,
+                bottom: r.bottom,
+            }
+        }
+    }
+
+    impl From<POINT> for Point {
+        fn from(p: POINT) -> Self {
+            Point::new(p.x, p.y)
+        }
+    }
+
+    impl From<Point> for POINT {
+        fn from(p: Point) -> Self {
+            POINT { x: p.x, y: p.y }
+        }
+    }
+
+    impl From<SIZE> for Size {
+        fn from(s: SIZE) -> Self {
+            Size::new(s.cx, s.cy)
+        }
+    }
+
+    impl From<Size> for SIZE {
+        fn from(s: Size) -> Self {
+            SIZE {
+                cx: s.width,
+                cy: s.height,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rect() {
+        let r = Rect::from_origin_size(Point::new(10, 20), Size::new(100, 50));
+        assert_eq!(r, Rect::new(10, 20, 110, 70));
+        assert_eq!(
+            (r.origin(), r.size()),
+            (Point::new(10, 20), Size::new(100, 50))
+        );
+
+        assert!(r.contains(Point::new(10, 20)));
+        assert!(r.contains(Point::new(109, 69)));
+        assert!(!r.contains(Point::new(110, 20)));
+        assert!(!r.contains(Point::new(10, 70)));
+
+        assert_eq!(
+            r.intersect(&Rect::new(50, 0, 200, 40)),
+            Rect::new(50, 20, 110, 40)
+        );
+        assert!(r.intersect(&Rect::new(200, 0, 300, 10)).is_empty());
+        assert_eq!(
+            r.inset(&Insets::new(1, 2, 3, 4)),
+            Rect::new(11, 22, 107, 66)
+        );
+        assert!(r.inset(&Insets::uniform(30)).is_empty());
+        assert_eq!(r.offset(-10, 5), Rect::new(0, 25, 100, 75));
+    }
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(Scale::from_dpi(144), Scale(1.5));
+        assert_eq!(Scale(1.25).dpi(), 120);
+        assert_eq!(Scale::default().dpi(), 96);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_win32_conversions() {
+        use winapi::shared::windef::{POINT, RECT};
+
+        let r = Rect::new(1, 2, 3, 4);
+        let raw: RECT = r.into();
+        assert_eq!((raw.left, raw.top, raw.right, raw.bottom), (1, 2, 3, 4));
+        assert_eq!(Rect::from(raw), r);
+
+        let p: POINT = Point::new(5, -6).into();
+        assert_eq!(Point::from(p), Point::new(5, -6));
+    }
+}
@@ -0,0 +1,765 @@
+//! Waiting for a host object to become usable from the page.
+//!
+//! `add_host_object_to_script` returns before the object is reachable from
+//! script, and may not take at all if the page is being replaced. `Binding`
+//! adds the object, then has the page check for it with `watch_script`,
+//! which runs in the current document and in every new one until binding is
+//! over. The page posts a web message once `chrome.webview.hostObjects.{name}`
+//! resolves, or if it fails to. After a failure the object is added again
+//! with a growing delay, up to `BindOptions::max_attempts` times and within
+//! `BindOptions::timeout`.
+//!
+//! `Binding` only decides what to do next; something else performs its
+//! `Action`s and reports back. On Windows `bind` does that for a `WebView`,
+//! and once the object is ready replaces the watch script with
+//! `quiet_script`, which posts nothing. Pages can wait for an object
+//! themselves with `chrome.webview.whenHostObject(name)`, which resolves once
+//! it is reachable.
+
+use serde_json::Value as Json;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The `type` of the web messages the page posts about host objects.
+pub const MESSAGE_TYPE: &str = "webview2.hostObject";
+
+/// Page side of the binding. `watch_script` brings it along.
+pub const SCRIPT: &str = r#"(function () {
+    var webview = window.chrome && window.chrome.webview;
+    if (!webview || webview.watchHostObject) {
+        return;
+    }
+    var ready = {};
+    var waiting = {};
+    webview.watchHostObject = function (name, quiet) {
+        function post(found) {
+            if (!quiet) {
+                webview.postMessage({ type: 'webview2.hostObject', name: name, ready: found });
+            }
+        }
+        Promise.resolve(webview.hostObjects[name]).then(function () {
+            ready[name] = true;
+            (waiting[name] || []).forEach(function (resolve) { resolve(); });
+            delete waiting[name];
+            post(true);
+        }, function () {
+            post(false);
+        });
+    };
+    webview.whenHostObject = function (name) {
+        if (ready[name]) {
+            return Promise.resolve();
+        }
+        return new Promise(function (resolve) {
+            (waiting[name] = waiting[name] || []).push(resolve);
+        });
+    };
+})();"#;
+
+/// The script that makes the page report whether `name` is reachable.
+pub fn watch_script(name: &str) -> String {
+    format!(
+        "{}\nwindow.chrome.webview.watchHostObject({});",
+        SCRIPT,
+        Json::from(name)
+    )
+}
+
+/// The script that lets the page wait for `name` with `whenHostObject`
+/// without reporting to the host, for documents created after binding.
+pub fn quiet_script(name: &str) -> String {
+    format!(
+        "{}\nwindow.chrome.webview.watchHostObject({}, true);",
+        SCRIPT,
+        Json::from(name)
+    )
+}
+
+/// What the page said about a host object.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Probe {
+    Ready,
+    Missing,
+}
+
+impl Probe {
+    /// The name of the object and what the page said about it, if `json` is
+    /// a web message from `SCRIPT`.
+    pub fn parse(json: &str) -> Option<(String, Probe)> {
+        let message = match serde_json::from_str::<Json>(json) {
+            Ok(Json::Object(message)) => message,
+            _ => return None,
+        };
+        if message.get("type")?.as_str()? != MESSAGE_TYPE {
+            return None;
+        }
+        let name = message.get("name")?.as_str()?.to_owned();
+        let probe = if message.get("ready")?.as_bool()? {
+            Probe::Ready
+        } else {
+            Probe::Missing
+        };
+        Some((name, probe))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindOptions {
+    /// Delay before the second attempt.
+    pub first_delay: Duration,
+    /// Each further delay is this many times the previous one...
+    pub backoff: f64,
+    /// ...up to this.
+    pub max_delay: Duration,
+    /// Attempts before giving up, at least 1.
+    pub max_attempts: u32,
+    /// Time from `Binding::start` before giving up.
+    pub timeout: Duration,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        BindOptions {
+            first_delay: Duration::from_millis(10),
+            backoff: 2.0,
+            max_delay: Duration::from_secs(1),
+            max_attempts: 20,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl BindOptions {
+    /// The delay after failed attempt `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.backoff.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        let delay = self.first_delay.as_secs_f64() * factor;
+        if delay >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(delay)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BindError {
+    /// `BindOptions::timeout` passed.
+    Timeout { attempts: u32 },
+    /// `BindOptions::max_attempts` attempts failed, the last one because of
+    /// `last`.
+    Exhausted { attempts: u32, last: String },
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindError::Timeout { attempts } => {
+                write!(f, "not reachable from script after {} attempts", attempts)
+            }
+            BindError::Exhausted { attempts, last } => {
+                write!(f, "gave up after {} attempts: {}", attempts, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BindError {}
+
+/// What the owner of a `Binding` should do next, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Call `add_host_object_to_script` and report to `Binding::added`.
+    AddObject,
+    /// Execute `script` in the current document. Report a failure to execute
+    /// it to `Binding::watch_failed` with `attempt`, and the messages it
+    /// posts to `Binding::probed`.
+    Watch {
+        attempt: u32,
+        script: String,
+    },
+    /// Call `Binding::tick` after this long, replacing any earlier request.
+    Wake(Duration),
+    /// The object is reachable.
+    Ready,
+    Fail(BindError),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BindState {
+    Idle,
+    /// Waiting for the page to report.
+    Watching,
+    /// Waiting to retry.
+    Waiting,
+    Ready,
+    Failed,
+    Cancelled,
+}
+
+/// The state of binding one host object.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    name: String,
+    options: BindOptions,
+    state: BindState,
+    attempts: u32,
+    deadline: Option<Instant>,
+    retry_at: Option<Instant>,
+}
+
+impl Binding {
+    pub fn new(name: &str, options: BindOptions) -> Self {
+        Binding {
+            name: name.to_owned(),
+            options,
+            state: BindState::Idle,
+            attempts: 0,
+            deadline: None,
+            retry_at: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> BindState {
+        self.state
+    }
+
+    /// Attempts made so far.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.state {
+            BindState::Ready | BindState::Failed | BindState::Cancelled => true,
+            BindState::Idle | BindState::Watching | BindState::Waiting => false,
+        }
+    }
+
+    pub fn start(&mut self, now: Instant) -> Vec<Action> {
+        if self.state != BindState::Idle {
+            return Vec::new();
+        }
+        self.deadline = Some(now + self.options.timeout);
+        self.attempt()
+    }
+
+    /// Stop without reporting anything. Later results are ignored.
+    pub fn cancel(&mut self) {
+        if !self.is_finished() {
+            self.state = BindState::Cancelled;
+        }
+    }
+
+    fn attempt(&mut self) -> Vec<Action> {
+        self.attempts += 1;
+        self.state = BindState::Watching;
+        self.retry_at = None;
+        vec![Action::AddObject]
+    }
+
+    /// Report the result of `Action::AddObject`.
+    pub fn added(&mut self, result: Result<(), String>, now: Instant) -> Vec<Action> {
+        if self.state != BindState::Watching {
+            return Vec::new();
+        }
+        match result {
+            Ok(()) => vec![
+                Action::Watch {
+                    attempt: self.attempts,
+                    script: watch_script(&self.name),
+                },
+                Action::Wake(self.until_deadline(now)),
+            ],
+            Err(e) => self.failed(format!("add_host_object_to_script failed: {}", e), now),
+        }
+    }
+
+    /// Report that the script of `Action::Watch` could not be executed.
+    pub fn watch_failed(&mut self, attempt: u32, error: String, now: Instant) -> Vec<Action> {
+        // Results of earlier attempts may come in late.
+        if self.state != BindState::Watching || attempt != self.attempts {
+            return Vec::new();
+        }
+        self.failed(format!("execute_script failed: {}", error), now)
+    }
+
+    /// Report what the page said. Any document may say the object is ready,
+    /// e.g. one created while waiting to retry.
+    pub fn probed(&mut self, probe: Probe, now: Instant) -> Vec<Action> {
+        match (probe, self.state) {
+            (Probe::Ready, BindState::Watching) | (Probe::Ready, BindState::Waiting) => {
+                self.state = BindState::Ready;
+                vec![Action::Ready]
+            }
+            (Probe::Missing, BindState::Watching) => {
+                self.failed("not reachable from script".to_owned(), now)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Handle an `Action::Wake`. Early or extra calls are harmless.
+    pub fn tick(&mut self, now: Instant) -> Vec<Action> {
+        if self.is_finished() || self.state == BindState::Idle {
+            return Vec::new();
+        }
+        if matches!(self.deadline, Some(deadline) if now >= deadline) {
+            self.state = BindState::Failed;
+            return vec![Action::Fail(BindError::Timeout {
+                attempts: self.attempts,
+            })];
+        }
+        match self.retry_at {
+            Some(retry_at) if self.state == BindState::Waiting && now >= retry_at => self.attempt(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn failed(&mut self, reason: String, now: Instant) -> Vec<Action> {
+        if self.attempts >= self.options.max_attempts.max(1) {
+            self.state = BindState::Failed;
+            return vec![Action::Fail(BindError::Exhausted {
+                attempts: self.attempts,
+                last: reason,
+            })];
+        }
+        let delay = self.options.delay(self.attempts);
+        self.state = BindState::Waiting;
+        self.retry_at = Some(now + delay);
+        vec![Action::Wake(delay.min(self.until_deadline(now)))]
+    }
+
+    fn until_deadline(&self, now: Instant) -> Duration {
+        self.deadline.map_or(Duration::from_secs(0), |deadline| {
+            deadline - now.min(deadline)
+        })
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::*;
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::host_object::Variant;
+    use crate::util::script::Slot;
+    use crate::util::timer::Timer;
+    use crate::{EventRegistrationToken, WebView};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    type OnReady = Box<dyn FnOnce(WebView)>;
+    type OnError = Box<dyn FnOnce(WebView, BindError)>;
+
+    struct Driver {
+        webview: WebView,
+        object: RefCell<Variant>,
+        binding: RefCell<Binding>,
+        timer: Timer,
+        token: Cell<Option<EventRegistrationToken>>,
+        script: RefCell<Option<Slot>>,
+        on_ready: Cell<Option<OnReady>>,
+        on_error: Cell<Option<OnError>>,
+    }
+
+    impl Driver {
+        fn perform(self: &Rc<Self>, actions: Vec<Action>) {
+            let mut actions = std::collections::VecDeque::from(actions);
+            while let Some(action) = actions.pop_front() {
+                let next = match action {
+                    Action::AddObject => {
+                        let name = self.binding.borrow().name().to_owned();
+                        let result = self
+                            .webview
+                            .add_host_object_to_script(&name, &mut self.object.borrow_mut().0)
+                            .map_err(|e| e.to_string());
+                        self.binding.borrow_mut().added(result, Instant::now())
+                    }
+                    Action::Watch { attempt, script } => {
+                        // The page answers with a web message.
+                        match self.webview.execute_script(&script, |_| Ok(())) {
+                            Ok(()) => Vec::new(),
+                            Err(e) => self.binding.borrow_mut().watch_failed(
+                                attempt,
+                                e.to_string(),
+                                Instant::now(),
+                            ),
+                        }
+                    }
+                    Action::Wake(delay) => {
+                        self.set_timer(delay);
+                        Vec::new()
+                    }
+                    Action::Ready => {
+                        self.stop();
+                        // Later documents still get `whenHostObject`.
+                        let name = self.binding.borrow().name().to_owned();
+                        *self.script.borrow_mut() =
+                            Slot::add(&self.webview, &quiet_script(&name)).ok();
+                        if let Some(on_ready) = self.on_ready.take() {
+                            on_ready(self.webview.clone());
+                        }
+                        Vec::new()
+                    }
+                    Action::Fail(e) => {
+                        self.stop();
+                        if let Some(on_error) = self.on_error.take() {
+                            on_error(self.webview.clone(), e);
+                        }
+                        Vec::new()
+                    }
+                };
+                actions.extend(next);
+            }
+        }
+
+        fn set_timer(self: &Rc<Self>, delay: Duration) {
+            let driver = self.clone();
+            self.timer.set(delay, move || {
+                let next = driver.binding.borrow_mut().tick(Instant::now());
+                driver.perform(next);
+            });
+        }
+
+        /// Stop listening to the page, stop watching new documents and
+        /// cancel the pending timer.
+        fn stop(&self) {
+            self.timer.clear();
+            if let Some(token) = self.token.take() {
+                let _ = self.webview.remove_web_message_received(token);
+            }
+            if let Some(script) = self.script.borrow_mut().take() {
+                let _ = script.remove(&self.webview);
+            }
+        }
+    }
+
+    /// Cancels a `bind` in progress.
+    pub struct BindHandle(Rc<Driver>);
+
+    impl BindHandle {
+        pub fn state(&self) -> BindState {
+            self.0.binding.borrow().state()
+        }
+
+        /// Stop retrying. Neither callback is called afterwards.
+        pub fn cancel(&self) {
+            self.0.binding.borrow_mut().cancel();
+            self.0.stop();
+            self.0.on_ready.take();
+            self.0.on_error.take();
+        }
+    }
+
+    /// Add `object` as `name` and call `on_ready` once the page reports that
+    /// it can reach it, or `on_error` if it cannot within `options`. Runs on
+    /// the UI thread, the retries are timed with thread timers so it needs a
+    /// message loop.
+    pub fn bind(
+        webview: WebView,
+        name: &str,
+        object: Variant,
+        options: BindOptions,
+        on_ready: impl FnOnce(WebView) + 'static,
+        on_error: impl FnOnce(WebView, BindError) + 'static,
+    ) -> BindHandle {
+        let driver = Rc::new(Driver {
+            webview: webview.clone(),
+            object: RefCell::new(object),
+            binding: RefCell::new(Binding::new(name, options)),
+            timer: Timer::new(),
+            token: Cell::new(None),
+            script: RefCell::new(None),
+            on_ready: Cell::new(Some(Box::new(on_ready))),
+            on_error: Cell::new(Some(Box::new(on_error))),
+        });
+
+        let weak = Rc::downgrade(&driver);
+        let token = webview.add_web_message_received(move |_, args| {
+            let driver = match weak.upgrade() {
+                Some(driver) => driver,
+                None => return Ok(()),
+            };
+            let probe = match Probe::parse(&args.get_web_message_as_json()?) {
+                Some((name, probe)) if name == driver.binding.borrow().name() => probe,
+                _ => return Ok(()),
+            };
+            let next = driver.binding.borrow_mut().probed(probe, Instant::now());
+            driver.perform(next);
+            Ok(())
+        });
+        let actions = match token {
+            Ok(token) => {
+                driver.token.set(Some(token));
+                // Documents created later report too, until it is over.
+                *driver.script.borrow_mut() = Slot::add(&webview, &watch_script(name)).ok();
+                driver.binding.borrow_mut().start(Instant::now())
+            }
+            Err(e) => {
+                let mut binding = driver.binding.borrow_mut();
+                binding.state = BindState::Failed;
+                vec![Action::Fail(BindError::Exhausted {
+                    attempts: 0,
+                    last: format!("add_web_message_received failed: {}", e),
+                })]
+            }
+        };
+        driver.perform(actions);
+        BindHandle(driver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the webview: performs the actions of a `Binding` against
+    /// a scripted page and a fake clock.
+    struct FakePage {
+        /// Results of `add_host_object_to_script`, then `Ok`.
+        add_results: Vec<Result<(), String>>,
+        /// What the page says to each watch script in order, or why it could
+        /// not be executed, then `Ready`. `None` never answers.
+        probes: Vec<Option<Result<Probe, String>>>,
+        now: Instant,
+        wake: Option<Duration>,
+        adds: u32,
+        /// Watch scripts that have not answered yet.
+        unanswered: Vec<u32>,
+        outcome: Option<Result<(), BindError>>,
+    }
+
+    impl FakePage {
+        fn new(
+            add_results: Vec<Result<(), String>>,
+            probes: Vec<Option<Result<Probe, String>>>,
+        ) -> Self {
+            FakePage {
+                add_results,
+                probes,
+                now: Instant::now(),
+                wake: None,
+                adds: 0,
+                unanswered: Vec::new(),
+                outcome: None,
+            }
+        }
+
+        fn perform(&mut self, binding: &mut Binding, actions: Vec<Action>) {
+            let mut actions = actions;
+            while !actions.is_empty() {
+                let mut next = Vec::new();
+                for action in actions {
+                    assert!(self.outcome.is_none(), "{:?} after finishing", action);
+                    match action {
+                        Action::AddObject => {
+                            self.adds += 1;
+                            let result = if self.add_results.is_empty() {
+                                Ok(())
+                            } else {
+                                self.add_results.remove(0)
+                            };
+                            next.extend(binding.added(result, self.now));
+                        }
+                        Action::Watch { attempt, script } => {
+                            assert!(script.ends_with("watchHostObject(\"api\");"));
+                            let probe = if self.probes.is_empty() {
+                                Some(Ok(Probe::Ready))
+                            } else {
+                                self.probes.remove(0)
+                            };
+                            match probe {
+                                Some(Ok(probe)) => next.extend(binding.probed(probe, self.now)),
+                                Some(Err(e)) => {
+                                    next.extend(binding.watch_failed(attempt, e, self.now))
+                                }
+                                None => self.unanswered.push(attempt),
+                            }
+                        }
+                        Action::Wake(delay) => self.wake = Some(delay),
+                        Action::Ready => self.outcome = Some(Ok(())),
+                        Action::Fail(e) => self.outcome = Some(Err(e)),
+                    }
+                }
+                actions = next;
+            }
+        }
+
+        /// Run the binding to the end, returning the wake delays in order.
+        fn run(&mut self, binding: &mut Binding) -> Vec<Duration> {
+            let mut delays = Vec::new();
+            let actions = binding.start(self.now);
+            self.perform(binding, actions);
+            while self.outcome.is_none() {
+                let delay = self.wake.take().expect("stuck without a wake up");
+                delays.push(delay);
+                self.now += delay;
+                let actions = binding.tick(self.now);
+                self.perform(binding, actions);
+            }
+            delays
+        }
+    }
+
+    fn options() -> BindOptions {
+        BindOptions {
+            first_delay: Duration::from_millis(10),
+            backoff: 2.0,
+            max_delay: Duration::from_millis(50),
+            max_attempts: 5,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    fn missing() -> Option<Result<Probe, String>> {
+        Some(Ok(Probe::Missing))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Probe::parse(r#"{"type":"webview2.hostObject","name":"api","ready":true}"#),
+            Some(("api".to_owned(), Probe::Ready))
+        );
+        assert_eq!(
+            Probe::parse(r#"{"type":"webview2.hostObject","name":"api","ready":false}"#),
+            Some(("api".to_owned(), Probe::Missing))
+        );
+        assert_eq!(
+            Probe::parse(r#"{"type":"other","name":"api","ready":true}"#),
+            None
+        );
+        assert_eq!(Probe::parse(r#""ready""#), None);
+    }
+
+    #[test]
+    fn test_scripts() {
+        assert!(
+            watch_script("a\"b").ends_with("\nwindow.chrome.webview.watchHostObject(\"a\\\"b\");")
+        );
+        assert!(quiet_script("api")
+            .ends_with("\nwindow.chrome.webview.watchHostObject(\"api\", true);"));
+        assert!(SCRIPT.contains("if (!quiet) {"));
+    }
+
+    #[test]
+    fn test_ready() {
+        let mut binding = Binding::new("api", options());
+        let mut page = FakePage::new(vec![], vec![]);
+        assert!(page.run(&mut binding).is_empty());
+        assert_eq!(page.outcome, Some(Ok(())));
+        assert_eq!(binding.state(), BindState::Ready);
+        assert_eq!(binding.attempts(), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let ms = Duration::from_millis;
+        let o = options();
+        assert_eq!(
+            (1..6).map(|a| o.delay(a)).collect::<Vec<_>>(),
+            [ms(10), ms(20), ms(40), ms(50), ms(50)]
+        );
+
+        let mut binding = Binding::new("api", options());
+        let mut page = FakePage::new(
+            vec![Err("busy".to_owned())],
+            vec![missing(), Some(Err("disabled".to_owned())), missing()],
+        );
+        assert_eq!(page.run(&mut binding), [ms(10), ms(20), ms(40), ms(50)]);
+        assert_eq!(page.outcome, Some(Ok(())));
+        assert_eq!(page.adds, 5);
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let mut binding = Binding::new("api", options());
+        let mut page = FakePage::new(
+            vec![],
+            vec![
+                missing(),
+                missing(),
+                missing(),
+                missing(),
+                Some(Err("disabled".to_owned())),
+            ],
+        );
+        page.run(&mut binding);
+        assert_eq!(
+            page.outcome,
+            Some(Err(BindError::Exhausted {
+                attempts: 5,
+                last: "execute_script failed: disabled".to_owned()
+            }))
+        );
+        assert_eq!(binding.state(), BindState::Failed);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut binding = Binding::new("api", options());
+        let mut page = FakePage::new(vec![], vec![missing(), None]);
+        assert_eq!(
+            page.run(&mut binding),
+            [Duration::from_millis(10), Duration::from_millis(990)]
+        );
+        assert_eq!(page.outcome, Some(Err(BindError::Timeout { attempts: 2 })));
+        // The late answer is ignored.
+        let now = page.now;
+        assert!(binding.probed(Probe::Ready, now).is_empty());
+        assert_eq!(binding.state(), BindState::Failed);
+    }
+
+    #[test]
+    fn test_cancel_and_stale_results() {
+        let now = Instant::now();
+        let mut binding = Binding::new("api", options());
+        assert_eq!(binding.start(now), [Action::AddObject]);
+        binding.added(Ok(()), now);
+        binding.probed(Probe::Missing, now);
+        let later = now + Duration::from_millis(10);
+        assert_eq!(binding.tick(later), [Action::AddObject]);
+        binding.added(Ok(()), later);
+        // A failure to execute the first watch script.
+        assert!(binding
+            .watch_failed(1, "disabled".to_owned(), later)
+            .is_empty());
+        assert_eq!(binding.state(), BindState::Watching);
+
+        binding.cancel();
+        assert_eq!(binding.state(), BindState::Cancelled);
+        assert!(binding.probed(Probe::Ready, later).is_empty());
+        assert!(binding.tick(now + Duration::from_secs(5)).is_empty());
+        assert!(binding.start(now).is_empty());
+    }
+
+    /// The timeout cuts the wait before an attempt short, and objects that
+    /// were never added are not taken for ready.
+    #[test]
+    fn test_limits() {
+        let ms = Duration::from_millis;
+        let short = BindOptions {
+            timeout: ms(25),
+            ..options()
+        };
+        let mut binding = Binding::new("api", short);
+        let mut page = FakePage::new(vec![], (0..10).map(|_| missing()).collect());
+        assert_eq!(page.run(&mut binding), [ms(10), ms(15)]);
+        assert_eq!(page.outcome, Some(Err(BindError::Timeout { attempts: 2 })));
+
+        let mut binding = Binding::new("api", options());
+        let mut page = FakePage::new((0..5).map(|_| Err("busy".to_owned())).collect(), vec![]);
+        page.run(&mut binding);
+        assert!(matches!(
+            page.outcome,
+            Some(Err(BindError::Exhausted { attempts: 5, .. }))
+        ));
+        assert_eq!(binding.state(), BindState::Failed);
+    }
+}
@@ -0,0 +1,831 @@
+//! The COM-free half of a host object's `IDispatch`.
+//!
+//! `HostObjectBuilder` collects named methods and properties into a
+//! `DispatchTable`, which hands out DISPIDs, checks argument counts and types
+//! and calls the Rust closures. On Windows `HostObject::to_dispatch` wraps the
+//! table in an `IDispatch` that converts between `VARIANT`s and `Value`s.
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//! use webview2::host_object::HostObjectBuilder;
+//!
+//! let count = Rc::new(Cell::new(0));
+//! let (get, set) = (count.clone(), count.clone());
+//! let object = HostObjectBuilder::new()
+//!     .with_method("add", |a: i32, b: i32| a + b)
+//!     .with_property("count", move || get.get(), move |v: i32| set.set(v))
+//!     .build();
+//! # let _ = object;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A member id, as returned by `GetIDsOfNames`.
+pub type DispId = i32;
+
+/// The id of the default member, used when the object itself is called.
+pub const DISPID_VALUE: DispId = 0;
+
+/// The `wFlags` of `IDispatch::Invoke`. Same values as `DISPATCH_FLAGS`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InvokeFlags(pub u16);
+
+impl InvokeFlags {
+    pub const METHOD: InvokeFlags = InvokeFlags(1);
+    pub const PROPERTY_GET: InvokeFlags = InvokeFlags(2);
+    pub const PROPERTY_PUT: InvokeFlags = InvokeFlags(4);
+    pub const PROPERTY_PUT_REF: InvokeFlags = InvokeFlags(8);
+
+    pub fn contains(self, other: InvokeFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn is_put(self) -> bool {
+        self.contains(Self::PROPERTY_PUT) || self.contains(Self::PROPERTY_PUT_REF)
+    }
+}
+
+impl std::ops::BitOr for InvokeFlags {
+    type Output = InvokeFlags;
+
+    fn bitor(self, other: InvokeFlags) -> InvokeFlags {
+        InvokeFlags(self.0 | other.0)
+    }
+}
+
+/// A value passed to or returned from a host object member.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `undefined` or `null`.
+    Empty,
+    Bool(bool),
+    I32(i32),
+    F64(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(HostObject),
+}
+
+/// Why a call failed. Each variant maps to a `DISP_E_*` code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DispatchError {
+    /// `DISP_E_UNKNOWNNAME`.
+    UnknownName(String),
+    /// `DISP_E_MEMBERNOTFOUND`: no such DISPID, or the member does not
+    /// support the requested kind of access, e.g. writing a read-only
+    /// property.
+    MemberNotFound,
+    /// `DISP_E_BADPARAMCOUNT`.
+    BadParamCount { min: usize, max: usize, got: usize },
+    /// `DISP_E_TYPEMISMATCH`. `index` counts from the first argument as
+    /// written in JavaScript.
+    TypeMismatch {
+        index: usize,
+        expected: &'static str,
+    },
+    /// `DISP_E_OVERFLOW`: a number does not fit the parameter type.
+    Overflow { index: usize },
+    /// `DISP_E_NONAMEDARGS`.
+    NoNamedArgs,
+    /// `DISP_E_EXCEPTION`: the member itself failed. Thrown in JavaScript
+    /// with this message.
+    Exception(String),
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DispatchError::UnknownName(name) => write!(f, "unknown name {:?}", name),
+            DispatchError::MemberNotFound => f.write_str("member not found"),
+            DispatchError::BadParamCount { min, max, got } if min == max => {
+                write!(f, "expected {} arguments, got {}", min, got)
+            }
+            DispatchError::BadParamCount { min, max, got } => {
+                write!(f, "expected {} to {} arguments, got {}", min, max, got)
+            }
+            DispatchError::TypeMismatch { index, expected } => {
+                write!(f, "argument {}: expected {}", index, expected)
+            }
+            DispatchError::Overflow { index } => write!(f, "argument {}: out of range", index),
+            DispatchError::NoNamedArgs => f.write_str("named arguments are not supported"),
+            DispatchError::Exception(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+/// How a `Value` failed to convert in `FromValue`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Type,
+    Overflow,
+}
+
+impl Mismatch {
+    /// The error for argument `index`.
+    pub fn at(self, index: usize, expected: &'static str) -> DispatchError {
+        match self {
+            Mismatch::Type => DispatchError::TypeMismatch { index, expected },
+            Mismatch::Overflow => DispatchError::Overflow { index },
+        }
+    }
+}
+
+/// Parameter types of host object members.
+pub trait FromValue: Sized {
+    /// Shown in type mismatch errors.
+    const EXPECTED: &'static str;
+
+    fn from_value(value: Value) -> Result<Self, Mismatch>;
+
+    /// Whether the argument may be left out. Only trailing arguments can be.
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+impl FromValue for Value {
+    const EXPECTED: &'static str = "any value";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        Ok(value)
+    }
+}
+
+impl FromValue for bool {
+    const EXPECTED: &'static str = "a boolean";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(Mismatch::Type),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    const EXPECTED: &'static str = "an integer";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::I32(i) => Ok(i),
+            // JavaScript numbers often arrive as doubles.
+            Value::F64(f) if f.fract() == 0.0 => {
+                if f >= i32::MIN as f64 && f <= i32::MAX as f64 {
+                    Ok(f as i32)
+                } else {
+                    Err(Mismatch::Overflow)
+                }
+            }
+            _ => Err(Mismatch::Type),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::I32(i) => Ok(i as f64),
+            Value::F64(f) => Ok(f),
+            _ => Err(Mismatch::Type),
+        }
+    }
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::String(s) => Ok(s),
+            _ => Err(Mismatch::Type),
+        }
+    }
+}
+
+impl FromValue for HostObject {
+    const EXPECTED: &'static str = "a host object";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::Object(object) => Ok(object),
+            _ => Err(Mismatch::Type),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    const EXPECTED: &'static str = "an array";
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::Array(values) => values.into_iter().map(T::from_value).collect(),
+            _ => Err(Mismatch::Type),
+        }
+    }
+}
+
+/// `undefined` and `null` convert to `None`.
+impl<T: FromValue> FromValue for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_value(value: Value) -> Result<Self, Mismatch> {
+        match value {
+            Value::Empty => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+/// Return and property types of host object members.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Empty
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::I32(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::F64(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_owned())
+    }
+}
+
+impl IntoValue for HostObject {
+    fn into_value(self) -> Value {
+        Value::Object(self)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Array(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Empty, IntoValue::into_value)
+    }
+}
+
+/// What members may return: an `IntoValue`, or a `Result` whose error is
+/// thrown in JavaScript.
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, DispatchError>;
+}
+
+impl<T: IntoValue> IntoResult for T {
+    fn into_result(self) -> Result<Value, DispatchError> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: fmt::Display> IntoResult for Result<T, E> {
+    fn into_result(self) -> Result<Value, DispatchError> {
+        self.map(IntoValue::into_value)
+            .map_err(|e| DispatchError::Exception(e.to_string()))
+    }
+}
+
+/// Closures that can be registered as methods, with up to six `FromValue`
+/// parameters.
+pub trait Method<Args>: 'static {
+    /// The minimum and maximum number of arguments.
+    fn arity() -> (usize, usize);
+
+    fn call(&self, args: Vec<Value>) -> Result<Value, DispatchError>;
+}
+
+fn arity(optional: &[bool]) -> (usize, usize) {
+    let min = optional.iter().rposition(|o| !o).map_or(0, |i| i + 1);
+    (min, optional.len())
+}
+
+fn next_arg<T: FromValue>(
+    args: &mut std::vec::IntoIter<Value>,
+    index: &mut usize,
+) -> Result<T, DispatchError> {
+    let i = *index;
+    *index += 1;
+    T::from_value(args.next().unwrap_or(Value::Empty)).map_err(|e| e.at(i, T::EXPECTED))
+}
+
+macro_rules! impl_method {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> Method<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoResult,
+            $($arg: FromValue,)*
+        {
+            fn arity() -> (usize, usize) {
+                arity(&[$($arg::is_optional()),*])
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, args: Vec<Value>) -> Result<Value, DispatchError> {
+                let mut args = args.into_iter();
+                let mut index = 0;
+                $(let $arg = next_arg::<$arg>(&mut args, &mut index)?;)*
+                self($($arg),*).into_result()
+            }
+        }
+    };
+}
+
+impl_method!();
+impl_method!(A);
+impl_method!(A, B);
+impl_method!(A, B, C);
+impl_method!(A, B, C, D);
+impl_method!(A, B, C, D, E);
+impl_method!(A, B, C, D, E, G);
+
+type Call = Box<dyn Fn(Vec<Value>) -> Result<Value, DispatchError>>;
+type Get = Box<dyn Fn() -> Result<Value, DispatchError>>;
+type Set = Box<dyn Fn(Value) -> Result<(), DispatchError>>;
+
+enum Member {
+    Method { call: Call, min: usize, max: usize },
+    Property { get: Get, set: Option<Set> },
+}
+
+/// Named members and their DISPIDs.
+///
+/// DISPIDs are handed out from 1 in registration order and never change, so
+/// two objects built the same way agree on them. Registering a name again
+/// replaces the member but keeps its DISPID. Names are case sensitive, like
+/// JavaScript.
+#[derive(Default)]
+pub struct DispatchTable {
+    ids: HashMap<String, DispId>,
+    members: HashMap<DispId, (String, Member)>,
+    next_id: DispId,
+}
+
+impl DispatchTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn id_of_name(&self, name: &str) -> Result<DispId, DispatchError> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| DispatchError::UnknownName(name.to_owned()))
+    }
+
+    pub fn name_of(&self, id: DispId) -> Option<&str> {
+        self.members.get(&id).map(|(name, _)| name.as_str())
+    }
+
+    /// Member names in DISPID order. The default member is not included.
+    pub fn names(&self) -> Vec<&str> {
+        let mut ids: Vec<_> = self.ids.values().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter().filter_map(|id| self.name_of(id)).collect()
+    }
+
+    /// Call member `id`. `args` are in JavaScript order, which is the
+    /// reverse of `DISPPARAMS::rgvarg`.
+    ///
+    /// Methods need `METHOD`. Properties are read with `PROPERTY_GET` or
+    /// `METHOD` and no arguments, and written with `PROPERTY_PUT` or
+    /// `PROPERTY_PUT_REF` and exactly one.
+    pub fn invoke(
+        &self,
+        id: DispId,
+        flags: InvokeFlags,
+        args: Vec<Value>,
+    ) -> Result<Value, DispatchError> {
+        let member = match self.members.get(&id) {
+            Some((_, member)) => member,
+            None => return Err(DispatchError::MemberNotFound),
+        };
+        let count = |min: usize, max: usize| {
+            if args.len() < min || args.len() > max {
+                Err(DispatchError::BadParamCount {
+                    min,
+                    max,
+                    got: args.len(),
+                })
+            } else {
+                Ok(())
+            }
+        };
+
+        match member {
+            Member::Method { call, min, max } if flags.contains(InvokeFlags::METHOD) => {
+                count(*min, *max)?;
+                call(args)
+            }
+            Member::Method { .. } => Err(DispatchError::MemberNotFound),
+            Member::Property { set, .. } if flags.is_put() => {
+                count(1, 1)?;
+                let set = set.as_ref().ok_or(DispatchError::MemberNotFound)?;
+                set(args.into_iter().next().unwrap())?;
+                Ok(Value::Empty)
+            }
+            Member::Property { get, .. }
+                if flags.contains(InvokeFlags::PROPERTY_GET)
+                    || flags.contains(InvokeFlags::METHOD) =>
+            {
+                count(0, 0)?;
+                get()
+            }
+            Member::Property { .. } => Err(DispatchError::MemberNotFound),
+        }
+    }
+
+    fn insert(&mut self, name: &str, member: Member) {
+        let id = match self.ids.get(name) {
+            Some(id) => *id,
+            None => {
+                self.next_id += 1;
+                self.ids.insert(name.to_owned(), self.next_id);
+                self.next_id
+            }
+        };
+        self.members.insert(id, (name.to_owned(), member));
+    }
+}
+
+/// A built host object. Cheap to clone; clones share their members.
+#[derive(Clone)]
+pub struct HostObject {
+    table: Rc<DispatchTable>,
+}
+
+impl HostObject {
+    pub fn builder() -> HostObjectBuilder {
+        HostObjectBuilder::new()
+    }
+
+    pub fn table(&self) -> &DispatchTable {
+        &self.table
+    }
+}
+
+impl fmt::Debug for HostObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HostObject")
+            .field("members", &self.table.names())
+            .finish()
+    }
+}
+
+/// Objects are equal if they are the same object.
+impl PartialEq for HostObject {
+    fn eq(&self, other: &HostObject) -> bool {
+        Rc::ptr_eq(&self.table, &other.table)
+    }
+}
+
+/// Builds a `HostObject` from closures.
+///
+/// Properties are backed by a getter and, unless read-only, a setter; use
+/// `Cell`s or `RefCell`s shared between them for the state.
+#[derive(Default)]
+pub struct HostObjectBuilder {
+    table: DispatchTable,
+}
+
+impl HostObjectBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_method<Args, M: Method<Args>>(mut self, name: &str, method: M) -> Self {
+        let member = method_member(method);
+        self.table.insert(name, member);
+        self
+    }
+
+    /// The member called when the object itself is called, as in
+    /// `chrome.webview.hostObjects.sync.name(arg)`.
+    pub fn with_default_method<Args, M: Method<Args>>(mut self, method: M) -> Self {
+        let member = method_member(method);
+        self.table
+            .members
+            .insert(DISPID_VALUE, (String::new(), member));
+        self
+    }
+
+    pub fn with_property<R, T, U>(
+        mut self,
+        name: &str,
+        get: impl Fn() -> R + 'static,
+        set: impl Fn(T) -> U + 'static,
+    ) -> Self
+    where
+        R: IntoResult,
+        T: FromValue,
+        U: IntoResult,
+    {
+        let set = move |value| {
+            let value = T::from_value(value).map_err(|e| e.at(0, T::EXPECTED))?;
+            set(value).into_result().map(drop)
+        };
+        self.table.insert(
+            name,
+            Member::Property {
+                get: Box::new(move || get().into_result()),
+                set: Some(Box::new(set)),
+            },
+        );
+        self
+    }
+
+    pub fn with_readonly_property<R: IntoResult>(
+        mut self,
+        name: &str,
+        get: impl Fn() -> R + 'static,
+    ) -> Self {
+        self.table.insert(
+            name,
+            Member::Property {
+                get: Box::new(move || get().into_result()),
+                set: None,
+            },
+        );
+        self
+    }
+
+    /// A read-only property holding another host object.
+    pub fn with_object(self, name: &str, object: HostObject) -> Self {
+        self.with_readonly_property(name, move || object.clone())
+    }
+
+    pub fn build(self) -> HostObject {
+        HostObject {
+            table: Rc::new(self.table),
+        }
+    }
+}
+
+fn method_member<Args, M: Method<Args>>(method: M) -> Member {
+    let (min, max) = M::arity();
+    Member::Method {
+        call: Box::new(move |args| method.call(args)),
+        min,
+        max,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const METHOD: InvokeFlags = InvokeFlags::METHOD;
+    const GET: InvokeFlags = InvokeFlags::PROPERTY_GET;
+    const PUT: InvokeFlags = InvokeFlags::PROPERTY_PUT;
+
+    fn call(object: &HostObject, name: &str, args: Vec<Value>) -> Result<Value, DispatchError> {
+        let table = object.table();
+        table.invoke(table.id_of_name(name)?, METHOD | GET, args)
+    }
+
+    #[test]
+    fn test_dispids_are_stable() {
+        let build = || {
+            HostObjectBuilder::new()
+                .with_method("a", || ())
+                .with_readonly_property("b", || 1)
+                .with_method("c", || ())
+                .build()
+        };
+        let (x, y) = (build(), build());
+        for name in &["a", "b", "c"] {
+            assert_eq!(x.table().id_of_name(name), y.table().id_of_name(name));
+        }
+        assert_eq!(x.table().id_of_name("a"), Ok(1));
+        assert_eq!(x.table().names(), vec!["a", "b", "c"]);
+        assert_eq!(
+            x.table().id_of_name("A"),
+            Err(DispatchError::UnknownName("A".to_owned()))
+        );
+
+        // Replacing a member keeps its id.
+        let object = HostObjectBuilder::new()
+            .with_method("a", || 1)
+            .with_method("b", || 2)
+            .with_method("a", || 3)
+            .build();
+        assert_eq!(object.table().id_of_name("a"), Ok(1));
+        assert_eq!(call(&object, "a", vec![]), Ok(Value::I32(3)));
+    }
+
+    #[test]
+    fn test_typed_arguments() {
+        let object = HostObjectBuilder::new()
+            .with_method("add", |a: i32, b: f64| a as f64 + b)
+            .with_method("join", |parts: Vec<String>, sep: Option<String>| {
+                parts.join(&sep.unwrap_or_default())
+            })
+            .with_method("not", |b: bool| !b)
+            .build();
+
+        assert_eq!(
+            call(&object, "add", vec![Value::F64(2.0), Value::I32(3)]),
+            Ok(Value::F64(5.0))
+        );
+        let parts = Value::Array(vec![Value::String("a".into()), Value::String("b".into())]);
+        assert_eq!(
+            call(&object, "join", vec![parts.clone()]),
+            Ok(Value::String("ab".into()))
+        );
+        assert_eq!(
+            call(&object, "join", vec![parts, Value::String("-".into())]),
+            Ok(Value::String("a-b".into()))
+        );
+        assert_eq!(
+            call(&object, "not", vec![Value::Bool(true)]),
+            Ok(Value::Bool(false))
+        );
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        let object = HostObjectBuilder::new()
+            .with_method("f", |_: i32, _: String, _: Option<bool>| ())
+            .build();
+
+        assert_eq!(
+            call(&object, "f", vec![Value::I32(1)]),
+            Err(DispatchError::BadParamCount {
+                min: 2,
+                max: 3,
+                got: 1
+            })
+        );
+        assert_eq!(
+            call(&object, "f", vec![Value::I32(1), Value::I32(2)]),
+            Err(DispatchError::TypeMismatch {
+                index: 1,
+                expected: "a string"
+            })
+        );
+        assert_eq!(
+            call(
+                &object,
+                "f",
+                vec![Value::F64(1.5), Value::String("".into())]
+            ),
+            Err(DispatchError::TypeMismatch {
+                index: 0,
+                expected: "an integer"
+            })
+        );
+        assert_eq!(
+            call(
+                &object,
+                "f",
+                vec![Value::F64(1e10), Value::String("".into())]
+            ),
+            Err(DispatchError::Overflow { index: 0 })
+        );
+        let s = Value::String("".into());
+        assert_eq!(
+            call(&object, "f", vec![Value::I32(1), s.clone(), Value::Empty]),
+            Ok(Value::Empty)
+        );
+        assert_eq!(
+            call(
+                &object,
+                "f",
+                vec![Value::I32(1), s, Value::Empty, Value::Empty]
+            ),
+            Err(DispatchError::BadParamCount {
+                min: 2,
+                max: 3,
+                got: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_properties() {
+        let value = Rc::new(Cell::new(1));
+        let (get, set) = (value.clone(), value.clone());
+        let object = HostObjectBuilder::new()
+            .with_property("value", move || get.get(), move |v: i32| set.set(v))
+            .with_readonly_property("name", || "host")
+            .with_method("fail", || Err::<(), _>("nope"))
+            .build();
+        let table = object.table();
+        let id = table.id_of_name("value").unwrap();
+
+        assert_eq!(table.invoke(id, GET, vec![]), Ok(Value::I32(1)));
+        assert_eq!(table.invoke(id, PUT, vec![Value::I32(7)]), Ok(Value::Empty));
+        assert_eq!(value.get(), 7);
+        assert_eq!(
+            table.invoke(id, PUT, vec![Value::Bool(true)]),
+            Err(DispatchError::TypeMismatch {
+                index: 0,
+                expected: "an integer"
+            })
+        );
+        assert!(matches!(
+            table.invoke(id, GET, vec![Value::I32(1)]),
+            Err(DispatchError::BadParamCount { .. })
+        ));
+
+        let name = table.id_of_name("name").unwrap();
+        assert_eq!(
+            table.invoke(name, PUT, vec![Value::I32(1)]),
+            Err(DispatchError::MemberNotFound)
+        );
+        assert_eq!(
+            call(&object, "fail", vec![]),
+            Err(DispatchError::Exception("nope".to_owned()))
+        );
+
+        // Methods are not readable as properties.
+        let fail = table.id_of_name("fail").unwrap();
+        assert_eq!(
+            table.invoke(fail, GET, vec![]),
+            Err(DispatchError::MemberNotFound)
+        );
+        assert_eq!(
+            table.invoke(100, METHOD, vec![]),
+            Err(DispatchError::MemberNotFound)
+        );
+    }
+
+    #[test]
+    fn test_nested_objects_and_default_method() {
+        let inner = HostObjectBuilder::new()
+            .with_method("ping", || "pong")
+            .build();
+        let object = HostObjectBuilder::new()
+            .with_object("inner", inner.clone())
+            .with_method("same", |o: HostObject| o.table().names().len() as i32)
+            .with_default_method(|s: String| s.len() as i32)
+            .build();
+
+        assert_eq!(
+            call(&object, "inner", vec![]),
+            Ok(Value::Object(inner.clone()))
+        );
+        assert_eq!(
+            call(&inner, "ping", vec![]),
+            Ok(Value::String("pong".into()))
+        );
+        assert_eq!(
+            call(&object, "same", vec![Value::Object(inner)]),
+            Ok(Value::I32(1))
+        );
+        assert_eq!(
+            object
+                .table()
+                .invoke(DISPID_VALUE, METHOD, vec![Value::String("abc".into())]),
+            Ok(Value::I32(3))
+        );
+        assert_eq!(object.table().names(), vec!["inner", "same"]);
+    }
+}
@@ -0,0 +1,686 @@
+use super::binding::{bind, BindError, BindHandle, BindOptions};
+use super::dispatch::{
+    DispId, DispatchError, HostObject, HostObjectBuilder, InvokeFlags, Mismatch, Value,
+};
+use super::variant::{Decimal, SafeArray, VarType, VariantError, VariantValue};
+use crate::WebView;
+use once_cell::sync::Lazy;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+use std::rc::Rc;
+use std::sync::Mutex;
+use windows::core::*;
+pub use windows::Win32::System::Com::IDispatch;
+
+use windows::Win32::Foundation::{
+    DECIMAL, DECIMAL_0, DECIMAL_0_0, DECIMAL_1, DISP_E_BADPARAMCOUNT, DISP_E_EXCEPTION,
+    DISP_E_MEMBERNOTFOUND, DISP_E_NONAMEDARGS, DISP_E_OVERFLOW, DISP_E_TYPEMISMATCH,
+    DISP_E_UNKNOWNNAME, E_NOTIMPL, E_OUTOFMEMORY, VARIANT_FALSE, VARIANT_TRUE,
+};
+use windows::Win32::System::{
+    Com::{IDispatch_Impl, ITypeInfo, CY, DISPATCH_FLAGS, DISPPARAMS, EXCEPINFO, SAFEARRAY},
+    Ole::{
+        SafeArrayCreateVector, SafeArrayDestroy, SafeArrayGetDim, SafeArrayGetElement,
+        SafeArrayGetLBound, SafeArrayGetUBound, SafeArrayGetVartype, SafeArrayPutElement,
+        DISPID_PROPERTYPUT, DISPID_UNKNOWN,
+    },
+    Variant::{
+        VariantClear, VariantCopy, VARENUM, VARIANT, VARIANT_0, VARIANT_0_0, VARIANT_0_0_0,
+        VT_BSTR, VT_DISPATCH, VT_I4,
+    },
+};
+
+/// An owned `VARIANT`, released with `VariantClear` when dropped.
+///
+/// Pass `&mut variant.0` where a `*mut VARIANT` input is expected, e.g. to
+/// `WebView::add_host_object_to_script`. `VariantValue` converts to and from
+/// it with `TryFrom`.
+#[derive(Default)]
+pub struct Variant(pub VARIANT);
+
+impl Variant {
+    /// Takes ownership of `contents`, e.g. of a `BSTR` or interface pointer
+    /// in it.
+    pub fn new(num: VARENUM, contents: VARIANT_0_0_0) -> Variant {
+        Variant(raw_variant(num, contents))
+    }
+
+    /// Copy the contents out.
+    pub fn value(&self) -> std::result::Result<VariantValue, VariantError> {
+        VariantValue::try_from(&self.0)
+    }
+
+    /// Give up ownership, e.g. to return the `VARIANT` through an out
+    /// parameter.
+    pub fn into_raw(self) -> VARIANT {
+        let this = ManuallyDrop::new(self);
+        unsafe { std::ptr::read(&this.0) }
+    }
+}
+
+impl Clone for Variant {
+    fn clone(&self) -> Variant {
+        let mut copy = Variant::default();
+        unsafe { VariantCopy(&mut copy.0, &self.0) }.expect("VariantCopy");
+        copy
+    }
+}
+
+impl Drop for Variant {
+    fn drop(&mut self) {
+        let _ = unsafe { VariantClear(&mut self.0) };
+    }
+}
+
+impl From<String> for Variant {
+    fn from(value: String) -> Variant {
+        Variant::new(
+            VT_BSTR,
+            VARIANT_0_0_0 {
+                bstrVal: ManuallyDrop::new(BSTR::from(value)),
+            },
+        )
+    }
+}
+
+impl From<&str> for Variant {
+    fn from(value: &str) -> Variant {
+        Variant::from(value.to_string())
+    }
+}
+
+impl From<i32> for Variant {
+    fn from(value: i32) -> Variant {
+        Variant::new(VT_I4, VARIANT_0_0_0 { lVal: value })
+    }
+}
+
+impl From<ManuallyDrop<Option<IDispatch>>> for Variant {
+    fn from(value: ManuallyDrop<Option<IDispatch>>) -> Variant {
+        Variant::new(VT_DISPATCH, VARIANT_0_0_0 { pdispVal: value })
+    }
+}
+
+impl From<HostObject> for Variant {
+    fn from(value: HostObject) -> Variant {
+        Variant::from(ManuallyDrop::new(Some(value.to_dispatch())))
+    }
+}
+
+/// Fails for `ByRef`, see `VariantValue::ByRef`.
+impl TryFrom<VariantValue> for Variant {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> std::result::Result<Variant, VariantError> {
+        unsafe { write(value) }.map(Variant)
+    }
+}
+
+impl TryFrom<&Variant> for VariantValue {
+    type Error = VariantError;
+
+    fn try_from(value: &Variant) -> std::result::Result<VariantValue, VariantError> {
+        value.value()
+    }
+}
+
+/// Reads without taking ownership: strings are copied and interface pointers
+/// `AddRef`ed.
+impl TryFrom<&VARIANT> for VariantValue {
+    type Error = VariantError;
+
+    fn try_from(value: &VARIANT) -> std::result::Result<VariantValue, VariantError> {
+        unsafe { read(value) }
+    }
+}
+
+fn raw_variant(num: VARENUM, contents: VARIANT_0_0_0) -> VARIANT {
+    VARIANT {
+        Anonymous: VARIANT_0 {
+            Anonymous: ManuallyDrop::new(VARIANT_0_0 {
+                vt: num,
+                wReserved1: 0,
+                wReserved2: 0,
+                wReserved3: 0,
+                Anonymous: contents,
+            }),
+        },
+    }
+}
+
+fn failed(e: Error) -> VariantError {
+    VariantError::Failed(e.code().0)
+}
+
+unsafe fn var_type(v: &VARIANT) -> VarType {
+    VarType(v.Anonymous.Anonymous.vt.0)
+}
+
+unsafe fn set_var_type(v: &mut VARIANT, vt: VarType) {
+    (*v.Anonymous.Anonymous).vt = VARENUM(vt.0);
+}
+
+unsafe fn data_ptr(v: &mut VARIANT) -> *mut c_void {
+    &mut (*v.Anonymous.Anonymous).Anonymous as *mut VARIANT_0_0_0 as *mut c_void
+}
+
+/// Size of a value of type `vt` that fits in the data of a `VARIANT`.
+fn element_size(vt: VarType) -> Option<usize> {
+    Some(match vt {
+        VarType::I1 | VarType::UI1 => 1,
+        VarType::I2 | VarType::UI2 | VarType::BOOL => 2,
+        VarType::I4
+        | VarType::UI4
+        | VarType::INT
+        | VarType::UINT
+        | VarType::R4
+        | VarType::ERROR => 4,
+        VarType::I8 | VarType::UI8 | VarType::R8 | VarType::CY | VarType::DATE => 8,
+        VarType::BSTR | VarType::DISPATCH | VarType::UNKNOWN => std::mem::size_of::<usize>(),
+        _ => return None,
+    })
+}
+
+fn from_decimal(d: &DECIMAL) -> std::result::Result<Decimal, VariantError> {
+    let (scale, sign) = unsafe { (d.Anonymous1.Anonymous.scale, d.Anonymous1.Anonymous.sign) };
+    let mantissa = (d.Hi32 as u128) << 64 | unsafe { d.Anonymous2.Lo64 } as u128;
+    Decimal::new(mantissa, scale, sign & 0x80 != 0).ok_or(VariantError::Overflow)
+}
+
+fn to_decimal(d: Decimal) -> DECIMAL {
+    DECIMAL {
+        wReserved: 0,
+        Anonymous1: DECIMAL_0 {
+            Anonymous: DECIMAL_0_0 {
+                scale: d.scale(),
+                sign: if d.is_negative() { 0x80 } else { 0 },
+            },
+        },
+        Hi32: (d.mantissa() >> 64) as u32,
+        Anonymous2: DECIMAL_1 {
+            Lo64: d.mantissa() as u64,
+        },
+    }
+}
+
+unsafe fn read(v: &VARIANT) -> std::result::Result<VariantValue, VariantError> {
+    let vt = var_type(v);
+    let data = &v.Anonymous.Anonymous.Anonymous;
+    if vt.is_byref() {
+        let value = read_ref(VarType(vt.0 & !VarType::BYREF.0), data.byref)?;
+        return Ok(VariantValue::ByRef(Box::new(value)));
+    }
+    if vt.is_array() {
+        return read_array(data.parray);
+    }
+    Ok(match vt {
+        VarType::EMPTY => VariantValue::Empty,
+        VarType::NULL => VariantValue::Null,
+        VarType::BOOL => VariantValue::Bool(data.boolVal.0 != 0),
+        VarType::I1 => VariantValue::I1(data.cVal as i8),
+        VarType::I2 => VariantValue::I2(data.iVal),
+        VarType::I4 => VariantValue::I4(data.lVal),
+        VarType::I8 => VariantValue::I8(data.llVal),
+        VarType::UI1 => VariantValue::UI1(data.bVal),
+        VarType::UI2 => VariantValue::UI2(data.uiVal),
+        VarType::UI4 => VariantValue::UI4(data.ulVal),
+        VarType::UI8 => VariantValue::UI8(data.ullVal),
+        VarType::INT => VariantValue::Int(data.intVal),
+        VarType::UINT => VariantValue::UInt(data.uintVal),
+        VarType::R4 => VariantValue::R4(data.fltVal),
+        VarType::R8 => VariantValue::R8(data.dblVal),
+        VarType::BSTR => VariantValue::BStr(
+            String::from_utf16(data.bstrVal.as_wide())
+                .map_err(|_| VariantError::NotRepresentable("invalid UTF-16"))?,
+        ),
+        VarType::DATE => VariantValue::Date(data.date),
+        VarType::CY => VariantValue::Cy(data.cyVal.int64),
+        VarType::DECIMAL => VariantValue::Decimal(from_decimal(&v.Anonymous.decVal)?),
+        VarType::ERROR => VariantValue::Error(data.scode),
+        VarType::DISPATCH => match (*data.pdispVal).as_ref().and_then(own_object) {
+            Some(object) => VariantValue::Object(object),
+            None => VariantValue::Dispatch((*data.pdispVal).clone()),
+        },
+        VarType::UNKNOWN => VariantValue::Unknown((*data.punkVal).clone()),
+        vt => return Err(VariantError::UnsupportedType(vt)),
+    })
+}
+
+unsafe fn read_ref(
+    vt: VarType,
+    ptr: *mut c_void,
+) -> std::result::Result<VariantValue, VariantError> {
+    if ptr.is_null() {
+        return Err(VariantError::NotRepresentable("a null VT_BYREF pointer"));
+    }
+    if vt == VarType::VARIANT {
+        return read(&*(ptr as *const VARIANT));
+    }
+    if vt.is_array() {
+        return read_array(*(ptr as *const *mut SAFEARRAY));
+    }
+    if vt == VarType::DECIMAL {
+        return from_decimal(&*(ptr as *const DECIMAL)).map(VariantValue::Decimal);
+    }
+    let size = element_size(vt).ok_or(VariantError::UnsupportedType(vt))?;
+    // A borrowed copy: `read` does not take ownership, and `view` is not
+    // cleared.
+    let mut view = VARIANT::default();
+    std::ptr::copy_nonoverlapping(ptr as *const u8, data_ptr(&mut view) as *mut u8, size);
+    set_var_type(&mut view, vt);
+    read(&view)
+}
+
+unsafe fn read_array(array: *const SAFEARRAY) -> std::result::Result<VariantValue, VariantError> {
+    if array.is_null() {
+        return Err(VariantError::NotRepresentable("a null SAFEARRAY"));
+    }
+    if SafeArrayGetDim(array) != 1 {
+        return Err(VariantError::MultiDimensional);
+    }
+    let element = VarType(SafeArrayGetVartype(array).map_err(failed)?.0);
+    if element != VarType::VARIANT && element != VarType::DECIMAL && element_size(element).is_none()
+    {
+        return Err(VariantError::UnsupportedType(element));
+    }
+    let lower = SafeArrayGetLBound(array, 1).map_err(failed)?;
+    let upper = SafeArrayGetUBound(array, 1).map_err(failed)?;
+
+    let mut elements = Vec::new();
+    for i in lower..=upper {
+        let value = if element == VarType::DECIMAL {
+            let mut d = DECIMAL::default();
+            SafeArrayGetElement(array, &i, &mut d as *mut DECIMAL as *mut c_void)
+                .map_err(failed)?;
+            VariantValue::Decimal(from_decimal(&d)?)
+        } else {
+            // Elements are copied out, so they are ours to clear.
+            let mut e = VARIANT::default();
+            if element == VarType::VARIANT {
+                SafeArrayGetElement(array, &i, &mut e as *mut VARIANT as *mut c_void)
+                    .map_err(failed)?;
+            } else {
+                SafeArrayGetElement(array, &i, data_ptr(&mut e)).map_err(failed)?;
+                set_var_type(&mut e, element);
+            }
+            let value = read(&e);
+            let _ = VariantClear(&mut e);
+            value?
+        };
+        elements.push(value);
+    }
+    SafeArray::typed(element, lower, elements).map(VariantValue::Array)
+}
+
+/// Make an owned `VARIANT`.
+unsafe fn write(value: VariantValue) -> std::result::Result<VARIANT, VariantError> {
+    let (vt, data) = match value {
+        VariantValue::Empty => return Ok(VARIANT::default()),
+        VariantValue::Null => (VarType::NULL, VARIANT_0_0_0 { llVal: 0 }),
+        VariantValue::Bool(b) => (
+            VarType::BOOL,
+            VARIANT_0_0_0 {
+                boolVal: if b { VARIANT_TRUE } else { VARIANT_FALSE },
+            },
+        ),
+        VariantValue::I1(i) => (VarType::I1, VARIANT_0_0_0 { cVal: i as u8 }),
+        VariantValue::I2(i) => (VarType::I2, VARIANT_0_0_0 { iVal: i }),
+        VariantValue::I4(i) => (VarType::I4, VARIANT_0_0_0 { lVal: i }),
+        VariantValue::I8(i) => (VarType::I8, VARIANT_0_0_0 { llVal: i }),
+        VariantValue::UI1(i) => (VarType::UI1, VARIANT_0_0_0 { bVal: i }),
+        VariantValue::UI2(i) => (VarType::UI2, VARIANT_0_0_0 { uiVal: i }),
+        VariantValue::UI4(i) => (VarType::UI4, VARIANT_0_0_0 { ulVal: i }),
+        VariantValue::UI8(i) => (VarType::UI8, VARIANT_0_0_0 { ullVal: i }),
+        VariantValue::Int(i) => (VarType::INT, VARIANT_0_0_0 { intVal: i }),
+        VariantValue::UInt(i) => (VarType::UINT, VARIANT_0_0_0 { uintVal: i }),
+        VariantValue::R4(f) => (VarType::R4, VARIANT_0_0_0 { fltVal: f }),
+        VariantValue::R8(f) => (VarType::R8, VARIANT_0_0_0 { dblVal: f }),
+        VariantValue::BStr(s) => (
+            VarType::BSTR,
+            VARIANT_0_0_0 {
+                bstrVal: ManuallyDrop::new(BSTR::from(s)),
+            },
+        ),
+        VariantValue::Date(date) => (VarType::DATE, VARIANT_0_0_0 { date }),
+        VariantValue::Cy(int64) => (
+            VarType::CY,
+            VARIANT_0_0_0 {
+                cyVal: CY { int64 },
+            },
+        ),
+        VariantValue::Decimal(d) => {
+            // The `DECIMAL` covers the whole `VARIANT`, so `vt` goes last.
+            let mut v = VARIANT::default();
+            v.Anonymous.decVal = to_decimal(d);
+            set_var_type(&mut v, VarType::DECIMAL);
+            return Ok(v);
+        }
+        VariantValue::Error(scode) => (VarType::ERROR, VARIANT_0_0_0 { scode }),
+        VariantValue::Array(array) => (
+            VarType::ARRAY | array.element_type(),
+            VARIANT_0_0_0 {
+                parray: write_array(array)?,
+            },
+        ),
+        VariantValue::ByRef(_) => return Err(VariantError::ByRef),
+        VariantValue::Object(object) => (
+            VarType::DISPATCH,
+            VARIANT_0_0_0 {
+                pdispVal: ManuallyDrop::new(Some(object.to_dispatch())),
+            },
+        ),
+        VariantValue::Dispatch(dispatch) => (
+            VarType::DISPATCH,
+            VARIANT_0_0_0 {
+                pdispVal: ManuallyDrop::new(dispatch),
+            },
+        ),
+        VariantValue::Unknown(unknown) => (
+            VarType::UNKNOWN,
+            VARIANT_0_0_0 {
+                punkVal: ManuallyDrop::new(unknown),
+            },
+        ),
+    };
+    Ok(raw_variant(VARENUM(vt.0), data))
+}
+
+unsafe fn write_array(array: SafeArray) -> std::result::Result<*mut SAFEARRAY, VariantError> {
+    let element = array.element_type();
+    if element != VarType::VARIANT && element != VarType::DECIMAL && element_size(element).is_none()
+    {
+        return Err(VariantError::UnsupportedType(element));
+    }
+    let lower = array.lower_bound();
+    let elements = array.into_elements();
+    let raw = SafeArrayCreateVector(VARENUM(element.0), lower, elements.len() as u32);
+    if raw.is_null() {
+        return Err(VariantError::Failed(E_OUTOFMEMORY.0));
+    }
+    for (i, value) in elements.into_iter().enumerate() {
+        if let Err(e) = put_element(raw, lower + i as i32, element, value) {
+            let _ = SafeArrayDestroy(raw);
+            return Err(e);
+        }
+    }
+    Ok(raw)
+}
+
+unsafe fn put_element(
+    array: *mut SAFEARRAY,
+    index: i32,
+    element: VarType,
+    value: VariantValue,
+) -> std::result::Result<(), VariantError> {
+    let mut v = write(value)?;
+    let found = var_type(&v);
+    let data = if element == VarType::VARIANT {
+        &v as *const VARIANT as *const c_void
+    } else if found != element {
+        let _ = VariantClear(&mut v);
+        return Err(VariantError::TypeMismatch {
+            expected: element,
+            found,
+        });
+    } else if element == VarType::DECIMAL {
+        &v.Anonymous.decVal as *const DECIMAL as *const c_void
+    } else if element == VarType::BSTR
+        || element == VarType::DISPATCH
+        || element == VarType::UNKNOWN
+    {
+        // These are passed as the pointer itself.
+        *(data_ptr(&mut v) as *const *const c_void)
+    } else {
+        data_ptr(&mut v)
+    };
+    // This copies the element.
+    let result = SafeArrayPutElement(array, &index, data).map_err(failed);
+    let _ = VariantClear(&mut v);
+    result
+}
+
+impl HostObject {
+    /// Wrap the object in an `IDispatch`. Every call makes a new COM object,
+    /// but they all share the same members.
+    pub fn to_dispatch(&self) -> IDispatch {
+        let dispatch: IDispatch = Dispatcher {
+            object: self.clone(),
+            identity: Cell::new(0),
+        }
+        .into();
+        let identity = dispatch
+            .cast::<IUnknown>()
+            .map_or(0, |unknown| unknown.as_raw() as usize);
+        // Just made from a `Dispatcher`.
+        let this: &Dispatcher = unsafe { dispatch.as_impl() };
+        this.identity.set(identity);
+        DISPATCHERS.lock().unwrap().insert(identity);
+        dispatch
+    }
+}
+
+/// `IUnknown` identities of the live `Dispatcher`s, so that host objects
+/// passed back as arguments can be told apart from other `IDispatch`es.
+static DISPATCHERS: Lazy<Mutex<HashSet<usize>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// The host object behind `dispatch`, if it is one made by `to_dispatch`.
+fn own_object(dispatch: &IDispatch) -> Option<HostObject> {
+    let identity = dispatch.cast::<IUnknown>().ok()?.as_raw() as usize;
+    if !DISPATCHERS.lock().unwrap().contains(&identity) {
+        return None;
+    }
+    // A `Dispatcher` implements no other `IDispatch`, so this is its own.
+    let this: &Dispatcher = unsafe { dispatch.as_impl() };
+    Some(this.object.clone())
+}
+
+/// Calls `sender` with the string the object is called with, as in
+/// `chrome.webview.hostObjects.sync.functioncall("hello")`.
+pub struct FunctionWithStringArgument {
+    pub sender: std::sync::mpsc::Sender<String>,
+}
+
+impl From<FunctionWithStringArgument> for HostObject {
+    fn from(f: FunctionWithStringArgument) -> HostObject {
+        HostObjectBuilder::new()
+            .with_default_method(move |s: String| {
+                let reply = format!(
+                    r#"Successful sync call functionWithStringArgument, and the argument is "{}"."#,
+                    s
+                );
+                f.sender.send(s).map(|_| reply)
+            })
+            .build()
+    }
+}
+
+impl From<FunctionWithStringArgument> for IDispatch {
+    fn from(f: FunctionWithStringArgument) -> IDispatch {
+        HostObject::from(f).to_dispatch()
+    }
+}
+
+#[implement(IDispatch)]
+struct Dispatcher {
+    object: HostObject,
+    /// Key in `DISPATCHERS`.
+    identity: Cell<usize>,
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        DISPATCHERS.lock().unwrap().remove(&self.identity.get());
+    }
+}
+
+impl Dispatcher {
+    unsafe fn invoke(
+        &self,
+        id: DispId,
+        flags: DISPATCH_FLAGS,
+        params: &DISPPARAMS,
+    ) -> std::result::Result<Value, DispatchError> {
+        let flags = InvokeFlags(flags.0);
+        let named = slice(params.rgdispidNamedArgs, params.cNamedArgs);
+        let put = flags.contains(InvokeFlags::PROPERTY_PUT)
+            || flags.contains(InvokeFlags::PROPERTY_PUT_REF);
+        if !(named.is_empty() || put && named == [DISPID_PROPERTYPUT]) {
+            return Err(DispatchError::NoNamedArgs);
+        }
+
+        // `rgvarg` is in reverse order.
+        let args = slice(params.rgvarg, params.cArgs)
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, arg)| {
+                VariantValue::try_from(arg)
+                    .map_err(|_| Mismatch::Type)
+                    .and_then(Value::try_from)
+                    .map_err(|e| e.at(i, "a supported value"))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        self.object.table().invoke(id, flags, args)
+    }
+}
+
+impl IDispatch_Impl for Dispatcher {
+    #![allow(non_snake_case)]
+    fn GetTypeInfoCount(&self) -> windows::core::Result<u32> {
+        Ok(0)
+    }
+
+    fn GetTypeInfo(&self, _itinfo: u32, _lcid: u32) -> windows::core::Result<ITypeInfo> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetIDsOfNames(
+        &self,
+        _riid: *const GUID,
+        rgsznames: *const PCWSTR,
+        cnames: u32,
+        _lcid: u32,
+        rgdispid: *mut i32,
+    ) -> windows::core::Result<()> {
+        let names = unsafe { slice(rgsznames, cnames) };
+        let mut result = Ok(());
+        for (i, name) in names.iter().enumerate() {
+            // Any further names are parameter names, which are not supported.
+            let id = match unsafe { name.to_string() } {
+                Ok(name) if i == 0 => self.object.table().id_of_name(&name).ok(),
+                _ => None,
+            };
+            if id.is_none() {
+                result = Err(DISP_E_UNKNOWNNAME.into());
+            }
+            unsafe { *rgdispid.add(i) = id.unwrap_or(DISPID_UNKNOWN) };
+        }
+        result
+    }
+
+    fn Invoke(
+        &self,
+        dispidmember: i32,
+        _riid: *const GUID,
+        _lcid: u32,
+        wflags: DISPATCH_FLAGS,
+        pdispparams: *const DISPPARAMS,
+        pvarresult: *mut VARIANT,
+        pexcepinfo: *mut EXCEPINFO,
+        puargerr: *mut u32,
+    ) -> windows::core::Result<()> {
+        let params = match unsafe { pdispparams.as_ref() } {
+            Some(params) => *params,
+            None => DISPPARAMS::default(),
+        };
+        match unsafe { self.invoke(dispidmember, wflags, &params) } {
+            Ok(value) => {
+                let result = Variant::try_from(VariantValue::from(value))
+                    .map_err(|e| DispatchError::Exception(e.to_string()));
+                match result {
+                    Ok(result) if !pvarresult.is_null() => unsafe {
+                        pvarresult.write(result.into_raw());
+                    },
+                    Ok(_) => {}
+                    Err(e) => return Err(unsafe { report(e, params.cArgs, pexcepinfo, puargerr) }),
+                }
+                Ok(())
+            }
+            Err(e) => Err(unsafe { report(e, params.cArgs, pexcepinfo, puargerr) }),
+        }
+    }
+}
+
+/// Turn `error` into the HRESULT to return from `Invoke`. The message goes to
+/// `EXCEPINFO` if the caller passed one, which is how it reaches JavaScript.
+unsafe fn report(
+    error: DispatchError,
+    arg_count: u32,
+    excepinfo: *mut EXCEPINFO,
+    arg_err: *mut u32,
+) -> Error {
+    let code = match error {
+        DispatchError::UnknownName(_) => DISP_E_UNKNOWNNAME,
+        DispatchError::MemberNotFound => DISP_E_MEMBERNOTFOUND,
+        DispatchError::BadParamCount { .. } => DISP_E_BADPARAMCOUNT,
+        DispatchError::TypeMismatch { .. } => DISP_E_TYPEMISMATCH,
+        DispatchError::Overflow { .. } => DISP_E_OVERFLOW,
+        DispatchError::NoNamedArgs => DISP_E_NONAMEDARGS,
+        DispatchError::Exception(_) => DISP_E_EXCEPTION,
+    };
+    match error {
+        DispatchError::TypeMismatch { index, .. } | DispatchError::Overflow { index }
+            if !arg_err.is_null() =>
+        {
+            *arg_err = arg_count.saturating_sub(1 + index as u32);
+        }
+        _ => {}
+    }
+    if excepinfo.is_null() {
+        return code.into();
+    }
+    excepinfo.write(EXCEPINFO {
+        bstrSource: ManuallyDrop::new(BSTR::from("webview2")),
+        bstrDescription: ManuallyDrop::new(BSTR::from(error.to_string())),
+        scode: code.0,
+        ..Default::default()
+    });
+    DISP_E_EXCEPTION.into()
+}
+
+unsafe fn slice<'a, T>(ptr: *const T, len: u32) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, len as usize)
+    }
+}
+
+/// `binding::bind` with the default options. `cb` gets the outcome either
+/// way.
+pub fn ensure_bind<F>(w: WebView, name: String, obj: Variant, cb: F) -> BindHandle
+where
+    F: FnOnce(WebView, std::result::Result<(), BindError>) + 'static,
+{
+    let cb = Rc::new(Cell::new(Some(cb)));
+    let on_error = cb.clone();
+    bind(
+        w,
+        &name,
+        obj,
+        BindOptions::default(),
+        move |w| {
+            if let Some(cb) = cb.take() {
+                cb(w, Ok(()));
+            }
+        },
+        move |w, e| {
+            if let Some(cb) = on_error.take() {
+                cb(w, Err(e));
+            }
+        },
+    )
+}
@@ -0,0 +1,687 @@
+//! Host methods that settle a JavaScript `Promise` later.
+//!
+//! `IDispatch::Invoke` runs on the UI thread and the page waits for it, so
+//! slow work must not happen there. An async host method instead returns a
+//! `Pending` right away, which reaches the page as a call id. The page turns
+//! it into a `Promise` with `chrome.webview.hostPromise`, added by `SCRIPT`:
+//!
+//! ```js
+//! const text = await chrome.webview.hostPromise(
+//!     chrome.webview.hostObjects.api.download(url));
+//! ```
+//!
+//! The call is finished either by a future, polled on the UI thread, or by a
+//! `Completion`, which can be sent to and settled from any thread. Both end
+//! up as scripts that `Promises::run` hands out on the UI thread; the
+//! `Scheduler` tells that thread when to call it. Navigating away cancels
+//! everything that is still running, see `Promises::cancel`.
+
+use super::dispatch::{IntoValue, Value};
+use serde_json::Value as Json;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// Page side of the promises: `chrome.webview.hostPromise(id)` returns a
+/// `Promise` for the call, and also accepts a `Promise` of the id as returned
+/// by the async `hostObjects` proxies.
+pub const SCRIPT: &str = r#"(function () {
+    var webview = window.chrome && window.chrome.webview;
+    if (!webview || webview.hostPromise) {
+        return;
+    }
+    var pending = {};
+    var early = {};
+    webview.hostPromise = function (id) {
+        return Promise.resolve(id).then(function (id) {
+            return new Promise(function (resolve, reject) {
+                if (early.hasOwnProperty(id)) {
+                    var settled = early[id];
+                    delete early[id];
+                    if (settled.ok) {
+                        resolve(settled.value);
+                    } else {
+                        reject(new Error(settled.value));
+                    }
+                } else {
+                    pending[id] = { resolve: resolve, reject: reject };
+                }
+            });
+        });
+    };
+    webview.settleHostPromise = function (id, ok, value) {
+        var p = pending[id];
+        if (!p) {
+            early[id] = { ok: ok, value: value };
+            return;
+        }
+        delete pending[id];
+        if (ok) {
+            p.resolve(value);
+        } else {
+            p.reject(new Error(value));
+        }
+    };
+})();"#;
+
+/// Identifies a call until it is settled. Ids are unique per `Promises`.
+pub type CallId = u32;
+
+/// The outcome of a call: the value to resolve the `Promise` with, or the
+/// message of the `Error` to reject it with.
+pub type Settlement = Result<Json, String>;
+
+/// Asks the UI thread to call `Promises::run` soon, e.g. by posting it a
+/// window message. Called from any thread, at most once between two runs.
+pub trait Scheduler: Send + Sync + 'static {
+    fn schedule(&self);
+}
+
+impl<F: Fn() + Send + Sync + 'static> Scheduler for F {
+    fn schedule(&self) {
+        self()
+    }
+}
+
+/// State shared with `Completion`s and wakers on other threads.
+struct Shared {
+    queue: Mutex<Queue>,
+    scheduler: Box<dyn Scheduler>,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// Bumped by `Promises::cancel`, anything started earlier is dropped.
+    generation: u64,
+    woken: Vec<CallId>,
+    settled: Vec<(CallId, Settlement)>,
+    scheduled: bool,
+}
+
+impl Shared {
+    /// Runs `f` on the queue unless `generation` is stale, and schedules a
+    /// run if there was none pending.
+    fn push(&self, generation: u64, f: impl FnOnce(&mut Queue)) {
+        let schedule = {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.generation != generation {
+                return;
+            }
+            f(&mut queue);
+            !std::mem::replace(&mut queue.scheduled, true)
+        };
+        if schedule {
+            self.scheduler.schedule();
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.queue.lock().unwrap().generation
+    }
+}
+
+struct TaskWaker {
+    id: CallId,
+    generation: u64,
+    shared: Arc<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let id = self.id;
+        self.shared.push(self.generation, |q| q.woken.push(id));
+    }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = Settlement>>>,
+    waker: Waker,
+}
+
+#[derive(Default)]
+struct Local {
+    next_id: CallId,
+    tasks: HashMap<CallId, Task>,
+}
+
+/// The calls in flight for one webview. Lives on the UI thread.
+#[derive(Clone)]
+pub struct Promises {
+    local: Rc<RefCell<Local>>,
+    shared: Arc<Shared>,
+}
+
+impl Promises {
+    pub fn new(scheduler: impl Scheduler) -> Self {
+        Promises {
+            local: Rc::default(),
+            shared: Arc::new(Shared {
+                queue: Mutex::default(),
+                scheduler: Box::new(scheduler),
+            }),
+        }
+    }
+
+    fn next_id(&self) -> CallId {
+        let mut local = self.local.borrow_mut();
+        local.next_id = local.next_id.wrapping_add(1);
+        local.next_id
+    }
+
+    /// Settle the call with the output of `future`. It is first polled by the
+    /// next `run`, and again by the `run` after each wake up.
+    pub fn spawn<F, T, E>(&self, future: F) -> Pending
+    where
+        F: Future<Output = Result<T, E>> + 'static,
+        T: Into<Json>,
+        E: fmt::Display,
+    {
+        let id = self.next_id();
+        let generation = self.shared.generation();
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            generation,
+            shared: self.shared.clone(),
+        }));
+        waker.wake_by_ref();
+        let future = async move { future.await.map(Into::into).map_err(|e| e.to_string()) };
+        self.local.borrow_mut().tasks.insert(
+            id,
+            Task {
+                future: Box::pin(future),
+                waker,
+            },
+        );
+        Pending(id)
+    }
+
+    /// Start a call to be settled through the `Completion`, e.g. from a
+    /// worker thread.
+    pub fn defer(&self) -> (Pending, Completion) {
+        let id = self.next_id();
+        let completion = Completion {
+            id,
+            generation: self.shared.generation(),
+            shared: Some(self.shared.clone()),
+        };
+        (Pending(id), completion)
+    }
+
+    /// Drop all unsettled calls, as their page is going away. Their futures
+    /// are dropped and their `Completion`s report `is_cancelled`.
+    pub fn cancel(&self) {
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.generation += 1;
+            queue.woken.clear();
+            queue.settled.clear();
+        }
+        // Dropped outside the borrow, in case a future's `Drop` starts
+        // another call.
+        let tasks = std::mem::take(&mut self.local.borrow_mut().tasks);
+        drop(tasks);
+    }
+
+    /// The number of calls with a future that has not finished.
+    pub fn running(&self) -> usize {
+        self.local.borrow().tasks.len()
+    }
+
+    /// Poll the woken futures and collect what has been settled since the
+    /// last run, as scripts to execute in the page in order.
+    pub fn run(&self) -> Vec<String> {
+        let (generation, mut woken) = {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.scheduled = false;
+            (queue.generation, std::mem::take(&mut queue.woken))
+        };
+        woken.sort_unstable();
+        woken.dedup();
+
+        let mut scripts = Vec::new();
+        for id in woken {
+            let task = self.local.borrow_mut().tasks.remove(&id);
+            let mut task = match task {
+                Some(task) => task,
+                None => continue,
+            };
+            let poll = {
+                let mut cx = Context::from_waker(&task.waker);
+                task.future.as_mut().poll(&mut cx)
+            };
+            match poll {
+                Poll::Ready(settlement) => scripts.push(settle_script(id, &settlement)),
+                // Unless the future cancelled its own page.
+                Poll::Pending if self.shared.generation() == generation => {
+                    self.local.borrow_mut().tasks.insert(id, task);
+                }
+                Poll::Pending => {}
+            }
+        }
+
+        let settled = std::mem::take(&mut self.shared.queue.lock().unwrap().settled);
+        scripts.extend(settled.iter().map(|(id, s)| settle_script(*id, s)));
+        scripts
+    }
+}
+
+impl fmt::Debug for Promises {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Promises")
+            .field("running", &self.running())
+            .finish()
+    }
+}
+
+fn settle_script(id: CallId, settlement: &Settlement) -> String {
+    let (ok, value) = match settlement {
+        Ok(value) => (true, value.to_string()),
+        Err(message) => (false, Json::from(message.as_str()).to_string()),
+    };
+    format!(
+        "window.chrome.webview.settleHostPromise({}, {}, {});",
+        id, ok, value
+    )
+}
+
+/// What an async host method returns: the id the page passes to
+/// `chrome.webview.hostPromise`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Pending(pub CallId);
+
+impl IntoValue for Pending {
+    fn into_value(self) -> Value {
+        Value::F64(self.0.into())
+    }
+}
+
+/// Settles one call from any thread.
+///
+/// A `Completion` dropped without settling rejects its call, so the page is
+/// never left waiting.
+pub struct Completion {
+    id: CallId,
+    generation: u64,
+    shared: Option<Arc<Shared>>,
+}
+
+impl Completion {
+    pub fn id(&self) -> CallId {
+        self.id
+    }
+
+    /// Whether the page that made the call has gone away. Settling it then
+    /// does nothing, so long running work may as well stop.
+    pub fn is_cancelled(&self) -> bool {
+        match &self.shared {
+            Some(shared) => shared.generation() != self.generation,
+            None => true,
+        }
+    }
+
+    pub fn resolve(self, value: impl Into<Json>) {
+        self.settle(Ok(value.into()))
+    }
+
+    pub fn reject(self, message: impl fmt::Display) {
+        self.settle(Err(message.to_string()))
+    }
+
+    pub fn settle(mut self, settlement: Settlement) {
+        self.send(settlement)
+    }
+
+    fn send(&mut self, settlement: Settlement) {
+        if let Some(shared) = self.shared.take() {
+            let id = self.id;
+            shared.push(self.generation, |q| q.settled.push((id, settlement)));
+        }
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        self.send(Err("the host dropped the call".to_owned()))
+    }
+}
+
+impl fmt::Debug for Completion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Completion").field("id", &self.id).finish()
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::*;
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::{Result, WebView};
+    use winapi::shared::minwindef::{LPARAM, UINT, WPARAM};
+    use winapi::shared::windef::HWND;
+    use winapi::um::winuser::PostMessageW;
+
+    /// Posts `message` to a window, whose window procedure should then call
+    /// `Promises::flush`.
+    #[derive(Debug, Copy, Clone)]
+    pub struct PostMessageScheduler {
+        hwnd: usize,
+        message: UINT,
+    }
+
+    impl PostMessageScheduler {
+        pub fn new(hwnd: HWND, message: UINT) -> Self {
+            PostMessageScheduler {
+                hwnd: hwnd as usize,
+                message,
+            }
+        }
+    }
+
+    impl Scheduler for PostMessageScheduler {
+        fn schedule(&self) {
+            unsafe { PostMessageW(self.hwnd as HWND, self.message, 0 as WPARAM, 0 as LPARAM) };
+        }
+    }
+
+    impl Promises {
+        /// Add `SCRIPT` to every document `webview` loads, and cancel the
+        /// calls in flight whenever it navigates.
+        pub fn attach(&self, webview: &WebView) -> Result<()> {
+            webview.add_script_to_execute_on_document_created(SCRIPT, |_| Ok(()))?;
+            let promises = self.clone();
+            webview.add_navigation_starting(move |_, _| {
+                promises.cancel();
+                Ok(())
+            })?;
+            Ok(())
+        }
+
+        /// `run`, and execute the scripts in `webview`.
+        pub fn flush(&self, webview: &WebView) -> Result<()> {
+            for script in self.run() {
+                webview.execute_script(&script, |_| Ok(()))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_object::dispatch::InvokeFlags;
+    use crate::host_object::HostObjectBuilder;
+    use crate::util::testing::Rng;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Counts schedule requests instead of waking a real UI thread.
+    #[derive(Clone, Default)]
+    struct FakeScheduler(Arc<AtomicUsize>);
+
+    impl FakeScheduler {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Scheduler for FakeScheduler {
+        fn schedule(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn promises() -> (Promises, FakeScheduler) {
+        let scheduler = FakeScheduler::default();
+        (Promises::new(scheduler.clone()), scheduler)
+    }
+
+    /// A future that waits for a value sent from elsewhere.
+    struct Slot(Rc<RefCell<(Option<Settlement>, Option<Waker>)>>);
+
+    impl Future for Slot {
+        type Output = Settlement;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Settlement> {
+            let mut slot = self.0.borrow_mut();
+            match slot.0.take() {
+                Some(s) => Poll::Ready(s),
+                None => {
+                    slot.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    fn slot() -> (Slot, Rc<RefCell<(Option<Settlement>, Option<Waker>)>>) {
+        let cell = Rc::new(RefCell::new((None, None)));
+        (Slot(cell.clone()), cell)
+    }
+
+    #[test]
+    fn test_spawn_and_defer() {
+        let (promises, scheduler) = promises();
+        let a = promises.spawn(async { Ok::<_, String>(json!({ "a": 1 })) });
+        let (b, completion) = promises.defer();
+        assert_ne!(a, b);
+        assert_eq!(scheduler.count(), 1);
+
+        let worker = thread::spawn(move || completion.resolve("done"));
+        worker.join().unwrap();
+        // Already scheduled.
+        assert_eq!(scheduler.count(), 1);
+
+        assert_eq!(
+            promises.run(),
+            [
+                format!(
+                    "window.chrome.webview.settleHostPromise({}, true, {{\"a\":1}});",
+                    a.0
+                ),
+                format!(
+                    "window.chrome.webview.settleHostPromise({}, true, \"done\");",
+                    b.0
+                ),
+            ]
+        );
+        assert!(promises.run().is_empty());
+        assert_eq!(promises.running(), 0);
+
+        let c = promises.spawn(async { Err::<Json, _>("no \"luck\"") });
+        assert_eq!(scheduler.count(), 2);
+        assert_eq!(
+            promises.run(),
+            [format!(
+                "window.chrome.webview.settleHostPromise({}, false, \"no \\\"luck\\\"\");",
+                c.0
+            )]
+        );
+
+        let (d, completion) = promises.defer();
+        drop(completion);
+        assert_eq!(
+            promises.run(),
+            [format!(
+                "window.chrome.webview.settleHostPromise({}, false, \"the host dropped the call\");",
+                d.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_wake_from_other_threads() {
+        let (promises, scheduler) = promises();
+        let (tx, rx) = mpsc::channel::<Waker>();
+        let (future, cell) = slot();
+        let id = promises.spawn(future);
+        assert!(promises.run().is_empty());
+        assert_eq!(promises.running(), 1);
+
+        cell.borrow_mut().0 = Some(Ok(json!(7)));
+        let waker = cell.borrow_mut().1.take().unwrap();
+        tx.send(waker).unwrap();
+        thread::spawn(move || rx.recv().unwrap().wake())
+            .join()
+            .unwrap();
+        assert_eq!(scheduler.count(), 2);
+        assert_eq!(
+            promises.run(),
+            [format!(
+                "window.chrome.webview.settleHostPromise({}, true, 7);",
+                id.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (promises, _) = promises();
+        let (future, cell) = slot();
+        promises.spawn(future);
+        let (_, completion) = promises.defer();
+        let (_, settled) = promises.defer();
+        settled.resolve(1);
+        promises.run();
+        assert_eq!(promises.running(), 1);
+        assert!(!completion.is_cancelled());
+
+        promises.cancel();
+        assert_eq!(promises.running(), 0);
+        assert!(completion.is_cancelled());
+        // Stale wake ups and settlements are ignored.
+        cell.borrow_mut().1.take().unwrap().wake();
+        completion.resolve(2);
+        assert!(promises.run().is_empty());
+
+        let (after, completion) = promises.defer();
+        completion.resolve(3);
+        assert_eq!(
+            promises.run(),
+            [format!(
+                "window.chrome.webview.settleHostPromise({}, true, 3);",
+                after.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_host_methods() {
+        let (promises, _) = promises();
+        let (p, q) = (promises.clone(), promises.clone());
+        let (tx, rx) = mpsc::channel();
+        let object = HostObjectBuilder::new()
+            .with_method("double", move |n: i32| {
+                p.spawn(async move { Ok::<_, String>(n * 2) })
+            })
+            .with_method("later", move |s: String| {
+                let (pending, completion) = q.defer();
+                tx.send((s, completion)).unwrap();
+                pending
+            })
+            .build();
+        let table = object.table();
+        let call = |name: &str, arg: Value| {
+            let id = table.id_of_name(name).unwrap();
+            table.invoke(id, InvokeFlags::METHOD, vec![arg]).unwrap()
+        };
+
+        assert_eq!(call("double", Value::I32(21)), Value::F64(1.0));
+        assert_eq!(
+            call("later", Value::String("x".to_owned())),
+            Value::F64(2.0)
+        );
+        let (s, completion) = rx.recv().unwrap();
+        thread::spawn(move || completion.resolve(s + "y"))
+            .join()
+            .unwrap();
+        assert_eq!(
+            promises.run(),
+            [
+                "window.chrome.webview.settleHostPromise(1, true, 42);",
+                "window.chrome.webview.settleHostPromise(2, true, \"xy\");",
+            ]
+        );
+    }
+
+    /// Random interleavings of starting, waking, settling and cancelling:
+    /// every call that started after the last cancel is settled exactly once.
+    #[test]
+    fn test_random_schedules() {
+        let mut rng = Rng(35);
+        for _ in 0..2000 {
+            let (promises, scheduler) = promises();
+            let mut slots = Vec::new();
+            let mut completions = Vec::new();
+            let mut live: Vec<CallId> = Vec::new();
+            let mut settled: Vec<CallId> = Vec::new();
+            let mut runs = 0;
+            for _ in 0..rng.range(1, 30) {
+                match rng.range(0, 6) {
+                    0 => {
+                        let (future, cell) = slot();
+                        live.push(promises.spawn(future).0);
+                        slots.push(cell);
+                    }
+                    1 => {
+                        let (pending, completion) = promises.defer();
+                        live.push(pending.0);
+                        completions.push(completion);
+                    }
+                    2 if !slots.is_empty() => {
+                        let cell = slots.swap_remove(rng.range(0, slots.len() as i32) as usize);
+                        let waker = cell.borrow_mut().1.take();
+                        cell.borrow_mut().0 = Some(Ok(json!(1)));
+                        if let Some(waker) = waker {
+                            waker.wake();
+                        }
+                    }
+                    3 if !completions.is_empty() => {
+                        let completion = completions
+                            .swap_remove(rng.range(0, completions.len() as i32) as usize);
+                        if rng.bool() {
+                            completion.resolve(1);
+                        } else {
+                            drop(completion);
+                        }
+                    }
+                    4 => {
+                        promises.cancel();
+                        live.clear();
+                        slots.clear();
+                    }
+                    _ => {
+                        runs += 1;
+                        for script in promises.run() {
+                            let id = script
+                                .trim_start_matches("window.chrome.webview.settleHostPromise(")
+                                .split(',')
+                                .next()
+                                .unwrap()
+                                .parse::<CallId>()
+                                .unwrap();
+                            assert!(live.contains(&id), "{} settled after cancel", id);
+                            assert!(!settled.contains(&id), "{} settled twice", id);
+                            settled.push(id);
+                        }
+                    }
+                }
+                // Never more schedule requests than runs can answer.
+                assert!(scheduler.count() <= runs + 1);
+            }
+        }
+    }
+}
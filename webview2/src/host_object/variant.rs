@@ -0,0 +1,822 @@
+//! A safe model of everything a `VARIANT` can hold.
+//!
+//! `VariantValue` owns its data, so it can be inspected, compared and
+//! converted without any `unsafe`. The conversions to and from `VARIANT`
+//! live with the rest of the COM code (`host_object::Variant`); those to and
+//! from Rust types, JSON and dispatch `Value`s are here.
+
+use super::dispatch::{HostObject, Mismatch, Value};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+#[cfg(windows)]
+use windows::{core::IUnknown, Win32::System::Com::IDispatch};
+
+/// The `SCODE` of the `VT_ERROR` passed in place of a missing optional
+/// argument.
+pub const DISP_E_PARAMNOTFOUND: i32 = 0x8002_0004_u32 as i32;
+
+/// A `VARTYPE`, e.g. `VarType::I4` or `VarType::ARRAY | VarType::BSTR`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VarType(pub u16);
+
+impl VarType {
+    pub const EMPTY: VarType = VarType(0);
+    pub const NULL: VarType = VarType(1);
+    pub const I2: VarType = VarType(2);
+    pub const I4: VarType = VarType(3);
+    pub const R4: VarType = VarType(4);
+    pub const R8: VarType = VarType(5);
+    pub const CY: VarType = VarType(6);
+    pub const DATE: VarType = VarType(7);
+    pub const BSTR: VarType = VarType(8);
+    pub const DISPATCH: VarType = VarType(9);
+    pub const ERROR: VarType = VarType(10);
+    pub const BOOL: VarType = VarType(11);
+    pub const VARIANT: VarType = VarType(12);
+    pub const UNKNOWN: VarType = VarType(13);
+    pub const DECIMAL: VarType = VarType(14);
+    pub const I1: VarType = VarType(16);
+    pub const UI1: VarType = VarType(17);
+    pub const UI2: VarType = VarType(18);
+    pub const UI4: VarType = VarType(19);
+    pub const I8: VarType = VarType(20);
+    pub const UI8: VarType = VarType(21);
+    pub const INT: VarType = VarType(22);
+    pub const UINT: VarType = VarType(23);
+    pub const ARRAY: VarType = VarType(0x2000);
+    pub const BYREF: VarType = VarType(0x4000);
+
+    /// The type without the `ARRAY` and `BYREF` flags.
+    pub fn base(self) -> VarType {
+        VarType(self.0 & 0x0fff)
+    }
+
+    pub fn is_array(self) -> bool {
+        self.0 & Self::ARRAY.0 != 0
+    }
+
+    pub fn is_byref(self) -> bool {
+        self.0 & Self::BYREF.0 != 0
+    }
+}
+
+impl std::ops::BitOr for VarType {
+    type Output = VarType;
+
+    fn bitor(self, other: VarType) -> VarType {
+        VarType(self.0 | other.0)
+    }
+}
+
+/// A `DECIMAL`: a 96 bit integer scaled down by a power of ten.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: u128,
+    scale: u8,
+    negative: bool,
+}
+
+impl Decimal {
+    pub const MAX_SCALE: u8 = 28;
+    pub const MAX_MANTISSA: u128 = (1 << 96) - 1;
+
+    /// `None` if the mantissa needs more than 96 bits or the scale is above
+    /// 28.
+    pub fn new(mantissa: u128, scale: u8, negative: bool) -> Option<Decimal> {
+        if mantissa > Self::MAX_MANTISSA || scale > Self::MAX_SCALE {
+            return None;
+        }
+        Some(Decimal {
+            mantissa,
+            scale,
+            negative,
+        })
+    }
+
+    pub fn mantissa(&self) -> u128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// The nearest `f64`. Precision is lost beyond 15 or so digits.
+    pub fn to_f64(&self) -> f64 {
+        let value = self.mantissa as f64 / 10f64.powi(self.scale as i32);
+        if self.negative {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+/// Exact, e.g. `-12.500` for mantissa 12500, scale 3.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        write_scaled(f, sign, self.mantissa, self.scale as usize)
+    }
+}
+
+fn write_scaled(f: &mut impl fmt::Write, sign: &str, mantissa: u128, scale: usize) -> fmt::Result {
+    let digits = format!("{:0width$}", mantissa, width = scale + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    if scale == 0 {
+        write!(f, "{}{}", sign, int)
+    } else {
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+/// A one dimensional `SAFEARRAY`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafeArray {
+    element: VarType,
+    lower_bound: i32,
+    elements: Vec<VariantValue>,
+}
+
+impl SafeArray {
+    /// An array of `VARIANT`s, which may hold anything, indexed from 0.
+    pub fn new(elements: Vec<VariantValue>) -> SafeArray {
+        SafeArray {
+            element: VarType::VARIANT,
+            lower_bound: 0,
+            elements,
+        }
+    }
+
+    /// An array of `element`s indexed from `lower_bound`. Every element must
+    /// be of that type, unless it is `VarType::VARIANT`.
+    pub fn typed(
+        element: VarType,
+        lower_bound: i32,
+        elements: Vec<VariantValue>,
+    ) -> Result<SafeArray, VariantError> {
+        if element != VarType::VARIANT {
+            if let Some(e) = elements.iter().find(|e| e.var_type() != element) {
+                return Err(VariantError::TypeMismatch {
+                    expected: element,
+                    found: e.var_type(),
+                });
+            }
+        }
+        Ok(SafeArray {
+            element,
+            lower_bound,
+            elements,
+        })
+    }
+
+    pub fn element_type(&self) -> VarType {
+        self.element
+    }
+
+    pub fn lower_bound(&self) -> i32 {
+        self.lower_bound
+    }
+
+    pub fn elements(&self) -> &[VariantValue] {
+        &self.elements
+    }
+
+    pub fn into_elements(self) -> Vec<VariantValue> {
+        self.elements
+    }
+}
+
+/// The contents of a `VARIANT`, one variant per `VT_` type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantValue {
+    Empty,
+    Null,
+    Bool(bool),
+    I1(i8),
+    I2(i16),
+    I4(i32),
+    I8(i64),
+    UI1(u8),
+    UI2(u16),
+    UI4(u32),
+    UI8(u64),
+    Int(i32),
+    UInt(u32),
+    R4(f32),
+    R8(f64),
+    BStr(String),
+    /// An OLE automation date: days since 1899-12-30.
+    Date(f64),
+    /// Currency, in ten-thousandths.
+    Cy(i64),
+    Decimal(Decimal),
+    /// An `SCODE`. Also how a missing optional argument is passed, as
+    /// `DISP_E_PARAMNOTFOUND`.
+    Error(i32),
+    Array(SafeArray),
+    /// What a `VT_BYREF` variant points to. Only ever read from a `VARIANT`;
+    /// a `VARIANT` can't be made from it because nothing would own the
+    /// referent.
+    ByRef(Box<VariantValue>),
+    /// A host object. Becomes a `VT_DISPATCH` on the way to a `VARIANT`, and
+    /// is read back from one made by `HostObject::to_dispatch`.
+    Object(HostObject),
+    #[cfg(windows)]
+    Dispatch(Option<IDispatch>),
+    #[cfg(windows)]
+    Unknown(Option<IUnknown>),
+}
+
+impl VariantValue {
+    pub fn var_type(&self) -> VarType {
+        match self {
+            VariantValue::Empty => VarType::EMPTY,
+            VariantValue::Null => VarType::NULL,
+            VariantValue::Bool(_) => VarType::BOOL,
+            VariantValue::I1(_) => VarType::I1,
+            VariantValue::I2(_) => VarType::I2,
+            VariantValue::I4(_) => VarType::I4,
+            VariantValue::I8(_) => VarType::I8,
+            VariantValue::UI1(_) => VarType::UI1,
+            VariantValue::UI2(_) => VarType::UI2,
+            VariantValue::UI4(_) => VarType::UI4,
+            VariantValue::UI8(_) => VarType::UI8,
+            VariantValue::Int(_) => VarType::INT,
+            VariantValue::UInt(_) => VarType::UINT,
+            VariantValue::R4(_) => VarType::R4,
+            VariantValue::R8(_) => VarType::R8,
+            VariantValue::BStr(_) => VarType::BSTR,
+            VariantValue::Date(_) => VarType::DATE,
+            VariantValue::Cy(_) => VarType::CY,
+            VariantValue::Decimal(_) => VarType::DECIMAL,
+            VariantValue::Error(_) => VarType::ERROR,
+            VariantValue::Array(array) => VarType::ARRAY | array.element,
+            VariantValue::ByRef(value) => VarType::BYREF | value.var_type(),
+            VariantValue::Object(_) => VarType::DISPATCH,
+            #[cfg(windows)]
+            VariantValue::Dispatch(_) => VarType::DISPATCH,
+            #[cfg(windows)]
+            VariantValue::Unknown(_) => VarType::UNKNOWN,
+        }
+    }
+
+    /// Follow `ByRef`s to the value.
+    pub fn dereferenced(&self) -> &VariantValue {
+        match self {
+            VariantValue::ByRef(value) => value.dereferenced(),
+            value => value,
+        }
+    }
+
+    fn integer(&self) -> Option<i128> {
+        Some(match *self.dereferenced() {
+            VariantValue::I1(i) => i as i128,
+            VariantValue::I2(i) => i as i128,
+            VariantValue::I4(i) | VariantValue::Int(i) => i as i128,
+            VariantValue::I8(i) => i as i128,
+            VariantValue::UI1(i) => i as i128,
+            VariantValue::UI2(i) => i as i128,
+            VariantValue::UI4(i) | VariantValue::UInt(i) => i as i128,
+            VariantValue::UI8(i) => i as i128,
+            _ => return None,
+        })
+    }
+
+    fn mismatch(&self, expected: VarType) -> VariantError {
+        VariantError::TypeMismatch {
+            expected,
+            found: self.dereferenced().var_type(),
+        }
+    }
+
+    /// Convert to JSON.
+    ///
+    /// Currency and decimals become strings so no digits are lost. Fails on
+    /// objects, error codes and non-finite numbers.
+    pub fn to_json(&self) -> Result<serde_json::Value, VariantError> {
+        use serde_json::Value as Json;
+
+        let number = |f: f64| {
+            serde_json::Number::from_f64(f)
+                .map(Json::Number)
+                .ok_or(VariantError::NotRepresentable("a non-finite number"))
+        };
+        Ok(match self.dereferenced() {
+            VariantValue::Empty | VariantValue::Null => Json::Null,
+            VariantValue::Bool(b) => Json::Bool(*b),
+            VariantValue::R4(f) => number(*f as f64)?,
+            VariantValue::R8(f) | VariantValue::Date(f) => number(*f)?,
+            VariantValue::BStr(s) => Json::String(s.clone()),
+            VariantValue::Cy(cy) => {
+                let mut s = String::new();
+                let sign = if *cy < 0 { "-" } else { "" };
+                write_scaled(&mut s, sign, cy.unsigned_abs() as u128, 4).unwrap();
+                Json::String(s)
+            }
+            VariantValue::Decimal(d) => Json::String(d.to_string()),
+            VariantValue::Array(array) => Json::Array(
+                array
+                    .elements
+                    .iter()
+                    .map(VariantValue::to_json)
+                    .collect::<Result<_, _>>()?,
+            ),
+            VariantValue::Error(_) => return Err(VariantError::NotRepresentable("an error code")),
+            value => match value.integer() {
+                Some(i) if i < 0 => Json::from(i as i64),
+                Some(i) => Json::from(i as u64),
+                None => return Err(VariantError::NotRepresentable("an object")),
+            },
+        })
+    }
+
+    /// Convert from JSON. Integers become `I4` if they fit, else `I8` or
+    /// `UI8`; other numbers `R8`; arrays `VARIANT` arrays. Fails on objects.
+    pub fn from_json(json: &serde_json::Value) -> Result<VariantValue, VariantError> {
+        use serde_json::Value as Json;
+
+        Ok(match json {
+            Json::Null => VariantValue::Null,
+            Json::Bool(b) => VariantValue::Bool(*b),
+            Json::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => i32::try_from(i).map_or(VariantValue::I8(i), VariantValue::I4),
+                (None, Some(u)) => VariantValue::UI8(u),
+                _ => VariantValue::R8(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(s) => VariantValue::BStr(s.clone()),
+            Json::Array(values) => VariantValue::Array(SafeArray::new(
+                values
+                    .iter()
+                    .map(VariantValue::from_json)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Json::Object(_) => return Err(VariantError::NotRepresentable("an object")),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VariantError {
+    /// The `VARIANT` holds a type this model has no variant for, like
+    /// `VT_RECORD`.
+    UnsupportedType(VarType),
+    TypeMismatch {
+        expected: VarType,
+        found: VarType,
+    },
+    /// The value does not fit the target type.
+    Overflow,
+    /// Only one dimensional arrays are supported.
+    MultiDimensional,
+    /// See `VariantValue::ByRef`.
+    ByRef,
+    /// The value has no counterpart in the target, e.g. an object in JSON.
+    NotRepresentable(&'static str),
+    /// A COM call failed with this `HRESULT`.
+    Failed(i32),
+}
+
+impl fmt::Display for VariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariantError::UnsupportedType(vt) => write!(f, "unsupported VARTYPE {:#x}", vt.0),
+            VariantError::TypeMismatch { expected, found } => write!(
+                f,
+                "expected VARTYPE {:#x}, found {:#x}",
+                expected.0, found.0
+            ),
+            VariantError::Overflow => f.write_str("value out of range"),
+            VariantError::MultiDimensional => f.write_str("multi-dimensional SAFEARRAY"),
+            VariantError::ByRef => f.write_str("cannot own a VT_BYREF value"),
+            VariantError::NotRepresentable(what) => write!(f, "cannot represent {}", what),
+            VariantError::Failed(hr) => write!(f, "HRESULT {:#010x}", hr),
+        }
+    }
+}
+
+impl std::error::Error for VariantError {}
+
+macro_rules! impl_integer {
+    ($($ty:ty => $variant:ident,)*) => {
+        $(
+            impl From<$ty> for VariantValue {
+                fn from(value: $ty) -> VariantValue {
+                    VariantValue::$variant(value)
+                }
+            }
+
+            /// Any integer variant, if the value fits.
+            impl TryFrom<VariantValue> for $ty {
+                type Error = VariantError;
+
+                fn try_from(value: VariantValue) -> Result<$ty, VariantError> {
+                    let i = value
+                        .integer()
+                        .ok_or_else(|| value.mismatch(VarType::$variant))?;
+                    i.try_into().map_err(|_| VariantError::Overflow)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer! {
+    i8 => I1,
+    i16 => I2,
+    i32 => I4,
+    i64 => I8,
+    u8 => UI1,
+    u16 => UI2,
+    u32 => UI4,
+    u64 => UI8,
+}
+
+impl From<bool> for VariantValue {
+    fn from(value: bool) -> VariantValue {
+        VariantValue::Bool(value)
+    }
+}
+
+impl TryFrom<VariantValue> for bool {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> Result<bool, VariantError> {
+        match *value.dereferenced() {
+            VariantValue::Bool(b) => Ok(b),
+            _ => Err(value.mismatch(VarType::BOOL)),
+        }
+    }
+}
+
+impl From<f32> for VariantValue {
+    fn from(value: f32) -> VariantValue {
+        VariantValue::R4(value)
+    }
+}
+
+impl TryFrom<VariantValue> for f32 {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> Result<f32, VariantError> {
+        match *value.dereferenced() {
+            VariantValue::R4(f) => Ok(f),
+            _ => Err(value.mismatch(VarType::R4)),
+        }
+    }
+}
+
+impl From<f64> for VariantValue {
+    fn from(value: f64) -> VariantValue {
+        VariantValue::R8(value)
+    }
+}
+
+/// `R4`, `R8`, or an integer that converts exactly.
+impl TryFrom<VariantValue> for f64 {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> Result<f64, VariantError> {
+        match *value.dereferenced() {
+            VariantValue::R4(f) => Ok(f as f64),
+            VariantValue::R8(f) => Ok(f),
+            _ => match value.integer() {
+                Some(i) if i as f64 as i128 == i => Ok(i as f64),
+                Some(_) => Err(VariantError::Overflow),
+                None => Err(value.mismatch(VarType::R8)),
+            },
+        }
+    }
+}
+
+impl From<String> for VariantValue {
+    fn from(value: String) -> VariantValue {
+        VariantValue::BStr(value)
+    }
+}
+
+impl From<&str> for VariantValue {
+    fn from(value: &str) -> VariantValue {
+        VariantValue::BStr(value.to_owned())
+    }
+}
+
+impl TryFrom<VariantValue> for String {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> Result<String, VariantError> {
+        match value {
+            VariantValue::BStr(s) => Ok(s),
+            VariantValue::ByRef(value) => String::try_from(*value),
+            value => Err(value.mismatch(VarType::BSTR)),
+        }
+    }
+}
+
+impl From<Decimal> for VariantValue {
+    fn from(value: Decimal) -> VariantValue {
+        VariantValue::Decimal(value)
+    }
+}
+
+impl TryFrom<VariantValue> for Decimal {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> Result<Decimal, VariantError> {
+        match *value.dereferenced() {
+            VariantValue::Decimal(d) => Ok(d),
+            _ => Err(value.mismatch(VarType::DECIMAL)),
+        }
+    }
+}
+
+impl From<SafeArray> for VariantValue {
+    fn from(value: SafeArray) -> VariantValue {
+        VariantValue::Array(value)
+    }
+}
+
+impl From<Vec<VariantValue>> for VariantValue {
+    fn from(value: Vec<VariantValue>) -> VariantValue {
+        VariantValue::Array(SafeArray::new(value))
+    }
+}
+
+impl TryFrom<VariantValue> for SafeArray {
+    type Error = VariantError;
+
+    fn try_from(value: VariantValue) -> Result<SafeArray, VariantError> {
+        match value {
+            VariantValue::Array(array) => Ok(array),
+            VariantValue::ByRef(value) => SafeArray::try_from(*value),
+            value => Err(value.mismatch(VarType::ARRAY | VarType::VARIANT)),
+        }
+    }
+}
+
+impl From<HostObject> for VariantValue {
+    fn from(value: HostObject) -> VariantValue {
+        VariantValue::Object(value)
+    }
+}
+
+impl From<Value> for VariantValue {
+    fn from(value: Value) -> VariantValue {
+        match value {
+            Value::Empty => VariantValue::Empty,
+            Value::Bool(b) => VariantValue::Bool(b),
+            Value::I32(i) => VariantValue::I4(i),
+            Value::F64(f) => VariantValue::R8(f),
+            Value::String(s) => VariantValue::BStr(s),
+            Value::Array(values) => VariantValue::from(
+                values
+                    .into_iter()
+                    .map(VariantValue::from)
+                    .collect::<Vec<_>>(),
+            ),
+            Value::Object(object) => VariantValue::Object(object),
+        }
+    }
+}
+
+/// What host object members see. Integers that don't fit an `i32`, dates,
+/// currency and decimals arrive as numbers. Missing optional arguments arrive
+/// as `Value::Empty`. Objects other than host objects are not supported.
+impl TryFrom<VariantValue> for Value {
+    type Error = Mismatch;
+
+    fn try_from(value: VariantValue) -> Result<Value, Mismatch> {
+        Ok(match value {
+            VariantValue::Empty | VariantValue::Null => Value::Empty,
+            VariantValue::Error(DISP_E_PARAMNOTFOUND) => Value::Empty,
+            VariantValue::Bool(b) => Value::Bool(b),
+            VariantValue::R4(f) => Value::F64(f as f64),
+            VariantValue::R8(f) | VariantValue::Date(f) => Value::F64(f),
+            VariantValue::Cy(cy) => Value::F64(cy as f64 / 10_000.0),
+            VariantValue::Decimal(d) => Value::F64(d.to_f64()),
+            VariantValue::BStr(s) => Value::String(s),
+            VariantValue::Array(array) => Value::Array(
+                array
+                    .elements
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            VariantValue::ByRef(value) => Value::try_from(*value)?,
+            VariantValue::Object(object) => Value::Object(object),
+            #[cfg(windows)]
+            VariantValue::Dispatch(None) => Value::Empty,
+            value => match value.integer() {
+                Some(i) => i32::try_from(i).map_or(Value::F64(i as f64), Value::I32),
+                None => return Err(Mismatch::Type),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::Rng;
+
+    fn random_integer(rng: &mut Rng) -> VariantValue {
+        let bits = rng.next();
+        match rng.range(0, 10) {
+            0 => VariantValue::I1(bits as i8),
+            1 => VariantValue::I2(bits as i16),
+            2 => VariantValue::I4(bits as i32),
+            3 => VariantValue::I8(bits as i64),
+            4 => VariantValue::UI1(bits as u8),
+            5 => VariantValue::UI2(bits as u16),
+            6 => VariantValue::UI4(bits as u32),
+            7 => VariantValue::UI8(bits),
+            8 => VariantValue::Int(bits as i32),
+            _ => VariantValue::UInt(bits as u32),
+        }
+    }
+
+    // Values that survive a JSON round trip unchanged.
+    fn random_json_value(rng: &mut Rng, depth: u32) -> VariantValue {
+        match rng.range(0, if depth > 2 { 6 } else { 7 }) {
+            0 => VariantValue::Null,
+            1 => VariantValue::Bool(rng.bool()),
+            2 => VariantValue::I4(rng.next() as i32),
+            3 => {
+                let i = rng.next() as i64;
+                i32::try_from(i).map_or(VariantValue::I8(i), VariantValue::I4)
+            }
+            4 => VariantValue::R8((rng.next() as i16) as f64 / 64.0 + 0.5),
+            5 => {
+                let len = rng.range(0, 8) as usize;
+                VariantValue::BStr(
+                    (0..len)
+                        .map(|_| rng.pick(&['a', 'é', '"', '\\', '😀']))
+                        .collect(),
+                )
+            }
+            _ => {
+                let len = rng.range(0, 4);
+                VariantValue::from(
+                    (0..len)
+                        .map(|_| random_json_value(rng, depth + 1))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        }
+    }
+
+    #[test]
+    fn test_integer_conversions_are_lossless() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let value = random_integer(&mut rng);
+            let exact = value.integer().unwrap();
+
+            // Each target either holds the exact value or reports overflow.
+            macro_rules! check {
+                ($($ty:ty),*) => {$(
+                    match <$ty>::try_from(value.clone()) {
+                        Ok(v) => assert_eq!(v as i128, exact, "{:?}", value),
+                        Err(e) => {
+                            assert_eq!(e, VariantError::Overflow);
+                            assert!(<$ty>::try_from(exact).is_err());
+                        }
+                    }
+                )*};
+            }
+            check!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+            match f64::try_from(value.clone()) {
+                Ok(f) => assert_eq!(f as i128, exact),
+                Err(e) => assert_eq!(e, VariantError::Overflow),
+            }
+            assert!(matches!(
+                String::try_from(value.clone()),
+                Err(VariantError::TypeMismatch { .. })
+            ));
+            let by_ref = VariantValue::ByRef(Box::new(value.clone()));
+            assert_eq!(by_ref.integer(), Some(exact));
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let mut rng = Rng(42);
+        for _ in 0..2000 {
+            let bits = rng.next();
+            assert_eq!(
+                i64::try_from(VariantValue::from(bits as i64)),
+                Ok(bits as i64)
+            );
+            assert_eq!(u64::try_from(VariantValue::from(bits)), Ok(bits));
+            let f = f64::from_bits(bits);
+            if !f.is_nan() {
+                assert_eq!(f64::try_from(VariantValue::from(f)), Ok(f));
+            }
+            let g = f32::from_bits(bits as u32);
+            if !g.is_nan() {
+                assert_eq!(f32::try_from(VariantValue::from(g)), Ok(g));
+                assert_eq!(f64::try_from(VariantValue::from(g)), Ok(g as f64));
+            }
+            let s = format!("{:x}", bits);
+            assert_eq!(String::try_from(VariantValue::from(s.as_str())), Ok(s));
+        }
+        assert_eq!(bool::try_from(VariantValue::from(true)), Ok(true));
+        assert!(f32::try_from(VariantValue::R8(1.0)).is_err());
+        assert!(i32::try_from(VariantValue::R8(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let mut rng = Rng(7);
+        for _ in 0..2000 {
+            let value = random_json_value(&mut rng, 0);
+            let json = value.to_json().unwrap();
+            assert_eq!(VariantValue::from_json(&json), Ok(value.clone()));
+            let text = serde_json::to_string(&json).unwrap();
+            let parsed: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(VariantValue::from_json(&parsed), Ok(value));
+        }
+
+        assert_eq!(
+            VariantValue::Cy(-12_345).to_json(),
+            Ok(serde_json::json!("-1.2345"))
+        );
+        let d = Decimal::new(Decimal::MAX_MANTISSA, 28, false).unwrap();
+        assert_eq!(
+            VariantValue::from(d).to_json(),
+            Ok(serde_json::json!("7.9228162514264337593543950335"))
+        );
+        assert_eq!(
+            VariantValue::UI8(u64::MAX).to_json(),
+            Ok(serde_json::json!(u64::MAX))
+        );
+        assert!(VariantValue::R8(f64::INFINITY).to_json().is_err());
+        assert!(VariantValue::from_json(&serde_json::json!({ "a": 1 })).is_err());
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(
+            Decimal::new(12_500, 3, true).unwrap().to_string(),
+            "-12.500"
+        );
+        assert_eq!(Decimal::new(5, 2, false).unwrap().to_string(), "0.05");
+        assert_eq!(Decimal::new(5, 0, false).unwrap().to_string(), "5");
+        assert_eq!(Decimal::new(1 << 96, 0, false), None);
+        assert_eq!(Decimal::new(1, 29, false), None);
+
+        let mut rng = Rng(3);
+        for _ in 0..2000 {
+            let mantissa = (rng.next() as u128) << 32 | rng.next() as u128 & 0xffff_ffff;
+            let scale = rng.range(0, 29) as u8;
+            let d = Decimal::new(mantissa, scale, rng.bool()).unwrap();
+            let parsed: f64 = d.to_string().parse().unwrap();
+            assert!((parsed - d.to_f64()).abs() <= parsed.abs() * 1e-12);
+            assert_eq!(Decimal::try_from(VariantValue::from(d)), Ok(d));
+        }
+    }
+
+    #[test]
+    fn test_dispatch_values() {
+        let mut rng = Rng(11);
+        for _ in 0..2000 {
+            let value = random_json_value(&mut rng, 0);
+            // Through a dispatch `Value` and back, integers that fit an `i32`
+            // come back as `I4`, as do all other numbers as `R8`.
+            let dispatch = Value::try_from(value.clone()).unwrap();
+            let back = VariantValue::from(dispatch.clone());
+            assert_eq!(Value::try_from(back), Ok(dispatch));
+        }
+
+        assert_eq!(
+            Value::try_from(VariantValue::UI8(u64::MAX)),
+            Ok(Value::F64(u64::MAX as f64))
+        );
+        assert_eq!(
+            Value::try_from(VariantValue::Cy(25_000)),
+            Ok(Value::F64(2.5))
+        );
+        assert_eq!(Value::try_from(VariantValue::Error(0)), Err(Mismatch::Type));
+        assert_eq!(
+            Value::try_from(VariantValue::Error(DISP_E_PARAMNOTFOUND)),
+            Ok(Value::Empty)
+        );
+        assert_eq!(
+            SafeArray::typed(VarType::I4, 0, vec![VariantValue::I2(1)]),
+            Err(VariantError::TypeMismatch {
+                expected: VarType::I4,
+                found: VarType::I2
+            })
+        );
+        assert_eq!(
+            VariantValue::ByRef(Box::new(VariantValue::from(vec![]))).var_type(),
+            VarType::BYREF | VarType::ARRAY | VarType::VARIANT
+        );
+    }
+}
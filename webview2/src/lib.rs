@@ -49,44 +49,97 @@ This brings some complexities:
   between Visual Studio 2015, 2017, and
   2019](https://docs.microsoft.com/en-us/cpp/porting/binary-compat-2015-2017?view=vs-2019).
 
+# Cargo features
+
+The bindings need no features. Helpers that need extra dependencies are
+behind their own:
+
+* `assets`: serving files from a directory or an archive.
+* `web_resource`: the HTTP model of web resources and the router.
+* `policy`: the URL policy and the content blocker.
+* `rewrite`: rewriting responses.
+* `security`: security headers and script nonces.
+* `cache`: the offline cache.
+
 # Examples
 
 See the `examples` directory, especially the heavily commented `win32` example.
 "###]
-#![cfg(windows)]
 // Caused by the `com_interface` macro.
 #![allow(clippy::cmp_null)]
 #![allow(clippy::type_complexity)]
 #![allow(clippy::upper_case_acronyms)]
 #![allow(non_camel_case_types)]
 
+// Modules keep their Windows parts behind `#[cfg(windows)]`, so the rest of
+// them builds and is tested on any host.
+#[cfg(feature = "assets")]
+pub mod assets;
+#[cfg(feature = "policy")]
+pub mod blocker;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod config;
+pub mod dpi;
+pub mod frames;
+pub mod geometry;
 pub mod host_object;
+pub mod messaging;
+#[cfg(feature = "policy")]
+pub mod policy;
+#[cfg(feature = "rewrite")]
+pub mod rewrite;
+#[cfg(feature = "web_resource")]
+pub mod router;
+pub mod rpc;
+#[cfg(feature = "security")]
+pub mod security;
+pub mod stream;
 pub mod util;
+#[cfg(feature = "web_resource")]
+pub mod web_resource;
 
+#[cfg(windows)]
 use com::{interfaces::IUnknown, ComInterface, ComPtr, ComRc};
+#[cfg(windows)]
 use std::cell::{Cell, RefCell};
+#[cfg(windows)]
 use std::fmt;
+#[cfg(windows)]
 use std::io;
+#[cfg(windows)]
 use std::mem::{self, MaybeUninit};
+#[cfg(windows)]
 use std::path::Path;
+#[cfg(windows)]
 use std::ptr;
+#[cfg(windows)]
 use webview2_sys::*;
+#[cfg(windows)]
 use widestring::{NulError, WideCStr, WideCString};
+#[cfg(windows)]
 use winapi::shared::minwindef::*;
+#[cfg(windows)]
 use winapi::shared::ntdef::*;
+#[cfg(windows)]
 use winapi::shared::windef::*;
+#[cfg(windows)]
 use winapi::shared::winerror::{
-    E_FAIL, E_INVALIDARG, E_NOINTERFACE, FACILITY_WIN32, HRESULT_CODE, HRESULT_FROM_WIN32,
-    MAKE_HRESULT, SEVERITY_ERROR, SUCCEEDED, S_OK,
+    ERROR_NO_DATA, E_FAIL, E_INVALIDARG, E_NOINTERFACE, FACILITY_WIN32, HRESULT_CODE,
+    HRESULT_FROM_WIN32, MAKE_HRESULT, SEVERITY_ERROR, SUCCEEDED, S_OK,
 };
+#[cfg(windows)]
 use winapi::um::combaseapi::{CoTaskMemAlloc, CoTaskMemFree};
+#[cfg(windows)]
 use windows::Win32::System::Variant::VARIANT;
 
+#[cfg(windows)]
 static DEFAULT_TARGET_COMPATIBLE_BROWSER_VERSION: &str = "89.0.765";
 
 /// Returns a pointer that implements the COM callback interface with the specified closure.
 /// Inspired by C++ Microsoft::WRT::Callback.
 #[macro_export]
+#[cfg(windows)]
 macro_rules! callback {
     ($name:ident, move | $($arg:ident : $arg_type:ty),* $(,)?| -> $ret_type:ty { $($body:tt)* }) => {{
         #[com::co_class(implements($name))]
@@ -131,6 +184,7 @@ macro_rules! callback {
 }
 
 // Call `AddRef` and convert to `ComRc`.
+#[cfg(windows)]
 unsafe fn add_ref_to_rc<T: ComInterface + ?Sized>(
     ptr: *mut *mut <T as ComInterface>::VTable,
 ) -> ComRc<T> {
@@ -139,10 +193,12 @@ unsafe fn add_ref_to_rc<T: ComInterface + ?Sized>(
     ptr.upgrade()
 }
 
+#[cfg(windows)]
 include!("interfaces.rs");
 
 // Put it in a module so that the `EnvironmentOptionsImplClassFactory` struct
 // does not leak into our public API.
+#[cfg(windows)]
 mod environment_options {
     use super::*;
 
@@ -278,6 +334,7 @@ mod environment_options {
     }
 }
 
+#[cfg(windows)]
 pub fn get_available_browser_version_string(
     browser_executable_folder: Option<&Path>,
 ) -> Result<String> {
@@ -305,6 +362,7 @@ pub fn get_available_browser_version_string(
     result1
 }
 
+#[cfg(windows)]
 pub fn compare_browser_versions(version1: &str, version2: &str) -> Result<std::cmp::Ordering> {
     let version1 = WideCString::from_str(version1)?;
     let version2 = WideCString::from_str(version2)?;
@@ -323,6 +381,7 @@ pub fn compare_browser_versions(version1: &str, version2: &str) -> Result<std::c
 ///
 /// Use [Environment::builder()](./struct.Environment.html#method.builder) to create one.
 #[derive(Default)]
+#[cfg(windows)]
 pub struct EnvironmentBuilder<'a> {
     browser_executable_folder: Option<&'a Path>,
     user_data_folder: Option<&'a Path>,
@@ -332,6 +391,7 @@ pub struct EnvironmentBuilder<'a> {
     allow_single_sign_on_using_osprimary_account: bool,
 }
 
+#[cfg(windows)]
 impl<'a> EnvironmentBuilder<'a> {
     // Hidden. Prefer `Environment::builder()`.
     #[doc(hidden)]
@@ -450,6 +510,7 @@ impl<'a> EnvironmentBuilder<'a> {
     }
 }
 
+#[cfg(windows)]
 macro_rules! get {
     ($get_method:ident, $T: ident) => {
         pub fn $get_method(&self) -> Result<$T> {
@@ -460,6 +521,7 @@ macro_rules! get {
     };
 }
 
+#[cfg(windows)]
 macro_rules! put {
     ($put_method:ident, $arg_name:ident : $T:ident) => {
         pub fn $put_method(&self, $arg_name: $T) -> Result<()> {
@@ -468,6 +530,7 @@ macro_rules! put {
     };
 }
 
+#[cfg(windows)]
 macro_rules! get_interface {
     ($get_method:ident, $T: ident) => {
         pub fn $get_method(&self) -> Result<$T> {
@@ -486,6 +549,7 @@ macro_rules! get_interface {
     };
 }
 
+#[cfg(windows)]
 macro_rules! put_interface {
     ($put_method:ident, $T: ident) => {
         pub fn $put_method(&self, i: $T) -> Result<()> {
@@ -497,6 +561,7 @@ macro_rules! put_interface {
     };
 }
 
+#[cfg(windows)]
 macro_rules! get_bool {
     ($get_method:ident) => {
         pub fn $get_method(&self) -> Result<bool> {
@@ -507,6 +572,7 @@ macro_rules! get_bool {
     };
 }
 
+#[cfg(windows)]
 macro_rules! put_bool {
     ($put_method:ident) => {
         pub fn $put_method(&self, enabled: bool) -> Result<()> {
@@ -516,6 +582,7 @@ macro_rules! put_bool {
     };
 }
 
+#[cfg(windows)]
 macro_rules! get_string {
     ($get_string_method:ident) => {
         pub fn $get_string_method(&self) -> Result<String> {
@@ -531,6 +598,7 @@ macro_rules! get_string {
     };
 }
 
+#[cfg(windows)]
 macro_rules! put_string {
     ($put_string_method:ident) => {
         pub fn $put_string_method(&self, message_string: &str) -> Result<()> {
@@ -540,6 +608,7 @@ macro_rules! put_string {
     };
 }
 
+#[cfg(windows)]
 macro_rules! call {
     ($method:ident) => {
         pub fn $method(&self) -> Result<()> {
@@ -548,6 +617,7 @@ macro_rules! call {
     };
 }
 
+#[cfg(windows)]
 macro_rules! add_event_handler_controller {
     ($method:ident, $arg_type:ident) => {
         pub fn $method(
@@ -577,6 +647,7 @@ macro_rules! add_event_handler_controller {
     };
 }
 
+#[cfg(windows)]
 macro_rules! add_event_handler_view {
     ($method:ident, $arg_type:ident) => {
         pub fn $method(
@@ -606,6 +677,7 @@ macro_rules! add_event_handler_view {
     };
 }
 
+#[cfg(windows)]
 macro_rules! add_event_handler {
     ($method:ident, $arg_type:ident, $arg_args:ident, $arg_args_type:ident) => {
         pub fn $method(
@@ -632,6 +704,67 @@ macro_rules! add_event_handler {
     };
 }
 
+#[cfg(windows)]
+macro_rules! add_event_handler_frame {
+    ($method:ident, $arg_type:ident) => {
+        pub fn $method(
+            &self,
+            event_handler: impl Fn(Frame) -> Result<()> + 'static,
+        ) -> Result<EventRegistrationToken> {
+            let mut token = MaybeUninit::<EventRegistrationToken>::uninit();
+
+            let event_handler = callback!(
+                $arg_type,
+                move |sender: *mut *mut ICoreWebView2FrameVTable,
+                      _args: *mut *mut com::interfaces::iunknown::IUnknownVTable|
+                      -> HRESULT {
+                    let sender = Frame {
+                        inner: unsafe { add_ref_to_rc(sender) },
+                    };
+                    to_hresult(event_handler(sender))
+                }
+            );
+
+            check_hresult(unsafe {
+                self.inner
+                    .$method(event_handler.as_raw(), token.as_mut_ptr())
+            })?;
+            Ok(unsafe { token.assume_init() })
+        }
+    };
+}
+
+#[cfg(windows)]
+macro_rules! add_frame_event_handler {
+    ($method:ident, $arg_type:ident, $arg_args:ident, $arg_args_type:ident) => {
+        pub fn $method(
+            &self,
+            handler: impl Fn(Frame, $arg_args) -> Result<()> + 'static,
+        ) -> Result<EventRegistrationToken> {
+            let mut token = MaybeUninit::<EventRegistrationToken>::uninit();
+
+            let handler = callback!(
+                $arg_type,
+                move |sender: *mut *mut ICoreWebView2FrameVTable,
+                      args: *mut *mut $arg_args_type|
+                      -> HRESULT {
+                    let sender = Frame {
+                        inner: unsafe { add_ref_to_rc(sender) },
+                    };
+                    let args = $arg_args {
+                        inner: unsafe { add_ref_to_rc(args) },
+                    };
+                    to_hresult(handler(sender, args))
+                }
+            );
+
+            check_hresult(unsafe { self.inner.$method(handler.as_raw(), token.as_mut_ptr()) })?;
+            Ok(unsafe { token.assume_init() })
+        }
+    };
+}
+
+#[cfg(windows)]
 macro_rules! remove_event_handler {
     ($method:ident) => {
         pub fn $method(&self, token: EventRegistrationToken) -> Result<()> {
@@ -640,6 +773,30 @@ macro_rules! remove_event_handler {
     };
 }
 
+#[cfg(windows)]
+fn execute_script_completed(
+    callback: impl FnOnce(String) -> Result<()> + 'static,
+) -> ComPtr<dyn ICoreWebView2ExecuteScriptCompletedHandler> {
+    let callback = Cell::new(Some(callback));
+    callback!(
+        ICoreWebView2ExecuteScriptCompletedHandler,
+        move |error_code: HRESULT, result_object_as_json: LPCWSTR| -> HRESULT {
+            to_hresult(check_hresult(error_code).and_then(|_| {
+                let result_object_as_json_string =
+                    unsafe { WideCStr::from_ptr_str(result_object_as_json) }
+                        .to_string()
+                        .map_err(|_| Error::new(E_FAIL))?;
+                if let Some(callback) = callback.take() {
+                    callback(result_object_as_json_string)
+                } else {
+                    Ok(())
+                }
+            }))
+        }
+    )
+}
+
+#[cfg(windows)]
 impl Environment {
     pub fn builder<'a>() -> EnvironmentBuilder<'a> {
         EnvironmentBuilder::new()
@@ -733,6 +890,7 @@ impl Environment {
     }
 }
 
+#[cfg(windows)]
 impl Environment3 {
     pub fn create_composition_controller(
         &self,
@@ -761,8 +919,24 @@ impl Environment3 {
                 .create_core_web_view2_composition_controller(parent_window, completed.as_raw())
         })
     }
+
+    /// Create an empty `PointerInfo` to be filled in and passed to
+    /// `CompositionController::send_pointer_input`.
+    pub fn create_pointer_info(&self) -> Result<PointerInfo> {
+        let mut ppv: *mut *mut ICoreWebView2PointerInfoVTable = ptr::null_mut();
+        check_hresult(unsafe { self.inner.create_core_web_view2_pointer_info(&mut ppv) })?;
+        if ppv.is_null() {
+            Err(Error::new(E_FAIL))
+        } else {
+            // The out parameter is already add-ref'd.
+            Ok(PointerInfo {
+                inner: unsafe { ComRc::from_raw(ppv) },
+            })
+        }
+    }
 }
 
+#[cfg(windows)]
 impl Controller {
     get_bool!(get_is_visible);
     put_bool!(put_is_visible);
@@ -868,13 +1042,25 @@ impl Controller {
             .ok_or_else(|| Error::new(E_NOINTERFACE))?;
         Ok(Controller3 { inner })
     }
+
+    /// Only succeeds for controllers created with
+    /// `Environment3::create_composition_controller`.
+    pub fn get_composition_controller(&self) -> Result<CompositionController> {
+        let inner = self
+            .inner
+            .get_interface::<dyn ICoreWebView2CompositionController>()
+            .ok_or_else(|| Error::new(E_NOINTERFACE))?;
+        Ok(CompositionController { inner })
+    }
 }
 
+#[cfg(windows)]
 impl Controller2 {
     get!(get_default_background_color, Color);
     put!(put_default_background_color, color: Color);
 }
 
+#[cfg(windows)]
 impl Controller3 {
     get!(get_rasterization_scale, f64);
     put!(put_rasterization_scale, scale: f64);
@@ -882,15 +1068,106 @@ impl Controller3 {
     get!(get_should_detect_monitor_scale_changes, i32);
     put!(put_should_detect_monitor_scale_changes, v: i32);
 
-    // TODO: add_RasterizationScaleChanged
-    // TODO: remove_RasterizationScaleChanged
+    add_event_handler_controller!(
+        add_rasterization_scale_changed,
+        ICoreWebView2RasterizationScaleChangedEventHandler
+    );
+    remove_event_handler!(remove_rasterization_scale_changed);
 
     get!(get_bounds_mode, BoundsMode);
     put!(put_bounds_mode, mode: BoundsMode);
 }
 
+#[cfg(windows)]
 impl CompositionController {
+    /// Set the DirectComposition visual (`IDCompositionVisual` or
+    /// `IDCompositionTarget`) the WebView renders into.
+    ///
+    /// # Safety
+    ///
+    /// `target` must be null or a valid pointer to one of the interfaces above.
+    pub unsafe fn put_root_visual_target(
+        &self,
+        target: *mut *mut com::interfaces::iunknown::IUnknownVTable,
+    ) -> Result<()> {
+        check_hresult(self.inner.put_root_visual_target(target))
+    }
+    /// `point` is in client coordinates of the WebView, i.e. relative to the
+    /// top left corner of its bounds.
+    pub fn send_mouse_input(
+        &self,
+        event_kind: MouseEventKind,
+        virtual_keys: MouseEventVirtualKeys,
+        mouse_data: u32,
+        point: POINT,
+    ) -> Result<()> {
+        check_hresult(unsafe {
+            self.inner
+                .send_mouse_input(event_kind, virtual_keys, mouse_data, point)
+        })
+    }
+    /// Like `send_mouse_input`, but `virtual_keys` can combine several
+    /// `MouseEventVirtualKeys` flags (`MK_*` bits), which the enum cannot hold.
+    pub fn send_mouse_input_with_keys(
+        &self,
+        event_kind: MouseEventKind,
+        virtual_keys: u32,
+        mouse_data: u32,
+        point: POINT,
+    ) -> Result<()> {
+        type SendMouseInput = unsafe extern "system" fn(
+            *mut *mut ICoreWebView2CompositionControllerVTable,
+            MouseEventKind,
+            u32,
+            u32,
+            POINT,
+        ) -> HRESULT;
+
+        let this = self.inner.as_raw();
+        check_hresult(unsafe {
+            // `MouseEventVirtualKeys` is `repr(u32)`, so this only changes how
+            // the argument is typed on the Rust side.
+            let send: SendMouseInput = mem::transmute((**this).SendMouseInput);
+            send(this, event_kind, virtual_keys, mouse_data, point)
+        })
+    }
+    pub fn send_pointer_input(
+        &self,
+        event_kind: PointerEventKind,
+        pointer_info: &PointerInfo,
+    ) -> Result<()> {
+        check_hresult(unsafe {
+            self.inner
+                .send_pointer_input(event_kind, pointer_info.inner.as_raw())
+        })
+    }
     get!(get_cursor, HCURSOR);
+    get!(get_system_cursor_id, u32);
+    pub fn add_cursor_changed(
+        &self,
+        event_handler: impl Fn(CompositionController) -> Result<()> + 'static,
+    ) -> Result<EventRegistrationToken> {
+        let mut token = MaybeUninit::<EventRegistrationToken>::uninit();
+
+        let event_handler = callback!(
+            ICoreWebView2CursorChangedEventHandler,
+            move |sender: *mut *mut ICoreWebView2CompositionControllerVTable,
+                  _args: *mut *mut com::interfaces::iunknown::IUnknownVTable|
+                  -> HRESULT {
+                let sender = CompositionController {
+                    inner: unsafe { add_ref_to_rc(sender) },
+                };
+                to_hresult(event_handler(sender))
+            }
+        );
+
+        check_hresult(unsafe {
+            self.inner
+                .add_cursor_changed(event_handler.as_raw(), token.as_mut_ptr())
+        })?;
+        Ok(unsafe { token.assume_init() })
+    }
+    remove_event_handler!(remove_cursor_changed);
 
     pub fn get_controller(&self) -> Result<Controller> {
         let inner = self
@@ -901,6 +1178,67 @@ impl CompositionController {
     }
 }
 
+#[cfg(windows)]
+impl PointerInfo {
+    get!(get_pointer_kind, DWORD);
+    put!(put_pointer_kind, pointer_kind: DWORD);
+    get!(get_pointer_id, u32);
+    put!(put_pointer_id, pointer_id: u32);
+    get!(get_frame_id, u32);
+    put!(put_frame_id, frame_id: u32);
+    get!(get_pointer_flags, u32);
+    put!(put_pointer_flags, pointer_flags: u32);
+    get!(get_pointer_device_rect, RECT);
+    put!(put_pointer_device_rect, pointer_device_rect: RECT);
+    get!(get_display_rect, RECT);
+    put!(put_display_rect, display_rect: RECT);
+    get!(get_pixel_location, POINT);
+    put!(put_pixel_location, pixel_location: POINT);
+    get!(get_himetric_location, POINT);
+    put!(put_himetric_location, himetric_location: POINT);
+    get!(get_pixel_location_raw, POINT);
+    put!(put_pixel_location_raw, pixel_location_raw: POINT);
+    get!(get_himetric_location_raw, POINT);
+    put!(put_himetric_location_raw, himetric_location_raw: POINT);
+    get!(get_time, DWORD);
+    put!(put_time, time: DWORD);
+    get!(get_history_count, u32);
+    put!(put_history_count, history_count: u32);
+    get!(get_input_data, i32);
+    put!(put_input_data, input_data: i32);
+    get!(get_key_states, DWORD);
+    put!(put_key_states, key_states: DWORD);
+    get!(get_performance_count, u64);
+    put!(put_performance_count, performance_count: u64);
+    get!(get_button_change_kind, i32);
+    put!(put_button_change_kind, button_change_kind: i32);
+    get!(get_pen_flags, u32);
+    put!(put_pen_flags, pen_flags: u32);
+    get!(get_pen_mask, u32);
+    put!(put_pen_mask, pen_mask: u32);
+    get!(get_pen_pressure, u32);
+    put!(put_pen_pressure, pen_pressure: u32);
+    get!(get_pen_rotation, u32);
+    put!(put_pen_rotation, pen_rotation: u32);
+    get!(get_pen_tilt_x, i32);
+    put!(put_pen_tilt_x, pen_tilt_x: i32);
+    get!(get_pen_tilt_y, i32);
+    put!(put_pen_tilt_y, pen_tilt_y: i32);
+    get!(get_touch_flags, u32);
+    put!(put_touch_flags, touch_flags: u32);
+    get!(get_touch_mask, u32);
+    put!(put_touch_mask, touch_mask: u32);
+    get!(get_touch_contact, RECT);
+    put!(put_touch_contact, touch_contact: RECT);
+    get!(get_touch_contact_raw, RECT);
+    put!(put_touch_contact_raw, touch_contact_raw: RECT);
+    get!(get_touch_orientation, u32);
+    put!(put_touch_orientation, touch_orientation: u32);
+    get!(get_touch_pressure, u32);
+    put!(put_touch_pressure, touch_pressure: u32);
+}
+
+#[cfg(windows)]
 impl WebView {
     pub fn get_settings(&self) -> Result<Settings> {
         let mut ppv: *mut *mut ICoreWebView2SettingsVTable = ptr::null_mut();
@@ -1012,23 +1350,7 @@ impl WebView {
         callback: impl FnOnce(String) -> Result<()> + 'static,
     ) -> Result<()> {
         let script = WideCString::from_str(script)?;
-        let callback = Cell::new(Some(callback));
-        let callback = callback!(
-            ICoreWebView2ExecuteScriptCompletedHandler,
-            move |error_code: HRESULT, result_object_as_json: LPCWSTR| -> HRESULT {
-                to_hresult(check_hresult(error_code).and_then(|_| {
-                    let result_object_as_json_string =
-                        unsafe { WideCStr::from_ptr_str(result_object_as_json) }
-                            .to_string()
-                            .map_err(|_| Error::new(E_FAIL))?;
-                    if let Some(callback) = callback.take() {
-                        callback(result_object_as_json_string)
-                    } else {
-                        Ok(())
-                    }
-                }))
-            }
-        );
+        let callback = execute_script_completed(callback);
         check_hresult(unsafe {
             self.inner
                 .execute_script(script.as_ptr(), callback.as_raw())
@@ -1162,13 +1484,37 @@ impl WebView {
             .ok_or_else(|| Error::new(E_NOINTERFACE))?;
         Ok(WebView_3 { inner })
     }
+
+    pub fn get_webview_4(&self) -> Result<WebView_4> {
+        let inner = self
+            .inner
+            .get_interface::<dyn ICoreWebView2_4>()
+            .ok_or_else(|| Error::new(E_NOINTERFACE))?;
+        Ok(WebView_4 { inner })
+    }
 }
 
+#[cfg(windows)]
 impl WebView_2 {
     get_interface!(get_cookie_manager, CookieManager);
     get_interface!(get_environment, Environment);
+    add_event_handler!(
+        add_web_resource_response_received,
+        ICoreWebView2WebResourceResponseReceivedEventHandler,
+        WebResourceResponseReceivedEventArgs,
+        ICoreWebView2WebResourceResponseReceivedEventArgsVTable
+    );
+    remove_event_handler!(remove_web_resource_response_received);
+    add_event_handler!(
+        add_domcontent_loaded,
+        ICoreWebView2DOMContentLoadedEventHandler,
+        DOMContentLoadedEventArgs,
+        ICoreWebView2DOMContentLoadedEventArgsVTable
+    );
+    remove_event_handler!(remove_domcontent_loaded);
 }
 
+#[cfg(windows)]
 impl WebView_3 {
     pub fn set_virtual_host_name_to_folder_mapping(
         &self,
@@ -1196,6 +1542,151 @@ impl WebView_3 {
     }
 }
 
+#[cfg(windows)]
+impl WebView_4 {
+    add_event_handler!(
+        add_frame_created,
+        ICoreWebView2FrameCreatedEventHandler,
+        FrameCreatedEventArgs,
+        ICoreWebView2FrameCreatedEventArgsVTable
+    );
+    remove_event_handler!(remove_frame_created);
+}
+
+#[cfg(windows)]
+impl FrameCreatedEventArgs {
+    get_interface!(get_frame, Frame);
+}
+
+#[cfg(windows)]
+impl Frame {
+    get_string!(get_name);
+    add_event_handler_frame!(add_name_changed, ICoreWebView2FrameNameChangedEventHandler);
+    remove_event_handler!(remove_name_changed);
+    add_event_handler_frame!(add_destroyed, ICoreWebView2FrameDestroyedEventHandler);
+    remove_event_handler!(remove_destroyed);
+    get_bool!(is_destroyed);
+
+    /// Add `object` as `chrome.webview.hostObjects.{name}` in the frame, for
+    /// documents from one of `origins` only. `"*"` is any origin and
+    /// `"file://"` any file; no origins adds it for none.
+    pub fn add_host_object_to_script(
+        &self,
+        name: &str,
+        object: *mut VARIANT,
+        origins: &[&str],
+    ) -> Result<()> {
+        let name = WideCString::from_str(name)?;
+        let origins = origins
+            .iter()
+            .map(WideCString::from_str)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut origins = origins.iter().map(|o| o.as_ptr()).collect::<Vec<_>>();
+        check_hresult(unsafe {
+            self.inner.add_host_object_to_script_with_origins(
+                name.as_ptr(),
+                std::mem::transmute(object),
+                origins.len() as u32,
+                origins.as_mut_ptr(),
+            )
+        })
+    }
+
+    pub fn remove_host_object_from_script(&self, name: &str) -> Result<()> {
+        let name = WideCString::from_str(name)?;
+        check_hresult(unsafe { self.inner.remove_host_object_from_script(name.as_ptr()) })
+    }
+
+    /// Same as `get_frame_2()?.post_web_message_as_json(..)`.
+    pub fn post_web_message_as_json(&self, json: &str) -> Result<()> {
+        self.get_frame_2()?.post_web_message_as_json(json)
+    }
+
+    /// Same as `get_frame_2()?.execute_script(..)`.
+    pub fn execute_script(
+        &self,
+        script: &str,
+        callback: impl FnOnce(String) -> Result<()> + 'static,
+    ) -> Result<()> {
+        self.get_frame_2()?.execute_script(script, callback)
+    }
+
+    pub fn get_frame_2(&self) -> Result<Frame2> {
+        let inner = self
+            .inner
+            .get_interface::<dyn ICoreWebView2Frame2>()
+            .ok_or_else(|| Error::new(E_NOINTERFACE))?;
+        Ok(Frame2 { inner })
+    }
+
+    pub fn get_frame_5(&self) -> Result<Frame5> {
+        let inner = self
+            .inner
+            .get_interface::<dyn ICoreWebView2Frame5>()
+            .ok_or_else(|| Error::new(E_NOINTERFACE))?;
+        Ok(Frame5 { inner })
+    }
+}
+
+#[cfg(windows)]
+impl Frame2 {
+    add_frame_event_handler!(
+        add_navigation_starting,
+        ICoreWebView2FrameNavigationStartingEventHandler,
+        NavigationStartingEventArgs,
+        ICoreWebView2NavigationStartingEventArgsVTable
+    );
+    remove_event_handler!(remove_navigation_starting);
+    add_frame_event_handler!(
+        add_content_loading,
+        ICoreWebView2FrameContentLoadingEventHandler,
+        ContentLoadingEventArgs,
+        ICoreWebView2ContentLoadingEventArgsVTable
+    );
+    remove_event_handler!(remove_content_loading);
+    add_frame_event_handler!(
+        add_navigation_completed,
+        ICoreWebView2FrameNavigationCompletedEventHandler,
+        NavigationCompletedEventArgs,
+        ICoreWebView2NavigationCompletedEventArgsVTable
+    );
+    remove_event_handler!(remove_navigation_completed);
+    add_frame_event_handler!(
+        add_domcontent_loaded,
+        ICoreWebView2FrameDOMContentLoadedEventHandler,
+        DOMContentLoadedEventArgs,
+        ICoreWebView2DOMContentLoadedEventArgsVTable
+    );
+    remove_event_handler!(remove_domcontent_loaded);
+    pub fn execute_script(
+        &self,
+        script: &str,
+        callback: impl FnOnce(String) -> Result<()> + 'static,
+    ) -> Result<()> {
+        let script = WideCString::from_str(script)?;
+        let callback = execute_script_completed(callback);
+        check_hresult(unsafe {
+            self.inner
+                .execute_script(script.as_ptr(), callback.as_raw())
+        })
+    }
+    put_string!(post_web_message_as_json);
+    put_string!(post_web_message_as_string);
+    add_frame_event_handler!(
+        add_web_message_received,
+        ICoreWebView2FrameWebMessageReceivedEventHandler,
+        WebMessageReceivedEventArgs,
+        ICoreWebView2WebMessageReceivedEventArgsVTable
+    );
+    remove_event_handler!(remove_web_message_received);
+}
+
+#[cfg(windows)]
+impl Frame5 {
+    get!(get_frame_id, u32);
+}
+
+#[cfg(windows)]
 impl Settings {
     get_bool!(get_is_script_enabled);
     put_bool!(put_is_script_enabled);
@@ -1265,16 +1756,19 @@ impl Settings {
     }
 }
 
+#[cfg(windows)]
 impl Settings2 {
     get_string!(get_user_agent);
     put_string!(put_user_agent);
 }
 
+#[cfg(windows)]
 impl Settings3 {
     get_bool!(get_are_browser_accelerator_keys_enabled);
     put_bool!(put_are_browser_accelerator_keys_enabled);
 }
 
+#[cfg(windows)]
 impl Settings4 {
     get_bool!(get_is_password_autosave_enabled);
     put_bool!(put_is_password_autosave_enabled);
@@ -1282,27 +1776,37 @@ impl Settings4 {
     put_bool!(put_is_general_autofill_enabled);
 }
 
+#[cfg(windows)]
 impl Settings5 {
     get_bool!(get_is_pinch_zoom_enabled);
     put_bool!(put_is_pinch_zoom_enabled);
 }
 
+#[cfg(windows)]
 impl Settings6 {
     get_bool!(get_is_swipe_navigation_enabled);
     put_bool!(put_is_swipe_navigation_enabled);
 }
 
+#[cfg(windows)]
 impl ContentLoadingEventArgs {
     get_bool!(get_is_error_page);
     get!(get_navigation_id, u64);
 }
 
+#[cfg(windows)]
+impl DOMContentLoadedEventArgs {
+    get!(get_navigation_id, u64);
+}
+
+#[cfg(windows)]
 impl WebMessageReceivedEventArgs {
     get_string!(get_source);
     get_string!(try_get_web_message_as_string);
     get_string!(get_web_message_as_json);
 }
 
+#[cfg(windows)]
 impl HttpHeadersCollectionIterator {
     pub fn get_current_header(&self) -> Result<(String, String)> {
         let mut name = MaybeUninit::<LPWSTR>::uninit();
@@ -1331,6 +1835,7 @@ impl HttpHeadersCollectionIterator {
     get_bool!(move_next);
 }
 
+#[cfg(windows)]
 impl Iterator for HttpHeadersCollectionIterator {
     type Item = (String, String);
 
@@ -1344,6 +1849,7 @@ impl Iterator for HttpHeadersCollectionIterator {
     }
 }
 
+#[cfg(windows)]
 impl HttpRequestHeaders {
     pub fn get_header(&self, name: &str) -> Result<String> {
         let name = WideCString::from_str(name)?;
@@ -1384,6 +1890,7 @@ impl HttpRequestHeaders {
     get_interface!(get_iterator, HttpHeadersCollectionIterator);
 }
 
+#[cfg(windows)]
 impl HttpResponseHeaders {
     pub fn get_header(&self, name: &str) -> Result<String> {
         let name = WideCString::from_str(name)?;
@@ -1423,10 +1930,12 @@ impl HttpResponseHeaders {
     get_interface!(get_iterator, HttpHeadersCollectionIterator);
 }
 
+#[cfg(windows)]
 impl Deferral {
     call!(complete);
 }
 
+#[cfg(windows)]
 impl WebResourceRequest {
     get_string!(get_uri);
     put_string!(put_uri);
@@ -1437,6 +1946,7 @@ impl WebResourceRequest {
     get_interface!(get_headers, HttpRequestHeaders);
 }
 
+#[cfg(windows)]
 impl WebResourceResponse {
     get_interface!(get_content, Stream);
     put_interface!(put_content, Stream);
@@ -1447,6 +1957,53 @@ impl WebResourceResponse {
     put_string!(put_reason_phrase);
 }
 
+#[cfg(windows)]
+impl WebResourceResponseView {
+    get_interface!(get_headers, HttpResponseHeaders);
+    get!(get_status_code, i32);
+    get_string!(get_reason_phrase);
+
+    /// Get the content of the response. It is `None` for responses without
+    /// content, e.g. redirects, which complete with `ERROR_NO_DATA`.
+    pub fn get_content(
+        &self,
+        completed: impl FnOnce(Result<Option<Stream>>) -> Result<()> + 'static,
+    ) -> Result<()> {
+        let completed = Cell::new(Some(completed));
+        let completed = callback!(
+            ICoreWebView2WebResourceResponseViewGetContentCompletedHandler,
+            move |result: HRESULT, content: *mut *mut IStreamVTable| -> HRESULT {
+                let result = if result == HRESULT_FROM_WIN32(ERROR_NO_DATA) {
+                    Ok(None)
+                } else {
+                    check_hresult(result).map(|_| {
+                        if content.is_null() {
+                            None
+                        } else {
+                            Some(Stream {
+                                inner: unsafe { add_ref_to_rc(content) },
+                            })
+                        }
+                    })
+                };
+                if let Some(completed) = completed.take() {
+                    to_hresult(completed(result))
+                } else {
+                    S_OK
+                }
+            }
+        );
+        check_hresult(unsafe { self.inner.get_content(completed.as_raw()) })
+    }
+}
+
+#[cfg(windows)]
+impl WebResourceResponseReceivedEventArgs {
+    get_interface!(get_request, WebResourceRequest);
+    get_interface!(get_response, WebResourceResponseView);
+}
+
+#[cfg(windows)]
 impl WebResourceRequestedEventArgs {
     get_interface!(get_request, WebResourceRequest);
     get_interface!(get_response, WebResourceResponse);
@@ -1455,12 +2012,14 @@ impl WebResourceRequestedEventArgs {
     get!(get_resource_context, WebResourceContext);
 }
 
+#[cfg(windows)]
 impl NavigationCompletedEventArgs {
     get_bool!(get_is_success);
     get!(get_web_error_status, WebErrorStatus);
     get!(get_navigation_id, u64);
 }
 
+#[cfg(windows)]
 impl NavigationStartingEventArgs {
     get_string!(get_uri);
     get_bool!(get_is_user_initiated);
@@ -1479,14 +2038,17 @@ impl NavigationStartingEventArgs {
     }
 }
 
+#[cfg(windows)]
 impl NavigationStartingEventArgs3 {
     get!(get_navigation_kind, NavigationKind);
 }
 
+#[cfg(windows)]
 impl SourceChangedEventArgs {
     get_bool!(get_is_new_document);
 }
 
+#[cfg(windows)]
 impl ScriptDialogOpeningEventArgs {
     get_string!(get_uri);
     get!(get_kind, ScriptDialogKind);
@@ -1498,6 +2060,7 @@ impl ScriptDialogOpeningEventArgs {
     get_interface!(get_deferral, Deferral);
 }
 
+#[cfg(windows)]
 impl PermissionRequestedEventArgs {
     get_string!(get_uri);
     get!(get_permission_kind, PermissionKind);
@@ -1507,10 +2070,12 @@ impl PermissionRequestedEventArgs {
     get_interface!(get_deferral, Deferral);
 }
 
+#[cfg(windows)]
 impl ProcessFailedEventArgs {
     get!(get_process_failed_kind, ProcessFailedKind);
 }
 
+#[cfg(windows)]
 impl NewWindowRequestedEventArgs {
     get_string!(get_uri);
     put_interface!(put_new_window, WebView);
@@ -1522,6 +2087,7 @@ impl NewWindowRequestedEventArgs {
     get_interface!(get_window_features, WindowFeatures);
 }
 
+#[cfg(windows)]
 impl WindowFeatures {
     get_bool!(get_has_position);
     get_bool!(get_has_size);
@@ -1535,12 +2101,14 @@ impl WindowFeatures {
     get_bool!(get_should_display_scroll_bars);
 }
 
+#[cfg(windows)]
 impl MoveFocusRequestedEventArgs {
     get!(get_reason, MoveFocusReason);
     get_bool!(get_handled);
     put_bool!(put_handled);
 }
 
+#[cfg(windows)]
 impl AcceleratorKeyPressedEventArgs {
     get!(get_key_event_kind, KeyEventKind);
     get!(get_virtual_key, u32);
@@ -1551,10 +2119,12 @@ impl AcceleratorKeyPressedEventArgs {
 }
 
 // Missing in winapi APIs. But present in its import libraries.
+#[cfg(windows)]
 extern "stdcall" {
     fn SHCreateMemStream(p_init: *const u8, cb_init: UINT) -> *mut *mut IStreamVTable;
 }
 
+#[cfg(windows)]
 impl Stream {
     /// Create a stream from a byte buffer. (`SHCreateMemStream`)
     pub fn from_bytes(buf: &[u8]) -> Self {
@@ -1578,6 +2148,7 @@ impl Stream {
     }
 }
 
+#[cfg(windows)]
 impl io::Read for Stream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let mut read_bytes = MaybeUninit::uninit();
@@ -1593,6 +2164,7 @@ impl io::Read for Stream {
     }
 }
 
+#[cfg(windows)]
 impl io::Write for Stream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let mut written_bytes = MaybeUninit::uninit();
@@ -1612,6 +2184,7 @@ impl io::Write for Stream {
     }
 }
 
+#[cfg(windows)]
 impl io::Seek for Stream {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
         use std::convert::TryInto;
@@ -1637,36 +2210,44 @@ impl io::Seek for Stream {
 }
 
 #[doc(inline)]
+#[cfg(windows)]
 pub use webview2_sys::{
     BoundsMode, CapturePreviewImageFormat, Color, EventRegistrationToken, HostResourceAccessKind,
-    KeyEventKind, MoveFocusReason, PermissionKind, PermissionState, PhysicalKeyStatus,
-    ProcessFailedKind, ScriptDialogKind, WebErrorStatus, WebResourceContext, NavigationKind
+    KeyEventKind, MouseEventKind, MouseEventVirtualKeys, MoveFocusReason, NavigationKind,
+    PermissionKind, PermissionState, PhysicalKeyStatus, PointerEventKind, ProcessFailedKind,
+    ScriptDialogKind, WebErrorStatus, WebResourceContext,
 };
 
 /// WebView2 Error.
 ///
 /// Actually it's just an `HRESULT`.
 #[derive(Eq, PartialEq)]
+#[cfg(windows)]
 pub struct Error {
     hresult: HRESULT,
 }
 
+#[cfg(windows)]
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(windows)]
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "webview2 error, HRESULT {:#X}", self.hresult as u32)
     }
 }
 
+#[cfg(windows)]
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "webview2 error, HRESULT {:#X}", self.hresult as u32)
     }
 }
 
+#[cfg(windows)]
 impl std::error::Error for Error {}
 
+#[cfg(windows)]
 impl From<NulError<u16>> for Error {
     fn from(_: NulError<u16>) -> Error {
         Error {
@@ -1675,6 +2256,7 @@ impl From<NulError<u16>> for Error {
     }
 }
 
+#[cfg(windows)]
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
         match e.raw_os_error() {
@@ -1684,6 +2266,7 @@ impl From<io::Error> for Error {
     }
 }
 
+#[cfg(windows)]
 impl Error {
     pub fn new(hresult: HRESULT) -> Self {
         Self { hresult }
@@ -1706,6 +2289,7 @@ impl Error {
 
 /// Check a `HRESULT`, if it is `SUCCEEDED`, return `Ok(())`. Otherwide return
 /// an error containing the `HRESULT`.
+#[cfg(windows)]
 pub fn check_hresult(hresult: HRESULT) -> Result<()> {
     if SUCCEEDED(hresult) {
         Ok(())
@@ -1714,6 +2298,7 @@ pub fn check_hresult(hresult: HRESULT) -> Result<()> {
     }
 }
 
+#[cfg(windows)]
 fn to_hresult<T>(r: Result<T>) -> HRESULT {
     match r {
         Ok(_) => S_OK,
@@ -1722,6 +2307,7 @@ fn to_hresult<T>(r: Result<T>) -> HRESULT {
 }
 
 #[cfg(test)]
+#[cfg(windows)]
 mod tests {
     use super::*;
     use std::io::{Read, Seek, Write};
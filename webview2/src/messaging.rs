@@ -0,0 +1,438 @@
+//! Typed web messages.
+//!
+//! Messages are JSON. Those sent by the page are routed by their `type`
+//! field: `Router::on` registers a handler for one type, which receives the
+//! whole message decoded as its own Rust type, and `Router::otherwise` takes
+//! the rest. Going the other way, anything `Serialize` can be posted.
+//!
+//! Messages longer than a limit, `DEFAULT_LIMIT` unless set otherwise, are
+//! rejected in both directions. Errors about received messages name the
+//! origin they came from.
+//!
+//! ```
+//! use serde::Deserialize;
+//! use webview2::messaging::Router;
+//!
+//! #[derive(Deserialize)]
+//! struct Resize {
+//!     width: u32,
+//!     height: u32,
+//! }
+//!
+//! let router = Router::new().on("resize", |r: Resize, _origin: &str| {
+//!     println!("{}x{}", r.width, r.height);
+//! });
+//! router
+//!     .dispatch(r#"{"type":"resize","width":640,"height":480}"#, "https://example.com")
+//!     .unwrap();
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// The field messages are routed by.
+pub const TYPE_FIELD: &str = "type";
+
+/// The default limit on the length of a message, in bytes of JSON.
+pub const DEFAULT_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MessageError {
+    TooLarge {
+        size: usize,
+        limit: usize,
+    },
+    /// A message to post could not be serialized.
+    Encode(String),
+    /// A received message is not JSON, or not what its handler expects.
+    Decode {
+        origin: String,
+        error: String,
+    },
+    /// A received message has no `type` and there is no `Router::otherwise`.
+    MissingType {
+        origin: String,
+    },
+    /// No handler for the `type` of a received message.
+    Unhandled {
+        origin: String,
+        kind: String,
+    },
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageError::TooLarge { size, limit } => write!(
+                f,
+                "message of {} bytes is over the limit of {} bytes",
+                size, limit
+            ),
+            MessageError::Encode(e) => write!(f, "cannot encode message: {}", e),
+            MessageError::Decode { origin, error } => {
+                write!(f, "cannot decode message from {}: {}", origin, error)
+            }
+            MessageError::MissingType { origin } => {
+                write!(f, "message from {} has no {:?}", origin, TYPE_FIELD)
+            }
+            MessageError::Unhandled { origin, kind } => {
+                write!(f, "no handler for message type {:?} from {}", kind, origin)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+fn check_size(size: usize, limit: usize) -> Result<(), MessageError> {
+    if size > limit {
+        Err(MessageError::TooLarge { size, limit })
+    } else {
+        Ok(())
+    }
+}
+
+/// Serialize a message to post.
+pub fn encode<T: Serialize + ?Sized>(message: &T, limit: usize) -> Result<String, MessageError> {
+    let json = serde_json::to_string(message).map_err(|e| MessageError::Encode(e.to_string()))?;
+    check_size(json.len(), limit)?;
+    Ok(json)
+}
+
+/// Deserialize a message received from `origin`.
+pub fn decode<T: DeserializeOwned>(
+    json: &str,
+    origin: &str,
+    limit: usize,
+) -> Result<T, MessageError> {
+    check_size(json.len(), limit)?;
+    serde_json::from_str(json).map_err(|e| decode_error(origin, e))
+}
+
+fn decode_error(origin: &str, error: serde_json::Error) -> MessageError {
+    MessageError::Decode {
+        origin: origin.to_owned(),
+        error: error.to_string(),
+    }
+}
+
+type Handler = Rc<dyn Fn(Json, &str) -> Result<(), MessageError>>;
+
+fn handler<T, F>(f: F) -> Handler
+where
+    T: DeserializeOwned,
+    F: Fn(T, &str) + 'static,
+{
+    Rc::new(move |json, origin| {
+        let message = serde_json::from_value(json).map_err(|e| decode_error(origin, e))?;
+        f(message, origin);
+        Ok(())
+    })
+}
+
+/// Routes received messages to handlers by their `type`.
+///
+/// Handlers may run a nested message loop, e.g. for a modal dialog, and the
+/// router then dispatches the messages received meanwhile.
+#[derive(Clone)]
+pub struct Router {
+    limit: usize,
+    handlers: HashMap<String, Handler>,
+    otherwise: Option<Handler>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router {
+            limit: DEFAULT_LIMIT,
+            handlers: HashMap::new(),
+            otherwise: None,
+        }
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Handle messages with `"type": kind`. A later handler for the same type
+    /// replaces the earlier one.
+    pub fn on<T, F>(mut self, kind: &str, f: F) -> Self
+    where
+        T: DeserializeOwned,
+        F: Fn(T, &str) + 'static,
+    {
+        self.handlers.insert(kind.to_owned(), handler(f));
+        self
+    }
+
+    /// Handle messages that no `on` handler takes, including those without a
+    /// `type`.
+    pub fn otherwise<T, F>(mut self, f: F) -> Self
+    where
+        T: DeserializeOwned,
+        F: Fn(T, &str) + 'static,
+    {
+        self.otherwise = Some(handler(f));
+        self
+    }
+
+    /// Hand `json`, received from `origin`, to its handler.
+    pub fn dispatch(&self, json: &str, origin: &str) -> Result<(), MessageError> {
+        let message: Json = decode(json, origin, self.limit)?;
+        let kind = message
+            .get(TYPE_FIELD)
+            .and_then(Json::as_str)
+            .map(str::to_owned);
+        if let Some(handler) = kind.as_ref().and_then(|k| self.handlers.get(k)) {
+            return handler(message, origin);
+        }
+        match (&self.otherwise, kind) {
+            (Some(otherwise), _) => otherwise(message, origin),
+            (None, Some(kind)) => Err(MessageError::Unhandled {
+                origin: origin.to_owned(),
+                kind,
+            }),
+            (None, None) => Err(MessageError::MissingType {
+                origin: origin.to_owned(),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut kinds = self.handlers.keys().collect::<Vec<_>>();
+        kinds.sort();
+        f.debug_struct("Router")
+            .field("limit", &self.limit)
+            .field("kinds", &kinds)
+            .field("otherwise", &self.otherwise.is_some())
+            .finish()
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::{Error, EventRegistrationToken, Result, WebView};
+    use winapi::shared::winerror::E_INVALIDARG;
+
+    impl WebView {
+        /// Post `message` as JSON, to be received by `chrome.webview`'s
+        /// `message` listeners as an object. Fails with `E_INVALIDARG` if it
+        /// cannot be serialized or is longer than `DEFAULT_LIMIT`.
+        pub fn post_message<T: Serialize + ?Sized>(&self, message: &T) -> Result<()> {
+            let json = encode(message, DEFAULT_LIMIT).map_err(|_| Error::new(E_INVALIDARG))?;
+            self.post_web_message_as_json(&json)
+        }
+
+        /// Decode every message as `T`. Messages that do not decode go to
+        /// `on_error`.
+        pub fn add_message_handler<T, F>(
+            &self,
+            f: F,
+            on_error: impl Fn(MessageError) + 'static,
+        ) -> Result<EventRegistrationToken>
+        where
+            T: DeserializeOwned,
+            F: Fn(T, &str) + 'static,
+        {
+            self.add_message_router(Router::new().otherwise(f), on_error)
+        }
+
+        /// Hand every message to `router`. Messages it cannot route go to
+        /// `on_error`.
+        pub fn add_message_router(
+            &self,
+            router: Router,
+            on_error: impl Fn(MessageError) + 'static,
+        ) -> Result<EventRegistrationToken> {
+            self.add_web_message_received(move |_, args| {
+                let origin = args.get_source()?;
+                let json = args.get_web_message_as_json()?;
+                // Nothing is borrowed while the handler runs, so one that
+                // pumps messages gets the next one dispatched right away.
+                if let Err(e) = router.dispatch(&json, &origin) {
+                    on_error(e);
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Click {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "lowercase")]
+    enum Event {
+        Click { x: i32, y: i32 },
+        Key { code: String },
+        Scroll { delta: f64 },
+    }
+
+    const ORIGIN: &str = "https://example.com";
+
+    fn log<T: 'static>() -> (Rc<RefCell<Vec<T>>>, impl Fn(T, &str)) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let l = log.clone();
+        (log, move |t, origin: &str| {
+            assert_eq!(origin, ORIGIN);
+            l.borrow_mut().push(t);
+        })
+    }
+
+    #[test]
+    fn test_routing() {
+        let (clicks, on_click) = log::<Click>();
+        let (others, otherwise) = log::<Json>();
+        let router = Router::new().on("click", on_click);
+        router
+            .dispatch(r#"{"type":"click","x":1,"y":2}"#, ORIGIN)
+            .unwrap();
+        assert_eq!(*clicks.borrow(), [Click { x: 1, y: 2 }]);
+
+        assert_eq!(
+            router.dispatch(r#"{"type":"key"}"#, ORIGIN),
+            Err(MessageError::Unhandled {
+                origin: ORIGIN.to_owned(),
+                kind: "key".to_owned()
+            })
+        );
+        assert_eq!(
+            router.dispatch(r#""hello""#, ORIGIN),
+            Err(MessageError::MissingType {
+                origin: ORIGIN.to_owned()
+            })
+        );
+
+        let router = router.otherwise(otherwise);
+        router.dispatch(r#"{"type":"key"}"#, ORIGIN).unwrap();
+        router.dispatch(r#""hello""#, ORIGIN).unwrap();
+        router.dispatch(r#"{"type":7}"#, ORIGIN).unwrap();
+        assert_eq!(
+            *others.borrow(),
+            [
+                json!({ "type": "key" }),
+                json!("hello"),
+                json!({ "type": 7 })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let (_, on_click) = log::<Click>();
+        let router = Router::new().with_limit(40).on("click", on_click);
+        match router.dispatch(r#"{"type":"click","x":"1"}"#, ORIGIN) {
+            Err(MessageError::Decode { origin, error }) => {
+                assert_eq!(origin, ORIGIN);
+                assert!(error.contains("expected i32"), "{}", error);
+            }
+            r => panic!("{:?}", r),
+        }
+        match router.dispatch("{", ORIGIN) {
+            Err(e @ MessageError::Decode { .. }) => {
+                assert!(e
+                    .to_string()
+                    .starts_with("cannot decode message from https://example.com"))
+            }
+            r => panic!("{:?}", r),
+        }
+        assert_eq!(
+            router.dispatch(r#"{"type":"click","x":1,"y":2,"padding":10}"#, ORIGIN),
+            Err(MessageError::TooLarge {
+                size: 41,
+                limit: 40
+            })
+        );
+        assert_eq!(
+            encode(&"x".repeat(10), 11),
+            Err(MessageError::TooLarge {
+                size: 12,
+                limit: 11
+            })
+        );
+        assert_eq!(encode(&"x".repeat(9), 11).unwrap(), "\"xxxxxxxxx\"");
+
+        let mut map = HashMap::new();
+        map.insert(vec![1], 1);
+        assert!(matches!(encode(&map, 100), Err(MessageError::Encode(_))));
+    }
+
+    #[test]
+    fn test_reentrant_dispatch() {
+        // The click handler pumps messages, which dispatches a key meanwhile.
+        let (keys, on_key) = log::<Json>();
+        let this = Rc::new(RefCell::new(None::<Rc<Router>>));
+        let t = this.clone();
+        let router = Rc::new(Router::new().on("key", on_key).on(
+            "click",
+            move |_: Json, origin: &str| {
+                let router = t.borrow().clone().unwrap();
+                router.dispatch(r#"{"type":"key"}"#, origin).unwrap();
+            },
+        ));
+        *this.borrow_mut() = Some(router.clone());
+        router.dispatch(r#"{"type":"click"}"#, ORIGIN).unwrap();
+        assert_eq!(*keys.borrow(), [json!({ "type": "key" })]);
+        this.borrow_mut().take();
+    }
+
+    /// What is posted comes out of the router as the same value, through
+    /// the handler for its type, escapes and all.
+    #[test]
+    fn test_round_trips() {
+        let (events, on_event) = log::<Event>();
+        let events2 = events.clone();
+        let events3 = events.clone();
+        let router = Router::new()
+            .on("click", on_event)
+            .on("key", move |e: Event, _: &str| events2.borrow_mut().push(e))
+            .on("scroll", move |e: Event, _: &str| {
+                events3.borrow_mut().push(e)
+            });
+        let sent = vec![
+            Event::Click { x: -1000, y: 999 },
+            Event::Key {
+                code: "a\"\\\n\u{2028}😀".to_owned(),
+            },
+            Event::Key {
+                code: String::new(),
+            },
+            Event::Scroll { delta: -0.125 },
+        ];
+        for event in &sent {
+            let json = encode(event, DEFAULT_LIMIT).unwrap();
+            router.dispatch(&json, ORIGIN).unwrap();
+        }
+        assert_eq!(*events.borrow(), sent);
+    }
+}
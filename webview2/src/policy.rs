@@ -0,0 +1,913 @@
+//! Allow and deny rules for navigations, new windows and web resources.
+//!
+//! A `Policy` is an ordered list of rules, compiled once by
+//! `PolicyBuilder::build`. Each check is decided by the first rule that
+//! matches it, or by the default action if none does:
+//!
+//! ```
+//! use webview2::policy::{Action, Check, NavigationKind, Policy, Rule};
+//!
+//! let policy = Policy::builder(Action::Deny)
+//!     .rule(Rule::deny("no reloads").kind(NavigationKind::Reload))
+//!     .rule(Rule::allow("app").origin("https://app.local"))
+//!     .rule(Rule::allow("docs").wildcard("https://*.example.com/docs/*"))
+//!     .build()
+//!     .unwrap();
+//! assert!(policy.allows(&Check::navigation("https://app.local/index.html")));
+//! assert!(policy.allows(&Check::navigation("https://www.example.com/docs/a")));
+//! assert!(!policy.allows(&Check::navigation("https://evil.com/")));
+//! let reload = Check::navigation("https://app.local/").with_kind(NavigationKind::Reload);
+//! assert_eq!(policy.evaluate(&reload).rule, Some("no reloads"));
+//! ```
+//!
+//! Rules match URIs by origin, by a WebView2 wildcard pattern, the same as
+//! `add_web_resource_requested_filter` takes, or by a regular expression.
+//! URIs are normalized first, like WebView2 does before matching its
+//! filters. On Windows `Policy::attach` cancels denied navigations, drops
+//! denied new windows and answers denied web resource requests with
+//! `403 Forbidden`.
+
+pub use crate::util::uri::normalize_uri;
+use crate::util::uri::origin_of;
+use regex::Regex;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// What is being checked.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    /// `NavigationStarting` of the top level document.
+    Navigation,
+    /// `FrameNavigationStarting`.
+    FrameNavigation,
+    /// `NewWindowRequested`.
+    NewWindow,
+    /// `WebResourceRequested`.
+    WebResource,
+}
+
+/// `WebResourceContext`, which also exists where WebView2 does not.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResourceContext {
+    /// In a rule, any context.
+    All,
+    Document,
+    Stylesheet,
+    Image,
+    Media,
+    Font,
+    Script,
+    XmlHttpRequest,
+    Fetch,
+    TextTrack,
+    EventSource,
+    Websocket,
+    Manifest,
+    SignedExchange,
+    Ping,
+    CspViolationReport,
+    Other,
+}
+
+/// `NavigationKind`, which also exists where WebView2 does not.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NavigationKind {
+    Reload,
+    BackOrForward,
+    NewDocument,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    One,
+    /// `*`
+    Many,
+}
+
+/// A WebView2 wildcard pattern: `*` matches any characters, `/` included,
+/// `?` matches one character, and `\*` and `\?` match themselves. The
+/// pattern must match the whole URI. An empty pattern matches nothing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Wildcard {
+    tokens: Vec<Token>,
+}
+
+impl Wildcard {
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '\\' => match chars.peek() {
+                    Some(&c @ '*') | Some(&c @ '?') => {
+                        chars.next();
+                        Token::Char(c)
+                    }
+                    _ => Token::Char('\\'),
+                },
+                '*' if tokens.last() == Some(&Token::Many) => continue,
+                '*' => Token::Many,
+                '?' => Token::One,
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+        Wildcard { tokens }
+    }
+
+    /// Whether the pattern matches `uri`, as it is.
+    pub fn matches(&self, uri: &str) -> bool {
+        if self.tokens.is_empty() {
+            return false;
+        }
+        let text: Vec<char> = uri.chars().collect();
+        let (mut p, mut t) = (0, 0);
+        // Where to go back to if the rest does not match: after the last
+        // `*`, which then takes one more character.
+        let mut star = None;
+        while t < text.len() {
+            match self.tokens.get(p) {
+                Some(Token::Many) => {
+                    star = Some((p + 1, t));
+                    p += 1;
+                }
+                Some(Token::One) => {
+                    p += 1;
+                    t += 1;
+                }
+                Some(Token::Char(c)) if *c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match star {
+                    Some((after, from)) => {
+                        star = Some((after, from + 1));
+                        p = after;
+                        t = from + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.tokens[p..].iter().all(|t| *t == Token::Many)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum UriSpec {
+    Any,
+    Origin(String),
+    Wildcard(String),
+    Regex(String),
+}
+
+/// One rule of a `Policy`. A rule matches a check when all of its
+/// conditions do; a rule without conditions matches everything.
+///
+/// Conditions on the navigation kind or on whether the user started it
+/// only match checks that know those.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    name: String,
+    action: Action,
+    events: Vec<Event>,
+    uri: UriSpec,
+    contexts: Vec<ResourceContext>,
+    kinds: Vec<NavigationKind>,
+    user_initiated: Option<bool>,
+}
+
+impl Rule {
+    /// A rule that allows what it matches. The name is what gets logged.
+    pub fn allow(name: &str) -> Self {
+        Rule::new(name, Action::Allow)
+    }
+
+    pub fn deny(name: &str) -> Self {
+        Rule::new(name, Action::Deny)
+    }
+
+    fn new(name: &str, action: Action) -> Self {
+        Rule {
+            name: name.to_owned(),
+            action,
+            events: Vec::new(),
+            uri: UriSpec::Any,
+            contexts: Vec::new(),
+            kinds: Vec::new(),
+            user_initiated: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// Only match checks of `event`, or of any of the events given this way.
+    pub fn on(mut self, event: Event) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// Only match URIs of `origin`, like `https://app.local`.
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.uri = UriSpec::Origin(origin.to_owned());
+        self
+    }
+
+    /// Only match URIs matching a WebView2 wildcard pattern.
+    pub fn wildcard(mut self, pattern: &str) -> Self {
+        self.uri = UriSpec::Wildcard(pattern.to_owned());
+        self
+    }
+
+    /// Only match URIs a regular expression finds a match in. Anchor it with
+    /// `^` and `$` to match whole URIs.
+    pub fn regex(mut self, regex: &str) -> Self {
+        self.uri = UriSpec::Regex(regex.to_owned());
+        self
+    }
+
+    /// Only match web resources of `context`, or of any of the contexts
+    /// given this way. `ResourceContext::All` matches any.
+    pub fn context(mut self, context: ResourceContext) -> Self {
+        self.contexts.push(context);
+        self
+    }
+
+    /// Only match navigations of `kind`, or of any of the kinds given this
+    /// way.
+    pub fn kind(mut self, kind: NavigationKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Only match checks that the user started, or did not.
+    pub fn user_initiated(mut self, user_initiated: bool) -> Self {
+        self.user_initiated = Some(user_initiated);
+        self
+    }
+}
+
+/// Why `PolicyBuilder::build` failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleError {
+    /// The origin is not `scheme://host[:port]`.
+    Origin {
+        rule: String,
+        origin: String,
+    },
+    Regex {
+        rule: String,
+        error: regex::Error,
+    },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::Origin { rule, origin } => {
+                write!(f, "rule {:?}: invalid origin {:?}", rule, origin)
+            }
+            RuleError::Regex { rule, error } => write!(f, "rule {:?}: {}", rule, error),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Debug)]
+enum Matcher {
+    Any,
+    Origin(String),
+    Wildcard(Wildcard),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn compile(rule: &Rule) -> Result<Self, RuleError> {
+        Ok(match &rule.uri {
+            UriSpec::Any => Matcher::Any,
+            UriSpec::Origin(origin) => {
+                let normalized = normalize_uri(origin.trim_end_matches('/'));
+                match origin_of(&normalized) {
+                    Some(o) if o.len() + 1 == normalized.len() => Matcher::Origin(o.to_owned()),
+                    _ => {
+                        return Err(RuleError::Origin {
+                            rule: rule.name.clone(),
+                            origin: origin.clone(),
+                        })
+                    }
+                }
+            }
+            UriSpec::Wildcard(pattern) => Matcher::Wildcard(Wildcard::new(pattern)),
+            UriSpec::Regex(regex) => {
+                Matcher::Regex(Regex::new(regex).map_err(|error| RuleError::Regex {
+                    rule: rule.name.clone(),
+                    error,
+                })?)
+            }
+        })
+    }
+
+    fn matches(&self, uri: &str) -> bool {
+        match self {
+            Matcher::Any => true,
+            Matcher::Origin(origin) => origin_of(uri) == Some(origin.as_str()),
+            Matcher::Wildcard(wildcard) => wildcard.matches(uri),
+            Matcher::Regex(regex) => regex.is_match(uri),
+        }
+    }
+}
+
+/// Something to decide on.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Check<'a> {
+    pub event: Event,
+    pub uri: &'a str,
+    pub context: Option<ResourceContext>,
+    pub kind: Option<NavigationKind>,
+    pub user_initiated: Option<bool>,
+}
+
+impl<'a> Check<'a> {
+    pub fn new(event: Event, uri: &'a str) -> Self {
+        Check {
+            event,
+            uri,
+            context: None,
+            kind: None,
+            user_initiated: None,
+        }
+    }
+
+    pub fn navigation(uri: &'a str) -> Self {
+        Check::new(Event::Navigation, uri)
+    }
+
+    pub fn frame_navigation(uri: &'a str) -> Self {
+        Check::new(Event::FrameNavigation, uri)
+    }
+
+    pub fn new_window(uri: &'a str) -> Self {
+        Check::new(Event::NewWindow, uri)
+    }
+
+    pub fn web_resource(uri: &'a str, context: ResourceContext) -> Self {
+        Check {
+            context: Some(context),
+            ..Check::new(Event::WebResource, uri)
+        }
+    }
+
+    pub fn with_kind(mut self, kind: NavigationKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn with_user_initiated(mut self, user_initiated: bool) -> Self {
+        self.user_initiated = Some(user_initiated);
+        self
+    }
+}
+
+/// The outcome of a check, and the name of the rule that decided it, or
+/// `None` if it was the default.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Decision<'a> {
+    pub action: Action,
+    pub rule: Option<&'a str>,
+}
+
+impl<'a> Decision<'a> {
+    pub fn is_allowed(&self) -> bool {
+        self.action == Action::Allow
+    }
+}
+
+struct Compiled {
+    rule: Rule,
+    matcher: Matcher,
+}
+
+impl Compiled {
+    fn matches(&self, check: &Check, uri: &str) -> bool {
+        fn any<T: PartialEq>(allowed: &[T], value: Option<T>) -> bool {
+            allowed.is_empty()
+                || match value {
+                    Some(value) => allowed.contains(&value),
+                    None => false,
+                }
+        }
+        let rule = &self.rule;
+        let any_context = rule.contexts.contains(&ResourceContext::All);
+        let context = check
+            .context
+            .map(|c| if any_context { ResourceContext::All } else { c });
+        (rule.events.is_empty() || rule.events.contains(&check.event))
+            && any(&rule.contexts, context)
+            && any(&rule.kinds, check.kind)
+            && (rule.user_initiated.is_none() || rule.user_initiated == check.user_initiated)
+            && self.matcher.matches(uri)
+    }
+}
+
+type Logger = Box<dyn Fn(&Check, &Decision)>;
+
+/// Builds a `Policy`.
+pub struct PolicyBuilder {
+    default: Action,
+    rules: Vec<Rule>,
+    logger: Option<Logger>,
+}
+
+impl PolicyBuilder {
+    /// Add a rule, after the rules added before.
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Call `f` with every check and its decision.
+    pub fn logger(mut self, f: impl Fn(&Check, &Decision) + 'static) -> Self {
+        self.logger = Some(Box::new(f));
+        self
+    }
+
+    /// Compile the rules. Fails on the first invalid origin or regular
+    /// expression.
+    pub fn build(self) -> Result<Policy, RuleError> {
+        let rules = self
+            .rules
+            .into_iter()
+            .map(|rule| {
+                Ok(Compiled {
+                    matcher: Matcher::compile(&rule)?,
+                    rule,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Policy {
+            default: self.default,
+            rules,
+            logger: self.logger,
+        })
+    }
+}
+
+impl fmt::Debug for PolicyBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PolicyBuilder")
+            .field("default", &self.default)
+            .field("rules", &self.rules)
+            .finish()
+    }
+}
+
+/// Compiled rules, evaluated in order.
+pub struct Policy {
+    default: Action,
+    rules: Vec<Compiled>,
+    logger: Option<Logger>,
+}
+
+impl Policy {
+    /// A policy that takes `default` for checks no rule matches.
+    pub fn builder(default: Action) -> PolicyBuilder {
+        PolicyBuilder {
+            default,
+            rules: Vec::new(),
+            logger: None,
+        }
+    }
+
+    pub fn evaluate(&self, check: &Check) -> Decision<'_> {
+        let uri = normalize_uri(check.uri);
+        let decision = match self.rules.iter().find(|c| c.matches(check, &uri)) {
+            Some(c) => Decision {
+                action: c.rule.action,
+                rule: Some(&c.rule.name),
+            },
+            None => Decision {
+                action: self.default,
+                rule: None,
+            },
+        };
+        if let Some(logger) = &self.logger {
+            logger(check, &decision);
+        }
+        decision
+    }
+
+    pub fn allows(&self, check: &Check) -> bool {
+        self.evaluate(check).is_allowed()
+    }
+
+    /// Whether checks of `event` can be denied at all.
+    pub fn can_deny(&self, event: Event) -> bool {
+        self.default == Action::Deny
+            || self.rules.iter().any(|c| {
+                c.rule.action == Action::Deny
+                    && (c.rule.events.is_empty() || c.rule.events.contains(&event))
+            })
+    }
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Policy")
+            .field("default", &self.default)
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::{
+        Environment, EventRegistrationToken, NavigationKind as RawKind,
+        NavigationStartingEventArgs, Result, Stream, WebResourceContext as RawContext, WebView,
+    };
+    use std::rc::Rc;
+
+    impl From<RawContext> for ResourceContext {
+        fn from(context: RawContext) -> Self {
+            match context {
+                RawContext::All => ResourceContext::All,
+                RawContext::Document => ResourceContext::Document,
+                RawContext::Stylesheet => ResourceContext::Stylesheet,
+                RawContext::Image => ResourceContext::Image,
+                RawContext::Media => ResourceContext::Media,
+                RawContext::Font => ResourceContext::Font,
+                RawContext::Script => ResourceContext::Script,
+                RawContext::XmlHttpRequest => ResourceContext::XmlHttpRequest,
+                RawContext::Fetch => ResourceContext::Fetch,
+                RawContext::TextTrack => ResourceContext::TextTrack,
+                RawContext::EventSource => ResourceContext::EventSource,
+                RawContext::Websocket => ResourceContext::Websocket,
+                RawContext::Manifest => ResourceContext::Manifest,
+                RawContext::SignedExchange => ResourceContext::SignedExchange,
+                RawContext::Ping => ResourceContext::Ping,
+                RawContext::CspViolationReport => ResourceContext::CspViolationReport,
+                RawContext::Other => ResourceContext::Other,
+            }
+        }
+    }
+
+    impl From<RawKind> for NavigationKind {
+        fn from(kind: RawKind) -> Self {
+            match kind {
+                RawKind::Reload => NavigationKind::Reload,
+                RawKind::BackOrForward => NavigationKind::BackOrForward,
+                RawKind::NewDocument => NavigationKind::NewDocument,
+            }
+        }
+    }
+
+    /// The event handlers `Policy::attach` added.
+    #[derive(Debug)]
+    pub struct Attached {
+        tokens: Vec<(Event, EventRegistrationToken)>,
+    }
+
+    impl Attached {
+        /// Stop enforcing the policy. The web resource filter stays.
+        pub fn detach(self, webview: &WebView) -> Result<()> {
+            for (event, token) in self.tokens {
+                match event {
+                    Event::Navigation => webview.remove_navigation_starting(token)?,
+                    Event::FrameNavigation => webview.remove_frame_navigation_starting(token)?,
+                    Event::NewWindow => webview.remove_new_window_requested(token)?,
+                    Event::WebResource => webview.remove_web_resource_requested(token)?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn navigation_check<'a>(
+        event: Event,
+        uri: &'a str,
+        args: &NavigationStartingEventArgs,
+    ) -> Result<Check<'a>> {
+        let mut check = Check::new(event, uri).with_user_initiated(args.get_is_user_initiated()?);
+        // Older runtimes do not tell the kind.
+        if let Ok(args3) = args.get_args3() {
+            check = check.with_kind(args3.get_navigation_kind()?.into());
+        }
+        Ok(check)
+    }
+
+    impl Policy {
+        /// Enforce the policy on `webview`: cancel denied navigations, drop
+        /// denied new windows and answer denied web resource requests with
+        /// `403 Forbidden`.
+        ///
+        /// Web resources are only checked if the policy can deny some, since
+        /// that means filtering every request.
+        pub fn attach(self, webview: &WebView, environment: Environment) -> Result<Attached> {
+            let policy = Rc::new(self);
+            let mut tokens = Vec::new();
+            for &event in &[Event::Navigation, Event::FrameNavigation] {
+                let p = policy.clone();
+                let handler = move |_: WebView, args: NavigationStartingEventArgs| {
+                    let uri = args.get_uri()?;
+                    if !p.allows(&navigation_check(event, &uri, &args)?) {
+                        args.put_cancel(true)?;
+                    }
+                    Ok(())
+                };
+                let token = match event {
+                    Event::Navigation => webview.add_navigation_starting(handler)?,
+                    _ => webview.add_frame_navigation_starting(handler)?,
+                };
+                tokens.push((event, token));
+            }
+
+            let p = policy.clone();
+            let token = webview.add_new_window_requested(move |_, args| {
+                let uri = args.get_uri()?;
+                let check =
+                    Check::new_window(&uri).with_user_initiated(args.get_is_user_initiated()?);
+                if !p.allows(&check) {
+                    args.put_handled(true)?;
+                }
+                Ok(())
+            })?;
+            tokens.push((Event::NewWindow, token));
+
+            if policy.can_deny(Event::WebResource) {
+                webview.add_web_resource_requested_filter("*", RawContext::All)?;
+                let token = webview.add_web_resource_requested(move |_, args| {
+                    let uri = args.get_request()?.get_uri()?;
+                    let check = Check::web_resource(&uri, args.get_resource_context()?.into());
+                    if !policy.allows(&check) {
+                        let response = environment.create_web_resource_response(
+                            Stream::from_bytes(&[]),
+                            403,
+                            "Forbidden",
+                            "",
+                        )?;
+                        args.put_response(response)?;
+                    }
+                    Ok(())
+                })?;
+                tokens.push((Event::WebResource, token));
+            }
+            Ok(Attached { tokens })
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::Attached;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::Rng;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_wildcard() {
+        let matches = |pattern: &str, uri: &str| Wildcard::new(pattern).matches(uri);
+        assert!(matches("*", "https://a/"));
+        assert!(matches("*", ""));
+        assert!(!matches("", ""));
+        assert!(!matches("", "https://a/"));
+        assert!(matches("https://a/*", "https://a/"));
+        assert!(matches("https://a/*", "https://a/b/c?d"));
+        assert!(!matches("https://a/*", "https://ab/"));
+        assert!(matches("https://*.a.com/*", "https://x.y.a.com/z"));
+        assert!(!matches("https://*.a.com/*", "https://a.com/z"));
+        // `*` is not limited to one part of the URI.
+        assert!(matches("https://*.a.com/*", "https://evil.com/.a.com/"));
+        assert!(matches("*.png", "https://a/b.png"));
+        assert!(!matches("*.png", "https://a/b.png?x"));
+        assert!(matches("https://a/?", "https://a/b"));
+        assert!(!matches("https://a/?", "https://a/"));
+        assert!(!matches("https://a/?", "https://a/bc"));
+        assert!(matches(r"https://a/\*", "https://a/*"));
+        assert!(!matches(r"https://a/\*", "https://a/b"));
+        assert!(matches(r"https://a/\?", "https://a/?"));
+        assert!(!matches(r"https://a/\?", "https://a/b"));
+        assert!(matches(r"https://a/\b", r"https://a/\b"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(!matches("*a*b*", "xxbxxaxx"));
+        // Matching is case sensitive. URIs are normalized instead.
+        assert!(!matches("https://a/B", "https://a/b"));
+    }
+
+    /// Compare with a straightforward recursive matcher.
+    #[test]
+    fn test_wildcard_random() {
+        fn reference(p: &[char], t: &[char]) -> bool {
+            match p.split_first() {
+                None => t.is_empty(),
+                Some(('*', rest)) => (0..=t.len()).any(|i| reference(rest, &t[i..])),
+                Some(('?', rest)) => !t.is_empty() && reference(rest, &t[1..]),
+                Some((c, rest)) => t.first() == Some(c) && reference(rest, &t[1..]),
+            }
+        }
+        let mut rng = Rng(44);
+        for _ in 0..2000 {
+            let p: Vec<char> = (0..rng.range(1, 7))
+                .map(|_| rng.pick(&['a', 'b', '/', '*', '?']))
+                .collect();
+            let t: Vec<char> = (0..rng.range(0, 8))
+                .map(|_| rng.pick(&['a', 'b', '/']))
+                .collect();
+            let pattern: String = p.iter().collect();
+            let text: String = t.iter().collect();
+            assert_eq!(
+                Wildcard::new(&pattern).matches(&text),
+                reference(&p, &t),
+                "{:?} {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    fn policy() -> Policy {
+        Policy::builder(Action::Deny)
+            .rule(
+                Rule::deny("history")
+                    .on(Event::Navigation)
+                    .kind(NavigationKind::Reload)
+                    .kind(NavigationKind::BackOrForward),
+            )
+            .rule(Rule::allow("app").origin("https://APP.local/"))
+            .rule(
+                Rule::allow("popups")
+                    .on(Event::NewWindow)
+                    .wildcard("https://*.example.com/*")
+                    .user_initiated(true),
+            )
+            .rule(
+                Rule::deny("trackers")
+                    .on(Event::WebResource)
+                    .regex(r"^https://[^/]*tracker\."),
+            )
+            .rule(
+                Rule::allow("images")
+                    .on(Event::WebResource)
+                    .context(ResourceContext::Image)
+                    .context(ResourceContext::Media),
+            )
+            .rule(
+                Rule::allow("frames")
+                    .on(Event::FrameNavigation)
+                    .wildcard("https://embed.example.com/*"),
+            )
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = policy();
+        let decide = |check: Check| policy.evaluate(&check).rule;
+
+        assert_eq!(
+            decide(Check::navigation("https://app.local/a#b")),
+            Some("app")
+        );
+        assert_eq!(
+            decide(Check::navigation("https://app.local:443")),
+            Some("app")
+        );
+        assert_eq!(decide(Check::navigation("https://app.local.evil/")), None);
+        assert_eq!(
+            decide(Check::navigation("https://app.local/").with_kind(NavigationKind::Reload)),
+            Some("history")
+        );
+        assert_eq!(
+            decide(Check::navigation("https://app.local/").with_kind(NavigationKind::NewDocument)),
+            Some("app")
+        );
+        // The history rule is for the top level document only.
+        assert_eq!(
+            decide(Check::frame_navigation("https://app.local/").with_kind(NavigationKind::Reload)),
+            Some("app")
+        );
+        assert_eq!(
+            decide(Check::frame_navigation("https://embed.example.com/v")),
+            Some("frames")
+        );
+        assert_eq!(
+            decide(Check::navigation("https://embed.example.com/v")),
+            None
+        );
+
+        let popup = Check::new_window("https://www.example.com/");
+        assert_eq!(decide(popup), None);
+        assert_eq!(decide(popup.with_user_initiated(false)), None);
+        assert_eq!(decide(popup.with_user_initiated(true)), Some("popups"));
+
+        let resource = |uri, context| decide(Check::web_resource(uri, context));
+        assert_eq!(
+            resource("https://tracker.example.com/p.gif", ResourceContext::Image),
+            Some("trackers")
+        );
+        assert_eq!(
+            resource("https://cdn.example.com/p.gif", ResourceContext::Image),
+            Some("images")
+        );
+        assert_eq!(
+            resource("https://cdn.example.com/a.js", ResourceContext::Script),
+            None
+        );
+        assert_eq!(
+            resource("https://app.local/a.js", ResourceContext::Script),
+            Some("app")
+        );
+
+        assert!(policy.allows(&Check::navigation("https://app.local/")));
+        assert!(!policy.allows(&Check::navigation("https://example.com/")));
+    }
+
+    #[test]
+    fn test_any_context() {
+        let policy = Policy::builder(Action::Allow)
+            .rule(Rule::deny("resources").context(ResourceContext::All))
+            .build()
+            .unwrap();
+        assert!(!policy.allows(&Check::web_resource("https://a/", ResourceContext::Font)));
+        // Navigations have no resource context.
+        assert!(policy.allows(&Check::navigation("https://a/")));
+        assert!(policy.can_deny(Event::Navigation));
+        assert!(!Policy::builder(Action::Allow)
+            .rule(Rule::deny("frames").on(Event::FrameNavigation))
+            .build()
+            .unwrap()
+            .can_deny(Event::WebResource));
+    }
+
+    #[test]
+    fn test_logger() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let l = log.clone();
+        let policy = Policy::builder(Action::Allow)
+            .rule(Rule::deny("evil").origin("https://evil.com"))
+            .logger(move |check, decision| {
+                l.borrow_mut().push(format!(
+                    "{} {:?} {:?}",
+                    check.uri, decision.action, decision.rule
+                ))
+            })
+            .build()
+            .unwrap();
+        policy.evaluate(&Check::navigation("https://evil.com/x"));
+        policy.evaluate(&Check::new_window("https://good.com/"));
+        assert_eq!(
+            *log.borrow(),
+            [
+                r#"https://evil.com/x Deny Some("evil")"#,
+                "https://good.com/ Allow None",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        for origin in &[
+            "app.local",
+            "https://app.local/path",
+            "https://",
+            "about:blank",
+        ] {
+            let error = Policy::builder(Action::Allow)
+                .rule(Rule::allow("bad").origin(origin))
+                .build()
+                .unwrap_err();
+            assert_eq!(
+                error,
+                RuleError::Origin {
+                    rule: "bad".to_owned(),
+                    origin: (*origin).to_owned()
+                }
+            );
+        }
+        let error = Policy::builder(Action::Allow)
+            .rule(Rule::allow("fine").origin("http://localhost:8080"))
+            .rule(Rule::deny("bad").regex("("))
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, RuleError::Regex { ref rule, .. } if rule == "bad"));
+        assert!(error.to_string().starts_with("rule \"bad\": "));
+    }
+}
@@ -0,0 +1,1133 @@
+//! Rewriting intercepted responses before the page gets them.
+//!
+//! A `Rewriter` runs transforms, in the order they were added, over a
+//! response from a `Source`: the asset server, the router, or an HTTP client
+//! fetching from upstream. Transforms change a `Content`, which decodes the
+//! body to text the first time a transform asks for it. Decoding undoes
+//! `gzip` and `deflate` content encodings and honours the charset of the
+//! `Content-Type`, a byte order mark or, in HTML, a `<meta charset>`. A
+//! response that was decoded is sent as UTF-8. Bodies longer than a limit,
+//! `DEFAULT_LIMIT` unless set otherwise, before or after decompression, are
+//! not decoded and go out as they came.
+//!
+//! A body can be a stream, which is only read into memory if a transform
+//! needs its content; otherwise the webview reads it as it goes. On Windows,
+//! `Rewriter::attach` takes an `AsyncSource`, which can answer after the
+//! event handler returned, e.g. once an HTTP client on another thread has
+//! posted the response back to the UI thread.
+//!
+//! ```
+//! use webview2::rewrite::{HeaderEdit, InjectHead, Replace, Rewriter};
+//! use webview2::web_resource::http::{header, Response};
+//!
+//! let rewriter = Rewriter::new()
+//!     .with(InjectHead::new("<script src=\"https://app.local/patch.js\"></script>"))
+//!     .with(Replace::new("https://cdn.example.com/", "https://app.local/cdn/"))
+//!     .with(HeaderEdit::new().remove(header::CONTENT_SECURITY_POLICY));
+//! let response = Response::builder()
+//!     .header("content-type", "text/html; charset=iso-8859-1")
+//!     .header("content-security-policy", "script-src 'self'")
+//!     .body(b"<html><head><title>Caf\xe9</title></head>\
+//!             <img src=\"https://cdn.example.com/a.png\"></html>".to_vec())
+//!     .unwrap();
+//! let response = rewriter.rewrite(response);
+//! assert_eq!(
+//!     std::str::from_utf8(response.body().bytes().unwrap()).unwrap(),
+//!     "<html><head><script src=\"https://app.local/patch.js\"></script>\
+//!      <title>Café</title></head><img src=\"https://app.local/cdn/a.png\"></html>"
+//! );
+//! assert_eq!(response.headers()["content-type"], "text/html; charset=utf-8");
+//! assert!(!response.headers().contains_key("content-security-policy"));
+//! ```
+
+use crate::stream::SharedStream;
+use crate::web_resource::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use crate::web_resource::http::{response, Request, Response, StatusCode};
+use crate::web_resource::Body;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use std::fmt;
+use std::io::{self, Read};
+
+/// The default limit on the length of a body that is decoded, in bytes.
+pub const DEFAULT_LIMIT: u64 = 16 << 20;
+
+/// A response body: in memory, or a stream the webview reads as it goes.
+#[derive(Clone)]
+pub enum Payload {
+    Buffered(Body),
+    Streamed(SharedStream),
+}
+
+impl Payload {
+    /// The body, if it is in memory.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            Payload::Buffered(body) => Some(body),
+            Payload::Streamed(_) => None,
+        }
+    }
+
+    /// The body in memory, read to the end if it is a stream.
+    pub fn into_body(self) -> io::Result<Body> {
+        match self {
+            Payload::Buffered(body) => Ok(body),
+            Payload::Streamed(mut stream) => {
+                let mut body = Vec::new();
+                stream.read_to_end(&mut body)?;
+                Ok(body)
+            }
+        }
+    }
+}
+
+impl From<Body> for Payload {
+    fn from(body: Body) -> Self {
+        Payload::Buffered(body)
+    }
+}
+
+impl From<SharedStream> for Payload {
+    fn from(stream: SharedStream) -> Self {
+        Payload::Streamed(stream)
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Payload::Buffered(body) => f.debug_tuple("Buffered").field(&body.len()).finish(),
+            Payload::Streamed(stream) => f.debug_tuple("Streamed").field(&stream.len()).finish(),
+        }
+    }
+}
+
+/// A response being rewritten.
+pub struct Content {
+    head: response::Parts,
+    body: Body,
+    /// The body while nothing needed it in memory. `body` is empty then.
+    stream: Option<SharedStream>,
+    /// The body as text, once a transform asked for it. `body` is stale then.
+    text: Option<String>,
+    /// The body could not be decoded as text.
+    binary: bool,
+    limit: u64,
+}
+
+impl Content {
+    pub fn new<B: Into<Payload>>(response: Response<B>) -> Self {
+        let (head, body) = response.into_parts();
+        let (body, stream) = match body.into() {
+            Payload::Buffered(body) => (body, None),
+            Payload::Streamed(stream) => (Vec::new(), Some(stream)),
+        };
+        Content {
+            head,
+            body,
+            stream,
+            text: None,
+            binary: false,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+
+    /// Only decode bodies of at most `limit` bytes, before and after
+    /// decompression.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.head.status
+    }
+
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.head.status = status;
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.head.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.head.headers
+    }
+
+    /// The MIME type of the `Content-Type`, in lower case and without
+    /// parameters.
+    pub fn mime_type(&self) -> Option<String> {
+        let value = self.head.headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        let essence = value.split(';').next().unwrap_or("").trim();
+        Some(essence.to_ascii_lowercase())
+    }
+
+    pub fn is_html(&self) -> bool {
+        matches!(self.mime_type().as_deref(), Some("text/html"))
+    }
+
+    /// Whether the `Content-Type` is one that `text` decodes.
+    pub fn is_text(&self) -> bool {
+        match self.mime_type() {
+            Some(mime) => {
+                mime.starts_with("text/")
+                    || mime.ends_with("+json")
+                    || mime.ends_with("+xml")
+                    || matches!(
+                        mime.as_str(),
+                        "application/javascript"
+                            | "application/x-javascript"
+                            | "application/ecmascript"
+                            | "application/json"
+                            | "application/xml"
+                    )
+            }
+            None => false,
+        }
+    }
+
+    /// The body as text, or `None` if it is not text, cannot be decoded or
+    /// is over the limit.
+    pub fn text(&mut self) -> Option<&str> {
+        self.text_mut().map(|t| &**t)
+    }
+
+    pub fn text_mut(&mut self) -> Option<&mut String> {
+        if self.text.is_none() && !self.binary {
+            match self.decode() {
+                Some(text) => {
+                    self.text = Some(text);
+                    self.stream = None;
+                    self.head.headers.remove(header::CONTENT_ENCODING);
+                    set_charset_utf8(&mut self.head.headers);
+                }
+                None => self.binary = true,
+            }
+        }
+        self.text.as_mut()
+    }
+
+    /// The body as it will be sent, read into memory if it is a stream.
+    pub fn body_mut(&mut self) -> io::Result<&mut Body> {
+        if let Some(text) = self.text.take() {
+            self.body = text.into_bytes();
+        }
+        if let Some(stream) = &self.stream {
+            let mut body = Vec::new();
+            stream.clone().read_to_end(&mut body)?;
+            self.body = body;
+            self.stream = None;
+        }
+        Ok(&mut self.body)
+    }
+
+    /// The response, with a stream that no transform needed still a stream.
+    pub fn into_response(mut self) -> Response<Payload> {
+        if let Some(text) = self.text.take() {
+            self.body = text.into_bytes();
+        }
+        let body = match self.stream {
+            Some(stream) => Payload::Streamed(stream),
+            None => {
+                if self.head.headers.contains_key(header::CONTENT_LENGTH) {
+                    self.head
+                        .headers
+                        .insert(header::CONTENT_LENGTH, self.body.len().into());
+                }
+                Payload::Buffered(self.body)
+            }
+        };
+        Response::from_parts(self.head, body)
+    }
+
+    fn decode(&self) -> Option<String> {
+        if !self.is_text() {
+            return None;
+        }
+        let body = match &self.stream {
+            Some(stream) => read_limited(stream.clone(), self.limit).ok()??,
+            None if self.body.len() as u64 > self.limit => return None,
+            None => self.body.clone(),
+        };
+        let bytes = decompress(&self.head.headers, body, self.limit)?;
+        let encoding = self
+            .charset_label()
+            .or_else(|| {
+                if self.is_html() {
+                    meta_charset(&bytes)
+                } else {
+                    None
+                }
+            })
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .unwrap_or(UTF_8);
+        // A byte order mark wins over the label.
+        let (text, _, had_errors) = encoding.decode(&bytes);
+        if had_errors {
+            None
+        } else {
+            Some(text.into_owned())
+        }
+    }
+
+    fn charset_label(&self) -> Option<String> {
+        let value = self.head.headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        value.split(';').skip(1).find_map(|param| {
+            let (name, value) = split_once(param, '=')?;
+            if name.trim().eq_ignore_ascii_case("charset") {
+                Some(value.trim().trim_matches('"').to_owned())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl fmt::Debug for Content {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Content")
+            .field("head", &self.head)
+            .field("len", &self.body.len())
+            .field("streamed", &self.stream.is_some())
+            .field("decoded", &self.text.is_some())
+            .finish()
+    }
+}
+
+fn split_once(s: &str, c: char) -> Option<(&str, &str)> {
+    let i = s.find(c)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+/// `reader` read to the end, or `None` if that is more than `limit` bytes.
+fn read_limited(reader: impl Read, limit: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut out = Vec::new();
+    reader.take(limit.saturating_add(1)).read_to_end(&mut out)?;
+    Ok(if out.len() as u64 > limit {
+        None
+    } else {
+        Some(out)
+    })
+}
+
+/// The body without its content encoding, or `None` for encodings other
+/// than `gzip` and `deflate` and for bodies that decompress to more than
+/// `limit` bytes.
+fn decompress(headers: &HeaderMap, body: Vec<u8>, limit: u64) -> Option<Vec<u8>> {
+    let encoding = match headers.get(header::CONTENT_ENCODING) {
+        Some(value) => value.to_str().ok()?.trim().to_ascii_lowercase(),
+        None => return Some(body),
+    };
+    match encoding.as_str() {
+        "" | "identity" => Some(body),
+        "gzip" | "x-gzip" => read_limited(flate2::read::GzDecoder::new(&body[..]), limit).ok()?,
+        // Servers send both zlib wrapped and raw deflate.
+        "deflate" => match read_limited(flate2::read::ZlibDecoder::new(&body[..]), limit) {
+            Ok(out) => out,
+            Err(_) => read_limited(flate2::read::DeflateDecoder::new(&body[..]), limit).ok()?,
+        },
+        _ => None,
+    }
+}
+
+/// The charset of a `<meta charset>` or `<meta http-equiv="Content-Type">`
+/// in the first 1024 bytes of an HTML document.
+fn meta_charset(html: &[u8]) -> Option<String> {
+    let prefix = &html[..html.len().min(1024)];
+    let prefix = String::from_utf8_lossy(prefix).to_ascii_lowercase();
+    let mut rest = prefix.as_str();
+    while let Some(i) = rest.find("<meta") {
+        rest = &rest[i + 5..];
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        if let Some(j) = tag.find("charset=") {
+            let value = tag[j + 8..].trim_start_matches(&['"', '\''][..]);
+            let end = value
+                .find(&['"', '\'', ';', ' ', '/', '>'][..])
+                .unwrap_or(value.len());
+            if end > 0 {
+                return Some(value[..end].to_owned());
+            }
+        }
+    }
+    None
+}
+
+fn set_charset_utf8(headers: &mut HeaderMap) {
+    let value = match headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(value) => value,
+        None => return,
+    };
+    let mut parts: Vec<&str> = value
+        .split(';')
+        .map(str::trim)
+        .filter(|p| match split_once(p, '=') {
+            Some((name, _)) => !name.trim().eq_ignore_ascii_case("charset"),
+            None => true,
+        })
+        .collect();
+    parts.push("charset=utf-8");
+    if let Ok(value) = HeaderValue::from_str(&parts.join("; ")) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+}
+
+/// One step of a `Rewriter`.
+pub trait Transform {
+    fn apply(&self, content: &mut Content);
+}
+
+impl<F: Fn(&mut Content)> Transform for F {
+    fn apply(&self, content: &mut Content) {
+        self(content)
+    }
+}
+
+/// Inserts HTML right after the `<head>` tag of HTML documents, or after
+/// `<html>` if there is no `<head>`, or at the start.
+#[derive(Debug, Clone)]
+pub struct InjectHead {
+    html: String,
+}
+
+impl InjectHead {
+    pub fn new(html: &str) -> Self {
+        InjectHead {
+            html: html.to_owned(),
+        }
+    }
+}
+
+/// The end of the first `<name ...>` start tag in `html`.
+fn after_start_tag(html: &str, name: &str) -> Option<usize> {
+    let lower = html.to_ascii_lowercase();
+    let needle = format!("<{}", name);
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(&needle) {
+        let start = from + i + needle.len();
+        match lower[start..].chars().next() {
+            Some('>') | Some('/') => return Some(start + lower[start..].find('>')? + 1),
+            Some(c) if c.is_ascii_whitespace() => {
+                return Some(start + lower[start..].find('>')? + 1)
+            }
+            _ => from = start,
+        }
+    }
+    None
+}
+
+impl Transform for InjectHead {
+    fn apply(&self, content: &mut Content) {
+        if !content.is_html() {
+            return;
+        }
+        if let Some(text) = content.text_mut() {
+            let at = after_start_tag(text, "head")
+                .or_else(|| after_start_tag(text, "html"))
+                .unwrap_or(0);
+            text.insert_str(at, &self.html);
+        }
+    }
+}
+
+enum HeaderOp {
+    Set(HeaderName, HeaderValue),
+    Append(HeaderName, HeaderValue),
+    Remove(HeaderName),
+    Edit(HeaderName, Box<dyn Fn(&str) -> Option<String>>),
+}
+
+/// Changes response headers, in the order the changes were added.
+#[derive(Default)]
+pub struct HeaderEdit {
+    ops: Vec<HeaderOp>,
+}
+
+impl HeaderEdit {
+    pub fn new() -> Self {
+        HeaderEdit::default()
+    }
+
+    /// Replace all values of `name` with `value`.
+    pub fn set(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.ops.push(HeaderOp::Set(name, value));
+        self
+    }
+
+    pub fn append(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.ops.push(HeaderOp::Append(name, value));
+        self
+    }
+
+    pub fn remove(mut self, name: HeaderName) -> Self {
+        self.ops.push(HeaderOp::Remove(name));
+        self
+    }
+
+    /// Map each value of `name` with `f`, which removes the values it
+    /// returns `None` or an invalid value for. Values that are not visible
+    /// ASCII are left alone.
+    pub fn edit(mut self, name: HeaderName, f: impl Fn(&str) -> Option<String> + 'static) -> Self {
+        self.ops.push(HeaderOp::Edit(name, Box::new(f)));
+        self
+    }
+}
+
+impl Transform for HeaderEdit {
+    fn apply(&self, content: &mut Content) {
+        let headers = content.headers_mut();
+        for op in &self.ops {
+            match op {
+                HeaderOp::Set(name, value) => {
+                    headers.insert(name, value.clone());
+                }
+                HeaderOp::Append(name, value) => {
+                    headers.append(name, value.clone());
+                }
+                HeaderOp::Remove(name) => {
+                    headers.remove(name);
+                }
+                HeaderOp::Edit(name, f) => {
+                    let values: Vec<HeaderValue> = headers
+                        .get_all(name)
+                        .iter()
+                        .filter_map(|value| match value.to_str() {
+                            Ok(s) => f(s).and_then(|s| HeaderValue::from_str(&s).ok()),
+                            Err(_) => Some(value.clone()),
+                        })
+                        .collect();
+                    headers.remove(name);
+                    for value in values {
+                        headers.append(name, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for HeaderEdit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HeaderEdit")
+            .field("ops", &self.ops.len())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Find {
+    Text(String),
+    Regex(Regex),
+}
+
+/// Replaces text in text bodies.
+#[derive(Debug, Clone)]
+pub struct Replace {
+    find: Find,
+    with: String,
+    mime_types: Vec<String>,
+}
+
+impl Replace {
+    /// Replace every `from` with `to`.
+    pub fn new(from: &str, to: &str) -> Self {
+        Replace {
+            find: Find::Text(from.to_owned()),
+            with: to.to_owned(),
+            mime_types: Vec::new(),
+        }
+    }
+
+    /// Replace every match of `regex` with `to`, which can refer to groups
+    /// like `$1`.
+    pub fn regex(regex: &str, to: &str) -> Result<Self, regex::Error> {
+        Ok(Replace {
+            find: Find::Regex(Regex::new(regex)?),
+            with: to.to_owned(),
+            mime_types: Vec::new(),
+        })
+    }
+
+    /// Only replace in bodies of `mime_type`, or of any of the MIME types
+    /// given this way.
+    pub fn only(mut self, mime_type: &str) -> Self {
+        self.mime_types.push(mime_type.to_ascii_lowercase());
+        self
+    }
+}
+
+impl Transform for Replace {
+    fn apply(&self, content: &mut Content) {
+        if !self.mime_types.is_empty() {
+            match content.mime_type() {
+                Some(mime) if self.mime_types.contains(&mime) => {}
+                _ => return,
+            }
+        }
+        let text = match content.text_mut() {
+            Some(text) => text,
+            None => return,
+        };
+        let replaced = match &self.find {
+            Find::Text(from) if from.is_empty() || !text.contains(from.as_str()) => return,
+            Find::Text(from) => text.replace(from.as_str(), &self.with),
+            Find::Regex(regex) => regex.replace_all(text, self.with.as_str()).into_owned(),
+        };
+        *text = replaced;
+    }
+}
+
+/// Where a `Rewriter` gets responses from.
+pub trait Source {
+    /// The response to `request`, or `None` to let the webview load it as
+    /// usual.
+    fn fetch(&self, request: &Request<Body>) -> io::Result<Option<Response<Body>>>;
+}
+
+impl<F: Fn(&Request<Body>) -> io::Result<Option<Response<Body>>>> Source for F {
+    fn fetch(&self, request: &Request<Body>) -> io::Result<Option<Response<Body>>> {
+        self(request)
+    }
+}
+
+type FetchedFn = Box<dyn FnOnce(io::Result<Option<Response<Payload>>>)>;
+
+/// Answers one `AsyncSource::fetch_async`, possibly later.
+///
+/// Dropping it without answering answers with an error, so the page is
+/// never left waiting.
+pub struct Fetched {
+    f: Option<FetchedFn>,
+}
+
+impl Fetched {
+    pub fn new(f: impl FnOnce(io::Result<Option<Response<Payload>>>) + 'static) -> Self {
+        Fetched {
+            f: Some(Box::new(f)),
+        }
+    }
+
+    /// Answer with a response, `None` to let the webview load the request
+    /// as usual, or an error.
+    pub fn send<B: Into<Payload>>(mut self, result: io::Result<Option<Response<B>>>) {
+        if let Some(f) = self.f.take() {
+            f(result.map(|response| response.map(|r| r.map(Into::into))));
+        }
+    }
+}
+
+impl Drop for Fetched {
+    fn drop(&mut self) {
+        if let Some(f) = self.f.take() {
+            f(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "the source dropped the request",
+            )));
+        }
+    }
+}
+
+impl fmt::Debug for Fetched {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Fetched")
+            .field("sent", &self.f.is_none())
+            .finish()
+    }
+}
+
+/// A source that can answer after returning, and with a streamed body.
+/// Every `Source` is one that answers right away.
+pub trait AsyncSource {
+    fn fetch_async(&self, request: Request<Body>, reply: Fetched);
+}
+
+impl<S: Source> AsyncSource for S {
+    fn fetch_async(&self, request: Request<Body>, reply: Fetched) {
+        reply.send(self.fetch(&request))
+    }
+}
+
+/// Ordered transforms.
+pub struct Rewriter {
+    transforms: Vec<Box<dyn Transform>>,
+    limit: u64,
+}
+
+impl Default for Rewriter {
+    fn default() -> Self {
+        Rewriter {
+            transforms: Vec::new(),
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl Rewriter {
+    pub fn new() -> Self {
+        Rewriter::default()
+    }
+
+    /// Only decode bodies of at most `limit` bytes, see `Content::with_limit`.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Add a transform, after the ones added before.
+    pub fn with(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn rewrite<B: Into<Payload>>(&self, response: Response<B>) -> Response<Payload> {
+        let mut content = Content::new(response).with_limit(self.limit);
+        for transform in &self.transforms {
+            transform.apply(&mut content);
+        }
+        content.into_response()
+    }
+
+    /// Fetch `request` from `source` and rewrite the response. Errors become
+    /// a `502 Bad Gateway`.
+    pub fn fetch(&self, source: &dyn Source, request: &Request<Body>) -> Option<Response<Payload>> {
+        self.fetched(source.fetch(request))
+    }
+
+    /// Rewrite what a source answered: a response is rewritten, an error
+    /// becomes a `502 Bad Gateway`, and `None` stays `None`.
+    pub fn fetched<B: Into<Payload>>(
+        &self,
+        result: io::Result<Option<Response<B>>>,
+    ) -> Option<Response<Payload>> {
+        match result {
+            Ok(response) => Some(self.rewrite(response?)),
+            Err(e) => {
+                let mut response = Response::new(Payload::from(e.to_string().into_bytes()));
+                *response.status_mut() = StatusCode::BAD_GATEWAY;
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                );
+                Some(response)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Rewriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rewriter")
+            .field("transforms", &self.transforms.len())
+            .finish()
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::web_resource::RawResponse;
+    use crate::{
+        Environment, Error, EventRegistrationToken, Result, Stream, WebResourceContext,
+        WebResourceRequestedEventArgs, WebResourceResponse, WebView,
+    };
+    use std::rc::Rc;
+    use winapi::shared::winerror::E_INVALIDARG;
+
+    impl Environment {
+        /// `create_http_response` for a `Payload`, handing a streamed body to
+        /// the webview without reading it first.
+        pub fn create_payload_response(
+            &self,
+            response: Response<Payload>,
+        ) -> Result<WebResourceResponse> {
+            let (head, payload) = response.into_parts();
+            let raw = RawResponse::from_http(Response::from_parts(head, Vec::new()))
+                .map_err(|_| Error::new(E_INVALIDARG))?;
+            let content = match payload {
+                Payload::Buffered(body) => Stream::from_vec(body),
+                Payload::Streamed(stream) => Stream::from_shared(stream),
+            };
+            self.create_web_resource_response(content, raw.status as i32, &raw.reason, &raw.headers)
+        }
+    }
+
+    impl WebResourceRequestedEventArgs {
+        /// Answer with `response`, or with `500 Internal Server Error` if
+        /// it cannot be converted, e.g. for an invalid reason phrase.
+        pub fn put_payload_response(
+            &self,
+            environment: &Environment,
+            response: Response<Payload>,
+        ) -> Result<()> {
+            let response = environment.create_payload_response(response).or_else(|_| {
+                let mut error = Response::new(Vec::new());
+                *error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                environment.create_http_response(error)
+            })?;
+            self.put_response(response)
+        }
+    }
+
+    impl Rewriter {
+        /// Answer the requests `webview` makes to URIs matching `filter`, a
+        /// WebView2 wildcard pattern, with rewritten responses from
+        /// `source`, until the returned token is passed to
+        /// `remove_web_resource_requested`. Requests answered later hold a
+        /// deferral until then.
+        pub fn attach(
+            self,
+            webview: &WebView,
+            environment: Environment,
+            filter: &str,
+            source: impl AsyncSource + 'static,
+        ) -> Result<EventRegistrationToken> {
+            webview.add_web_resource_requested_filter(filter, WebResourceContext::All)?;
+            let rewriter = Rc::new(self);
+            webview.add_web_resource_requested(move |_, args| {
+                let request = args.get_request()?.to_http()?;
+                let deferral = args.get_deferral()?;
+                let rewriter = rewriter.clone();
+                let environment = environment.clone();
+                source.fetch_async(
+                    request,
+                    Fetched::new(move |result| {
+                        if let Some(response) = rewriter.fetched(result) {
+                            args.put_payload_response(&environment, response).ok();
+                        }
+                        deferral.complete().ok();
+                    }),
+                );
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::typed;
+    use std::io::Write;
+
+    fn text(response: &Response<Payload>) -> &str {
+        std::str::from_utf8(response.body().bytes().unwrap()).unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decoding() {
+        let decode = |response: Response<Body>| Content::new(response).text().map(str::to_owned);
+        assert_eq!(
+            decode(typed("text/plain", "héllo".as_bytes())).as_deref(),
+            Some("héllo")
+        );
+        assert_eq!(
+            decode(typed("text/plain; charset=\"ISO-8859-1\"", b"h\xe9llo")).as_deref(),
+            Some("héllo")
+        );
+        assert_eq!(
+            decode(typed("text/css;charset=shift_jis", b"\x93\xfa\x96\x7b")).as_deref(),
+            Some("日本")
+        );
+        // The byte order mark wins.
+        assert_eq!(
+            decode(typed("text/plain; charset=latin1", b"\xff\xfeh\0i\0")).as_deref(),
+            Some("hi")
+        );
+        assert_eq!(
+            decode(typed(
+                "text/html",
+                b"<html><head><meta charset=\"windows-1252\"><title>\x80</title>"
+            ))
+            .as_deref(),
+            Some("<html><head><meta charset=\"windows-1252\"><title>€</title>")
+        );
+        assert_eq!(
+            meta_charset(b"<META http-equiv=Content-Type content='text/html; charset=EUC-KR'>")
+                .as_deref(),
+            Some("euc-kr")
+        );
+        // Invalid for the charset, or not text at all.
+        assert_eq!(decode(typed("text/plain", b"\xff")), None);
+        assert_eq!(decode(typed("image/png", b"png")), None);
+        assert_eq!(decode(Response::new(b"none".to_vec())), None);
+
+        let mut compressed = typed("application/javascript", &gzip(b"let a = 1;"));
+        compressed
+            .headers_mut()
+            .insert("content-encoding", HeaderValue::from_static("gzip"));
+        let mut content = Content::new(compressed);
+        assert_eq!(content.text(), Some("let a = 1;"));
+        assert!(!content.headers().contains_key("content-encoding"));
+        assert_eq!(
+            content.headers()["content-type"],
+            "application/javascript; charset=utf-8"
+        );
+
+        let mut brotli = typed("text/plain", b"\x0b\x01\x80hi\x03");
+        brotli
+            .headers_mut()
+            .insert("content-encoding", HeaderValue::from_static("br"));
+        let mut content = Content::new(brotli);
+        assert_eq!(content.text(), None);
+        assert_eq!(content.headers()["content-encoding"], "br");
+    }
+
+    #[test]
+    fn test_inject_head() {
+        let inject = |html: &str| {
+            let rewriter = Rewriter::new().with(InjectHead::new("<x>"));
+            let response = rewriter.rewrite(typed("text/html", html.as_bytes()));
+            text(&response).to_owned()
+        };
+        assert_eq!(
+            inject("<html><head></head></html>"),
+            "<html><head><x></head></html>"
+        );
+        assert_eq!(
+            inject("<!DOCTYPE html><HTML><HEAD lang=en><title>"),
+            "<!DOCTYPE html><HTML><HEAD lang=en><x><title>"
+        );
+        assert_eq!(
+            inject("<html><header></header><head>"),
+            "<html><header></header><head><x>"
+        );
+        assert_eq!(inject("<html lang=en><body>"), "<html lang=en><x><body>");
+        assert_eq!(inject("<p>fragment"), "<x><p>fragment");
+
+        let rewriter = Rewriter::new().with(InjectHead::new("<x>"));
+        let css = rewriter.rewrite(typed("text/css", b"<head>"));
+        assert_eq!(text(&css), "<head>");
+    }
+
+    #[test]
+    fn test_header_edit() {
+        let edit = HeaderEdit::new()
+            .edit(header::CONTENT_SECURITY_POLICY, |csp| {
+                if csp.contains("report-only") {
+                    None
+                } else {
+                    Some(format!("{}; script-src 'self' https://app.local", csp))
+                }
+            })
+            .set(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))
+            .append(header::VARY, HeaderValue::from_static("Origin"))
+            .remove(header::SERVER);
+        let response = Response::builder()
+            .header("content-security-policy", "default-src 'self'")
+            .header("content-security-policy", "report-only")
+            .header("cache-control", "max-age=60")
+            .header("cache-control", "public")
+            .header("vary", "Accept")
+            .header("server", "upstream")
+            .body(b"\xff binary bodies are left alone".to_vec())
+            .unwrap();
+        let response = Rewriter::new().with(edit).rewrite(response);
+        let headers = response.headers();
+        let all = |name| {
+            headers
+                .get_all(name)
+                .iter()
+                .map(|v| v.to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            all("content-security-policy"),
+            ["default-src 'self'; script-src 'self' https://app.local"]
+        );
+        assert_eq!(all("cache-control"), ["no-store"]);
+        assert_eq!(all("vary"), ["Accept", "Origin"]);
+        assert!(all("server").is_empty());
+        assert_eq!(&response.body().bytes().unwrap()[..1], b"\xff");
+    }
+
+    #[test]
+    fn test_replace() {
+        let rewriter = Rewriter::new()
+            .with(Replace::new("http://", "https://"))
+            .with(
+                Replace::regex(r#"src="/assets/([^"]+)""#, r#"src="https://app.local/$1""#)
+                    .unwrap()
+                    .only("text/html"),
+            );
+        let response = rewriter.rewrite(typed(
+            "text/html; charset=utf-8",
+            br#"<img src="/assets/a.png"><a href="http://x">"#,
+        ));
+        assert_eq!(
+            text(&response),
+            r#"<img src="https://app.local/a.png"><a href="https://x">"#
+        );
+        let response = rewriter.rewrite(typed(
+            "application/javascript",
+            br#"fetch("http://x"); 'src="/assets/a"'"#,
+        ));
+        assert_eq!(text(&response), r#"fetch("https://x"); 'src="/assets/a"'"#);
+        assert!(Replace::regex("(", "").is_err());
+    }
+
+    /// Transforms run in order and see what the ones before did.
+    #[test]
+    fn test_pipeline() {
+        let rewriter = Rewriter::new()
+            .with(Replace::new("a", "b"))
+            .with(Replace::new("b", "c"))
+            .with(|content: &mut Content| {
+                let len = content.text().map_or(0, str::len);
+                content.set_status(StatusCode::CREATED);
+                content.headers_mut().insert("x-len", len.into());
+            })
+            .with(|content: &mut Content| content.body_mut().unwrap().extend_from_slice(b"!"));
+        assert_eq!(rewriter.len(), 4);
+        let mut original = typed("text/plain; charset=latin1", b"ab\xe9");
+        original
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, 3.into());
+        let response = rewriter.rewrite(original);
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(text(&response), "cc\u{e9}!");
+        assert_eq!(response.headers()["x-len"], "4");
+        assert_eq!(response.headers()["content-length"], "5");
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; charset=utf-8"
+        );
+
+        // Nothing decoded, nothing changed.
+        let rewriter = Rewriter::new().with(Replace::new("a", "b"));
+        let mut original = typed("image/png", b"abc");
+        original
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, 3.into());
+        let response = rewriter.rewrite(original);
+        assert_eq!(response.body().bytes(), Some(&b"abc"[..]));
+        assert_eq!(response.headers()["content-type"], "image/png");
+        assert_eq!(response.headers()["content-length"], "3");
+    }
+
+    #[test]
+    fn test_fetch() {
+        let rewriter = Rewriter::new().with(Replace::new("upstream", "patched"));
+        let source = |request: &Request<Body>| match request.uri().path() {
+            "/page" => Ok(Some(typed("text/plain", b"from upstream"))),
+            "/down" => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "connection refused",
+            )),
+            _ => Ok(None),
+        };
+        let request = |path: &str| {
+            Request::get(format!("https://example.com{}", path))
+                .body(Vec::new())
+                .unwrap()
+        };
+        let response = rewriter.fetch(&source, &request("/page")).unwrap();
+        assert_eq!(text(&response), "from patched");
+        let response = rewriter.fetch(&source, &request("/down")).unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(text(&response), "connection refused");
+        assert!(rewriter.fetch(&source, &request("/other")).is_none());
+    }
+
+    /// Sources answering later, or not at all.
+    #[test]
+    fn test_fetch_async() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let rewriter = Rc::new(Rewriter::new().with(Replace::new("upstream", "patched")));
+        let got = Rc::new(RefCell::new(Vec::new()));
+        let reply = || {
+            let rewriter = rewriter.clone();
+            let got = got.clone();
+            Fetched::new(move |result| got.borrow_mut().push(rewriter.fetched(result)))
+        };
+        let parked = RefCell::new(Vec::new());
+        let source = |request: Request<Body>, reply: Fetched| {
+            if request.uri().path() == "/later" {
+                parked.borrow_mut().push(reply);
+            }
+        };
+        let request = |path: &str| {
+            Request::get(format!("https://example.com{}", path))
+                .body(Vec::new())
+                .unwrap()
+        };
+        source(request("/later"), reply());
+        assert!(got.borrow().is_empty());
+        // Dropped without an answer.
+        source(request("/never"), reply());
+        assert_eq!(
+            got.borrow()[0].as_ref().unwrap().status(),
+            StatusCode::BAD_GATEWAY
+        );
+        let later = parked.borrow_mut().pop().unwrap();
+        later.send(Ok(Some(typed("text/plain", b"from upstream"))));
+        assert_eq!(text(got.borrow()[1].as_ref().unwrap()), "from patched");
+
+        // Every `Source` is an `AsyncSource` that answers right away.
+        let sync = |_: &Request<Body>| Ok(None);
+        sync.fetch_async(request("/"), reply());
+        assert!(got.borrow()[2].is_none());
+    }
+
+    /// Streamed bodies stay streams unless a transform reads them.
+    #[test]
+    fn test_streams() {
+        let streamed = |content_type: &str, body: &[u8]| {
+            Response::builder()
+                .header("content-type", content_type)
+                .body(SharedStream::from_vec(body.to_vec()))
+                .unwrap()
+        };
+        let rewriter = Rewriter::new().with(Replace::new("a", "b"));
+        let response = rewriter.rewrite(streamed("image/png", b"abc"));
+        assert!(response.body().bytes().is_none());
+        assert_eq!(response.into_body().into_body().unwrap(), b"abc");
+        let response = rewriter.rewrite(streamed("text/plain", b"abc"));
+        assert_eq!(text(&response), "bbc");
+
+        let mut content = Content::new(streamed("image/png", b"abc"));
+        content.body_mut().unwrap().push(b'd');
+        assert_eq!(content.into_response().body().bytes(), Some(&b"abcd"[..]));
+    }
+
+    /// Bodies over the limit are sent as they came, compressed or not.
+    #[test]
+    fn test_limit() {
+        let rewriter = Rewriter::new().with(Replace::new("a", "b")).with_limit(16);
+        let response = rewriter.rewrite(typed("text/plain", &[b'a'; 17]));
+        assert_eq!(response.body().bytes(), Some(&[b'a'; 17][..]));
+        let response = rewriter.rewrite(typed("text/plain", &[b'a'; 16]));
+        assert_eq!(text(&response), "b".repeat(16));
+
+        let bomb = gzip(&[b'a'; 1 << 20]);
+        assert!(bomb.len() < 16 << 10);
+        let mut compressed = typed("text/plain", &bomb);
+        compressed
+            .headers_mut()
+            .insert("content-encoding", HeaderValue::from_static("gzip"));
+        let rewriter = Rewriter::new()
+            .with(Replace::new("a", "b"))
+            .with_limit(64 << 10);
+        let response = rewriter.rewrite(compressed);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        assert_eq!(response.body().bytes(), Some(&bomb[..]));
+    }
+}
@@ -0,0 +1,1165 @@
+//! Rust handlers for requests the page makes to an app origin.
+//!
+//! A `Router` matches requests to an origin, like `https://app.local`, by
+//! method and path pattern and hands them to handlers, so that the page can
+//! `fetch("https://app.local/api/items/7")`. Patterns are `/`-separated
+//! segments: literals, `:name` parameters matching one segment, and a final
+//! `*name` matching the rest of the path.
+//!
+//! ```
+//! use webview2::router::{json, Cors, Router};
+//! use webview2::web_resource::http::{Request, StatusCode};
+//!
+//! let router = Router::new("https://app.local")
+//!     .with(Cors::new().allow_origin("https://example.com"))
+//!     .get("/api/items/:id", |request| {
+//!         let id = request.param("id").unwrap_or_default();
+//!         json(StatusCode::OK, &[id])
+//!     });
+//! let request = Request::get("https://app.local/api/items/7").body(Vec::new()).unwrap();
+//! let response = router.call(request).unwrap();
+//! assert_eq!(response.body(), br#"["7"]"#);
+//! ```
+//!
+//! Handlers registered with `route_async` get a `Reply` to answer with
+//! later, on the same thread; on Windows `Router::attach` holds a deferral
+//! of the request until then. Middleware, like `Cors`, `Logger` and `Auth`,
+//! runs around every handler in the order it was added, and may answer by
+//! itself or change the response.
+
+use crate::util::uri::percent_decode;
+use crate::web_resource::http::header::{self, HeaderValue};
+use crate::web_resource::http::{self, Method, Response, StatusCode};
+use crate::web_resource::Body;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// A request matched to a route, with the parameters of its path.
+#[derive(Debug)]
+pub struct Request {
+    inner: http::Request<Body>,
+    params: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn new(inner: http::Request<Body>) -> Self {
+        Request {
+            inner,
+            params: Vec::new(),
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        self.inner.method()
+    }
+
+    pub fn uri(&self) -> &http::Uri {
+        self.inner.uri()
+    }
+
+    pub fn headers(&self) -> &http::HeaderMap {
+        self.inner.headers()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.inner.headers().get(name)?.to_str().ok()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        self.inner.body()
+    }
+
+    /// For middleware to leave something, like the authenticated user, for
+    /// the handler.
+    pub fn extensions(&self) -> &http::Extensions {
+        self.inner.extensions()
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut http::Extensions {
+        self.inner.extensions_mut()
+    }
+
+    /// A `:name` or `*name` parameter of the route, percent decoded.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// The query as decoded name and value pairs, in order.
+    pub fn query(&self) -> Vec<(String, String)> {
+        parse_query(self.inner.uri().query().unwrap_or(""))
+    }
+
+    /// The first value of a query parameter.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query()
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// The body as JSON. Answer errors with `bad_request`.
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(self.body())
+    }
+
+    pub fn into_inner(self) -> http::Request<Body> {
+        self.inner
+    }
+}
+
+/// Decode `application/x-www-form-urlencoded` pairs. Malformed escapes are
+/// kept as they are.
+pub fn parse_query(query: &str) -> Vec<(String, String)> {
+    let decode = |s: &str| {
+        let s = s.replace('+', " ");
+        percent_decode(&s).unwrap_or(s)
+    };
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(i) => (decode(&pair[..i]), decode(&pair[i + 1..])),
+            None => (decode(pair), String::new()),
+        })
+        .collect()
+}
+
+/// A JSON response.
+pub fn json<T: Serialize + ?Sized>(status: StatusCode, value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => with_type(status, "application/json", body),
+        Err(e) => text(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("cannot encode response: {}", e),
+        ),
+    }
+}
+
+/// A plain text response.
+pub fn text(status: StatusCode, body: &str) -> Response<Body> {
+    with_type(
+        status,
+        "text/plain; charset=utf-8",
+        body.as_bytes().to_vec(),
+    )
+}
+
+/// A `400 Bad Request` response, for a request that could not be read.
+pub fn bad_request(error: impl fmt::Display) -> Response<Body> {
+    text(StatusCode::BAD_REQUEST, &format!("bad request: {}", error))
+}
+
+/// A response without a body.
+pub fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Vec::new());
+    *response.status_mut() = status;
+    response
+}
+
+fn with_type(status: StatusCode, content_type: &'static str, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+/// Answers one request, possibly later.
+///
+/// Dropping it without answering answers with `500 Internal Server Error`,
+/// so the page is never left waiting.
+pub struct Reply {
+    f: Option<Box<dyn FnOnce(Response<Body>)>>,
+}
+
+impl Reply {
+    pub fn new(f: impl FnOnce(Response<Body>) + 'static) -> Self {
+        Reply {
+            f: Some(Box::new(f)),
+        }
+    }
+
+    pub fn send(mut self, response: Response<Body>) {
+        if let Some(f) = self.f.take() {
+            f(response);
+        }
+    }
+
+    /// A reply that changes the response before sending it.
+    pub fn map(mut self, g: impl FnOnce(Response<Body>) -> Response<Body> + 'static) -> Reply {
+        match self.f.take() {
+            Some(f) => Reply::new(move |response| f(g(response))),
+            None => self,
+        }
+    }
+}
+
+impl Drop for Reply {
+    fn drop(&mut self) {
+        if let Some(f) = self.f.take() {
+            f(text(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "the handler dropped the request",
+            ));
+        }
+    }
+}
+
+impl fmt::Debug for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reply")
+            .field("sent", &self.f.is_none())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(String),
+}
+
+/// A path pattern like `/api/items/:id` or `/files/*path`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Pattern {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PatternError {
+    /// Patterns start with `/`.
+    Relative,
+    /// A `:` or `*` without a name.
+    Unnamed,
+    /// A `*name` before the last segment.
+    RestNotLast,
+    Duplicate(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::Relative => write!(f, "pattern does not start with /"),
+            PatternError::Unnamed => write!(f, "parameter without a name"),
+            PatternError::RestNotLast => write!(f, "* parameter is not the last segment"),
+            PatternError::Duplicate(name) => write!(f, "parameter {} appears twice", name),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        let rest = pattern.strip_prefix('/').ok_or(PatternError::Relative)?;
+        let parts = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split('/').collect()
+        };
+        let mut segments = Vec::new();
+        let mut names: Vec<&str> = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            let (name, segment) = if let Some(name) = part.strip_prefix(':') {
+                (name, Segment::Param(name.to_owned()))
+            } else if let Some(name) = part.strip_prefix('*') {
+                if i + 1 != parts.len() {
+                    return Err(PatternError::RestNotLast);
+                }
+                (name, Segment::Rest(name.to_owned()))
+            } else {
+                segments.push(Segment::Literal(decode_segment(part)));
+                continue;
+            };
+            if name.is_empty() {
+                return Err(PatternError::Unnamed);
+            }
+            if names.contains(&name) {
+                return Err(PatternError::Duplicate(name.to_owned()));
+            }
+            names.push(name);
+            segments.push(segment);
+        }
+        Ok(Pattern { segments })
+    }
+
+    /// The parameters if `path`, still percent encoded, matches.
+    pub fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let mut parts = if path.is_empty() {
+            Vec::new()
+        } else {
+            path.split('/').collect::<Vec<_>>()
+        };
+        let mut params = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.get(i).map(|p| decode_segment(p)) != Some(literal.clone()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let part = parts.get(i).filter(|p| !p.is_empty())?;
+                    params.push((name.clone(), decode_segment(part)));
+                }
+                Segment::Rest(name) => {
+                    let rest = parts.split_off(i.min(parts.len())).join("/");
+                    params.push((name.clone(), decode_segment(&rest)));
+                    return Some(params);
+                }
+            }
+        }
+        if parts.len() == self.segments.len() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+}
+
+fn decode_segment(segment: &str) -> String {
+    percent_decode(segment).unwrap_or_else(|| segment.to_owned())
+}
+
+type Handler = Rc<dyn Fn(Request, Reply)>;
+
+struct Route {
+    /// `None` for any method.
+    method: Option<Method>,
+    pattern: Pattern,
+    handler: Handler,
+}
+
+/// Runs around the handlers of a `Router`.
+pub trait Middleware {
+    /// Answer with `reply`, or pass the request on with `next.run`.
+    fn handle(&self, request: Request, reply: Reply, next: Next<'_>);
+}
+
+impl<F: Fn(Request, Reply, Next<'_>)> Middleware for F {
+    fn handle(&self, request: Request, reply: Reply, next: Next<'_>) {
+        self(request, reply, next)
+    }
+}
+
+/// The rest of the middleware and the handler.
+pub struct Next<'a> {
+    middleware: &'a [Box<dyn Middleware>],
+    handler: &'a dyn Fn(Request, Reply),
+}
+
+impl<'a> Next<'a> {
+    pub fn run(self, request: Request, reply: Reply) {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(
+                request,
+                reply,
+                Next {
+                    middleware: rest,
+                    handler: self.handler,
+                },
+            ),
+            None => (self.handler)(request, reply),
+        }
+    }
+}
+
+/// Routes requests to one origin to handlers.
+pub struct Router {
+    origin: String,
+    routes: Vec<Route>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Router {
+    pub fn new(origin: &str) -> Self {
+        Router {
+            origin: origin.trim_end_matches('/').to_ascii_lowercase(),
+            routes: Vec::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// The URI filter for `add_web_resource_requested_filter`.
+    pub fn filter(&self) -> String {
+        format!("{}/*", self.origin)
+    }
+
+    /// Whether `uri` is on the router's origin.
+    pub fn owns(&self, uri: &str) -> bool {
+        match uri.get(..self.origin.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(&self.origin) => {
+                matches!(
+                    uri[self.origin.len()..].chars().next(),
+                    None | Some('/') | Some('?') | Some('#')
+                )
+            }
+            _ => false,
+        }
+    }
+
+    /// Add middleware, inside the middleware added before.
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Answer `method` requests to `pattern` with `f`, or requests of any
+    /// method if `method` is `None`. Routes are tried in the order they were
+    /// added. Panics if `pattern` is invalid.
+    pub fn route_async(
+        mut self,
+        method: Option<Method>,
+        pattern: &str,
+        f: impl Fn(Request, Reply) + 'static,
+    ) -> Self {
+        let pattern = match Pattern::parse(pattern) {
+            Ok(p) => p,
+            Err(e) => panic!("invalid route pattern {:?}: {}", pattern, e),
+        };
+        self.routes.push(Route {
+            method,
+            pattern,
+            handler: Rc::new(f),
+        });
+        self
+    }
+
+    /// `route_async` with a handler that answers right away.
+    pub fn route(
+        self,
+        method: Option<Method>,
+        pattern: &str,
+        f: impl Fn(&Request) -> Response<Body> + 'static,
+    ) -> Self {
+        self.route_async(method, pattern, move |request, reply| {
+            reply.send(f(&request))
+        })
+    }
+
+    /// A `GET` route, which also answers `HEAD`.
+    pub fn get(self, pattern: &str, f: impl Fn(&Request) -> Response<Body> + 'static) -> Self {
+        self.route(Some(Method::GET), pattern, f)
+    }
+
+    pub fn post(self, pattern: &str, f: impl Fn(&Request) -> Response<Body> + 'static) -> Self {
+        self.route(Some(Method::POST), pattern, f)
+    }
+
+    pub fn put(self, pattern: &str, f: impl Fn(&Request) -> Response<Body> + 'static) -> Self {
+        self.route(Some(Method::PUT), pattern, f)
+    }
+
+    pub fn patch(self, pattern: &str, f: impl Fn(&Request) -> Response<Body> + 'static) -> Self {
+        self.route(Some(Method::PATCH), pattern, f)
+    }
+
+    pub fn delete(self, pattern: &str, f: impl Fn(&Request) -> Response<Body> + 'static) -> Self {
+        self.route(Some(Method::DELETE), pattern, f)
+    }
+
+    /// Run `request` through the middleware to its route, or to a `404` or
+    /// `405` if there is none. Requests to other origins get a `404` too.
+    pub fn handle(&self, request: http::Request<Body>, reply: Reply) {
+        let mut request = Request::new(request);
+        let is_head = request.method() == Method::HEAD;
+        let found = if self.owns(&request.uri().to_string()) {
+            self.find(request.method(), request.uri().path())
+        } else {
+            Found::None
+        };
+        let handler: Handler = match found {
+            Found::Route(route, params) => {
+                request.params = params;
+                route.handler.clone()
+            }
+            Found::Method(allowed) => Rc::new(move |_, reply: Reply| {
+                let mut response = empty(StatusCode::METHOD_NOT_ALLOWED);
+                if let Ok(allow) = HeaderValue::from_str(&allowed) {
+                    response.headers_mut().insert(header::ALLOW, allow);
+                }
+                reply.send(response)
+            }),
+            Found::None => {
+                Rc::new(|_, reply: Reply| reply.send(text(StatusCode::NOT_FOUND, "Not Found")))
+            }
+        };
+        let reply = if is_head {
+            reply.map(|mut response| {
+                response.body_mut().clear();
+                response
+            })
+        } else {
+            reply
+        };
+        Next {
+            middleware: &self.middleware,
+            handler: &*handler,
+        }
+        .run(request, reply)
+    }
+
+    /// `handle`, returning the response if it was sent before returning.
+    pub fn call(&self, request: http::Request<Body>) -> Option<Response<Body>> {
+        let slot = Rc::new(std::cell::RefCell::new(None));
+        let s = slot.clone();
+        self.handle(
+            request,
+            Reply::new(move |response| *s.borrow_mut() = Some(response)),
+        );
+        let response = slot.borrow_mut().take();
+        response
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Found<'_> {
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            let params = match route.pattern.matches(path) {
+                Some(params) => params,
+                None => continue,
+            };
+            match &route.method {
+                None => return Found::Route(route, params),
+                Some(m) if m == method || (m == Method::GET && method == Method::HEAD) => {
+                    return Found::Route(route, params)
+                }
+                Some(m) => {
+                    if !allowed.contains(&m.as_str()) {
+                        allowed.push(m.as_str());
+                    }
+                    if m == Method::GET && !allowed.contains(&"HEAD") {
+                        allowed.push("HEAD");
+                    }
+                }
+            }
+        }
+        if allowed.is_empty() {
+            Found::None
+        } else {
+            Found::Method(allowed.join(", "))
+        }
+    }
+}
+
+enum Found<'a> {
+    Route(&'a Route, Vec<(String, String)>),
+    /// The path matches, but only for these methods.
+    Method(String),
+    None,
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("origin", &self.origin)
+            .field("routes", &self.routes.len())
+            .field("middleware", &self.middleware.len())
+            .finish()
+    }
+}
+
+/// Cross-origin resource sharing, for pages on other origins that call the
+/// router. Answers preflight requests itself and adds the
+/// `Access-Control-Allow-*` headers to responses to allowed origins.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// `None` for any origin.
+    origins: Option<Vec<String>>,
+    methods: String,
+    headers: String,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            origins: Some(Vec::new()),
+            methods: "GET, HEAD, POST, PUT, PATCH, DELETE".to_owned(),
+            headers: "Content-Type".to_owned(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Allows no origin until told otherwise.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+        if let Some(origins) = &mut self.origins {
+            origins.push(origin);
+        }
+        self
+    }
+
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = None;
+        self
+    }
+
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        self
+    }
+
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.join(", ");
+        self
+    }
+
+    pub fn allow_credentials(mut self) -> Self {
+        self.credentials = true;
+        self
+    }
+
+    /// How long browsers may cache the answer to a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        match &self.origins {
+            Some(origins) => origins.iter().any(|o| o.eq_ignore_ascii_case(origin)),
+            None => true,
+        }
+    }
+
+    fn allow_headers_on(&self, response: &mut Response<Body>, origin: &HeaderValue) {
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(&self, request: Request, reply: Reply, next: Next<'_>) {
+        let origin = match request.headers().get(header::ORIGIN) {
+            Some(origin) => origin.clone(),
+            None => return next.run(request, reply),
+        };
+        if !self.allows(origin.to_str().unwrap_or("")) {
+            return next.run(request, reply);
+        }
+        let preflight = request.method() == Method::OPTIONS
+            && request
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        if preflight {
+            let mut response = empty(StatusCode::NO_CONTENT);
+            self.allow_headers_on(&mut response, &origin);
+            let headers = response.headers_mut();
+            for (name, value) in &[
+                (header::ACCESS_CONTROL_ALLOW_METHODS, &self.methods),
+                (header::ACCESS_CONTROL_ALLOW_HEADERS, &self.headers),
+            ] {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    headers.insert(name.clone(), value);
+                }
+            }
+            if let Some(max_age) = self.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+            }
+            return reply.send(response);
+        }
+        let cors = self.clone();
+        next.run(
+            request,
+            reply.map(move |mut response| {
+                cors.allow_headers_on(&mut response, &origin);
+                response
+            }),
+        )
+    }
+}
+
+/// What `Logger` reports about each request once it was answered.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LogRecord {
+    pub method: Method,
+    pub uri: http::Uri,
+    pub status: StatusCode,
+    pub elapsed: Duration,
+}
+
+/// Reports every request and its answer.
+pub struct Logger {
+    f: Rc<dyn Fn(&LogRecord)>,
+}
+
+impl Logger {
+    pub fn new(f: impl Fn(&LogRecord) + 'static) -> Self {
+        Logger { f: Rc::new(f) }
+    }
+}
+
+impl Middleware for Logger {
+    fn handle(&self, request: Request, reply: Reply, next: Next<'_>) {
+        let f = self.f.clone();
+        let (method, uri) = (request.method().clone(), request.uri().clone());
+        let start = Instant::now();
+        next.run(
+            request,
+            reply.map(move |response| {
+                f(&LogRecord {
+                    method,
+                    uri,
+                    status: response.status(),
+                    elapsed: start.elapsed(),
+                });
+                response
+            }),
+        )
+    }
+}
+
+/// Answers `401 Unauthorized` to requests that fail a check.
+pub struct Auth {
+    check: Box<dyn Fn(&mut Request) -> bool>,
+}
+
+impl Auth {
+    /// `check` may leave what it found out in the request's extensions.
+    pub fn new(check: impl Fn(&mut Request) -> bool + 'static) -> Self {
+        Auth {
+            check: Box::new(check),
+        }
+    }
+
+    /// Require `Authorization: Bearer <token>`.
+    pub fn bearer(token: &str) -> Self {
+        let expected = format!("Bearer {}", token);
+        Self::new(move |request| request.header("Authorization") == Some(expected.as_str()))
+    }
+}
+
+impl Middleware for Auth {
+    fn handle(&self, mut request: Request, reply: Reply, next: Next<'_>) {
+        if (self.check)(&mut request) {
+            next.run(request, reply)
+        } else {
+            let mut response = text(StatusCode::UNAUTHORIZED, "Unauthorized");
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            reply.send(response)
+        }
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Auth").finish()
+    }
+}
+
+impl fmt::Debug for Logger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Logger").finish()
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::{Environment, EventRegistrationToken, Result, WebResourceContext, WebView};
+
+    impl Router {
+        /// Answer the requests `webview` makes to the origin, until the
+        /// returned token is passed to `remove_web_resource_requested`.
+        /// Requests answered later hold a deferral until then.
+        pub fn attach(
+            self,
+            webview: &WebView,
+            environment: Environment,
+        ) -> Result<EventRegistrationToken> {
+            webview.add_web_resource_requested_filter(&self.filter(), WebResourceContext::All)?;
+            webview.add_web_resource_requested(move |_, args| {
+                let request = args.get_request()?;
+                if !self.owns(&request.get_uri()?) {
+                    return Ok(());
+                }
+                let request = request.to_http()?;
+                let deferral = args.get_deferral()?;
+                let environment = environment.clone();
+                self.handle(
+                    request,
+                    Reply::new(move |response| {
+                        // Say so rather than leave the request without an
+                        // answer, e.g. if a header could not be converted.
+                        let response = environment.create_http_response(response).or_else(|_| {
+                            let error =
+                                text(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error");
+                            environment.create_http_response(error)
+                        });
+                        if let Ok(response) = response {
+                            args.put_response(response).ok();
+                        }
+                        deferral.complete().ok();
+                    }),
+                );
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::request;
+    use serde::Deserialize;
+    use std::cell::RefCell;
+
+    fn body(response: &Response<Body>) -> &str {
+        std::str::from_utf8(response.body()).unwrap()
+    }
+
+    #[test]
+    fn test_patterns() {
+        let params = |pattern: &str, path: &str| Pattern::parse(pattern).unwrap().matches(path);
+        let pairs = |pairs: &[(&str, &str)]| {
+            Some(
+                pairs
+                    .iter()
+                    .map(|&(n, v)| (n.to_owned(), v.to_owned()))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(params("/", "/"), pairs(&[]));
+        assert_eq!(params("/", "/a"), None);
+        assert_eq!(params("/a/b", "/a/b"), pairs(&[]));
+        assert_eq!(params("/a/b", "/a/b/"), None);
+        assert_eq!(params("/a/b", "/a"), None);
+        assert_eq!(params("/a%20b", "/a%20b"), pairs(&[]));
+        assert_eq!(
+            params("/items/:id/tags/:tag", "/items/7/tags/a%2Fb"),
+            pairs(&[("id", "7"), ("tag", "a/b")])
+        );
+        assert_eq!(params("/items/:id", "/items/"), None);
+        assert_eq!(
+            params("/files/*path", "/files/a/b.txt"),
+            pairs(&[("path", "a/b.txt")])
+        );
+        assert_eq!(params("/files/*path", "/files"), pairs(&[("path", "")]));
+        assert_eq!(params("/files/*path", "/other"), None);
+
+        assert_eq!(Pattern::parse("a"), Err(PatternError::Relative));
+        assert_eq!(Pattern::parse("/:"), Err(PatternError::Unnamed));
+        assert_eq!(Pattern::parse("/*a/b"), Err(PatternError::RestNotLast));
+        assert_eq!(
+            Pattern::parse("/:a/*a"),
+            Err(PatternError::Duplicate("a".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(
+            parse_query("a=1&b=x+y%21&&c&a=2&bad=%zz"),
+            vec![
+                ("a".to_owned(), "1".to_owned()),
+                ("b".to_owned(), "x y!".to_owned()),
+                ("c".to_owned(), String::new()),
+                ("a".to_owned(), "2".to_owned()),
+                ("bad".to_owned(), "%zz".to_owned()),
+            ]
+        );
+        let request = Request::new(request("GET", "https://app.local/?q=hello%20world"));
+        assert_eq!(request.query_param("q").as_deref(), Some("hello world"));
+        assert_eq!(request.query_param("r"), None);
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Item {
+        name: String,
+    }
+
+    fn router() -> Router {
+        Router::new("https://app.local/")
+            .get("/api/items/:id", |r| {
+                json(StatusCode::OK, &format!("item {}", r.param("id").unwrap()))
+            })
+            .post("/api/items", |r| match r.json::<Item>() {
+                Ok(item) => json(StatusCode::CREATED, &item),
+                Err(e) => bad_request(e),
+            })
+            .delete("/api/items/:id", |_| empty(StatusCode::NO_CONTENT))
+            .route(None, "/any/*rest", |r| {
+                text(StatusCode::OK, r.method().as_str())
+            })
+    }
+
+    #[test]
+    fn test_routing() {
+        let router = router();
+        assert_eq!(router.filter(), "https://app.local/*");
+        let response = router
+            .call(request("GET", "https://app.local/api/items/7"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(&response), "\"item 7\"");
+        assert_eq!(response.headers()["content-type"], "application/json");
+
+        let response = router
+            .call(request("HEAD", "https://APP.local/api/items/7"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.body().is_empty());
+
+        let post = http::Request::post("https://app.local/api/items")
+            .body(br#"{"name":"x"}"#.to_vec())
+            .unwrap();
+        let response = router.call(post).unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(body(&response), r#"{"name":"x"}"#);
+        let post = http::Request::post("https://app.local/api/items")
+            .body(b"{".to_vec())
+            .unwrap();
+        let response = router.call(post).unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(body(&response).starts_with("bad request: EOF"));
+
+        let response = router
+            .call(request("PUT", "https://app.local/api/items/7"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["allow"], "GET, HEAD, DELETE");
+
+        assert_eq!(
+            body(
+                &router
+                    .call(request("PATCH", "https://app.local/any/x"))
+                    .unwrap()
+            ),
+            "PATCH"
+        );
+        let response = router
+            .call(request("GET", "https://app.local/api/items"))
+            .unwrap();
+        assert_eq!(response.headers()["allow"], "POST");
+        for uri in &[
+            "https://app.local/nothing",
+            "https://app.localhost/api/items/7",
+            "https://other.local/api/items/7",
+        ] {
+            let response = router.call(request("GET", uri)).unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+        assert!(router.owns("https://app.local"));
+        assert!(router.owns("https://app.local?x"));
+        assert!(!router.owns("https://app.local.evil/"));
+    }
+
+    #[test]
+    fn test_async_handlers() {
+        let pending = Rc::new(RefCell::new(Vec::new()));
+        let p = pending.clone();
+        let router = Router::new("https://app.local")
+            .route_async(Some(Method::GET), "/slow", move |_, reply| {
+                p.borrow_mut().push(reply)
+            })
+            .route_async(Some(Method::GET), "/dropped", |_, _| {});
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let l = log.clone();
+        let router = router.with(Logger::new(move |r| l.borrow_mut().push(r.status)));
+
+        assert!(router
+            .call(request("GET", "https://app.local/slow"))
+            .is_none());
+        assert!(log.borrow().is_empty());
+        let answered = Rc::new(RefCell::new(None));
+        let a = answered.clone();
+        router.handle(
+            request("GET", "https://app.local/slow"),
+            Reply::new(move |r| *a.borrow_mut() = Some(r.status())),
+        );
+        let mut replies = pending.borrow_mut().drain(..).collect::<Vec<_>>();
+        replies
+            .pop()
+            .unwrap()
+            .send(text(StatusCode::ACCEPTED, "done"));
+        assert_eq!(*answered.borrow(), Some(StatusCode::ACCEPTED));
+        // The first one, whose answer nobody waits for.
+        drop(replies);
+        assert_eq!(
+            *log.borrow(),
+            vec![StatusCode::ACCEPTED, StatusCode::INTERNAL_SERVER_ERROR]
+        );
+
+        let response = router
+            .call(request("GET", "https://app.local/dropped"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_middleware_order() {
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        let layer = |name: &'static str| {
+            let trace = trace.clone();
+            move |request: Request, reply: Reply, next: Next<'_>| {
+                trace.borrow_mut().push(format!("{} in", name));
+                let t = trace.clone();
+                next.run(
+                    request,
+                    reply.map(move |response| {
+                        t.borrow_mut().push(format!("{} out", name));
+                        response
+                    }),
+                )
+            }
+        };
+        let t = trace.clone();
+        let router = Router::new("https://app.local")
+            .with(layer("outer"))
+            .with(layer("inner"))
+            .get("/", move |_| {
+                t.borrow_mut().push("handler".to_owned());
+                empty(StatusCode::OK)
+            });
+        router.call(request("GET", "https://app.local/")).unwrap();
+        assert_eq!(
+            *trace.borrow(),
+            vec!["outer in", "inner in", "handler", "inner out", "outer out"]
+        );
+    }
+
+    #[test]
+    fn test_cors() {
+        let router = router().with(
+            Cors::new()
+                .allow_origin("https://Example.com/")
+                .allow_methods(&[Method::GET, Method::POST])
+                .allow_headers(&["Content-Type", "X-Token"])
+                .allow_credentials()
+                .max_age(Duration::from_secs(600)),
+        );
+        let preflight = http::Request::builder()
+            .method("OPTIONS")
+            .uri("https://app.local/api/items")
+            .header("Origin", "https://example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .body(Vec::new())
+            .unwrap();
+        let response = router.call(preflight).unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://example.com"
+        );
+        assert_eq!(headers["access-control-allow-methods"], "GET, POST");
+        assert_eq!(
+            headers["access-control-allow-headers"],
+            "Content-Type, X-Token"
+        );
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-max-age"], "600");
+
+        let get = |origin: &str| {
+            let request = http::Request::get("https://app.local/api/items/1")
+                .header("Origin", origin)
+                .body(Vec::new())
+                .unwrap();
+            router.call(request).unwrap()
+        };
+        let response = get("https://example.com");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://example.com"
+        );
+        assert_eq!(response.headers()["vary"], "Origin");
+        let response = get("https://evil.com");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+
+        let any = Router::new("https://app.local")
+            .with(Cors::new().allow_any_origin())
+            .get("/", |_| empty(StatusCode::OK));
+        let request = http::Request::get("https://app.local/")
+            .header("Origin", "https://anything.com")
+            .body(Vec::new())
+            .unwrap();
+        let response = any.call(request).unwrap();
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://anything.com"
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User(String);
+
+    #[test]
+    fn test_auth() {
+        let router = Router::new("https://app.local")
+            .with(Auth::bearer("secret"))
+            .get("/", |_| empty(StatusCode::OK));
+        let get = |authorization: Option<&str>| {
+            let mut request = http::Request::get("https://app.local/");
+            if let Some(a) = authorization {
+                request = request.header("Authorization", a);
+            }
+            router.call(request.body(Vec::new()).unwrap()).unwrap()
+        };
+        assert_eq!(get(Some("Bearer secret")).status(), StatusCode::OK);
+        let response = get(Some("Bearer wrong"));
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        assert_eq!(get(None).status(), StatusCode::UNAUTHORIZED);
+
+        let router = Router::new("https://app.local")
+            .with(Auth::new(|request| match request.header("X-User") {
+                Some(user) => {
+                    let user = User(user.to_owned());
+                    request.extensions_mut().insert(user);
+                    true
+                }
+                None => false,
+            }))
+            .get("/me", |r| {
+                text(StatusCode::OK, &r.extensions().get::<User>().unwrap().0)
+            });
+        let request = http::Request::get("https://app.local/me")
+            .header("X-User", "ann")
+            .body(Vec::new())
+            .unwrap();
+        assert_eq!(body(&router.call(request).unwrap()), "ann");
+    }
+}
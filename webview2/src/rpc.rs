@@ -0,0 +1,1371 @@
+//! JSON-RPC 2.0 between the host and the page.
+//!
+//! `Peer` is one end of the protocol and knows nothing about how messages
+//! travel: feed it what the other end sent with `Peer::receive`, run what
+//! `Peer::take_tasks` returns, and send what `Peer::take_outgoing` returns.
+//! Both ends can register methods and call the other's. On Windows,
+//! `RpcBridge` connects a `Peer` to a webview through web messages, and
+//! `SCRIPT` is the page's end:
+//!
+//! ```js
+//! chrome.webview.rpc.register('confirm', async ({ text }) => window.confirm(text));
+//! const files = await chrome.webview.rpc.call('listFiles', { dir: '.' }, { timeout: 5000 });
+//! chrome.webview.rpc.notify('log', ['ready']);
+//! ```
+//!
+//! Beyond the specification, either side can cancel a call it made with a
+//! `$/cancelRequest` notification carrying the call's `id`, after which the
+//! callee answers it with `RpcError::CANCELLED`.
+
+use crate::host_object::promise::Scheduler;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The notification that cancels a call.
+pub const CANCEL_METHOD: &str = "$/cancelRequest";
+
+/// Page side of the protocol, adds `chrome.webview.rpc`.
+pub const SCRIPT: &str = r#"(function () {
+    var webview = window.chrome && window.chrome.webview;
+    if (!webview || webview.rpc) {
+        return;
+    }
+    var nextId = 1;
+    var calls = {};
+    var methods = {};
+    var running = {};
+    var queue = null;
+
+    function send(message) {
+        if (queue) {
+            queue.push(message);
+        } else {
+            webview.postMessage(message);
+        }
+    }
+    function error(code, message) {
+        var e = new Error(message);
+        e.code = code;
+        return e;
+    }
+    function settle(response) {
+        var call = calls[response.id];
+        if (!call) {
+            return;
+        }
+        delete calls[response.id];
+        clearTimeout(call.timer);
+        if (response.error) {
+            var e = error(response.error.code, response.error.message);
+            e.data = response.error.data;
+            call.reject(e);
+        } else {
+            call.resolve(response.result);
+        }
+    }
+    function cancel(id, code, message) {
+        if (calls[id]) {
+            send({ jsonrpc: '2.0', method: '$/cancelRequest', params: { id: id } });
+            settle({ id: id, error: { code: code, message: message } });
+        }
+    }
+    function handle(message) {
+        if (message === null || typeof message !== 'object' || message.jsonrpc !== '2.0') {
+            return undefined;
+        }
+        if (typeof message.method !== 'string') {
+            settle(message);
+            return undefined;
+        }
+        var id = message.id;
+        var hasId = id !== undefined;
+        if (message.method === '$/cancelRequest') {
+            var cancelled = message.params && message.params.id;
+            if (running[cancelled]) {
+                delete running[cancelled];
+                send({ jsonrpc: '2.0', id: cancelled,
+                       error: { code: -32800, message: 'Request cancelled' } });
+            }
+            return undefined;
+        }
+        var method = methods[message.method];
+        if (!method) {
+            return hasId ? { jsonrpc: '2.0', id: id,
+                             error: { code: -32601, message: 'Method not found' } } : undefined;
+        }
+        if (hasId) {
+            running[id] = true;
+        }
+        Promise.resolve().then(function () {
+            var params = message.params;
+            return Array.isArray(params) ? method.apply(null, params) : method(params);
+        }).then(function (result) {
+            if (hasId && running[id]) {
+                delete running[id];
+                send({ jsonrpc: '2.0', id: id, result: result === undefined ? null : result });
+            }
+        }, function (e) {
+            if (hasId && running[id]) {
+                delete running[id];
+                send({ jsonrpc: '2.0', id: id, error: {
+                    code: typeof e.code === 'number' ? e.code : -32603,
+                    message: String(e && e.message || e),
+                    data: e && e.data } });
+            }
+        });
+        return undefined;
+    }
+    webview.addEventListener('message', function (e) {
+        var data = e.data;
+        if (Array.isArray(data)) {
+            var replies = data.map(handle).filter(function (reply) {
+                return reply !== undefined;
+            });
+            if (replies.length) {
+                send(replies);
+            }
+        } else {
+            var reply = handle(data);
+            if (reply) {
+                send(reply);
+            }
+        }
+    });
+    webview.rpc = {
+        call: function (method, params, options) {
+            var id = nextId++;
+            options = options || {};
+            return new Promise(function (resolve, reject) {
+                calls[id] = { resolve: resolve, reject: reject };
+                if (options.timeout) {
+                    calls[id].timer = setTimeout(function () {
+                        cancel(id, -32001, 'Request timed out');
+                    }, options.timeout);
+                }
+                if (options.signal) {
+                    options.signal.addEventListener('abort', function () {
+                        cancel(id, -32800, 'Request cancelled');
+                    });
+                }
+                send({ jsonrpc: '2.0', id: id, method: method, params: params });
+            });
+        },
+        notify: function (method, params) {
+            send({ jsonrpc: '2.0', method: method, params: params });
+        },
+        register: function (method, f) {
+            methods[method] = f;
+        },
+        unregister: function (method) {
+            delete methods[method];
+        },
+        batch: function (f) {
+            var outer = queue;
+            queue = [];
+            try {
+                f();
+            } finally {
+                var batch = queue;
+                queue = outer;
+                if (batch.length) {
+                    send(batch);
+                }
+            }
+        }
+    };
+})();"#;
+
+/// A request id. Ours are numbers, the other side may use strings too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(i64),
+    String(String),
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Id::Number(n) => write!(f, "{}", n),
+            Id::String(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// An error object, as in a response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Json>,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// The call took longer than its timeout.
+    pub const TIMEOUT: i64 = -32001;
+    /// The caller cancelled the call.
+    pub const CANCELLED: i64 = -32800;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: impl Into<Json>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    pub fn parse_error(detail: impl fmt::Display) -> Self {
+        Self::new(Self::PARSE_ERROR, "Parse error").with_data(detail.to_string())
+    }
+
+    pub fn invalid_request(detail: &str) -> Self {
+        Self::new(Self::INVALID_REQUEST, "Invalid Request").with_data(detail)
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(Self::METHOD_NOT_FOUND, "Method not found").with_data(method)
+    }
+
+    pub fn invalid_params(detail: impl fmt::Display) -> Self {
+        Self::new(Self::INVALID_PARAMS, "Invalid params").with_data(detail.to_string())
+    }
+
+    pub fn internal(detail: impl fmt::Display) -> Self {
+        Self::new(Self::INTERNAL_ERROR, "Internal error").with_data(detail.to_string())
+    }
+
+    pub fn timeout() -> Self {
+        Self::new(Self::TIMEOUT, "Request timed out")
+    }
+
+    pub fn cancelled() -> Self {
+        Self::new(Self::CANCELLED, "Request cancelled")
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)?;
+        if let Some(data) = &self.data {
+            write!(f, ": {}", data)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+pub type RpcResult = Result<Json, RpcError>;
+
+fn response(id: Option<Id>, result: RpcResult) -> Json {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+/// Params go out as an array or an object: `()` as none, other values that
+/// are neither as a one element array.
+fn to_params<P: Serialize>(params: P) -> Result<Option<Json>, RpcError> {
+    match serde_json::to_value(params).map_err(RpcError::invalid_params)? {
+        Json::Null => Ok(None),
+        p @ Json::Array(_) | p @ Json::Object(_) => Ok(Some(p)),
+        p => Ok(Some(Json::Array(vec![p]))),
+    }
+}
+
+/// Absent params decode like `null`, and a one element array like its
+/// element if the method does not take an array.
+fn from_params<P: DeserializeOwned>(params: Json) -> Result<P, RpcError> {
+    match serde_json::from_value(params.clone()) {
+        Ok(p) => Ok(p),
+        Err(e) => match params {
+            Json::Array(mut a) if a.len() == 1 => {
+                serde_json::from_value(a.pop().unwrap()).map_err(|_| RpcError::invalid_params(e))
+            }
+            _ => Err(RpcError::invalid_params(e)),
+        },
+    }
+}
+
+/// State shared with `Responder`s on other threads.
+struct Shared {
+    outbox: Mutex<Outbox>,
+    scheduler: Option<Box<dyn Scheduler>>,
+}
+
+#[derive(Default)]
+struct Outbox {
+    messages: Vec<Json>,
+    /// Collects everything sent while set, to go out as one batch.
+    batch: Option<Vec<Json>>,
+    /// Requests from the other side that still need a response.
+    running: HashSet<Id>,
+}
+
+impl Shared {
+    fn send(&self, message: Json) {
+        let schedule = {
+            let mut outbox = self.outbox.lock().unwrap();
+            match &mut outbox.batch {
+                Some(batch) => {
+                    batch.push(message);
+                    false
+                }
+                None => {
+                    outbox.messages.push(message);
+                    true
+                }
+            }
+        };
+        if let (true, Some(scheduler)) = (schedule, &self.scheduler) {
+            scheduler.schedule();
+        }
+    }
+
+    /// Answer request `id` unless it was answered or cancelled already.
+    fn respond(&self, id: Option<Id>, result: RpcResult) {
+        let id = match id {
+            Some(id) => id,
+            // A notification.
+            None => return,
+        };
+        if self.outbox.lock().unwrap().running.remove(&id) {
+            self.send(response(Some(id), result));
+        }
+    }
+
+    /// Send everything sent during `f` as one batch.
+    fn batched(&self, f: impl FnOnce()) {
+        let outer = self.outbox.lock().unwrap().batch.replace(Vec::new());
+        f();
+        let batch = {
+            let mut outbox = self.outbox.lock().unwrap();
+            std::mem::replace(&mut outbox.batch, outer).unwrap_or_default()
+        };
+        match batch.len() {
+            0 => {}
+            1 => self.send(batch.into_iter().next().unwrap()),
+            _ => self.send(Json::Array(batch)),
+        }
+    }
+}
+
+/// Answers one request, possibly later and from another thread.
+///
+/// Dropping it without answering answers with an internal error, so the
+/// caller is never left waiting.
+pub struct Responder {
+    id: Option<Id>,
+    shared: Option<Arc<Shared>>,
+}
+
+impl Responder {
+    /// `None` for a notification, which takes no answer.
+    pub fn id(&self) -> Option<&Id> {
+        self.id.as_ref()
+    }
+
+    /// Whether the caller cancelled the call, or it was answered already.
+    pub fn is_cancelled(&self) -> bool {
+        match (&self.id, &self.shared) {
+            (Some(id), Some(shared)) => !shared.outbox.lock().unwrap().running.contains(id),
+            _ => true,
+        }
+    }
+
+    pub fn respond(mut self, result: RpcResult) {
+        if let Some(shared) = self.shared.take() {
+            shared.respond(self.id.take(), result);
+        }
+    }
+
+    pub fn resolve<R: Serialize>(self, result: R) {
+        let result = serde_json::to_value(result).map_err(RpcError::internal);
+        self.respond(result)
+    }
+
+    pub fn reject(self, error: RpcError) {
+        self.respond(Err(error))
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            shared.respond(
+                self.id.take(),
+                Err(RpcError::internal("the host dropped the request")),
+            );
+        }
+    }
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Responder").field("id", &self.id).finish()
+    }
+}
+
+type Method = Rc<RefCell<dyn FnMut(Json, Responder)>>;
+type Callback = Box<dyn FnOnce(RpcResult)>;
+type Task = Box<dyn FnOnce()>;
+
+/// Methods and callbacks a `Peer` has left to run, see `Peer::take_tasks`.
+#[must_use]
+#[derive(Default)]
+pub struct Tasks(Vec<Task>);
+
+impl Tasks {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Run them in the order the peer queued them.
+    pub fn run(self) {
+        for task in self.0 {
+            task();
+        }
+    }
+}
+
+impl fmt::Debug for Tasks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Tasks").field(&self.0.len()).finish()
+    }
+}
+
+struct Call {
+    callback: Callback,
+    deadline: Option<Instant>,
+}
+
+/// One end of a JSON-RPC connection.
+///
+/// A peer never runs methods or callbacks itself: it queues them, to be run
+/// through `take_tasks` once the caller has let go of the peer. They can then
+/// use the peer again, even when it sits in a `RefCell`.
+pub struct Peer {
+    methods: HashMap<String, Method>,
+    calls: HashMap<i64, Call>,
+    last_id: i64,
+    tasks: Vec<Task>,
+    shared: Arc<Shared>,
+}
+
+impl Default for Peer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Peer {
+    pub fn new() -> Self {
+        Self::with_shared(None)
+    }
+
+    /// `scheduler` is told when a `Responder` answered from another thread
+    /// has left something to send.
+    pub fn with_scheduler(scheduler: impl Scheduler) -> Self {
+        Self::with_shared(Some(Box::new(scheduler)))
+    }
+
+    fn with_shared(scheduler: Option<Box<dyn Scheduler>>) -> Self {
+        Peer {
+            methods: HashMap::new(),
+            calls: HashMap::new(),
+            last_id: 0,
+            tasks: Vec::new(),
+            shared: Arc::new(Shared {
+                outbox: Mutex::default(),
+                scheduler,
+            }),
+        }
+    }
+
+    /// Register a method answered right away. Its params are decoded as `P`:
+    /// positional params as a tuple or `Vec`, named ones as a struct or map.
+    pub fn register<P, R, F>(&mut self, method: &str, mut f: F)
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: FnMut(P) -> Result<R, RpcError> + 'static,
+    {
+        self.register_async(method, move |params: P, responder: Responder| {
+            match f(params) {
+                Ok(result) => responder.resolve(result),
+                Err(e) => responder.reject(e),
+            }
+        })
+    }
+
+    /// Register a method answered through a `Responder`, which may be sent to
+    /// another thread.
+    pub fn register_async<P, F>(&mut self, method: &str, mut f: F)
+    where
+        P: DeserializeOwned,
+        F: FnMut(P, Responder) + 'static,
+    {
+        let method_f: Method = Rc::new(RefCell::new(move |params, responder| {
+            match from_params(params) {
+                Ok(params) => f(params, responder),
+                Err(e) => responder.reject(e),
+            }
+        }));
+        self.methods.insert(method.to_owned(), method_f);
+    }
+
+    pub fn unregister(&mut self, method: &str) -> bool {
+        self.methods.remove(method).is_some()
+    }
+
+    /// Call `method` on the other side. `callback` gets the result, a
+    /// `RpcError::TIMEOUT` once `timeout` has passed, or a
+    /// `RpcError::CANCELLED` if the call is cancelled first.
+    pub fn call<P, F>(
+        &mut self,
+        method: &str,
+        params: P,
+        timeout: Option<Duration>,
+        now: Instant,
+        callback: F,
+    ) -> Id
+    where
+        P: Serialize,
+        F: FnOnce(RpcResult) + 'static,
+    {
+        self.last_id += 1;
+        let id = self.last_id;
+        match to_params(params) {
+            Ok(params) => {
+                let mut request = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+                if let Some(params) = params {
+                    request["params"] = params;
+                }
+                self.calls.insert(
+                    id,
+                    Call {
+                        callback: Box::new(callback),
+                        deadline: timeout.map(|t| now + t),
+                    },
+                );
+                self.shared.send(request);
+            }
+            Err(e) => self.tasks.push(Box::new(move || callback(Err(e)))),
+        }
+        Id::Number(id)
+    }
+
+    /// `call`, decoding the result as `R`.
+    pub fn call_typed<P, R, F>(
+        &mut self,
+        method: &str,
+        params: P,
+        timeout: Option<Duration>,
+        now: Instant,
+        callback: F,
+    ) -> Id
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+        F: FnOnce(Result<R, RpcError>) + 'static,
+    {
+        self.call(method, params, timeout, now, move |result| {
+            callback(result.and_then(|r| serde_json::from_value(r).map_err(RpcError::internal)))
+        })
+    }
+
+    /// Call `method` without expecting an answer.
+    pub fn notify<P: Serialize>(&mut self, method: &str, params: P) -> Result<(), RpcError> {
+        let mut notification = json!({ "jsonrpc": "2.0", "method": method });
+        if let Some(params) = to_params(params)? {
+            notification["params"] = params;
+        }
+        self.shared.send(notification);
+        Ok(())
+    }
+
+    /// Give up on a call: its callback gets `RpcError::CANCELLED` and the
+    /// other side is told to stop. Returns false if it had finished.
+    pub fn cancel(&mut self, id: &Id) -> bool {
+        self.abandon(id, RpcError::cancelled())
+    }
+
+    fn abandon(&mut self, id: &Id, error: RpcError) -> bool {
+        let call = match id {
+            Id::Number(n) => self.calls.remove(n),
+            Id::String(_) => None,
+        };
+        match call {
+            Some(call) => {
+                self.shared.send(
+                    json!({ "jsonrpc": "2.0", "method": CANCEL_METHOD, "params": { "id": id } }),
+                );
+                self.tasks
+                    .push(Box::new(move || (call.callback)(Err(error))));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls still waiting for an answer.
+    pub fn pending(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// When `tick` should be called next to time out a call.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.calls.values().filter_map(|c| c.deadline).min()
+    }
+
+    /// Time out the calls whose deadline has passed.
+    pub fn tick(&mut self, now: Instant) {
+        let mut expired = self
+            .calls
+            .iter()
+            .filter(|(_, c)| matches!(c.deadline, Some(d) if d <= now))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        expired.sort_unstable();
+        for id in expired {
+            self.abandon(&Id::Number(id), RpcError::timeout());
+        }
+    }
+
+    /// Send everything `f` sends as one batch.
+    pub fn batch(&mut self, f: impl FnOnce(&mut Peer)) {
+        let shared = self.shared.clone();
+        shared.batched(|| f(self));
+    }
+
+    /// Methods to run and callbacks to call, in order. Run them after
+    /// releasing the peer, as they may call into it.
+    pub fn take_tasks(&mut self) -> Tasks {
+        Tasks(std::mem::take(&mut self.tasks))
+    }
+
+    /// Messages to send to the other side, in order.
+    pub fn take_outgoing(&self) -> Vec<String> {
+        let messages = std::mem::take(&mut self.shared.outbox.lock().unwrap().messages);
+        messages.iter().map(Json::to_string).collect()
+    }
+
+    /// Handle a message from the other side, in JSON text.
+    pub fn receive(&mut self, text: &str) {
+        match serde_json::from_str(text) {
+            Ok(message) => self.receive_value(message),
+            Err(e) => self
+                .shared
+                .send(response(None, Err(RpcError::parse_error(e)))),
+        }
+    }
+
+    /// Handle a message from the other side.
+    pub fn receive_value(&mut self, message: Json) {
+        match message {
+            Json::Array(batch) if batch.is_empty() => self.shared.send(response(
+                None,
+                Err(RpcError::invalid_request("empty batch")),
+            )),
+            Json::Array(batch) => {
+                // The answers given right away go out as one batch, so the
+                // batch is handled as a single task.
+                let start = self.tasks.len();
+                for message in batch {
+                    self.receive_one(message);
+                }
+                let tasks = self.tasks.split_off(start);
+                let shared = self.shared.clone();
+                self.tasks.push(Box::new(move || {
+                    shared.batched(|| Tasks(tasks).run());
+                }));
+            }
+            message => self.receive_one(message),
+        }
+    }
+
+    /// Queue `message`, so that it goes out in order with the answers of
+    /// the methods queued before it.
+    fn send_later(&mut self, message: Json) {
+        let shared = self.shared.clone();
+        self.tasks.push(Box::new(move || shared.send(message)));
+    }
+
+    fn receive_one(&mut self, message: Json) {
+        let mut object = match message {
+            Json::Object(object) => object,
+            _ => {
+                return self.send_later(response(
+                    None,
+                    Err(RpcError::invalid_request("not an object")),
+                ))
+            }
+        };
+        let id = match object.remove("id") {
+            None => None,
+            Some(id) => match serde_json::from_value::<Option<Id>>(id) {
+                Ok(id) => id,
+                Err(_) => {
+                    return self
+                        .send_later(response(None, Err(RpcError::invalid_request("bad id"))))
+                }
+            },
+        };
+        if object.get("jsonrpc").and_then(Json::as_str) != Some("2.0") {
+            let error = RpcError::invalid_request("jsonrpc must be \"2.0\"");
+            return self.send_later(response(id, Err(error)));
+        }
+
+        match object.remove("method") {
+            Some(Json::String(method)) => {
+                let params = object.remove("params").unwrap_or(Json::Null);
+                self.request(id, &method, params)
+            }
+            Some(_) => {
+                let error = RpcError::invalid_request("method must be a string");
+                self.send_later(response(id, Err(error)))
+            }
+            None => self.response(id, object),
+        }
+    }
+
+    fn request(&mut self, id: Option<Id>, method: &str, params: Json) {
+        match params {
+            Json::Null | Json::Array(_) | Json::Object(_) => {}
+            _ => {
+                let error = RpcError::invalid_request("params must be structured");
+                return self.send_later(response(id, Err(error)));
+            }
+        }
+        if method == CANCEL_METHOD {
+            let cancelled = params
+                .get("id")
+                .cloned()
+                .and_then(|id| serde_json::from_value(id).ok());
+            if let Some(cancelled) = cancelled {
+                self.shared
+                    .respond(Some(cancelled), Err(RpcError::cancelled()));
+            }
+            return;
+        }
+        if let Some(id) = &id {
+            self.shared
+                .outbox
+                .lock()
+                .unwrap()
+                .running
+                .insert(id.clone());
+        }
+        let responder = Responder {
+            id: id.clone(),
+            shared: Some(self.shared.clone()),
+        };
+        let task: Task = match self.methods.get(method) {
+            Some(f) => {
+                let f = f.clone();
+                Box::new(move || match f.try_borrow_mut() {
+                    Ok(mut f) => (*f)(params, responder),
+                    Err(_) => responder.reject(RpcError::internal("method re-entered")),
+                })
+            }
+            None => {
+                let error = RpcError::method_not_found(method);
+                Box::new(move || responder.reject(error))
+            }
+        };
+        self.tasks.push(task);
+    }
+
+    fn response(&mut self, id: Option<Id>, mut object: serde_json::Map<String, Json>) {
+        let result = match (object.remove("result"), object.remove("error")) {
+            (Some(result), None) => Ok(result),
+            (None, Some(error)) => Err(serde_json::from_value(error)
+                .unwrap_or_else(|e| RpcError::internal(format!("bad error object: {}", e)))),
+            // Nothing to answer a response with.
+            _ => return,
+        };
+        // Answers to calls that were cancelled or timed out are dropped.
+        if let Some(Id::Number(id)) = id {
+            if let Some(call) = self.calls.remove(&id) {
+                self.tasks.push(Box::new(move || (call.callback)(result)));
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut methods = self.methods.keys().collect::<Vec<_>>();
+        methods.sort();
+        f.debug_struct("Peer")
+            .field("methods", &methods)
+            .field("pending", &self.calls.len())
+            .finish()
+    }
+}
+
+#[cfg(windows)]
+pub use self::bridge::*;
+
+#[cfg(windows)]
+mod bridge {
+    use super::*;
+    use crate::util::script::Slot;
+    use crate::util::timer::Timer;
+    use crate::{Error, EventRegistrationToken, Result, WebView};
+    use std::cell::RefMut;
+    use winapi::shared::winerror::E_INVALIDARG;
+
+    struct Inner {
+        webview: WebView,
+        peer: RefCell<Peer>,
+        timer: Timer,
+        token: RefCell<Option<EventRegistrationToken>>,
+        script: Slot,
+    }
+
+    impl Drop for Inner {
+        fn drop(&mut self) {
+            let _ = self.script.remove(&self.webview);
+            if let Some(token) = self.token.get_mut().take() {
+                let _ = self.webview.remove_web_message_received(token);
+            }
+        }
+    }
+
+    /// Connects a `Peer` to a webview's page through web messages.
+    ///
+    /// Everything the peer sends is posted by `flush`, which the bridge calls
+    /// after each of its own calls and received messages. Answers from
+    /// `Responder`s on other threads wait for the next `flush`, so give the
+    /// `Peer` a `Scheduler` that gets the UI thread to call it.
+    #[derive(Clone)]
+    pub struct RpcBridge(Rc<Inner>);
+
+    impl RpcBridge {
+        /// Add `SCRIPT` to every document and start handling its messages,
+        /// until `detach` or the last clone is dropped.
+        pub fn new(webview: &WebView, peer: Peer) -> Result<Self> {
+            let script = Slot::add(webview, SCRIPT)?;
+            let bridge = RpcBridge(Rc::new(Inner {
+                webview: webview.clone(),
+                peer: RefCell::new(peer),
+                timer: Timer::new(),
+                token: RefCell::new(None),
+                script,
+            }));
+            let weak = Rc::downgrade(&bridge.0);
+            let token = webview.add_web_message_received(move |_, args| {
+                if let Some(inner) = weak.upgrade() {
+                    let bridge = RpcBridge(inner);
+                    let json = args.get_web_message_as_json()?;
+                    if is_rpc(&json) {
+                        bridge.with_peer(|peer| peer.receive(&json));
+                        bridge.flush()?;
+                    }
+                }
+                Ok(())
+            })?;
+            *bridge.0.token.borrow_mut() = Some(token);
+            Ok(bridge)
+        }
+
+        /// The peer, to register methods or make calls. Call `flush` after
+        /// calls made through it, which also runs the tasks they left.
+        pub fn peer(&self) -> RefMut<'_, Peer> {
+            self.0.peer.borrow_mut()
+        }
+
+        /// Run `f` on the peer, then the tasks it left once the peer is
+        /// released, so that methods and callbacks can use the bridge.
+        fn with_peer<R>(&self, f: impl FnOnce(&mut Peer) -> R) -> R {
+            let (result, tasks) = {
+                let mut peer = self.peer();
+                let result = f(&mut peer);
+                (result, peer.take_tasks())
+            };
+            tasks.run();
+            result
+        }
+
+        pub fn call<P, F>(
+            &self,
+            method: &str,
+            params: P,
+            timeout: Option<Duration>,
+            f: F,
+        ) -> Result<Id>
+        where
+            P: Serialize,
+            F: FnOnce(RpcResult) + 'static,
+        {
+            let id = self.with_peer(|peer| peer.call(method, params, timeout, Instant::now(), f));
+            self.flush()?;
+            Ok(id)
+        }
+
+        /// Fails with `E_INVALIDARG` if `params` cannot be encoded.
+        pub fn notify<P: Serialize>(&self, method: &str, params: P) -> Result<()> {
+            self.with_peer(|peer| peer.notify(method, params))
+                .map_err(|_| Error::new(E_INVALIDARG))?;
+            self.flush()
+        }
+
+        pub fn cancel(&self, id: &Id) -> Result<bool> {
+            let cancelled = self.with_peer(|peer| peer.cancel(id));
+            self.flush()?;
+            Ok(cancelled)
+        }
+
+        /// Post what the peer has to send and time out overdue calls.
+        pub fn flush(&self) -> Result<()> {
+            self.with_peer(|peer| peer.tick(Instant::now()));
+            let outgoing = self.peer().take_outgoing();
+            for message in outgoing {
+                self.0.webview.post_web_message_as_json(&message)?;
+            }
+            match self.peer().next_deadline() {
+                Some(deadline) => {
+                    let weak = Rc::downgrade(&self.0);
+                    let delay = deadline.saturating_duration_since(Instant::now());
+                    self.0.timer.set(delay, move || {
+                        if let Some(inner) = weak.upgrade() {
+                            RpcBridge(inner).flush().ok();
+                        }
+                    });
+                }
+                None => self.0.timer.clear(),
+            }
+            Ok(())
+        }
+
+        /// Stop handling messages and stop adding `SCRIPT` to new documents.
+        /// Calls in flight time out or stay pending.
+        pub fn detach(&self) -> Result<()> {
+            self.0.timer.clear();
+            self.0.script.remove(&self.0.webview)?;
+            match self.0.token.borrow_mut().take() {
+                Some(token) => self.0.webview.remove_web_message_received(token),
+                None => Ok(()),
+            }
+        }
+    }
+
+    /// Whether a web message is JSON-RPC rather than meant for someone else.
+    fn is_rpc(json: &str) -> bool {
+        match serde_json::from_str::<Json>(json) {
+            Ok(Json::Object(o)) => o.get("jsonrpc") == Some(&Json::from("2.0")),
+            Ok(Json::Array(a)) => a
+                .iter()
+                .any(|m| m.get("jsonrpc") == Some(&Json::from("2.0"))),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+
+    fn outgoing(peer: &Peer) -> Vec<Json> {
+        peer.take_outgoing()
+            .iter()
+            .map(|s| serde_json::from_str(s).unwrap())
+            .collect()
+    }
+
+    /// Answers from `peer` to `text`.
+    fn answer(peer: &mut Peer, text: &str) -> Vec<Json> {
+        peer.receive(text);
+        peer.take_tasks().run();
+        outgoing(peer)
+    }
+
+    fn server() -> Peer {
+        let mut peer = Peer::new();
+        peer.register("subtract", |(a, b): (i64, i64)| Ok::<_, RpcError>(a - b));
+        peer.register("sum", |v: Vec<i64>| {
+            Ok::<_, RpcError>(v.iter().sum::<i64>())
+        });
+        peer.register("get_data", |()| Ok::<_, RpcError>(json!(["hello", 5])));
+        peer.register("notify_hello", |_: Json| Ok::<_, RpcError>(()));
+        peer.register("fail", |()| {
+            Err::<(), _>(RpcError::new(7, "no").with_data(json!({ "why": 1 })))
+        });
+        peer
+    }
+
+    /// The examples of the JSON-RPC 2.0 specification.
+    #[test]
+    fn test_specification_examples() {
+        let mut peer = server();
+        peer.register("subtract_named", |p: HashMap<String, i64>| {
+            Ok::<_, RpcError>(p["minuend"] - p["subtrahend"])
+        });
+        assert_eq!(
+            answer(
+                &mut peer,
+                r#"{"jsonrpc": "2.0", "method": "subtract", "params": [42, 23], "id": 1}"#
+            ),
+            [json!({ "jsonrpc": "2.0", "result": 19, "id": 1 })]
+        );
+        assert_eq!(
+            answer(
+                &mut peer,
+                r#"{"jsonrpc": "2.0", "method": "subtract_named", "params": {"subtrahend": 23, "minuend": 42}, "id": "3"}"#
+            ),
+            [json!({ "jsonrpc": "2.0", "result": 19, "id": "3" })]
+        );
+        assert!(answer(
+            &mut peer,
+            r#"{"jsonrpc": "2.0", "method": "update", "params": [1,2,3,4,5]}"#
+        )
+        .is_empty());
+        assert_eq!(
+            answer(
+                &mut peer,
+                r#"{"jsonrpc": "2.0", "method": "foobar", "id": "1"}"#
+            ),
+            [json!({ "jsonrpc": "2.0", "id": "1", "error": {
+                "code": -32601, "message": "Method not found", "data": "foobar" } })]
+        );
+        let e = answer(
+            &mut peer,
+            r#"{"jsonrpc": "2.0", "method": "foobar, "params": "bar", "baz]"#,
+        );
+        assert_eq!(e[0]["id"], Json::Null);
+        assert_eq!(e[0]["error"]["code"], -32700);
+        assert_eq!(
+            answer(
+                &mut peer,
+                r#"{"jsonrpc": "2.0", "method": 1, "params": "bar"}"#
+            )[0]["error"]["code"],
+            -32600
+        );
+        assert_eq!(answer(&mut peer, "[]")[0]["error"]["code"], -32600);
+        let e = answer(&mut peer, "[1,2]");
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].as_array().unwrap().len(), 2);
+        assert_eq!(e[0][1]["error"]["code"], -32600);
+
+        let batch = answer(
+            &mut peer,
+            r#"[
+                {"jsonrpc": "2.0", "method": "sum", "params": [1,2,4], "id": "1"},
+                {"jsonrpc": "2.0", "method": "notify_hello", "params": [7]},
+                {"jsonrpc": "2.0", "method": "subtract", "params": [42,23], "id": "2"},
+                {"foo": "boo"},
+                {"jsonrpc": "2.0", "method": "foo.get", "params": {"name": "myself"}, "id": "5"},
+                {"jsonrpc": "2.0", "method": "get_data", "id": "9"}
+            ]"#,
+        );
+        let batch = batch[0].as_array().unwrap();
+        assert_eq!(batch.len(), 5);
+        assert_eq!(
+            batch[0],
+            json!({ "jsonrpc": "2.0", "result": 7, "id": "1" })
+        );
+        assert_eq!(
+            batch[1],
+            json!({ "jsonrpc": "2.0", "result": 19, "id": "2" })
+        );
+        assert_eq!(batch[2]["error"]["code"], -32600);
+        assert_eq!(batch[3]["error"]["code"], -32601);
+        assert_eq!(
+            batch[4],
+            json!({ "jsonrpc": "2.0", "result": ["hello", 5], "id": "9" })
+        );
+
+        assert!(answer(
+            &mut peer,
+            r#"[{"jsonrpc": "2.0", "method": "notify_sum", "params": [1,2,4]},
+                {"jsonrpc": "2.0", "method": "notify_hello", "params": [7]}]"#
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_params_and_errors() {
+        let mut peer = server();
+        let e = answer(
+            &mut peer,
+            r#"{"jsonrpc":"2.0","method":"subtract","params":["a",1],"id":1}"#,
+        );
+        assert_eq!(e[0]["error"]["code"], RpcError::INVALID_PARAMS);
+        let e = answer(
+            &mut peer,
+            r#"{"jsonrpc":"2.0","method":"sum","params":3,"id":2}"#,
+        );
+        assert_eq!(e[0]["error"]["code"], RpcError::INVALID_REQUEST);
+        let e = answer(
+            &mut peer,
+            r#"{"jsonrpc":"1.0","method":"sum","params":[],"id":3}"#,
+        );
+        assert_eq!(e[0]["id"], 3);
+        assert_eq!(e[0]["error"]["code"], RpcError::INVALID_REQUEST);
+        assert_eq!(
+            answer(&mut peer, r#"{"jsonrpc":"2.0","method":"fail","id":4}"#),
+            [
+                json!({ "jsonrpc": "2.0", "id": 4, "error": { "code": 7, "message": "no", "data": { "why": 1 } } })
+            ]
+        );
+        // One positional param for a method that does not take an array.
+        peer.register("double", |n: i64| Ok::<_, RpcError>(n * 2));
+        assert_eq!(
+            answer(
+                &mut peer,
+                r#"{"jsonrpc":"2.0","method":"double","params":[4],"id":5}"#
+            )[0]["result"],
+            8
+        );
+    }
+
+    /// Connects two peers in memory, returning how many messages moved.
+    fn pump(a: &mut Peer, b: &mut Peer) -> usize {
+        let mut moved = 0;
+        loop {
+            a.take_tasks().run();
+            b.take_tasks().run();
+            let to_b = a.take_outgoing();
+            let to_a = b.take_outgoing();
+            if to_b.is_empty() && to_a.is_empty() {
+                return moved;
+            }
+            moved += to_b.len() + to_a.len();
+            for m in to_b {
+                b.receive(&m);
+            }
+            for m in to_a {
+                a.receive(&m);
+            }
+        }
+    }
+
+    fn results() -> (Rc<RefCell<Vec<RpcResult>>>, impl FnOnce(RpcResult) + Clone) {
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let r = results.clone();
+        (results, move |result| r.borrow_mut().push(result))
+    }
+
+    #[test]
+    fn test_calls_between_peers() {
+        let now = Instant::now();
+        let mut client = Peer::new();
+        let mut server = server();
+        let (got, push) = results();
+
+        client.call("subtract", (5, 3), None, now, push.clone());
+        client.call("fail", (), None, now, push.clone());
+        client.call("missing", json!({ "a": 1 }), None, now, push.clone());
+        client.batch(|c| {
+            c.call("sum", vec![1, 2], None, now, push.clone());
+            c.notify("notify_hello", ()).unwrap();
+            c.call("get_data", (), None, now, push.clone());
+        });
+        assert_eq!(pump(&mut client, &mut server), 8);
+        let got = got.borrow();
+        assert_eq!(got[0], Ok(json!(2)));
+        assert_eq!(got[1].as_ref().unwrap_err().code, 7);
+        assert_eq!(
+            got[2].as_ref().unwrap_err().code,
+            RpcError::METHOD_NOT_FOUND
+        );
+        assert_eq!(got[3], Ok(json!(3)));
+        assert_eq!(got[4], Ok(json!(["hello", 5])));
+        assert_eq!(client.pending(), 0);
+
+        let typed = Rc::new(RefCell::new(None));
+        let t = typed.clone();
+        client.call_typed("subtract", (1, 3), None, now, move |r: Result<i64, _>| {
+            *t.borrow_mut() = Some(r)
+        });
+        pump(&mut client, &mut server);
+        assert_eq!(*typed.borrow(), Some(Ok(-2)));
+    }
+
+    #[test]
+    fn test_timeouts_and_cancellation() {
+        let now = Instant::now();
+        let mut client = Peer::new();
+        let mut server = Peer::new();
+        let parked = Rc::new(RefCell::new(Vec::new()));
+        let p = parked.clone();
+        server.register_async("slow", move |(): (), r: Responder| p.borrow_mut().push(r));
+        let (got, push) = results();
+
+        let a = client.call("slow", (), Some(Duration::from_secs(1)), now, push.clone());
+        let b = client.call("slow", (), Some(Duration::from_secs(2)), now, push.clone());
+        let c = client.call("slow", (), None, now, push.clone());
+        pump(&mut client, &mut server);
+        assert_eq!(parked.borrow().len(), 3);
+        assert_eq!(client.next_deadline(), Some(now + Duration::from_secs(1)));
+
+        client.tick(now + Duration::from_millis(1500));
+        assert!(client.cancel(&c));
+        assert!(!client.cancel(&a));
+        assert!(got.borrow().is_empty());
+        client.take_tasks().run();
+        assert_eq!(
+            *got.borrow(),
+            [Err(RpcError::timeout()), Err(RpcError::cancelled())]
+        );
+        pump(&mut client, &mut server);
+        // The server was told, so only `b` still expects an answer.
+        let parked = parked.borrow_mut().drain(..).collect::<Vec<_>>();
+        assert_eq!(
+            parked
+                .iter()
+                .map(Responder::is_cancelled)
+                .collect::<Vec<_>>(),
+            [true, false, true]
+        );
+        for r in parked {
+            r.resolve("late");
+        }
+        pump(&mut client, &mut server);
+        assert_eq!(got.borrow().len(), 3);
+        assert_eq!(got.borrow()[2], Ok(json!("late")));
+        assert_eq!(client.pending(), 0);
+        let _ = b;
+    }
+
+    #[test]
+    fn test_responders_on_other_threads() {
+        let now = Instant::now();
+        let scheduled = Arc::new(Mutex::new(0));
+        let s = scheduled.clone();
+        let mut server = Peer::with_scheduler(move || *s.lock().unwrap() += 1);
+        let mut client = Peer::new();
+        let workers = Rc::new(RefCell::new(Vec::new()));
+        let w = workers.clone();
+        server.register_async("work", move |(n,): (i64,), r: Responder| {
+            w.borrow_mut()
+                .push(thread::spawn(move || r.resolve(n * 10)));
+        });
+        server.register_async("drop", |(): (), r: Responder| drop(r));
+        let (got, push) = results();
+        client.call("work", (4,), None, now, push.clone());
+        client.call("drop", (), None, now, push);
+        pump(&mut client, &mut server);
+        for w in workers.borrow_mut().drain(..) {
+            w.join().unwrap();
+        }
+        assert!(*scheduled.lock().unwrap() >= 1);
+        pump(&mut client, &mut server);
+        let got = got.borrow();
+        assert_eq!(got[0].as_ref().unwrap_err().code, RpcError::INTERNAL_ERROR);
+        assert_eq!(got[1], Ok(json!(40)));
+    }
+
+    /// Methods and callbacks run after the peer is released, so they can
+    /// call into it from the `RefCell` it lives in.
+    #[test]
+    fn test_reentrant_tasks() {
+        let now = Instant::now();
+        let client = Rc::new(RefCell::new(Peer::new()));
+        let mut server = server();
+        let (got, push) = results();
+        let c = client.clone();
+        client.borrow_mut().register("again", move |(n,): (i64,)| {
+            c.borrow_mut()
+                .call("subtract", (n, 1), None, now, push.clone());
+            Ok::<_, RpcError>(n)
+        });
+        let (first, push) = results();
+        let c = client.clone();
+        client
+            .borrow_mut()
+            .call("subtract", (3, 1), None, now, move |r| {
+                c.borrow_mut().notify("notify_hello", ()).unwrap();
+                push(r)
+            });
+        server.call("again", (5,), None, now, |_| {});
+        for _ in 0..5 {
+            let tasks = client.borrow_mut().take_tasks();
+            tasks.run();
+            server.take_tasks().run();
+            for m in client.borrow().take_outgoing() {
+                server.receive(&m);
+            }
+            for m in server.take_outgoing() {
+                client.borrow_mut().receive(&m);
+            }
+        }
+        assert_eq!(*first.borrow(), [Ok(json!(2))]);
+        assert_eq!(*got.borrow(), [Ok(json!(4))]);
+        assert_eq!(client.borrow().pending(), 0);
+    }
+
+    /// A batch gets one batch of answers, including the errors.
+    #[test]
+    fn test_batch_answers() {
+        let mut peer = server();
+        let batch = answer(
+            &mut peer,
+            r#"[{"jsonrpc": "2.0", "method": "missing", "id": 1},
+                {"jsonrpc": "2.0", "method": "sum", "params": [1, 2], "id": 2},
+                {"jsonrpc": "2.0", "method": 3, "id": 3}]"#,
+        );
+        assert_eq!(batch.len(), 1);
+        let ids = batch[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [json!(1), json!(2), json!(3)]);
+    }
+
+    /// Calls cross in both directions around a batch of notifications, a
+    /// timeout and a cancellation: every call gets exactly one outcome, and
+    /// the late answers to the abandoned ones are dropped.
+    #[test]
+    fn test_crossing_calls() {
+        let now = Instant::now();
+        let (mut a, mut b) = (server(), server());
+        let (from_a, push_a) = results();
+        let (from_b, push_b) = results();
+        a.call("subtract", (5, 3), None, now, push_a.clone());
+        a.call(
+            "subtract",
+            (1, 1),
+            Some(Duration::from_millis(10)),
+            now,
+            push_a,
+        );
+        b.call("sum", vec![1, 2, 3], None, now, push_b.clone());
+        let cancelled = b.call("subtract", (0, 0), None, now, push_b);
+        a.batch(|a| {
+            a.notify("notify_hello", [1]).unwrap();
+            a.notify("notify_hello", [2]).unwrap();
+        });
+        assert!(b.cancel(&cancelled));
+        a.tick(now + Duration::from_millis(20));
+        pump(&mut a, &mut b);
+        assert_eq!(*from_a.borrow(), [Err(RpcError::timeout()), Ok(json!(2))]);
+        assert_eq!(*from_b.borrow(), [Err(RpcError::cancelled()), Ok(json!(6))]);
+        assert_eq!(a.pending() + b.pending(), 0);
+    }
+}
@@ -0,0 +1,1265 @@
+//! Security headers for the origins an app serves its own pages from.
+//!
+//! Pages from a virtual host mapping or an `AssetServer` come without a
+//! `Content-Security-Policy`, so a script injected into them runs with
+//! everything the app exposes to its pages, host objects included. A
+//! `SecurityPolicy` adds a CSP, `Cross-Origin-Opener-Policy`,
+//! `Cross-Origin-Embedder-Policy`, `Permissions-Policy` and
+//! `X-Content-Type-Options` to the responses of those origins. Everything is
+//! checked when the policy is built, so a typo like `self` for `'self'`
+//! fails there instead of silently allowing a host named `self`.
+//!
+//! A CSP can allow scripts by a nonce that changes with every document.
+//! Scripts given to `SecurityPolicyBuilder::nonce_script` get the nonce of
+//! their document as `nonce`, so they can add inline scripts and styles the
+//! policy lets through. Nothing else in the page can read it. On Windows,
+//! `SecurityPolicy::attach` answers the requests to the origins from a
+//! `Source`, adds the headers, and rolls the nonce on every navigation.
+//! Responses the source leaves to the webview, e.g. those of a virtual host
+//! mapping, cannot be changed, so the origins need to be served from a
+//! source.
+//!
+//! ```
+//! use webview2::security::{Csp, Nonce, PermissionsPolicy, SecurityPolicy};
+//! use webview2::web_resource::http::Response;
+//!
+//! let policy = SecurityPolicy::builder()
+//!     .origin("https://app.local")
+//!     .csp(Csp::new()
+//!         .directive("default-src", vec!["'self'"])
+//!         .directive("script-src", vec!["'self'"])
+//!         .nonce("script-src")
+//!         .directive("object-src", vec!["'none'"]))
+//!     .permissions(PermissionsPolicy::new().deny("camera").allow_self("fullscreen"))
+//!     .build()
+//!     .unwrap();
+//!
+//! let nonce = Nonce::new("r4nd0m").unwrap();
+//! let mut response = Response::builder()
+//!     .header("content-type", "text/html")
+//!     .body(b"<script src=app.js></script>".to_vec())
+//!     .unwrap();
+//! policy.apply(&mut response, Some(&nonce));
+//! assert_eq!(
+//!     response.headers()["content-security-policy"],
+//!     "default-src 'self'; script-src 'self' 'nonce-r4nd0m'; object-src 'none'"
+//! );
+//! assert_eq!(response.headers()["permissions-policy"], "camera=(), fullscreen=(self)");
+//! assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+//! ```
+
+use crate::util::to_js;
+use crate::util::uri::{self, is_scheme, normalize_uri};
+use crate::web_resource::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use crate::web_resource::http::Response;
+use serde_json::Value as Json;
+use std::fmt;
+
+/// Directives whose values are source lists.
+const SOURCE_LIST_DIRECTIVES: &[&str] = &[
+    "default-src",
+    "script-src",
+    "script-src-elem",
+    "script-src-attr",
+    "style-src",
+    "style-src-elem",
+    "style-src-attr",
+    "img-src",
+    "font-src",
+    "connect-src",
+    "media-src",
+    "object-src",
+    "frame-src",
+    "child-src",
+    "worker-src",
+    "manifest-src",
+    "base-uri",
+    "form-action",
+    "frame-ancestors",
+];
+
+/// The other directives, with values that are not checked beyond being
+/// tokens.
+const OTHER_DIRECTIVES: &[&str] = &[
+    "sandbox",
+    "report-uri",
+    "report-to",
+    "require-trusted-types-for",
+    "trusted-types",
+];
+
+/// Directives without values.
+const FLAG_DIRECTIVES: &[&str] = &["upgrade-insecure-requests", "block-all-mixed-content"];
+
+/// Directives a nonce applies to.
+const NONCE_DIRECTIVES: &[&str] = &[
+    "default-src",
+    "script-src",
+    "script-src-elem",
+    "style-src",
+    "style-src-elem",
+];
+
+const KEYWORDS: &[&str] = &[
+    "'self'",
+    "'none'",
+    "'unsafe-inline'",
+    "'unsafe-eval'",
+    "'unsafe-hashes'",
+    "'strict-dynamic'",
+    "'report-sample'",
+    "'wasm-unsafe-eval'",
+];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SecurityError {
+    /// Not a CSP directive.
+    Directive(String),
+    /// A CSP directive or a permission given more than once.
+    Duplicate(String),
+    /// A value that is not valid for its directive.
+    Source { directive: String, source: String },
+    /// `'none'` together with other sources.
+    NoneWithOthers(String),
+    /// A nonce for a directive that does not take one.
+    Nonce(String),
+    /// A fixed `'nonce-...'` source. A nonce that does not change is as good
+    /// as `'unsafe-inline'`; `Csp::nonce` adds one per document.
+    StaticNonce(String),
+    /// A permission name that is not a token.
+    Feature(String),
+    /// An origin that is not `scheme://host[:port]`.
+    Origin(String),
+    /// A policy without origins.
+    NoOrigins,
+    /// A nonce with characters other than those of base64.
+    NonceValue(String),
+    /// The system's random number generator failed.
+    Random(getrandom::Error),
+}
+
+impl fmt::Display for SecurityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecurityError::Directive(d) => write!(f, "unknown CSP directive {:?}", d),
+            SecurityError::Duplicate(d) => write!(f, "{:?} given more than once", d),
+            SecurityError::Source { directive, source } => {
+                write!(f, "invalid source {:?} in {}", source, directive)
+            }
+            SecurityError::NoneWithOthers(d) => {
+                write!(f, "'none' with other sources in {}", d)
+            }
+            SecurityError::Nonce(d) => write!(f, "{} does not take a nonce", d),
+            SecurityError::StaticNonce(d) => write!(f, "fixed nonce in {}", d),
+            SecurityError::Feature(name) => write!(f, "invalid permission {:?}", name),
+            SecurityError::Origin(o) => write!(f, "invalid origin {:?}", o),
+            SecurityError::NoOrigins => write!(f, "no origins"),
+            SecurityError::NonceValue(n) => write!(f, "invalid nonce {:?}", n),
+            SecurityError::Random(e) => write!(f, "no random nonce: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SecurityError {}
+
+/// A nonce for the scripts and styles of one document.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Nonce(String);
+
+impl Nonce {
+    /// A new nonce of 128 bits from the system's cryptographic random
+    /// number generator, `BCryptGenRandom` on Windows.
+    pub fn generate() -> Result<Self, SecurityError> {
+        let mut bytes = [0; 16];
+        getrandom::getrandom(&mut bytes).map_err(SecurityError::Random)?;
+        Ok(Nonce(bytes.iter().map(|b| format!("{:02x}", b)).collect()))
+    }
+
+    /// A given nonce, e.g. from another generator. It needs to be base64,
+    /// with either alphabet.
+    pub fn new(value: &str) -> Result<Self, SecurityError> {
+        let body = value.trim_end_matches('=');
+        if !body.is_empty() && body.bytes().all(is_base64) {
+            Ok(Nonce(value.to_owned()))
+        } else {
+            Err(SecurityError::NonceValue(value.to_owned()))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn is_base64(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"+/-_".contains(&b)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Directive {
+    name: String,
+    values: Vec<String>,
+    nonce: bool,
+}
+
+/// A `Content-Security-Policy`, in the order its directives were added.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Csp {
+    directives: Vec<Directive>,
+}
+
+impl Csp {
+    pub fn new() -> Self {
+        Csp::default()
+    }
+
+    /// Add a directive with its values, e.g. `"img-src"` with `'self'` and
+    /// `data:`. Directive names are case-insensitive.
+    pub fn directive<I, S>(mut self, name: &str, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.directives.push(Directive {
+            name: name.to_ascii_lowercase(),
+            values: values.into_iter().map(|v| v.as_ref().to_owned()).collect(),
+            nonce: false,
+        });
+        self
+    }
+
+    /// Allow the scripts or styles with the nonce of the document in
+    /// directive `name`, adding the directive if there is none yet.
+    pub fn nonce(mut self, name: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        match self.directives.iter_mut().find(|d| d.name == name) {
+            Some(directive) => directive.nonce = true,
+            None => self.directives.push(Directive {
+                name,
+                values: Vec::new(),
+                nonce: true,
+            }),
+        }
+        self
+    }
+
+    pub fn uses_nonce(&self) -> bool {
+        self.directives.iter().any(|d| d.nonce)
+    }
+
+    /// The header value. A directive whose only source is the nonce becomes
+    /// `'none'` without one.
+    pub fn to_header(&self, nonce: Option<&Nonce>) -> String {
+        let mut header = String::new();
+        for directive in &self.directives {
+            if !header.is_empty() {
+                header.push_str("; ");
+            }
+            header.push_str(&directive.name);
+            for value in &directive.values {
+                header.push(' ');
+                header.push_str(value);
+            }
+            match nonce {
+                Some(nonce) if directive.nonce => {
+                    header.push_str(" 'nonce-");
+                    header.push_str(nonce.as_str());
+                    header.push('\'');
+                }
+                _ if directive.nonce && directive.values.is_empty() => {
+                    header.push_str(" 'none'");
+                }
+                _ => {}
+            }
+        }
+        header
+    }
+
+    fn validate(&self) -> Result<(), SecurityError> {
+        for (i, directive) in self.directives.iter().enumerate() {
+            let name = directive.name.as_str();
+            if self.directives[..i].iter().any(|d| d.name == name) {
+                return Err(SecurityError::Duplicate(name.to_owned()));
+            }
+            let invalid = |value: &str| SecurityError::Source {
+                directive: name.to_owned(),
+                source: value.to_owned(),
+            };
+            if directive.nonce && !NONCE_DIRECTIVES.contains(&name) {
+                return Err(SecurityError::Nonce(name.to_owned()));
+            }
+            if SOURCE_LIST_DIRECTIVES.contains(&name) {
+                for value in &directive.values {
+                    let lower = value.to_ascii_lowercase();
+                    if lower.starts_with("'nonce-") {
+                        return Err(SecurityError::StaticNonce(name.to_owned()));
+                    }
+                    if !is_source(&lower) {
+                        return Err(invalid(value));
+                    }
+                }
+                let none = directive
+                    .values
+                    .iter()
+                    .any(|v| v.eq_ignore_ascii_case("'none'"));
+                if none && (directive.values.len() > 1 || directive.nonce) {
+                    return Err(SecurityError::NoneWithOthers(name.to_owned()));
+                }
+            } else if OTHER_DIRECTIVES.contains(&name) {
+                if let Some(value) = directive.values.iter().find(|v| !is_token(v)) {
+                    return Err(invalid(value));
+                }
+            } else if FLAG_DIRECTIVES.contains(&name) {
+                if let Some(value) = directive.values.first() {
+                    return Err(invalid(value));
+                }
+            } else {
+                return Err(SecurityError::Directive(name.to_owned()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Printable ASCII other than the separators of directives and policies.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && b != b';' && b != b',')
+}
+
+/// Whether `source`, in lower case, is a keyword, a hash, a scheme or a
+/// host source.
+fn is_source(source: &str) -> bool {
+    if !is_token(source) {
+        return false;
+    }
+    if KEYWORDS.contains(&source) {
+        return true;
+    }
+    if let Some(rest) = source.strip_prefix('\'') {
+        return ["sha256-", "sha384-", "sha512-"].iter().any(|algorithm| {
+            match rest
+                .strip_prefix(algorithm)
+                .and_then(|hash| hash.strip_suffix('\''))
+            {
+                Some(hash) => Nonce::new(hash).is_ok(),
+                None => false,
+            }
+        });
+    }
+    // Without quotes, a keyword would be a host.
+    if KEYWORDS.iter().any(|k| k.trim_matches('\'') == source) {
+        return false;
+    }
+    if let Some(scheme) = source.strip_suffix(':') {
+        return is_scheme(scheme);
+    }
+    let rest = match source.find("://") {
+        Some(i) if is_scheme(&source[..i]) => &source[i + 3..],
+        Some(_) => return false,
+        None => source,
+    };
+    let host_port = match rest.find('/') {
+        Some(i) => &rest[..i],
+        None => rest,
+    };
+    let host = match host_port.rfind(':') {
+        Some(i) => {
+            let port = &host_port[i + 1..];
+            if port != "*" && (port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit())) {
+                return false;
+            }
+            &host_port[..i]
+        }
+        None => host_port,
+    };
+    if host == "*" {
+        return true;
+    }
+    let host = host.strip_prefix("*.").unwrap_or(host);
+    host.split('.').all(|label| {
+        !label.is_empty()
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    })
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Allowlist {
+    All,
+    Listed {
+        same_origin: bool,
+        origins: Vec<String>,
+    },
+}
+
+/// A `Permissions-Policy`, in the order its permissions were added.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PermissionsPolicy {
+    features: Vec<(String, Allowlist)>,
+}
+
+impl PermissionsPolicy {
+    pub fn new() -> Self {
+        PermissionsPolicy::default()
+    }
+
+    /// Deny `feature`, e.g. `"camera"`, to every document.
+    pub fn deny(self, feature: &str) -> Self {
+        self.with(
+            feature,
+            Allowlist::Listed {
+                same_origin: false,
+                origins: Vec::new(),
+            },
+        )
+    }
+
+    /// Allow `feature` to documents from the page's own origin.
+    pub fn allow_self(self, feature: &str) -> Self {
+        self.allow(feature, Vec::<&str>::new())
+    }
+
+    /// Allow `feature` to documents from the page's own origin and
+    /// `origins`.
+    pub fn allow<I, S>(self, feature: &str, origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let origins = origins.into_iter().map(|o| o.as_ref().to_owned()).collect();
+        self.with(
+            feature,
+            Allowlist::Listed {
+                same_origin: true,
+                origins,
+            },
+        )
+    }
+
+    /// Allow `feature` to every document.
+    pub fn allow_all(self, feature: &str) -> Self {
+        self.with(feature, Allowlist::All)
+    }
+
+    fn with(mut self, feature: &str, allowlist: Allowlist) -> Self {
+        self.features.push((feature.to_owned(), allowlist));
+        self
+    }
+
+    pub fn to_header(&self) -> String {
+        let mut header = String::new();
+        for (feature, allowlist) in &self.features {
+            if !header.is_empty() {
+                header.push_str(", ");
+            }
+            header.push_str(feature);
+            header.push('=');
+            match allowlist {
+                Allowlist::All => header.push('*'),
+                Allowlist::Listed {
+                    same_origin,
+                    origins,
+                } => {
+                    let mut items = Vec::new();
+                    if *same_origin {
+                        items.push("self".to_owned());
+                    }
+                    items.extend(origins.iter().map(|o| format!("\"{}\"", o)));
+                    header.push('(');
+                    header.push_str(&items.join(" "));
+                    header.push(')');
+                }
+            }
+        }
+        header
+    }
+
+    /// Checks the permissions, and normalizes their origins.
+    fn validate(&mut self) -> Result<(), SecurityError> {
+        for i in 0..self.features.len() {
+            let feature = &self.features[i].0;
+            let mut bytes = feature.bytes();
+            let valid = match bytes.next() {
+                Some(b) if b.is_ascii_lowercase() => {
+                    bytes.all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+                }
+                _ => false,
+            };
+            if !valid {
+                return Err(SecurityError::Feature(feature.clone()));
+            }
+            if self.features[..i].iter().any(|(f, _)| f == feature) {
+                return Err(SecurityError::Duplicate(feature.clone()));
+            }
+            if let Allowlist::Listed { origins, .. } = &mut self.features[i].1 {
+                for origin in origins {
+                    *origin = normalize_origin(origin)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn normalize_origin(origin: &str) -> Result<String, SecurityError> {
+    uri::normalize_origin(origin).ok_or_else(|| SecurityError::Origin(origin.to_owned()))
+}
+
+/// `Cross-Origin-Opener-Policy`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OpenerPolicy {
+    UnsafeNone,
+    SameOriginAllowPopups,
+    SameOrigin,
+}
+
+impl OpenerPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OpenerPolicy::UnsafeNone => "unsafe-none",
+            OpenerPolicy::SameOriginAllowPopups => "same-origin-allow-popups",
+            OpenerPolicy::SameOrigin => "same-origin",
+        }
+    }
+}
+
+/// `Cross-Origin-Embedder-Policy`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EmbedderPolicy {
+    UnsafeNone,
+    RequireCorp,
+    Credentialless,
+}
+
+impl EmbedderPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmbedderPolicy::UnsafeNone => "unsafe-none",
+            EmbedderPolicy::RequireCorp => "require-corp",
+            EmbedderPolicy::Credentialless => "credentialless",
+        }
+    }
+}
+
+/// The headers for the responses of an app's origins. See the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct SecurityPolicy {
+    origins: Vec<String>,
+    csp: Option<Csp>,
+    /// The headers that do not depend on the nonce, for documents.
+    document_headers: Vec<(HeaderName, HeaderValue)>,
+    nosniff: bool,
+    scripts: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityPolicyBuilder {
+    origins: Vec<String>,
+    csp: Option<Csp>,
+    opener: Option<OpenerPolicy>,
+    embedder: Option<EmbedderPolicy>,
+    permissions: Option<PermissionsPolicy>,
+    nosniff: bool,
+    scripts: Vec<String>,
+}
+
+impl SecurityPolicyBuilder {
+    /// Protect the responses of `origin`, like `https://app.local`.
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.origins.push(origin.to_owned());
+        self
+    }
+
+    pub fn csp(mut self, csp: Csp) -> Self {
+        self.csp = Some(csp);
+        self
+    }
+
+    pub fn opener_policy(mut self, policy: OpenerPolicy) -> Self {
+        self.opener = Some(policy);
+        self
+    }
+
+    pub fn embedder_policy(mut self, policy: EmbedderPolicy) -> Self {
+        self.embedder = Some(policy);
+        self
+    }
+
+    pub fn permissions(mut self, permissions: PermissionsPolicy) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Whether to send `X-Content-Type-Options: nosniff`, with every
+    /// response. On by default.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = nosniff;
+        self
+    }
+
+    /// Run `script` in the documents of the protected origins, with the
+    /// nonce of the document as `nonce`. Only these scripts see it, the page
+    /// cannot read it.
+    pub fn nonce_script(mut self, script: &str) -> Self {
+        self.scripts.push(script.to_owned());
+        self
+    }
+
+    pub fn build(self) -> Result<SecurityPolicy, SecurityError> {
+        if self.origins.is_empty() {
+            return Err(SecurityError::NoOrigins);
+        }
+        let mut origins = Vec::new();
+        for origin in &self.origins {
+            let origin = normalize_origin(origin)?;
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+        if let Some(csp) = &self.csp {
+            csp.validate()?;
+        }
+
+        let mut document_headers = Vec::new();
+        if let Some(opener) = self.opener {
+            document_headers.push((
+                HeaderName::from_static("cross-origin-opener-policy"),
+                HeaderValue::from_static(opener.as_str()),
+            ));
+        }
+        if let Some(embedder) = self.embedder {
+            document_headers.push((
+                HeaderName::from_static("cross-origin-embedder-policy"),
+                HeaderValue::from_static(embedder.as_str()),
+            ));
+        }
+        if let Some(mut permissions) = self.permissions {
+            permissions.validate()?;
+            let header = permissions.to_header();
+            let value = HeaderValue::from_str(&header)
+                .map_err(|_| SecurityError::Feature(header.clone()))?;
+            document_headers.push((HeaderName::from_static("permissions-policy"), value));
+        }
+
+        Ok(SecurityPolicy {
+            origins,
+            csp: self.csp,
+            document_headers,
+            nosniff: self.nosniff,
+            scripts: self.scripts,
+        })
+    }
+}
+
+impl SecurityPolicy {
+    pub fn builder() -> SecurityPolicyBuilder {
+        SecurityPolicyBuilder {
+            origins: Vec::new(),
+            csp: None,
+            opener: None,
+            embedder: None,
+            permissions: None,
+            nosniff: true,
+            scripts: Vec::new(),
+        }
+    }
+
+    /// The protected origins, normalized.
+    pub fn origins(&self) -> &[String] {
+        &self.origins
+    }
+
+    /// Whether `uri` is on one of the protected origins.
+    pub fn applies_to(&self, uri: &str) -> bool {
+        let uri = normalize_uri(uri);
+        self.origins.iter().any(|origin| {
+            uri.starts_with(origin.as_str())
+                && match uri.as_bytes().get(origin.len()) {
+                    None => true,
+                    Some(b) => b"/?#".contains(b),
+                }
+        })
+    }
+
+    pub fn uses_nonce(&self) -> bool {
+        match &self.csp {
+            Some(csp) => csp.uses_nonce(),
+            None => false,
+        }
+    }
+
+    /// Add the headers to `response`. Documents, i.e. HTML, get all of them,
+    /// with `nonce` in the CSP; other responses only `X-Content-Type-Options`.
+    ///
+    /// The CSP is added next to any the response already has. Browsers
+    /// enforce all of them, so one from the source can only tighten it. The
+    /// other headers replace those of the source.
+    pub fn apply<B>(&self, response: &mut Response<B>, nonce: Option<&Nonce>) {
+        let headers = response.headers_mut();
+        if self.nosniff {
+            headers.insert(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            );
+        }
+        if !is_document(headers) {
+            return;
+        }
+        if let Some(csp) = &self.csp {
+            if let Ok(value) = HeaderValue::from_str(&csp.to_header(nonce)) {
+                headers.append(header::CONTENT_SECURITY_POLICY, value);
+            }
+        }
+        for (name, value) in &self.document_headers {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+
+    /// Whether there are scripts for `nonce_script`.
+    pub fn has_nonce_scripts(&self) -> bool {
+        !self.scripts.is_empty()
+    }
+
+    /// Runs the scripts of `nonce_script` with `nonce` in documents from
+    /// the protected origins, for `add_script_to_execute_on_document_created`.
+    /// Each script is a function of its own taking the nonce, so nothing of
+    /// it is left on `window`.
+    ///
+    /// A replaced script whose removal was still pending when the next
+    /// document was created runs in it too. Its nonce is the old one, which
+    /// the CSP of that document does not allow.
+    pub fn document_script(&self, nonce: &Nonce) -> String {
+        let scripts: String = self
+            .scripts
+            .iter()
+            .map(|script| format!("    (function (nonce) {{\n{}\n    }})(nonce);\n", script))
+            .collect();
+        format!(
+            r#"(function () {{
+    var origins = {};
+    if (origins.indexOf(window.location.origin.toLowerCase()) < 0) {{
+        return;
+    }}
+    var nonce = {};
+{}}})();"#,
+            to_js(&Json::from(self.origins.clone())),
+            to_js(&Json::from(nonce.as_str())),
+            scripts
+        )
+    }
+}
+
+fn is_document(headers: &HeaderMap) -> bool {
+    let content_type = match headers.get(header::CONTENT_TYPE).map(HeaderValue::to_str) {
+        Some(Ok(content_type)) => content_type,
+        _ => return false,
+    };
+    let mime_type = content_type.split(';').next().unwrap_or("").trim();
+    mime_type.eq_ignore_ascii_case("text/html")
+        || mime_type.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::rewrite::{AsyncSource, Fetched, Rewriter};
+    use crate::util::script::Slot;
+    use crate::{Environment, Error, EventRegistrationToken, Result, WebResourceContext, WebView};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use winapi::shared::winerror::E_FAIL;
+
+    /// The event handlers and script `SecurityPolicy::attach` added.
+    #[derive(Debug)]
+    pub struct Attached {
+        resource: EventRegistrationToken,
+        navigation: Option<EventRegistrationToken>,
+        state: Rc<RefCell<State>>,
+    }
+
+    #[derive(Debug)]
+    struct State {
+        nonce: Nonce,
+        script: Option<Slot>,
+    }
+
+    fn generate() -> Result<Nonce> {
+        Nonce::generate().map_err(|_| Error::new(E_FAIL))
+    }
+
+    impl Attached {
+        /// Stop adding headers. The web resource filters stay.
+        pub fn detach(self, webview: &WebView) -> Result<()> {
+            webview.remove_web_resource_requested(self.resource)?;
+            if let Some(token) = self.navigation {
+                webview.remove_navigation_starting(token)?;
+            }
+            match &self.state.borrow().script {
+                Some(script) => script.remove(webview),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl SecurityPolicy {
+        /// Answer the requests `webview` makes to the protected origins with
+        /// responses from `source` and the headers added. Errors of the
+        /// source become a `502 Bad Gateway`. Requests answered later hold a
+        /// deferral until then.
+        ///
+        /// With a nonce in the CSP, every navigation gets a new one, and the
+        /// document created script running the nonce scripts is replaced.
+        pub fn attach(
+            self,
+            webview: &WebView,
+            environment: Environment,
+            source: impl AsyncSource + 'static,
+        ) -> Result<Attached> {
+            let nonce = generate()?;
+            let script = if self.uses_nonce() && self.has_nonce_scripts() {
+                Some(Slot::add(webview, &self.document_script(&nonce))?)
+            } else {
+                None
+            };
+            let state = Rc::new(RefCell::new(State { nonce, script }));
+            let policy = Rc::new(self);
+
+            let navigation = if policy.uses_nonce() {
+                let policy = policy.clone();
+                let state = state.clone();
+                Some(webview.add_navigation_starting(move |w, _| {
+                    let nonce = generate()?;
+                    let mut state = state.borrow_mut();
+                    if state.script.is_some() {
+                        let script = Slot::add(&w, &policy.document_script(&nonce))?;
+                        if let Some(old) = state.script.replace(script) {
+                            old.remove(&w)?;
+                        }
+                    }
+                    state.nonce = nonce;
+                    Ok(())
+                })?)
+            } else {
+                None
+            };
+
+            for origin in policy.origins() {
+                webview.add_web_resource_requested_filter(
+                    &format!("{}/*", origin),
+                    WebResourceContext::All,
+                )?;
+            }
+            let rewriter = Rc::new(Rewriter::new());
+            let s = state.clone();
+            let resource = webview.add_web_resource_requested(move |_, args| {
+                let request = args.get_request()?.to_http()?;
+                if !policy.applies_to(&request.uri().to_string()) {
+                    return Ok(());
+                }
+                let deferral = args.get_deferral()?;
+                // The nonce of the navigation the request belongs to.
+                let nonce = s.borrow().nonce.clone();
+                let policy = policy.clone();
+                let rewriter = rewriter.clone();
+                let environment = environment.clone();
+                source.fetch_async(
+                    request,
+                    Fetched::new(move |result| {
+                        if let Some(mut response) = rewriter.fetched(result) {
+                            policy.apply(&mut response, Some(&nonce));
+                            args.put_payload_response(&environment, response).ok();
+                        }
+                        deferral.complete().ok();
+                    }),
+                );
+                Ok(())
+            })?;
+
+            Ok(Attached {
+                resource,
+                navigation,
+                state,
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::native::Attached;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::typed;
+    use crate::web_resource::Body;
+
+    fn html() -> Response<Body> {
+        typed("text/html; charset=utf-8", b"")
+    }
+
+    fn build(csp: Csp) -> Result<SecurityPolicy, SecurityError> {
+        SecurityPolicy::builder()
+            .origin("https://app.local")
+            .csp(csp)
+            .build()
+    }
+
+    #[test]
+    fn test_csp_header() {
+        let csp = Csp::new()
+            .directive("Default-Src", vec!["'self'"])
+            .nonce("style-src")
+            .directive(
+                "img-src",
+                vec!["'self'", "data:", "https://*.example.com:*/img/"],
+            )
+            .nonce("default-src")
+            .directive("upgrade-insecure-requests", Vec::<&str>::new());
+        assert!(csp.uses_nonce());
+        assert_eq!(
+            csp.to_header(Some(&Nonce::new("abc").unwrap())),
+            "default-src 'self' 'nonce-abc'; style-src 'nonce-abc'; \
+             img-src 'self' data: https://*.example.com:*/img/; upgrade-insecure-requests"
+        );
+        // A directive that would only allow the nonce allows nothing
+        // without one.
+        assert_eq!(
+            csp.to_header(None),
+            "default-src 'self'; style-src 'none'; \
+             img-src 'self' data: https://*.example.com:*/img/; upgrade-insecure-requests"
+        );
+        assert!(build(csp).is_ok());
+        assert!(!Csp::new()
+            .directive("default-src", vec!["'self'"])
+            .uses_nonce());
+    }
+
+    #[test]
+    fn test_csp_validation() {
+        let source = |directive: &str, source: &str| SecurityError::Source {
+            directive: directive.to_owned(),
+            source: source.to_owned(),
+        };
+        let cases = vec![
+            (
+                Csp::new().directive("scripts-src", vec!["'self'"]),
+                SecurityError::Directive("scripts-src".to_owned()),
+            ),
+            (
+                Csp::new()
+                    .directive("img-src", vec!["'self'"])
+                    .directive("IMG-SRC", vec!["data:"]),
+                SecurityError::Duplicate("img-src".to_owned()),
+            ),
+            (
+                Csp::new().directive("script-src", vec!["self"]),
+                source("script-src", "self"),
+            ),
+            (
+                Csp::new().directive("script-src", vec!["'unsafe'"]),
+                source("script-src", "'unsafe'"),
+            ),
+            (
+                Csp::new().directive("script-src", vec!["'sha256-a b'"]),
+                source("script-src", "'sha256-a b'"),
+            ),
+            (
+                Csp::new().directive("script-src", vec!["'self';"]),
+                source("script-src", "'self';"),
+            ),
+            (
+                Csp::new().directive("connect-src", vec!["https://a..b"]),
+                source("connect-src", "https://a..b"),
+            ),
+            (
+                Csp::new().directive("connect-src", vec!["wss://a.b:port"]),
+                source("connect-src", "wss://a.b:port"),
+            ),
+            (
+                Csp::new().directive("connect-src", vec!["1ws://a.b"]),
+                source("connect-src", "1ws://a.b"),
+            ),
+            (
+                Csp::new().directive("upgrade-insecure-requests", vec!["1"]),
+                source("upgrade-insecure-requests", "1"),
+            ),
+            (
+                Csp::new().directive("sandbox", vec!["allow-scripts,"]),
+                source("sandbox", "allow-scripts,"),
+            ),
+            (
+                Csp::new().directive("object-src", vec!["'none'", "'self'"]),
+                SecurityError::NoneWithOthers("object-src".to_owned()),
+            ),
+            (
+                Csp::new()
+                    .directive("script-src", vec!["'none'"])
+                    .nonce("script-src"),
+                SecurityError::NoneWithOthers("script-src".to_owned()),
+            ),
+            (
+                Csp::new().nonce("img-src"),
+                SecurityError::Nonce("img-src".to_owned()),
+            ),
+            (
+                Csp::new().directive("script-src", vec!["'NONCE-abc'"]),
+                SecurityError::StaticNonce("script-src".to_owned()),
+            ),
+        ];
+        for (csp, error) in cases {
+            assert_eq!(build(csp).unwrap_err(), error);
+        }
+
+        let valid = Csp::new()
+            .directive(
+                "script-src",
+                vec![
+                    "'SELF'",
+                    "'strict-dynamic'",
+                    "'sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU='",
+                    "*",
+                    "app.local",
+                    "*.cdn.example.com",
+                    "https:",
+                    "blob:",
+                    "http://localhost:8080",
+                ],
+            )
+            .directive("sandbox", vec!["allow-scripts", "allow-same-origin"])
+            .directive("report-to", vec!["csp-endpoint"])
+            .directive("frame-ancestors", vec!["'none'"]);
+        assert_eq!(build(valid).map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn test_permissions_policy() {
+        let permissions = PermissionsPolicy::new()
+            .deny("camera")
+            .allow_self("fullscreen")
+            .allow("geolocation", vec!["HTTPS://Maps.Example.com:443/"])
+            .allow_all("autoplay");
+        let policy = SecurityPolicy::builder()
+            .origin("https://app.local")
+            .permissions(permissions)
+            .build()
+            .unwrap();
+        let mut response = html();
+        policy.apply(&mut response, None);
+        assert_eq!(
+            response.headers()["permissions-policy"],
+            "camera=(), fullscreen=(self), geolocation=(self \"https://maps.example.com\"), \
+             autoplay=*"
+        );
+        assert!(!response.headers().contains_key("content-security-policy"));
+
+        let build = |permissions: PermissionsPolicy| {
+            SecurityPolicy::builder()
+                .origin("https://app.local")
+                .permissions(permissions)
+                .build()
+                .unwrap_err()
+        };
+        assert_eq!(
+            build(PermissionsPolicy::new().deny("Camera")),
+            SecurityError::Feature("Camera".to_owned())
+        );
+        assert_eq!(
+            build(PermissionsPolicy::new().deny("camera").allow_self("camera")),
+            SecurityError::Duplicate("camera".to_owned())
+        );
+        assert_eq!(
+            build(PermissionsPolicy::new().allow("usb", vec!["maps.example.com"])),
+            SecurityError::Origin("maps.example.com".to_owned())
+        );
+        assert_eq!(
+            build(PermissionsPolicy::new().allow("usb", vec!["https://a\"b"])),
+            SecurityError::Origin("https://a\"b".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_origins() {
+        assert_eq!(
+            SecurityPolicy::builder().build().unwrap_err(),
+            SecurityError::NoOrigins
+        );
+        for origin in &[
+            "app.local",
+            "https://app.local/path",
+            "https://",
+            "about:blank",
+        ] {
+            assert_eq!(
+                SecurityPolicy::builder()
+                    .origin(origin)
+                    .build()
+                    .unwrap_err(),
+                SecurityError::Origin(origin.to_string())
+            );
+        }
+
+        let policy = SecurityPolicy::builder()
+            .origin("HTTPS://App.Local:443/")
+            .origin("https://app.local")
+            .origin("http://localhost:8080")
+            .build()
+            .unwrap();
+        assert_eq!(
+            policy.origins(),
+            &[
+                "https://app.local".to_owned(),
+                "http://localhost:8080".to_owned()
+            ][..]
+        );
+        assert!(policy.applies_to("https://app.local"));
+        assert!(policy.applies_to("https://APP.local:443/index.html?x#y"));
+        assert!(policy.applies_to("http://localhost:8080?q"));
+        assert!(!policy.applies_to("https://app.local.evil.com/"));
+        assert!(!policy.applies_to("http://app.local/"));
+        assert!(!policy.applies_to("http://localhost:80801/"));
+    }
+
+    #[test]
+    fn test_apply() {
+        let policy = SecurityPolicy::builder()
+            .origin("https://app.local")
+            .csp(
+                Csp::new()
+                    .directive("default-src", vec!["'self'"])
+                    .nonce("script-src"),
+            )
+            .opener_policy(OpenerPolicy::SameOrigin)
+            .embedder_policy(EmbedderPolicy::RequireCorp)
+            .build()
+            .unwrap();
+        assert!(policy.uses_nonce());
+        let nonce = Nonce::new("n0nce==").unwrap();
+
+        let mut response = html();
+        response.headers_mut().insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("img-src 'none'"),
+        );
+        response.headers_mut().insert(
+            "cross-origin-opener-policy",
+            HeaderValue::from_static("unsafe-none"),
+        );
+        policy.apply(&mut response, Some(&nonce));
+        let headers = response.headers();
+        let csp: Vec<_> = headers
+            .get_all(header::CONTENT_SECURITY_POLICY)
+            .iter()
+            .collect();
+        assert_eq!(
+            csp,
+            vec![
+                "img-src 'none'",
+                "default-src 'self'; script-src 'nonce-n0nce=='"
+            ]
+        );
+        assert_eq!(headers["cross-origin-opener-policy"], "same-origin");
+        assert_eq!(headers["cross-origin-embedder-policy"], "require-corp");
+        assert_eq!(headers["x-content-type-options"], "nosniff");
+
+        // Not a document.
+        let mut response = Response::builder()
+            .header("content-type", "application/javascript")
+            .body(Body::new())
+            .unwrap();
+        policy.apply(&mut response, Some(&nonce));
+        assert_eq!(response.headers().len(), 2);
+        assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+
+        let policy = SecurityPolicy::builder()
+            .origin("https://app.local")
+            .nosniff(false)
+            .build()
+            .unwrap();
+        let mut response = Response::new(Body::new());
+        policy.apply(&mut response, None);
+        assert!(response.headers().is_empty());
+        assert!(!policy.uses_nonce());
+    }
+
+    #[test]
+    fn test_nonce() {
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            let nonce = Nonce::generate().unwrap();
+            assert_eq!(nonce.as_str().len(), 32);
+            assert_eq!(Nonce::new(nonce.as_str()), Ok(nonce.clone()));
+            assert!(!seen.contains(&nonce));
+            seen.push(nonce);
+        }
+
+        assert!(Nonce::new("a-b_c+d/e==").is_ok());
+        for invalid in &["", "==", "a b", "a'b", "a;b", "é"] {
+            assert_eq!(
+                Nonce::new(invalid),
+                Err(SecurityError::NonceValue(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_document_script() {
+        let policy = SecurityPolicy::builder()
+            .origin("https://App.Local")
+            .nonce_script("style(nonce);")
+            .nonce_script("var nonce = 1;")
+            .build()
+            .unwrap();
+        assert!(policy.has_nonce_scripts());
+        let script = policy.document_script(&Nonce::new("abc").unwrap());
+        assert_eq!(
+            script,
+            r#"(function () {
+    var origins = ["https://app.local"];
+    if (origins.indexOf(window.location.origin.toLowerCase()) < 0) {
+        return;
+    }
+    var nonce = "abc";
+    (function (nonce) {
+style(nonce);
+    })(nonce);
+    (function (nonce) {
+var nonce = 1;
+    })(nonce);
+})();"#
+        );
+    }
+
+    /// Sources built from valid parts are accepted, and breaking one with
+    /// a separator or a space is not.
+    #[test]
+    fn test_sources() {
+        for source in &[
+            "*",
+            "app.local",
+            "https://*.example.com",
+            "wss://localhost:*",
+            "chrome-extension://a-b.c1:8080/a/b.js",
+            "https://app.local/c/",
+            "*.example.com:8080/",
+        ] {
+            let csp = Csp::new().directive("connect-src", vec![*source]);
+            assert!(build(csp).is_ok(), "{}", source);
+        }
+        for broken in &[
+            " app.local",
+            "app.local;",
+            "https://*.exam ple.com",
+            "wss://localhost:,*",
+            "chrome-extension://a-b.c1:8080/a/b.js ",
+            "https;://app.local/c/",
+        ] {
+            let csp = Csp::new().directive("connect-src", vec![*broken]);
+            assert!(build(csp).is_err(), "{:?}", broken);
+        }
+    }
+}
@@ -0,0 +1,511 @@
+//! Read-only `IStream`s implemented in Rust.
+//!
+//! `Stream::from_bytes` copies its buffer into a `SHCreateMemStream`, so a
+//! response body has to be in memory before it can be handed to
+//! `create_web_resource_response`. On Windows, `Stream::from_vec`,
+//! `Stream::from_file` and `Stream::from_reader` instead wrap an owned
+//! buffer, a file or any `Read + Seek` in a COM object that reads from it on
+//! demand, so a large file is streamed lazily as the webview consumes it.
+//!
+//! The buffering and seeking is all in `SharedStream`, which does not need
+//! Windows:
+//!
+//! ```
+//! use std::io::{Read, Seek, SeekFrom};
+//! use webview2::stream::SharedStream;
+//!
+//! let mut stream = SharedStream::from_vec(b"hello world".to_vec());
+//! stream.seek(SeekFrom::Start(6)).unwrap();
+//! let mut clone = stream.clone();
+//! let mut word = String::new();
+//! clone.read_to_string(&mut word).unwrap();
+//! assert_eq!(word, "world");
+//! assert_eq!(stream.position(), 6);
+//! assert_eq!(stream.len(), 11);
+//! ```
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+#[derive(Clone)]
+enum Source {
+    Bytes(Arc<Vec<u8>>),
+    /// Shared by all clones, each seeking it to its own position before
+    /// reading.
+    Reader(Arc<Mutex<dyn ReadSeek>>),
+}
+
+/// A cursor over a buffer, a file or a reader that can be cloned.
+///
+/// Clones share the underlying data but have their own position, which is
+/// what `IStream::Clone` requires. The length is taken once when the stream
+/// is created. Seeking past the end is allowed, and reads there return
+/// nothing.
+#[derive(Clone)]
+pub struct SharedStream {
+    source: Source,
+    len: u64,
+    pos: u64,
+    modified: Option<SystemTime>,
+}
+
+impl SharedStream {
+    pub fn from_vec(buf: Vec<u8>) -> Self {
+        Self {
+            len: buf.len() as u64,
+            source: Source::Bytes(Arc::new(buf)),
+            pos: 0,
+            modified: None,
+        }
+    }
+
+    /// A stream over `file` from its start, with its length and
+    /// modification time from its metadata.
+    pub fn from_file(file: File) -> io::Result<Self> {
+        let metadata = file.metadata()?;
+        Ok(Self {
+            len: metadata.len(),
+            source: Source::Reader(Arc::new(Mutex::new(file))),
+            pos: 0,
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    /// A stream over `reader` from its start. Its length is found by seeking
+    /// to its end.
+    pub fn from_reader<R: Read + Seek + Send + 'static>(mut reader: R) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            len,
+            source: Source::Reader(Arc::new(Mutex::new(reader))),
+            pos: 0,
+            modified: None,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// When the file behind the stream was last modified, if it is one.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Copy at most `limit` bytes from the current position to `out`, as
+    /// `IStream::CopyTo` does. Returns the number of bytes copied.
+    pub fn copy_to(&mut self, out: &mut dyn Write, limit: u64) -> io::Result<u64> {
+        io::copy(&mut self.take(limit), out)
+    }
+}
+
+impl Read for SharedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let n = match &self.source {
+            Source::Bytes(bytes) => {
+                let rest = &bytes[self.pos as usize..];
+                let n = rest.len().min(buf.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                n
+            }
+            Source::Reader(reader) => {
+                let mut reader = reader
+                    .lock()
+                    .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "reader poisoned"))?;
+                reader.seek(SeekFrom::Start(self.pos))?;
+                let limit = (self.len - self.pos).min(buf.len() as u64) as usize;
+                reader.read(&mut buf[..limit])?
+            }
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SharedStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.len, offset),
+        };
+        let pos = if offset < 0 {
+            base.checked_sub(offset.unsigned_abs())
+        } else {
+            base.checked_add(offset as u64)
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// `time` as a `FILETIME`, the number of 100 nanosecond intervals since
+/// 1601-01-01. Times before 1970 are clamped to it.
+#[cfg(any(windows, test))]
+fn filetime(time: SystemTime) -> u64 {
+    const UNIX_EPOCH_TICKS: u64 = 116_444_736_000_000_000;
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH_TICKS
+        + since_epoch.as_secs() * 10_000_000
+        + u64::from(since_epoch.subsec_nanos() / 100)
+}
+
+#[cfg(windows)]
+mod native {
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::Stream;
+    use std::ffi::c_void;
+    use webview2_sys::IStreamVTable;
+    use windows::core::*;
+    use windows::Win32::Foundation::{
+        FILETIME, STG_E_ACCESSDENIED, STG_E_INVALIDFUNCTION, STG_E_READFAULT, S_FALSE, S_OK,
+    };
+    use windows::Win32::System::Com::{
+        ISequentialStream_Impl, IStream, IStream_Impl, LOCKTYPE, STATFLAG, STATSTG, STGC,
+        STGTY_STREAM, STREAM_SEEK, STREAM_SEEK_CUR, STREAM_SEEK_END, STREAM_SEEK_SET,
+    };
+
+    /// The `IStream` behind `Stream::from_vec`, `from_file` and
+    /// `from_reader`. Read only: `Write`, `SetSize` and the region locks fail.
+    #[implement(IStream)]
+    struct RustStream {
+        inner: Mutex<SharedStream>,
+    }
+
+    impl RustStream {
+        fn create(stream: SharedStream) -> IStream {
+            RustStream {
+                inner: Mutex::new(stream),
+            }
+            .into()
+        }
+
+        fn lock(&self) -> Result<std::sync::MutexGuard<'_, SharedStream>> {
+            self.inner.lock().map_err(|_| Error::from(STG_E_READFAULT))
+        }
+    }
+
+    fn read_fault(_: io::Error) -> Error {
+        Error::from(STG_E_READFAULT)
+    }
+
+    /// Writes to another `IStream`, for `CopyTo`.
+    struct Target<'a>(&'a IStream);
+
+    impl Write for Target<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut written = 0;
+            unsafe {
+                self.0
+                    .Write(
+                        buf.as_ptr() as *const c_void,
+                        buf.len() as u32,
+                        Some(&mut written),
+                    )
+                    .ok()
+                    .map_err(|e| io::Error::new(io::ErrorKind::WriteZero, e))?;
+            }
+            Ok(written as usize)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl ISequentialStream_Impl for RustStream {
+        fn Read(&self, pv: *mut c_void, cb: u32, pcbread: *mut u32) -> HRESULT {
+            let mut stream = match self.lock() {
+                Ok(stream) => stream,
+                Err(e) => return e.code(),
+            };
+            let buf = unsafe { std::slice::from_raw_parts_mut(pv as *mut u8, cb as usize) };
+            let mut read = 0;
+            // Unlike `Read::read`, `ISequentialStream::Read` only reads less
+            // than asked for at the end of the stream.
+            while read < buf.len() {
+                match stream.read(&mut buf[read..]) {
+                    Ok(0) => break,
+                    Ok(n) => read += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(_) => return STG_E_READFAULT,
+                }
+            }
+            if !pcbread.is_null() {
+                unsafe { *pcbread = read as u32 };
+            }
+            if read < buf.len() {
+                S_FALSE
+            } else {
+                S_OK
+            }
+        }
+
+        fn Write(&self, _pv: *const c_void, _cb: u32, pcbwritten: *mut u32) -> HRESULT {
+            if !pcbwritten.is_null() {
+                unsafe { *pcbwritten = 0 };
+            }
+            STG_E_ACCESSDENIED
+        }
+    }
+
+    impl IStream_Impl for RustStream {
+        fn Seek(
+            &self,
+            dlibmove: i64,
+            dworigin: STREAM_SEEK,
+            plibnewposition: *mut u64,
+        ) -> Result<()> {
+            let pos = match dworigin {
+                STREAM_SEEK_SET if dlibmove >= 0 => SeekFrom::Start(dlibmove as u64),
+                STREAM_SEEK_CUR => SeekFrom::Current(dlibmove),
+                STREAM_SEEK_END => SeekFrom::End(dlibmove),
+                _ => return Err(STG_E_INVALIDFUNCTION.into()),
+            };
+            let pos = self
+                .lock()?
+                .seek(pos)
+                .map_err(|_| Error::from(STG_E_INVALIDFUNCTION))?;
+            if !plibnewposition.is_null() {
+                unsafe { *plibnewposition = pos };
+            }
+            Ok(())
+        }
+
+        fn SetSize(&self, _libnewsize: u64) -> Result<()> {
+            Err(STG_E_ACCESSDENIED.into())
+        }
+
+        fn CopyTo(
+            &self,
+            pstm: Option<&IStream>,
+            cb: u64,
+            pcbread: *mut u64,
+            pcbwritten: *mut u64,
+        ) -> Result<()> {
+            let target = pstm.ok_or_else(|| Error::from(STG_E_INVALIDFUNCTION))?;
+            let copied = self
+                .lock()?
+                .copy_to(&mut Target(target), cb)
+                .map_err(read_fault)?;
+            unsafe {
+                if !pcbread.is_null() {
+                    *pcbread = copied;
+                }
+                if !pcbwritten.is_null() {
+                    *pcbwritten = copied;
+                }
+            }
+            Ok(())
+        }
+
+        fn Commit(&self, _grfcommitflags: &STGC) -> Result<()> {
+            Ok(())
+        }
+
+        fn Revert(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn LockRegion(&self, _liboffset: u64, _cb: u64, _dwlocktype: &LOCKTYPE) -> Result<()> {
+            Err(STG_E_INVALIDFUNCTION.into())
+        }
+
+        fn UnlockRegion(&self, _liboffset: u64, _cb: u64, _dwlocktype: u32) -> Result<()> {
+            Err(STG_E_INVALIDFUNCTION.into())
+        }
+
+        fn Stat(&self, pstatstg: *mut STATSTG, _grfstatflag: &STATFLAG) -> Result<()> {
+            let stream = self.lock()?;
+            let mtime = stream.modified().map(filetime).unwrap_or(0);
+            let mtime = FILETIME {
+                dwLowDateTime: mtime as u32,
+                dwHighDateTime: (mtime >> 32) as u32,
+            };
+            // There is no name to return, whatever the flag asks for.
+            unsafe {
+                *pstatstg = STATSTG {
+                    r#type: STGTY_STREAM.0 as u32,
+                    cbSize: stream.len(),
+                    mtime,
+                    ctime: mtime,
+                    atime: mtime,
+                    ..Default::default()
+                };
+            }
+            Ok(())
+        }
+
+        fn Clone(&self) -> Result<IStream> {
+            Ok(RustStream::create(self.lock()?.clone()))
+        }
+    }
+
+    impl Stream {
+        /// Create a stream that owns `buf`, without copying it.
+        pub fn from_vec(buf: Vec<u8>) -> Self {
+            Self::from_shared(SharedStream::from_vec(buf))
+        }
+
+        /// Create a stream that reads `file` as it is consumed.
+        pub fn from_file(file: File) -> io::Result<Self> {
+            Ok(Self::from_shared(SharedStream::from_file(file)?))
+        }
+
+        /// Create a stream that reads `reader` as it is consumed.
+        pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> io::Result<Self> {
+            Ok(Self::from_shared(SharedStream::from_reader(reader)?))
+        }
+
+        /// Create a stream from the current position of `stream`. Clones
+        /// of the stream read the same data.
+        pub fn from_shared(stream: SharedStream) -> Self {
+            let raw = RustStream::create(stream).into_raw();
+            unsafe { Stream::from_raw(raw as *mut *mut IStreamVTable) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::temp_path;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    fn read_all(stream: &mut SharedStream) -> Vec<u8> {
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut stream = SharedStream::from_vec(b"0123456789".to_vec());
+        assert_eq!(stream.seek(SeekFrom::End(-3)).unwrap(), 7);
+        assert_eq!(read_all(&mut stream), b"789");
+        assert_eq!(stream.seek(SeekFrom::Current(-5)).unwrap(), 5);
+        assert_eq!(stream.seek(SeekFrom::Current(2)).unwrap(), 7);
+        assert!(stream.seek(SeekFrom::Current(-8)).is_err());
+        assert_eq!(stream.position(), 7);
+        assert!(stream.seek(SeekFrom::End(i64::MIN)).is_err());
+
+        // Past the end is fine, and reads nothing.
+        assert_eq!(stream.seek(SeekFrom::End(5)).unwrap(), 15);
+        assert_eq!(read_all(&mut stream), b"");
+        assert_eq!(stream.seek(SeekFrom::Start(100)).unwrap(), 100);
+        assert_eq!(read_all(&mut stream), b"");
+    }
+
+    #[test]
+    fn test_clone_has_own_position() {
+        let mut stream = SharedStream::from_reader(Cursor::new(b"abcdef".to_vec())).unwrap();
+        assert_eq!(stream.len(), 6);
+        let mut first = [0; 2];
+        stream.read_exact(&mut first).unwrap();
+        assert_eq!(&first, b"ab");
+
+        let mut clone = stream.clone();
+        assert_eq!(read_all(&mut clone), b"cdef");
+        // Reading the clone moved the shared reader, but not this stream.
+        assert_eq!(stream.position(), 2);
+        assert_eq!(read_all(&mut stream), b"cdef");
+        clone.seek(SeekFrom::Start(1)).unwrap();
+        assert_eq!(read_all(&mut clone), b"bcdef");
+    }
+
+    #[test]
+    fn test_copy_to() {
+        let mut stream = SharedStream::from_vec(b"hello world".to_vec());
+        stream.seek(SeekFrom::Start(2)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(stream.copy_to(&mut out, 4).unwrap(), 4);
+        assert_eq!(out, b"llo ");
+        assert_eq!(stream.position(), 6);
+        assert_eq!(stream.copy_to(&mut out, 100).unwrap(), 5);
+        assert_eq!(out, b"llo world");
+        assert_eq!(stream.copy_to(&mut out, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_file() {
+        let path = temp_path("stream");
+        std::fs::write(&path, b"from a file").unwrap();
+        let mut stream = SharedStream::from_file(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(stream.len(), 11);
+        assert!(stream.modified().is_some());
+        stream.seek(SeekFrom::Start(7)).unwrap();
+        assert_eq!(read_all(&mut stream), b"file");
+    }
+
+    #[test]
+    fn test_filetime() {
+        assert_eq!(filetime(SystemTime::UNIX_EPOCH), 116_444_736_000_000_000);
+        // 2020-01-01T00:00:00Z.
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        assert_eq!(filetime(time), 132_223_104_000_000_000);
+        let time = time + Duration::from_nanos(1_234);
+        assert_eq!(filetime(time), 132_223_104_000_000_012);
+    }
+
+    /// A stream over a reader reads what one over the same bytes does, at
+    /// the start, across and past the end, and for empty reads.
+    #[test]
+    fn test_reader_matches_vec() {
+        let data: Vec<u8> = (0..1000).map(|i| (i * 31 % 256) as u8).collect();
+        let mut bytes = SharedStream::from_vec(data.clone());
+        let mut reader = SharedStream::from_reader(Cursor::new(data.clone())).unwrap();
+        for &(pos, len) in &[
+            (0, 4),
+            (500, 64),
+            (3, 0),
+            (990, 64),
+            (1000, 10),
+            (1050, 5),
+            (1, 1),
+        ] {
+            let pos = SeekFrom::Start(pos);
+            assert_eq!(bytes.seek(pos).unwrap(), reader.seek(pos).unwrap());
+            let mut a = vec![0; len];
+            let mut b = a.clone();
+            let n = bytes.read(&mut a).unwrap();
+            assert_eq!(reader.read(&mut b).unwrap(), n);
+            assert_eq!(a[..n], b[..n]);
+            let start = (bytes.position() as usize - n).min(data.len());
+            assert_eq!(a[..n], data[start..start + n]);
+        }
+    }
+}
@@ -1,4 +1,15 @@
-use winapi::shared::windef::RECT;
+pub mod layout;
+#[cfg(windows)]
+pub mod script;
+#[cfg(test)]
+pub(crate) mod testing;
+#[cfg(windows)]
+pub mod timer;
+pub(crate) mod uri;
+
+use crate::geometry::{Point, Rect, Scale};
+use layout::{Layout, Policy};
+use serde_json::Value as Json;
 
 pub fn empty(color: &str) -> String {
     format!(
@@ -18,35 +29,104 @@ pub fn empty(color: &str) -> String {
     )
 }
 
-pub fn calculate_bounds(
-    rect: RECT,
-    ref_width: i32,
-    ref_height: i32,
-    dpi: u32,
-) -> Option<(RECT, f64)> {
-    let width = rect.right - rect.left;
-    let height = rect.bottom - rect.top;
-    if width <= 0 || height <= 0 {
+/// JSON as a JavaScript expression. JSON allows U+2028 and U+2029 in
+/// strings, which older script engines treat as line breaks, so they are
+/// escaped too.
+pub fn to_js(value: &Json) -> String {
+    value
+        .to_string()
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+/// Fit a page made for `ref_width` x `ref_height` into `rect`, centred with
+/// letterbox margins. Returns the bounds and the zoom factor for the
+/// controller. See `layout::Layout` for other policies.
+///
+/// `rect` can be a `geometry::Rect` or, on Windows, a `RECT`. The bounds are
+/// returned as the same type.
+pub fn calculate_bounds<R>(rect: R, ref_width: i32, ref_height: i32, dpi: u32) -> Option<(R, f64)>
+where
+    R: Into<Rect> + From<Rect>,
+{
+    let placement =
+        Layout::new(Policy::Fit, ref_width, ref_height).place(rect.into(), Scale::from_dpi(dpi))?;
+    Some((placement.bounds.into(), placement.zoom))
+}
+
+/// Translate a point given in the same coordinate space as the `rect` passed to
+/// `calculate_bounds` into client coordinates of a webview placed at `bounds`
+/// (the rect returned by `calculate_bounds`).
+///
+/// Returns `None` if the point falls outside of `bounds`, e.g. on the
+/// letterbox margins.
+pub fn translate_point(bounds: Rect, point: Point) -> Option<Point> {
+    if !bounds.contains(point) {
         return None;
     }
+    Some(Point::new(point.x - bounds.left, point.y - bounds.top))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
-    let ratio_w = width as f64 / ref_width as f64;
-    let ratio_h = height as f64 / ref_height as f64;
+    fn translate(bounds: Rect, x: i32, y: i32) -> Option<(i32, i32)> {
+        translate_point(bounds, Point::new(x, y)).map(|p| (p.x, p.y))
+    }
 
-    let ratio = ratio_w.min(ratio_h);
+    #[test]
+    fn test_to_js() {
+        assert_eq!(
+            to_js(&json!("</script>\u{2028}\u{2029}\"\\\n")),
+            r#""</script>\u2028\u2029\"\\\n""#
+        );
+    }
 
-    // how much space should left in `left` side to ensure 16:9 contents
-    let margin_left = ((width as f64 - ref_width as f64 * ratio) / 2.0).floor() as i32;
-    let margin_top = ((height as f64 - ref_height as f64 * ratio) / 2.0).floor() as i32;
+    #[test]
+    fn test_calculate_bounds_letterbox() {
+        // Wider than 16:9, margins on the left and right.
+        let (bounds, zoom) = calculate_bounds(Rect::new(0, 0, 2000, 1080), 1920, 1080, 96).unwrap();
+        assert_eq!(bounds, Rect::new(40, 0, 1960, 1080));
+        assert_eq!(zoom, 1.0);
 
-    let rect = RECT {
-        left: rect.left + margin_left,
-        top: rect.top + margin_top,
-        right: rect.left + width - margin_left,
-        bottom: rect.top + height - margin_top,
-    };
+        // Taller than 16:9, margins on the top and bottom. DPI corrects zoom.
+        let (bounds, zoom) =
+            calculate_bounds(Rect::new(10, 20, 970, 1020), 1920, 1080, 192).unwrap();
+        assert_eq!(bounds, Rect::new(10, 250, 970, 790));
+        assert_eq!(zoom, 0.25);
 
-    let dpi = dpi as f64 / 96.0;
-    let zoom = ratio / dpi;
-    Some((rect, zoom))
+        assert!(calculate_bounds(Rect::new(0, 0, 0, 1080), 1920, 1080, 96).is_none());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_calculate_bounds_rect() {
+        use winapi::shared::windef::RECT;
+
+        let rect = RECT {
+            left: 0,
+            top: 0,
+            right: 2000,
+            bottom: 1080,
+        };
+        let (bounds, _) = calculate_bounds(rect, 1920, 1080, 96).unwrap();
+        assert_eq!(Rect::from(bounds), Rect::new(40, 0, 1960, 1080));
+    }
+
+    #[test]
+    fn test_translate_point() {
+        let (bounds, _) = calculate_bounds(Rect::new(100, 50, 2100, 1130), 1920, 1080, 96).unwrap();
+
+        assert_eq!(translate(bounds, 140, 50), Some((0, 0)));
+        assert_eq!(translate(bounds, 1000, 600), Some((860, 550)));
+        assert_eq!(translate(bounds, 2059, 1129), Some((1919, 1079)));
+
+        // On the margins.
+        assert_eq!(translate(bounds, 139, 600), None);
+        assert_eq!(translate(bounds, 2060, 600), None);
+        assert_eq!(translate(bounds, 1000, 49), None);
+        assert_eq!(translate(bounds, 1000, 1130), None);
+    }
 }
@@ -0,0 +1,441 @@
+//! Placing a page designed for a reference resolution inside a host area.
+//!
+//! A `Layout` turns the host area and its DPI into webview bounds and a zoom
+//! factor, and the resulting `Placement` maps host coordinates back into the
+//! webview, e.g. for forwarding input. `util::calculate_bounds` is
+//! `Layout::new(Policy::Fit, ..)` with the default anchor.
+
+use crate::geometry::{Insets, Point, Rect, Scale};
+
+/// How the reference resolution is scaled into the host area.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Policy {
+    /// Show all of the reference area, leaving letterbox margins.
+    Fit,
+    /// Cover the host area, cropping the reference area.
+    Fill,
+    /// Use the whole host area. WebView2 only zooms uniformly, so the page is
+    /// scaled by the smaller ratio and sees a viewport extended along the
+    /// other axis.
+    Stretch,
+    /// Like `Fit`, but with a whole number scale (or its reciprocal when the
+    /// host area is smaller than the reference) for pixel-perfect content.
+    Integer,
+    /// Use the whole host area and scale the reference width to its width.
+    WidthLocked,
+    /// Use the whole host area and scale the reference height to its height.
+    HeightLocked,
+}
+
+/// Alignment along one axis of content that does not match the host area.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+impl Align {
+    fn factor(self) -> f64 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => 0.5,
+            Align::End => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    pub x: Align,
+    pub y: Align,
+}
+
+impl Anchor {
+    pub fn new(x: Align, y: Align) -> Self {
+        Self { x, y }
+    }
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Self::new(Align::Center, Align::Center)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Layout {
+    policy: Policy,
+    ref_width: i32,
+    ref_height: i32,
+    anchor: Anchor,
+    insets: Insets,
+    min_zoom: Option<f64>,
+    max_zoom: Option<f64>,
+}
+
+impl Layout {
+    pub fn new(policy: Policy, ref_width: i32, ref_height: i32) -> Self {
+        Self {
+            policy,
+            ref_width,
+            ref_height,
+            anchor: Anchor::default(),
+            insets: Insets::default(),
+            min_zoom: None,
+            max_zoom: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    #[inline]
+    pub fn with_insets(mut self, insets: Insets) -> Self {
+        self.insets = insets;
+        self
+    }
+
+    /// Clamp the zoom factor passed to the controller. The clamp wins over
+    /// the policy, e.g. an `Integer` layout may no longer be pixel-perfect.
+    #[inline]
+    pub fn with_zoom_range(mut self, min_zoom: Option<f64>, max_zoom: Option<f64>) -> Self {
+        self.min_zoom = min_zoom;
+        self.max_zoom = max_zoom;
+        self
+    }
+
+    /// Place the webview in `area`, in device pixels, on a monitor with the
+    /// DPI `scale`. Returns `None` if the reference size or the area left
+    /// after the insets is empty.
+    pub fn place(&self, area: Rect, scale: Scale) -> Option<Placement> {
+        let inner = area.inset(&self.insets);
+        if inner.is_empty() || self.ref_width <= 0 || self.ref_height <= 0 || scale.0 <= 0.0 {
+            return None;
+        }
+
+        let ratio_w = inner.width() as f64 / self.ref_width as f64;
+        let ratio_h = inner.height() as f64 / self.ref_height as f64;
+        let ratio = match self.policy {
+            Policy::Fit | Policy::Stretch => ratio_w.min(ratio_h),
+            Policy::Fill => ratio_w.max(ratio_h),
+            Policy::Integer => {
+                let ratio = ratio_w.min(ratio_h);
+                if ratio >= 1.0 {
+                    ratio.floor()
+                } else {
+                    1.0 / (1.0 / ratio).ceil()
+                }
+            }
+            Policy::WidthLocked => ratio_w,
+            Policy::HeightLocked => ratio_h,
+        };
+
+        let dpi_scale = scale.0;
+        let mut zoom = ratio / dpi_scale;
+        if let Some(max_zoom) = self.max_zoom {
+            zoom = zoom.min(max_zoom);
+        }
+        if let Some(min_zoom) = self.min_zoom {
+            zoom = zoom.max(min_zoom);
+        }
+        let scale = zoom * dpi_scale;
+
+        let bounds = match self.policy {
+            Policy::Stretch | Policy::WidthLocked | Policy::HeightLocked => inner,
+            Policy::Fit | Policy::Fill | Policy::Integer => {
+                let (left, right) = align(
+                    inner.left,
+                    inner.right,
+                    self.ref_width as f64 * scale,
+                    self.anchor.x,
+                );
+                let (top, bottom) = align(
+                    inner.top,
+                    inner.bottom,
+                    self.ref_height as f64 * scale,
+                    self.anchor.y,
+                );
+                Rect::new(left, top, right, bottom)
+            }
+        };
+
+        Some(Placement {
+            bounds,
+            visible: bounds.intersect(&area),
+            zoom,
+            scale,
+        })
+    }
+}
+
+// Place `size` between `start` and `end`. The margins are rounded down, so
+// centred content keeps equal margins on both sides. The epsilon keeps an
+// exact fit from rounding to a negative margin.
+fn align(start: i32, end: i32, size: f64, align: Align) -> (i32, i32) {
+    const EPSILON: f64 = 1e-6;
+    let free = (end - start) as f64 - size;
+    let lead = (free * align.factor() + EPSILON).floor() as i32;
+    let trail = (free * (1.0 - align.factor()) + EPSILON).floor() as i32;
+    (start + lead, end - trail)
+}
+
+/// Result of `Layout::place`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Placement {
+    /// Bounds to give the controller. With `Policy::Fill` they extend past
+    /// the host area.
+    pub bounds: Rect,
+    /// The part of `bounds` inside the host area.
+    pub visible: Rect,
+    /// Zoom factor to give the controller.
+    pub zoom: f64,
+    /// Device pixels per page (reference) pixel, i.e. `zoom` times the DPI
+    /// scale.
+    pub scale: f64,
+}
+
+impl Placement {
+    /// Map a host point into webview client coordinates. Returns `None` if
+    /// the point is not on the visible part of the webview.
+    pub fn to_client(&self, point: Point) -> Option<Point> {
+        if !self.visible.contains(point) {
+            return None;
+        }
+        Some(Point::new(
+            point.x - self.bounds.left,
+            point.y - self.bounds.top,
+        ))
+    }
+
+    /// Map a host point into page coordinates, i.e. CSS pixels of the page
+    /// laid out for the reference resolution.
+    pub fn to_page(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let (left, top) = (self.visible.left as f64, self.visible.top as f64);
+        let (right, bottom) = (self.visible.right as f64, self.visible.bottom as f64);
+        if x < left || x >= right || y < top || y >= bottom {
+            return None;
+        }
+        Some((
+            (x - self.bounds.left as f64) / self.scale,
+            (y - self.bounds.top as f64) / self.scale,
+        ))
+    }
+
+    /// Map a point in page coordinates back into host coordinates.
+    pub fn from_page(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            x * self.scale + self.bounds.left as f64,
+            y * self.scale + self.bounds.top as f64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::Rng;
+
+    const POLICIES: &[Policy] = &[
+        Policy::Fit,
+        Policy::Fill,
+        Policy::Stretch,
+        Policy::Integer,
+        Policy::WidthLocked,
+        Policy::HeightLocked,
+    ];
+    const ALIGNS: &[Align] = &[Align::Start, Align::Center, Align::End];
+    const SCALES: &[Scale] = &[Scale(1.0), Scale(1.25), Scale(1.5), Scale(1.75), Scale(2.0)];
+
+    struct Case {
+        layout: Layout,
+        area: Rect,
+        scale: Scale,
+    }
+
+    fn cases(f: impl Fn(&Case, &Placement, &mut Rng)) {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let left = rng.range(-500, 500);
+            let top = rng.range(-500, 500);
+            let area = Rect::new(
+                left,
+                top,
+                left + rng.range(1, 4000),
+                top + rng.range(1, 3000),
+            );
+            let insets = Insets {
+                left: rng.range(0, 50),
+                top: rng.range(0, 50),
+                right: rng.range(0, 50),
+                bottom: rng.range(0, 50),
+            };
+            let layout = Layout::new(rng.pick(POLICIES), rng.range(1, 4000), rng.range(1, 3000))
+                .with_anchor(Anchor::new(rng.pick(ALIGNS), rng.pick(ALIGNS)))
+                .with_insets(insets);
+            let case = Case {
+                layout,
+                area,
+                scale: rng.pick(SCALES),
+            };
+
+            match layout.place(area, case.scale) {
+                Some(placement) => f(&case, &placement, &mut rng),
+                None => assert!(area.inset(&insets).is_empty()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounds_follow_policy() {
+        cases(|case, p, _| {
+            let inner = case.area.inset(&case.layout.insets);
+            let ref_w = case.layout.ref_width as f64 * p.scale;
+            let ref_h = case.layout.ref_height as f64 * p.scale;
+            match case.layout.policy {
+                Policy::Fit | Policy::Integer => {
+                    assert_eq!(inner.intersect(&p.bounds), p.bounds);
+                    assert!((p.bounds.width() as f64 - ref_w).abs() <= 2.0);
+                    assert!((p.bounds.height() as f64 - ref_h).abs() <= 2.0);
+                }
+                Policy::Fill => {
+                    assert_eq!(inner.intersect(&p.bounds), inner);
+                    assert!((p.bounds.width() as f64 - ref_w).abs() <= 2.0);
+                    assert!((p.bounds.height() as f64 - ref_h).abs() <= 2.0);
+                }
+                Policy::Stretch | Policy::WidthLocked | Policy::HeightLocked => {
+                    assert_eq!(p.bounds, inner);
+                }
+            }
+            if case.layout.policy == Policy::Integer {
+                let n = if p.scale >= 1.0 {
+                    p.scale
+                } else {
+                    1.0 / p.scale
+                };
+                assert!((n - n.round()).abs() < 1e-9, "scale {}", p.scale);
+            }
+        });
+    }
+
+    #[test]
+    fn test_zoom_matches_scale_and_dpi() {
+        cases(|case, p, _| {
+            let expected = p.scale / case.scale.0;
+            assert!((p.zoom - expected).abs() < 1e-9);
+            assert!(p.zoom > 0.0);
+        });
+    }
+
+    #[test]
+    fn test_anchor_edges() {
+        cases(|case, p, _| {
+            let inner = case.area.inset(&case.layout.insets);
+            if case.layout.anchor.x == Align::Start {
+                assert_eq!(p.bounds.left, inner.left);
+            }
+            if case.layout.anchor.x == Align::End {
+                assert_eq!(p.bounds.right, inner.right);
+            }
+            if case.layout.anchor.y == Align::Start {
+                assert_eq!(p.bounds.top, inner.top);
+            }
+            if case.layout.anchor.y == Align::End {
+                assert_eq!(p.bounds.bottom, inner.bottom);
+            }
+            if case.layout.anchor.x == Align::Center {
+                let lead = p.bounds.left - inner.left;
+                let trail = inner.right - p.bounds.right;
+                assert_eq!(lead, trail);
+            }
+        });
+    }
+
+    #[test]
+    fn test_inverse_transform() {
+        cases(|case, p, rng| {
+            assert_eq!(p.visible, p.bounds.intersect(&case.area));
+            if p.visible.is_empty() {
+                return;
+            }
+
+            let x = rng.range(p.visible.left, p.visible.right);
+            let y = rng.range(p.visible.top, p.visible.bottom);
+            let client = p.to_client(Point::new(x, y)).unwrap();
+            assert_eq!((client.x, client.y), (x - p.bounds.left, y - p.bounds.top));
+
+            let (px, py) = p.to_page(x as f64, y as f64).unwrap();
+            let (hx, hy) = p.from_page(px, py);
+            assert!((hx - x as f64).abs() < 1e-6 && (hy - y as f64).abs() < 1e-6);
+
+            assert!(p.to_client(Point::new(p.visible.right, y)).is_none());
+            assert!(p.to_client(Point::new(x, p.visible.top - 1)).is_none());
+            assert!(p.to_page(p.visible.left as f64 - 0.5, y as f64).is_none());
+        });
+    }
+
+    #[test]
+    fn test_zoom_range() {
+        let mut rng = Rng(7);
+        for _ in 0..500 {
+            let min_zoom = rng.range(1, 10) as f64 / 10.0;
+            let max_zoom = min_zoom + rng.range(0, 10) as f64 / 10.0;
+            let layout = Layout::new(rng.pick(POLICIES), 1920, 1080)
+                .with_zoom_range(Some(min_zoom), Some(max_zoom));
+            let area = Rect::new(0, 0, rng.range(1, 4000), rng.range(1, 3000));
+            let scale = rng.pick(SCALES);
+            let p = layout.place(area, scale).unwrap();
+            assert!(p.zoom >= min_zoom - 1e-9 && p.zoom <= max_zoom + 1e-9);
+            assert!((p.scale - p.zoom * scale.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_examples() {
+        let area = Rect::new(0, 0, 2000, 1080);
+
+        let p = Layout::new(Policy::Fit, 1920, 1080)
+            .place(area, Scale(1.0))
+            .unwrap();
+        assert_eq!(p.bounds, Rect::new(40, 0, 1960, 1080));
+        assert_eq!(p.zoom, 1.0);
+
+        let p = Layout::new(Policy::Fill, 1920, 1080)
+            .with_anchor(Anchor::new(Align::Start, Align::Start))
+            .place(area, Scale(1.0))
+            .unwrap();
+        assert_eq!(p.bounds, Rect::new(0, 0, 2000, 1125));
+        assert_eq!(p.visible, area);
+
+        let p = Layout::new(Policy::Integer, 640, 360)
+            .place(area, Scale(2.0))
+            .unwrap();
+        assert_eq!(p.bounds, Rect::new(40, 0, 1960, 1080));
+        assert_eq!((p.scale, p.zoom), (3.0, 1.5));
+
+        let p = Layout::new(Policy::Integer, 1920, 1080)
+            .place(Rect::new(0, 0, 1280, 720), Scale(1.0))
+            .unwrap();
+        assert_eq!(p.scale, 0.5);
+        assert_eq!(p.bounds, Rect::new(160, 90, 1120, 630));
+
+        let p = Layout::new(Policy::WidthLocked, 1000, 1000)
+            .with_insets(Insets {
+                left: 0,
+                top: 40,
+                right: 0,
+                bottom: 0,
+            })
+            .place(area, Scale(1.0))
+            .unwrap();
+        assert_eq!(p.bounds, Rect::new(0, 40, 2000, 1080));
+        assert_eq!(p.zoom, 2.0);
+
+        assert!(Layout::new(Policy::Fit, 0, 1080)
+            .place(area, Scale(1.0))
+            .is_none());
+    }
+}
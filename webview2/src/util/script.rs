@@ -0,0 +1,51 @@
+//! Scripts added with `add_script_to_execute_on_document_created`.
+//!
+//! The id needed to remove such a script only comes in once WebView2 has
+//! added it. A `Slot` remembers that the script was removed before then and
+//! removes it as soon as the id arrives.
+
+use crate::{Result, WebView};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+struct State {
+    /// Set once `add_script_to_execute_on_document_created` completes.
+    id: Option<String>,
+    /// Removed before its id came in, so remove it as soon as it does.
+    stale: bool,
+}
+
+/// A script run in every document created from now on, until removed.
+#[derive(Debug, Clone, Default)]
+pub struct Slot(Rc<RefCell<State>>);
+
+impl Slot {
+    /// Add `script` to the documents `webview` creates.
+    pub fn add(webview: &WebView, script: &str) -> Result<Self> {
+        let slot = Slot::default();
+        let state = slot.0.clone();
+        let w = webview.clone();
+        webview.add_script_to_execute_on_document_created(script, move |id| {
+            let mut state = state.borrow_mut();
+            if state.stale {
+                w.remove_script_to_execute_on_document_created(&id)
+            } else {
+                state.id = Some(id);
+                Ok(())
+            }
+        })?;
+        Ok(slot)
+    }
+
+    /// Stop running the script in new documents. Removing it again does
+    /// nothing.
+    pub fn remove(&self, webview: &WebView) -> Result<()> {
+        let mut state = self.0.borrow_mut();
+        state.stale = true;
+        match state.id.take() {
+            Some(id) => webview.remove_script_to_execute_on_document_created(&id),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Helpers shared by tests.
+
+#[cfg(feature = "web_resource")]
+use crate::web_resource::Body;
+#[cfg(feature = "web_resource")]
+use http::Request;
+#[cfg(feature = "rewrite")]
+use http::Response;
+use std::path::PathBuf;
+
+/// A small xorshift generator, so properties can be checked against many
+/// random inputs without extra dependencies.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        low + (self.next() % (high - low) as u64) as i32
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.next() & 1 == 0
+    }
+
+    pub fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+/// A path in the temporary directory for `name`, unique to this process.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("webview2-{}-{}", name, std::process::id()))
+}
+
+/// A request with an empty body.
+#[cfg(feature = "web_resource")]
+pub fn request(method: &str, uri: &str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Vec::new())
+        .unwrap()
+}
+
+/// A `200 OK` response with `headers` and `body`.
+#[cfg(feature = "rewrite")]
+pub fn response(headers: &[(&str, &str)], body: &[u8]) -> Response<Body> {
+    let mut builder = Response::builder();
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(body.to_vec()).unwrap()
+}
+
+/// A `200 OK` response of `content_type`.
+#[cfg(feature = "rewrite")]
+pub fn typed(content_type: &str, body: &[u8]) -> Response<Body> {
+    response(&[("content-type", content_type)], body)
+}
@@ -0,0 +1,98 @@
+//! One-shot timers on the UI thread.
+//!
+//! Thread timers are delivered by the message loop of the thread that set
+//! them, so callbacks run between other window messages and may use the
+//! webview freely.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::Duration;
+use winapi::shared::basetsd::UINT_PTR;
+use winapi::shared::minwindef::{DWORD, UINT};
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::{KillTimer, SetTimer};
+
+thread_local! {
+    static CALLBACKS: RefCell<Callbacks> = RefCell::default();
+}
+
+#[derive(Default)]
+struct Callbacks {
+    /// By timer id, with the token of the `Timer` that set them as ids are
+    /// reused.
+    by_id: HashMap<UINT_PTR, (u64, Box<dyn FnOnce()>)>,
+    last_token: u64,
+}
+
+/// At most one pending callback. Dropping the timer cancels it.
+#[derive(Default)]
+pub struct Timer {
+    pending: Cell<Option<(UINT_PTR, u64)>>,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` after `delay`, replacing the pending callback. Returns false
+    /// if the timer could not be set.
+    pub fn set(&self, delay: Duration, f: impl FnOnce() + 'static) -> bool {
+        self.clear();
+        let ms = delay.as_millis().max(1).min(DWORD::MAX as u128) as UINT;
+        let id = unsafe { SetTimer(0 as HWND, 0, ms, Some(on_timer)) };
+        if id == 0 {
+            return false;
+        }
+        let token = CALLBACKS.with(|c| {
+            let mut c = c.borrow_mut();
+            c.last_token += 1;
+            let token = c.last_token;
+            c.by_id.insert(id, (token, Box::new(f)));
+            token
+        });
+        self.pending.set(Some((id, token)));
+        true
+    }
+
+    pub fn is_pending(&self) -> bool {
+        match self.pending.get() {
+            Some((id, token)) => {
+                CALLBACKS.with(|c| c.borrow().by_id.get(&id).map(|e| e.0)) == Some(token)
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel the pending callback, if any.
+    pub fn clear(&self) {
+        if let Some((id, token)) = self.pending.take() {
+            let callback = CALLBACKS.with(|c| {
+                let mut c = c.borrow_mut();
+                match c.by_id.get(&id) {
+                    Some((t, _)) if *t == token => c.by_id.remove(&id),
+                    _ => None,
+                }
+            });
+            if callback.is_some() {
+                unsafe { KillTimer(0 as HWND, id) };
+            }
+            // Dropped outside the borrow, it may own another `Timer`.
+            drop(callback);
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+unsafe extern "system" fn on_timer(_hwnd: HWND, _msg: UINT, id: UINT_PTR, _time: DWORD) {
+    KillTimer(0 as HWND, id);
+    let callback = CALLBACKS.with(|c| c.borrow_mut().by_id.remove(&id));
+    if let Some((_, f)) = callback {
+        f();
+    }
+}
@@ -0,0 +1,119 @@
+//! URI helpers shared by the modules that look at page addresses.
+
+/// Normalize `uri` the way WebView2 does before matching filters: drop the
+/// fragment, lower case the scheme and host, drop the default port and
+/// make sure there is a path.
+pub fn normalize_uri(uri: &str) -> String {
+    let uri = match uri.find('#') {
+        Some(i) => &uri[..i],
+        None => uri,
+    };
+    let (scheme, rest) = match uri.find("://") {
+        Some(i) => (uri[..i].to_ascii_lowercase(), &uri[i + 3..]),
+        None => return uri.to_owned(),
+    };
+    let end = rest.find(&['/', '?'][..]).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(end);
+    let mut authority = authority.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" | "ws" => ":80",
+        "https" | "wss" => ":443",
+        _ => "",
+    };
+    if !default_port.is_empty() && authority.ends_with(default_port) {
+        authority.truncate(authority.len() - default_port.len());
+    }
+    let slash = if path.starts_with('/') { "" } else { "/" };
+    format!("{}://{}{}{}", scheme, authority, slash, path)
+}
+
+/// The `scheme://host[:port]` of a normalized URI, if it has one.
+pub(crate) fn origin_of(uri: &str) -> Option<&str> {
+    let start = uri.find("://")? + 3;
+    let end = uri[start..].find('/').map_or(uri.len(), |i| start + i);
+    if end == start {
+        None
+    } else {
+        Some(&uri[..end])
+    }
+}
+
+/// `origin` as `scheme://host[:port]`, in lower case and without a default
+/// port.
+pub(crate) fn normalize_origin(origin: &str) -> Option<String> {
+    // Normalizing adds the path, which an origin has none of.
+    let mut normalized = normalize_uri(origin.trim_end_matches('/'));
+    let valid = normalized.pop() == Some('/')
+        && match normalized.find("://") {
+            Some(i) => {
+                let host = &normalized[i + 3..];
+                is_scheme(&normalized[..i])
+                    && !host.is_empty()
+                    && host
+                        .bytes()
+                        .all(|b| b.is_ascii_graphic() && !b"/?#\"".contains(&b))
+            }
+            None => false,
+        };
+    if valid {
+        Some(normalized)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_scheme(scheme: &str) -> bool {
+    let mut bytes = scheme.bytes();
+    match bytes.next() {
+        Some(b) if b.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    bytes.all(|b| b.is_ascii_alphanumeric() || b"+.-".contains(&b))
+}
+
+#[cfg(any(feature = "assets", feature = "web_resource"))]
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let hex = std::str::from_utf8(hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_uri() {
+        for &(uri, normalized) in &[
+            ("HTTPS://App.Local", "https://app.local/"),
+            (
+                "https://app.local:443/A/B?Q#frag",
+                "https://app.local/A/B?Q",
+            ),
+            ("http://app.local:8080?x", "http://app.local:8080/?x"),
+            ("http://app.local:80/", "http://app.local/"),
+            ("about:blank", "about:blank"),
+            ("data:text/html,#x", "data:text/html,"),
+        ] {
+            assert_eq!(normalize_uri(uri), normalized);
+        }
+        assert_eq!(origin_of("https://app.local/a"), Some("https://app.local"));
+        assert_eq!(origin_of("file:///C:/a"), None);
+        assert_eq!(origin_of("about:blank"), None);
+    }
+}
@@ -0,0 +1,469 @@
+//! Owned requests and responses for the web resource APIs, as types of the
+//! `http` crate.
+//!
+//! A `WebResourceRequest` has its method, URI, headers and content behind
+//! separate calls, and `Environment::create_web_resource_response` takes all
+//! the headers as one string. On Windows, `WebResourceRequest::to_http` and
+//! `Environment::create_http_response` convert to and from
+//! `http::Request<Body>` and `http::Response<Body>`. The conversions
+//! themselves are here and do not need Windows:
+//!
+//! ```
+//! use webview2::web_resource::{http, RawResponse};
+//!
+//! let response = http::Response::builder()
+//!     .status(404)
+//!     .header("Content-Type", "text/plain")
+//!     .header("Set-Cookie", "a=1")
+//!     .header("Set-Cookie", "b=2")
+//!     .body(b"gone".to_vec())
+//!     .unwrap();
+//! let raw = RawResponse::from_http(response).unwrap();
+//! assert_eq!(raw.reason, "Not Found");
+//! assert_eq!(raw.headers, "content-type: text/plain\r\nset-cookie: a=1\r\nset-cookie: b=2");
+//! ```
+
+pub use http;
+
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::{Method, Request, Response, Uri};
+use std::fmt;
+
+/// The content of a request or response, read to the end.
+pub type Body = Vec<u8>;
+
+/// The reason phrase to send, as an extension of an `http::Response`.
+/// Without one, the canonical reason of the status is sent.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReasonPhrase(pub String);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HttpError {
+    Method(String),
+    Uri(String),
+    Status(u16),
+    HeaderName(String),
+    HeaderValue {
+        name: String,
+    },
+    /// A line of a header block without a `:`.
+    HeaderLine(String),
+    /// A reason phrase with a control character.
+    ReasonPhrase(String),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::Method(m) => write!(f, "invalid method {:?}", m),
+            HttpError::Uri(u) => write!(f, "invalid URI {:?}", u),
+            HttpError::Status(s) => write!(f, "invalid status {}", s),
+            HttpError::HeaderName(n) => write!(f, "invalid header name {:?}", n),
+            HttpError::HeaderValue { name } => write!(f, "invalid value of header {}", name),
+            HttpError::HeaderLine(l) => write!(f, "malformed header line {:?}", l),
+            HttpError::ReasonPhrase(r) => write!(f, "invalid reason phrase {:?}", r),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Headers from name and value pairs, like those of a
+/// `HttpHeadersCollectionIterator`. A name that comes again adds a value.
+/// Values are encoded as Latin-1, the inverse of `header_block`.
+pub fn header_map<I, N, V>(headers: I) -> Result<HeaderMap, HttpError>
+where
+    I: IntoIterator<Item = (N, V)>,
+    N: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let (name, value) = (name.as_ref(), value.as_ref());
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| HttpError::HeaderName(name.to_owned()))?;
+        let latin1 = value
+            .chars()
+            .map(|c| if c <= '\u{ff}' { Some(c as u8) } else { None })
+            .collect::<Option<Vec<u8>>>();
+        let value = latin1
+            .and_then(|bytes| HeaderValue::from_bytes(&bytes).ok())
+            .ok_or_else(|| HttpError::HeaderValue {
+                name: name.to_string(),
+            })?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// Headers from `Name: value` lines, separated by CRLF or LF.
+pub fn parse_header_block(block: &str) -> Result<HeaderMap, HttpError> {
+    let lines = block
+        .split('\n')
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty());
+    let mut pairs = Vec::new();
+    for line in lines {
+        match line.find(':') {
+            Some(colon) => pairs.push((&line[..colon], line[colon + 1..].trim())),
+            None => return Err(HttpError::HeaderLine(line.to_owned())),
+        }
+    }
+    header_map(pairs)
+}
+
+/// Headers as `create_web_resource_response` takes them: one `name: value`
+/// line per value, separated by CRLF, so that values that cannot be joined
+/// with commas, like `Set-Cookie`, stay apart. Bytes that are not ASCII are
+/// taken as Latin-1.
+pub fn header_block(headers: &HeaderMap) -> String {
+    let mut block = String::new();
+    for (name, value) in headers {
+        if !block.is_empty() {
+            block.push_str("\r\n");
+        }
+        block.push_str(name.as_str());
+        block.push_str(": ");
+        match value.to_str() {
+            Ok(value) => block.push_str(value),
+            Err(_) => block.extend(value.as_bytes().iter().map(|&b| b as char)),
+        }
+    }
+    block
+}
+
+/// A request from its parts as a `WebResourceRequest` has them.
+pub fn request_from_parts<I, N, V>(
+    method: &str,
+    uri: &str,
+    headers: I,
+    body: Body,
+) -> Result<Request<Body>, HttpError>
+where
+    I: IntoIterator<Item = (N, V)>,
+    N: AsRef<str>,
+    V: AsRef<str>,
+{
+    let method =
+        Method::from_bytes(method.as_bytes()).map_err(|_| HttpError::Method(method.to_owned()))?;
+    let uri = uri
+        .parse::<Uri>()
+        .map_err(|_| HttpError::Uri(uri.to_owned()))?;
+    let mut request = Request::new(body);
+    *request.method_mut() = method;
+    *request.uri_mut() = uri;
+    *request.headers_mut() = header_map(headers)?;
+    Ok(request)
+}
+
+/// The `ReasonPhrase` of `response`, or else the canonical reason of its
+/// status, or else nothing.
+pub fn reason_phrase<B>(response: &Response<B>) -> Result<String, HttpError> {
+    match response.extensions().get::<ReasonPhrase>() {
+        Some(ReasonPhrase(reason)) => {
+            if reason.chars().any(|c| c.is_control() && c != '\t') {
+                Err(HttpError::ReasonPhrase(reason.clone()))
+            } else {
+                Ok(reason.clone())
+            }
+        }
+        None => Ok(response
+            .status()
+            .canonical_reason()
+            .unwrap_or("")
+            .to_owned()),
+    }
+}
+
+/// A response in the parts `create_web_resource_response` takes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RawResponse {
+    pub status: u16,
+    pub reason: String,
+    /// See `header_block`.
+    pub headers: String,
+    pub body: Body,
+}
+
+impl RawResponse {
+    pub fn from_http(response: Response<Body>) -> Result<Self, HttpError> {
+        let reason = reason_phrase(&response)?;
+        let (parts, body) = response.into_parts();
+        Ok(RawResponse {
+            status: parts.status.as_u16(),
+            reason,
+            headers: header_block(&parts.headers),
+            body,
+        })
+    }
+
+    /// Back to an `http::Response`, with a `ReasonPhrase` if the reason is
+    /// not the canonical one.
+    pub fn to_http(&self) -> Result<Response<Body>, HttpError> {
+        let mut response = Response::new(self.body.clone());
+        *response.status_mut() =
+            http::StatusCode::from_u16(self.status).map_err(|_| HttpError::Status(self.status))?;
+        *response.headers_mut() = parse_header_block(&self.headers)?;
+        if response.status().canonical_reason() != Some(self.reason.as_str()) {
+            response
+                .extensions_mut()
+                .insert(ReasonPhrase(self.reason.clone()));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(windows)]
+mod native {
+    use super::*;
+    use crate::{
+        Environment, Error, Result, Stream, WebResourceRequest, WebResourceResponse,
+        WebResourceResponseView,
+    };
+    use std::io::Read;
+    use winapi::shared::winerror::{E_FAIL, E_INVALIDARG};
+
+    fn invalid(_: HttpError) -> Error {
+        Error::new(E_INVALIDARG)
+    }
+
+    /// Read a content stream to the end. `get_content` fails with `E_FAIL`
+    /// when there is none, which is an empty body.
+    fn read_content(content: Result<Stream>) -> Result<Body> {
+        let mut body = Vec::new();
+        match content {
+            Ok(mut stream) => {
+                stream.read_to_end(&mut body)?;
+            }
+            Err(e) if e.hresult() == E_FAIL => {}
+            Err(e) => return Err(e),
+        }
+        Ok(body)
+    }
+
+    impl WebResourceRequest {
+        /// The request as an `http::Request`, with its content read.
+        pub fn to_http(&self) -> Result<Request<Body>> {
+            let headers = self.get_headers()?.get_iterator()?;
+            let body = read_content(self.get_content())?;
+            request_from_parts(&self.get_method()?, &self.get_uri()?, headers, body)
+                .map_err(invalid)
+        }
+    }
+
+    impl WebResourceResponse {
+        /// The response as an `http::Response`, with its content read.
+        pub fn to_http(&self) -> Result<Response<Body>> {
+            let raw = RawResponse {
+                status: self.get_status_code()? as u16,
+                reason: self.get_reason_phrase()?,
+                headers: String::new(),
+                body: read_content(self.get_content())?,
+            };
+            let mut response = raw.to_http().map_err(invalid)?;
+            *response.headers_mut() =
+                header_map(self.get_headers()?.get_iterator()?).map_err(invalid)?;
+            Ok(response)
+        }
+    }
+
+    impl WebResourceResponseView {
+        /// The response as an `http::Response`, once its content is read.
+        /// Responses without content, like redirects, have an empty body.
+        pub fn to_http(
+            &self,
+            completed: impl FnOnce(Result<Response<Body>>) -> Result<()> + 'static,
+        ) -> Result<()> {
+            let raw = RawResponse {
+                status: self.get_status_code()? as u16,
+                reason: self.get_reason_phrase()?,
+                headers: String::new(),
+                body: Vec::new(),
+            };
+            let headers = header_map(self.get_headers()?.get_iterator()?).map_err(invalid)?;
+            self.get_content(move |content| {
+                let response = content.and_then(|content| {
+                    let mut raw = raw;
+                    if let Some(mut stream) = content {
+                        stream.read_to_end(&mut raw.body)?;
+                    }
+                    let mut response = raw.to_http().map_err(invalid)?;
+                    *response.headers_mut() = headers;
+                    Ok(response)
+                });
+                completed(response)
+            })
+        }
+    }
+
+    impl Environment {
+        /// `create_web_resource_response` from an `http::Response`. Fails
+        /// with `E_INVALIDARG` if its reason phrase is invalid.
+        pub fn create_http_response(
+            &self,
+            response: Response<Body>,
+        ) -> Result<WebResourceResponse> {
+            let raw = RawResponse::from_http(response).map_err(invalid)?;
+            self.create_web_resource_response(
+                Stream::from_vec(raw.body),
+                raw.status as i32,
+                &raw.reason,
+                &raw.headers,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_block() {
+        let mut headers = header_map(vec![
+            ("Content-Type", "text/html"),
+            ("Set-Cookie", "a=1; Path=/"),
+            ("X-Empty", ""),
+            ("set-cookie", "b=2"),
+        ])
+        .unwrap();
+        assert_eq!(
+            header_block(&headers),
+            "content-type: text/html\r\nset-cookie: a=1; Path=/\r\nset-cookie: b=2\r\nx-empty: "
+        );
+        headers.insert("x-latin", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        assert!(header_block(&headers).ends_with("\r\nx-latin: caf\u{e9}"));
+        assert_eq!(header_block(&HeaderMap::new()), "");
+
+        let parsed = parse_header_block("A: 1\r\n\r\nb:2\nA:  3  \n").unwrap();
+        assert_eq!(
+            parsed.get_all("a").iter().collect::<Vec<_>>(),
+            vec!["1", "3"]
+        );
+        assert_eq!(parsed["b"], "2");
+        assert_eq!(
+            parse_header_block("no colon"),
+            Err(HttpError::HeaderLine("no colon".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(
+            header_map(vec![("Bad Name", "x")]),
+            Err(HttpError::HeaderName("Bad Name".to_owned()))
+        );
+        assert_eq!(
+            header_map(vec![("x", "a\r\nInjected: 1")]),
+            Err(HttpError::HeaderValue {
+                name: "x".to_owned()
+            })
+        );
+        assert_eq!(
+            header_map(vec![("x", "\u{263a}")]),
+            Err(HttpError::HeaderValue {
+                name: "x".to_owned()
+            })
+        );
+        let latin1 = header_map(vec![("x", "caf\u{e9}")]).unwrap();
+        assert_eq!(latin1["x"].as_bytes(), b"caf\xe9");
+        assert_eq!(header_block(&latin1), "x: caf\u{e9}");
+        let headers: Vec<(String, String)> = Vec::new();
+        assert_eq!(
+            request_from_parts("GE T", "https://a/", headers.clone(), Vec::new()).unwrap_err(),
+            HttpError::Method("GE T".to_owned())
+        );
+        assert_eq!(
+            request_from_parts("GET", "https://a b/", headers, Vec::new()).unwrap_err(),
+            HttpError::Uri("https://a b/".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_requests() {
+        let request = request_from_parts(
+            "POST",
+            "https://app.local/api/items?id=7",
+            vec![
+                ("Content-Type".to_owned(), "application/json".to_owned()),
+                ("Accept".to_owned(), "a".to_owned()),
+                ("Accept".to_owned(), "b".to_owned()),
+            ],
+            b"{}".to_vec(),
+        )
+        .unwrap();
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.uri().host(), Some("app.local"));
+        assert_eq!(request.uri().query(), Some("id=7"));
+        assert_eq!(request.headers().get_all("accept").iter().count(), 2);
+        assert_eq!(request.body(), b"{}");
+        // Methods are case-sensitive, and extensions are allowed.
+        let request = request_from_parts("purge", "/x", Vec::<(&str, &str)>::new(), Vec::new());
+        assert_eq!(request.unwrap().method().as_str(), "purge");
+    }
+
+    #[test]
+    fn test_responses() {
+        let response = Response::builder().status(204).body(Vec::new()).unwrap();
+        let raw = RawResponse::from_http(response).unwrap();
+        assert_eq!((raw.status, raw.reason.as_str()), (204, "No Content"));
+
+        let mut response = Response::builder().status(299).body(Vec::new()).unwrap();
+        assert_eq!(reason_phrase(&response).unwrap(), "");
+        response
+            .extensions_mut()
+            .insert(ReasonPhrase("Fine Really".to_owned()));
+        let raw = RawResponse::from_http(response).unwrap();
+        assert_eq!(raw.reason, "Fine Really");
+        let back = raw.to_http().unwrap();
+        assert_eq!(
+            back.extensions().get::<ReasonPhrase>(),
+            Some(&ReasonPhrase("Fine Really".to_owned()))
+        );
+
+        let mut response = Response::new(Vec::new());
+        response
+            .extensions_mut()
+            .insert(ReasonPhrase("OK\r\nX: 1".to_owned()));
+        assert_eq!(
+            RawResponse::from_http(response),
+            Err(HttpError::ReasonPhrase("OK\r\nX: 1".to_owned()))
+        );
+
+        let raw = RawResponse::from_http(Response::new(Vec::new())).unwrap();
+        assert!(raw
+            .to_http()
+            .unwrap()
+            .extensions()
+            .get::<ReasonPhrase>()
+            .is_none());
+    }
+
+    /// Headers survive a round trip through a header block, with the values
+    /// of each name in order and separators inside values left alone.
+    #[test]
+    fn test_round_trips() {
+        let headers = header_map(vec![
+            ("set-cookie", "a=1; Path=/"),
+            ("vary", "accept"),
+            ("set-cookie", "b=\"x,y\""),
+            ("x-a", "key: value"),
+            ("x-a", "tab\tinside"),
+            ("x-a", ""),
+        ])
+        .unwrap();
+        let block = header_block(&headers);
+        assert_eq!(parse_header_block(&block).unwrap(), headers, "{:?}", block);
+
+        for status in &[100, 299, 599] {
+            let status = http::StatusCode::from_u16(*status).unwrap();
+            let mut response = Response::new(b"\0body".to_vec());
+            *response.status_mut() = status;
+            *response.headers_mut() = headers.clone();
+            let back = RawResponse::from_http(response).unwrap().to_http().unwrap();
+            assert_eq!(back.status(), status);
+            assert_eq!(back.headers(), &headers);
+            assert_eq!(back.body(), b"\0body");
+        }
+    }
+}
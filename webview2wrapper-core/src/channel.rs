@@ -0,0 +1,506 @@
+//! Message channel between the page and the engine.
+//!
+//! Web messages are strings. Binary payloads are sent as strings too: the
+//! `BINARY_PREFIX` character followed by the base64 encoded bytes. The
+//! `BOOTSTRAP_SCRIPT` adds `chrome.webview.postBinary` and
+//! `chrome.webview.addBinaryListener` to the page to produce and consume them.
+//!
+//! Pending messages are kept in a `BoundedQueue` and handed to the engine in
+//! batches. A batch is laid out as
+//!
+//! ```text
+//! u32 count
+//! count times:
+//!     u8  kind     (a `MessageKind`)
+//!     u32 length
+//!     u8  payload[length]
+//! ```
+//!
+//! with all integers little endian.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Marks a string web message as base64 encoded binary data.
+pub const BINARY_PREFIX: char = '\u{1}';
+
+/// Kind of a message in a batch.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MessageKind {
+    /// UTF-8 text.
+    Text = 0,
+    Binary = 1,
+}
+
+/// Page side of the binary channel.
+pub const BOOTSTRAP_SCRIPT: &str = r#"(function () {
+    var webview = window.chrome && window.chrome.webview;
+    if (!webview || webview.postBinary) {
+        return;
+    }
+    var PREFIX = '\u0001';
+    var listeners = [];
+    webview.postBinary = function (data) {
+        var bytes = data instanceof Uint8Array ? data : new Uint8Array(data.buffer || data);
+        var s = '';
+        for (var i = 0; i < bytes.length; i += 0x8000) {
+            s += String.fromCharCode.apply(null, bytes.subarray(i, i + 0x8000));
+        }
+        webview.postMessage(PREFIX + btoa(s));
+    };
+    webview.addBinaryListener = function (listener) {
+        listeners.push(listener);
+    };
+    webview.removeBinaryListener = function (listener) {
+        listeners = listeners.filter(function (l) { return l !== listener; });
+    };
+    webview.addEventListener('message', function (e) {
+        if (typeof e.data !== 'string' || e.data.charAt(0) !== PREFIX) {
+            return;
+        }
+        var s = atob(e.data.substring(1));
+        var bytes = new Uint8Array(s.length);
+        for (var i = 0; i < s.length; i++) {
+            bytes[i] = s.charCodeAt(i);
+        }
+        listeners.forEach(function (l) { l(bytes); });
+    });
+})();"#;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Message {
+    /// Decode a string web message.
+    pub fn decode(s: String) -> Message {
+        if let Some(encoded) = s.strip_prefix(BINARY_PREFIX) {
+            if let Some(bytes) = base64_decode(encoded) {
+                return Message::Binary(bytes);
+            }
+        }
+        Message::Text(s)
+    }
+
+    /// Encode as a string web message.
+    pub fn encode(&self) -> String {
+        match self {
+            Message::Text(s) => s.clone(),
+            Message::Binary(bytes) => encode_binary(bytes),
+        }
+    }
+}
+
+/// Encode bytes as a string web message.
+pub fn encode_binary(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(1 + bytes.len() / 3 * 4 + 4);
+    s.push(BINARY_PREFIX);
+    base64_encode(bytes, &mut s);
+    s
+}
+
+/// Append `messages` to `out` in the batch layout described in the module
+/// documentation.
+pub fn encode_batch(messages: &[Message], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(messages.len() as u32).to_le_bytes());
+    for m in messages {
+        let (kind, payload) = match m {
+            Message::Text(s) => (MessageKind::Text, s.as_bytes()),
+            Message::Binary(bytes) => (MessageKind::Binary, &bytes[..]),
+        };
+        out.push(kind as u8);
+        out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(payload);
+    }
+}
+
+/// Inverse of `encode_batch`. Returns `None` on malformed input.
+pub fn decode_batch(mut buf: &[u8]) -> Option<Vec<Message>> {
+    fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if buf.len() < n {
+            return None;
+        }
+        let (head, tail) = buf.split_at(n);
+        *buf = tail;
+        Some(head)
+    }
+    fn take_u32(buf: &mut &[u8]) -> Option<usize> {
+        Some(u32::from_le_bytes(take(buf, 4)?.try_into().ok()?) as usize)
+    }
+
+    let count = take_u32(&mut buf)?;
+    let mut messages = Vec::new();
+    for _ in 0..count {
+        let kind = take(&mut buf, 1)?[0];
+        let len = take_u32(&mut buf)?;
+        let payload = take(&mut buf, len)?;
+        messages.push(match kind {
+            k if k == MessageKind::Text as u8 => {
+                Message::Text(String::from_utf8(payload.to_vec()).ok()?)
+            }
+            k if k == MessageKind::Binary as u8 => Message::Binary(payload.to_vec()),
+            _ => return None,
+        });
+    }
+    if buf.is_empty() {
+        Some(messages)
+    } else {
+        None
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8], out: &mut String) {
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    }
+
+    let s = s.as_bytes();
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    for (i, chunk) in s.chunks(4).enumerate() {
+        if chunk.len() != 4 {
+            return None;
+        }
+        let last = (i + 1) * 4 == s.len();
+        let padding = if last {
+            chunk.iter().rev().take_while(|c| **c == b'=').count()
+        } else {
+            0
+        };
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0;
+        for c in &chunk[..4 - padding] {
+            n = n << 6 | value(*c)?;
+        }
+        n <<= 6 * padding;
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        out.extend_from_slice(&bytes[..3 - padding]);
+    }
+    Some(out)
+}
+
+/// What to do when a message arrives and the queue is full.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room.
+    DropOldest,
+    /// Discard the arriving message.
+    DropNewest,
+    /// Wait for the consumer to make room, at most for the given duration,
+    /// then discard the arriving message.
+    ///
+    /// Messages are produced on the UI thread. If the consumer runs on the
+    /// same thread, every overflow stalls it for the whole duration.
+    Block(Duration),
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    capacity: Option<usize>,
+    policy: OverflowPolicy,
+    dropped: u64,
+}
+
+impl<T> QueueState<T> {
+    fn is_full(&self) -> bool {
+        matches!(self.capacity, Some(c) if self.items.len() >= c)
+    }
+}
+
+/// A FIFO queue with an optional capacity and an `OverflowPolicy`.
+pub struct BoundedQueue<T> {
+    state: Mutex<QueueState<T>>,
+    not_full: Condvar,
+}
+
+impl<T> Default for BoundedQueue<T> {
+    fn default() -> Self {
+        Self::new(None, OverflowPolicy::DropOldest)
+    }
+}
+
+impl<T> BoundedQueue<T> {
+    /// `capacity` `None` means unbounded.
+    pub fn new(capacity: Option<usize>, policy: OverflowPolicy) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                capacity,
+                policy,
+                dropped: 0,
+            }),
+            not_full: Condvar::new(),
+        }
+    }
+
+    /// Change the limit. Messages already queued beyond a lower capacity are
+    /// kept; the limit applies to the following pushes.
+    pub fn set_limit(&self, capacity: Option<usize>, policy: OverflowPolicy) {
+        let mut state = self.state.lock().unwrap();
+        state.capacity = capacity;
+        state.policy = policy;
+        drop(state);
+        self.not_full.notify_all();
+    }
+
+    /// Returns whether `item` was queued.
+    pub fn push(&self, item: T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.is_full() {
+            match state.policy {
+                OverflowPolicy::DropOldest => {
+                    if state.capacity == Some(0) {
+                        state.dropped += 1;
+                        return false;
+                    }
+                    while state.is_full() {
+                        state.items.pop_front();
+                        state.dropped += 1;
+                    }
+                }
+                OverflowPolicy::DropNewest => {
+                    state.dropped += 1;
+                    return false;
+                }
+                OverflowPolicy::Block(timeout) => {
+                    let deadline = Instant::now() + timeout;
+                    while state.is_full() {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            state.dropped += 1;
+                            return false;
+                        }
+                        state = self.not_full.wait_timeout(state, deadline - now).unwrap().0;
+                    }
+                }
+            }
+        }
+        state.items.push_back(item);
+        true
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let item = self.state.lock().unwrap().items.pop_front();
+        if item.is_some() {
+            self.not_full.notify_all();
+        }
+        item
+    }
+
+    /// Remove and return all queued messages.
+    pub fn drain(&self) -> Vec<T> {
+        let items: Vec<T> = self.state.lock().unwrap().items.drain(..).collect();
+        self.not_full.notify_all();
+        items
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of messages discarded because of the overflow policy.
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_base64() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"", ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"fooba", "Zm9vYmE="),
+            (b"foobar", "Zm9vYmFy"),
+            (&[0, 0xff, 0xfe, 0x80], "AP/+gA=="),
+        ];
+        for (bytes, encoded) in cases {
+            let mut s = String::new();
+            base64_encode(bytes, &mut s);
+            assert_eq!(&s, encoded);
+            assert_eq!(base64_decode(encoded).as_deref(), Some(*bytes));
+        }
+
+        assert_eq!(base64_decode("Zg="), None);
+        assert_eq!(base64_decode("Z==="), None);
+        assert_eq!(base64_decode("Zg==Zg=="), None);
+        assert_eq!(base64_decode("Zm9*"), None);
+    }
+
+    #[test]
+    fn test_message_codec() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let encoded = encode_binary(&bytes);
+        assert!(encoded.starts_with(BINARY_PREFIX));
+        assert_eq!(Message::decode(encoded), Message::Binary(bytes));
+
+        assert_eq!(
+            Message::decode("hello".to_owned()),
+            Message::Text("hello".to_owned())
+        );
+        // Not valid base64 after the prefix, passed through as text.
+        let s = format!("{}not base64!", BINARY_PREFIX);
+        assert_eq!(Message::decode(s.clone()), Message::Text(s));
+
+        let m = Message::Binary(vec![1, 2, 3]);
+        assert_eq!(Message::decode(m.encode()), m);
+    }
+
+    #[test]
+    fn test_batch() {
+        let messages = vec![
+            Message::Text("héllo".to_owned()),
+            Message::Binary(vec![0, 1, 2]),
+            Message::Text(String::new()),
+        ];
+        let mut buf = Vec::new();
+        encode_batch(&messages, &mut buf);
+        assert_eq!(
+            buf,
+            [
+                &[3, 0, 0, 0][..],
+                &[MessageKind::Text as u8, 6, 0, 0, 0],
+                "héllo".as_bytes(),
+                &[MessageKind::Binary as u8, 3, 0, 0, 0, 0, 1, 2],
+                &[MessageKind::Text as u8, 0, 0, 0, 0],
+            ]
+            .concat()
+        );
+        assert_eq!(decode_batch(&buf), Some(messages));
+
+        buf.clear();
+        encode_batch(&[], &mut buf);
+        assert_eq!(decode_batch(&buf), Some(vec![]));
+
+        assert_eq!(
+            decode_batch(&[1, 0, 0, 0, MessageKind::Text as u8, 5, 0, 0, 0, b'a']),
+            None
+        );
+        assert_eq!(decode_batch(&[1, 0, 0, 0, 7, 0, 0, 0, 0]), None);
+        assert_eq!(decode_batch(&[0, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_queue_unbounded() {
+        let q = BoundedQueue::default();
+        for i in 0..1000 {
+            assert!(q.push(i));
+        }
+        assert_eq!(q.pop(), Some(0));
+        assert_eq!(q.drain().len(), 999);
+        assert!(q.is_empty());
+        assert_eq!(q.dropped(), 0);
+    }
+
+    #[test]
+    fn test_queue_drop_oldest() {
+        let q = BoundedQueue::new(Some(3), OverflowPolicy::DropOldest);
+        for i in 0..5 {
+            assert!(q.push(i));
+        }
+        assert_eq!(q.drain(), vec![2, 3, 4]);
+        assert_eq!(q.dropped(), 2);
+
+        // Lowering the capacity drops down to it on the next push.
+        for i in 0..3 {
+            q.push(i);
+        }
+        q.set_limit(Some(1), OverflowPolicy::DropOldest);
+        assert!(q.push(3));
+        assert_eq!(q.drain(), vec![3]);
+    }
+
+    #[test]
+    fn test_queue_drop_newest() {
+        let q = BoundedQueue::new(Some(3), OverflowPolicy::DropNewest);
+        for i in 0..5 {
+            q.push(i);
+        }
+        assert!(!q.push(5));
+        assert_eq!(q.drain(), vec![0, 1, 2]);
+        assert_eq!(q.dropped(), 3);
+    }
+
+    #[test]
+    fn test_queue_block_times_out() {
+        let q = BoundedQueue::new(Some(1), OverflowPolicy::Block(Duration::from_millis(20)));
+        assert!(q.push(0));
+        let start = Instant::now();
+        assert!(!q.push(1));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(q.drain(), vec![0]);
+        assert_eq!(q.dropped(), 1);
+    }
+
+    #[test]
+    fn test_queue_block_until_consumed() {
+        let q = Arc::new(BoundedQueue::new(
+            Some(2),
+            OverflowPolicy::Block(Duration::from_secs(60)),
+        ));
+        let producer = {
+            let q = q.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    assert!(q.push(i));
+                }
+            })
+        };
+        let mut received = Vec::new();
+        while received.len() < 100 {
+            match q.pop() {
+                Some(i) => received.push(i),
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+        assert_eq!(q.dropped(), 0);
+    }
+}
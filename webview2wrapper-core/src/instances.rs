@@ -0,0 +1,326 @@
+//! Bookkeeping for several webviews in one process.
+//!
+//! `EnvironmentCache` shares one environment between all webviews created
+//! with the same options, including those requested while the environment is
+//! still being created. `InstanceRegistry` remembers the parent window of
+//! every instance and their stacking order within each parent.
+//!
+//! Nothing here calls into Win32 or WebView2; the exports in `webview2wrapper`
+//! apply the results.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Options that must match for two webviews to share an environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct EnvironmentKey {
+    pub user_data_folder: Option<String>,
+    pub browser_arguments: String,
+    pub language: Option<String>,
+}
+
+/// Outcome of `EnvironmentCache::request`.
+pub enum Request<E, W> {
+    /// The environment is ready. The waiter is handed back to be run now.
+    Ready(E, W),
+    /// Nothing is cached. The caller must create the environment and report
+    /// the result with `complete`.
+    Create,
+    /// Creation is already in progress. The waiter runs on `complete`.
+    Queued,
+}
+
+enum Entry<E, W> {
+    Pending(Vec<W>),
+    Ready(E),
+}
+
+/// Environments by `EnvironmentKey`, with the waiters of those that are still
+/// being created.
+pub struct EnvironmentCache<E, W> {
+    entries: HashMap<EnvironmentKey, Entry<E, W>>,
+}
+
+impl<E, W> Default for EnvironmentCache<E, W> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<E: Clone, W> EnvironmentCache<E, W> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request(&mut self, key: &EnvironmentKey, waiter: W) -> Request<E, W> {
+        match self.entries.get_mut(key) {
+            Some(Entry::Ready(environment)) => Request::Ready(environment.clone(), waiter),
+            Some(Entry::Pending(waiters)) => {
+                waiters.push(waiter);
+                Request::Queued
+            }
+            None => {
+                self.entries
+                    .insert(key.clone(), Entry::Pending(vec![waiter]));
+                Request::Create
+            }
+        }
+    }
+
+    /// Record the result of creating the environment for `key` and return the
+    /// waiters to run, in request order. On failure (`None`) the key is
+    /// forgotten so the next request tries again.
+    pub fn complete(&mut self, key: &EnvironmentKey, environment: Option<E>) -> Vec<W> {
+        let waiters = match self.entries.remove(key) {
+            Some(Entry::Pending(waiters)) => waiters,
+            Some(ready) => {
+                self.entries.insert(key.clone(), ready);
+                return Vec::new();
+            }
+            None => Vec::new(),
+        };
+        if let Some(environment) = environment {
+            self.entries.insert(key.clone(), Entry::Ready(environment));
+        }
+        waiters
+    }
+
+    pub fn get(&self, key: &EnvironmentKey) -> Option<&E> {
+        match self.entries.get(key) {
+            Some(Entry::Ready(environment)) => Some(environment),
+            _ => None,
+        }
+    }
+
+    /// Forget a ready environment, e.g. after its browser process exited.
+    /// Pending entries are left alone.
+    pub fn remove(&mut self, key: &EnvironmentKey) -> Option<E> {
+        match self.entries.remove(key) {
+            Some(Entry::Ready(environment)) => Some(environment),
+            Some(pending) => {
+                self.entries.insert(key.clone(), pending);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Parent window and stacking order of every open instance.
+pub struct InstanceRegistry<H, P> {
+    parents: HashMap<H, P>,
+    // Bottom to top.
+    stacks: HashMap<P, Vec<H>>,
+}
+
+impl<H, P> Default for InstanceRegistry<H, P> {
+    fn default() -> Self {
+        Self {
+            parents: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+}
+
+impl<H: Copy + Eq + Hash, P: Copy + Eq + Hash> InstanceRegistry<H, P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `handle` on top of the other instances in `parent`. An instance
+    /// that is already registered is moved there.
+    pub fn insert(&mut self, handle: H, parent: P) {
+        self.remove(handle);
+        self.parents.insert(handle, parent);
+        self.stacks.entry(parent).or_default().push(handle);
+    }
+
+    /// Returns the parent the instance was attached to.
+    pub fn remove(&mut self, handle: H) -> Option<P> {
+        let parent = self.parents.remove(&handle)?;
+        if let Some(stack) = self.stacks.get_mut(&parent) {
+            stack.retain(|h| *h != handle);
+            if stack.is_empty() {
+                self.stacks.remove(&parent);
+            }
+        }
+        Some(parent)
+    }
+
+    pub fn parent(&self, handle: H) -> Option<P> {
+        self.parents.get(&handle).copied()
+    }
+
+    /// Move an instance to the top of another parent. Returns the previous
+    /// parent, or `None` if the instance is unknown.
+    pub fn set_parent(&mut self, handle: H, parent: P) -> Option<P> {
+        let old = self.remove(handle)?;
+        self.insert(handle, parent);
+        Some(old)
+    }
+
+    pub fn bring_to_front(&mut self, handle: H) -> bool {
+        self.reorder(handle, |stack, i| {
+            let h = stack.remove(i);
+            stack.push(h);
+        })
+    }
+
+    pub fn send_to_back(&mut self, handle: H) -> bool {
+        self.reorder(handle, |stack, i| {
+            let h = stack.remove(i);
+            stack.insert(0, h);
+        })
+    }
+
+    /// Place `handle` directly above `other`. Both must share a parent.
+    pub fn place_above(&mut self, handle: H, other: H) -> bool {
+        if handle == other
+            || self.parent(handle).is_none()
+            || self.parent(handle) != self.parent(other)
+        {
+            return false;
+        }
+        self.reorder(handle, |stack, i| {
+            let h = stack.remove(i);
+            let j = stack.iter().position(|h| *h == other).unwrap();
+            stack.insert(j + 1, h);
+        })
+    }
+
+    /// The instances in `parent`, bottom to top.
+    pub fn stack(&self, parent: P) -> &[H] {
+        self.stacks
+            .get(&parent)
+            .map_or(&[], |stack| stack.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    fn reorder(&mut self, handle: H, f: impl FnOnce(&mut Vec<H>, usize)) -> bool {
+        let parent = match self.parents.get(&handle) {
+            Some(parent) => *parent,
+            None => return false,
+        };
+        let stack = self.stacks.get_mut(&parent).unwrap();
+        let i = stack.iter().position(|h| *h == handle).unwrap();
+        f(stack, i);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(folder: &str) -> EnvironmentKey {
+        EnvironmentKey {
+            user_data_folder: Some(folder.to_owned()),
+            ..EnvironmentKey::default()
+        }
+    }
+
+    #[test]
+    fn test_environment_cache_shares_pending_creation() {
+        let mut cache = EnvironmentCache::<u32, &str>::new();
+        assert!(matches!(cache.request(&key("a"), "first"), Request::Create));
+        assert!(matches!(
+            cache.request(&key("a"), "second"),
+            Request::Queued
+        ));
+        assert!(matches!(cache.request(&key("b"), "other"), Request::Create));
+        assert_eq!(cache.get(&key("a")), None);
+
+        assert_eq!(cache.complete(&key("a"), Some(7)), vec!["first", "second"]);
+        assert_eq!(cache.get(&key("a")), Some(&7));
+        match cache.request(&key("a"), "third") {
+            Request::Ready(7, "third") => {}
+            _ => panic!("expected a cached environment"),
+        }
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_environment_cache_options_are_part_of_key() {
+        let mut cache = EnvironmentCache::<u32, ()>::new();
+        let mut with_args = key("a");
+        with_args.browser_arguments = "--mute-audio".to_owned();
+
+        cache.request(&key("a"), ());
+        cache.complete(&key("a"), Some(1));
+        assert!(matches!(cache.request(&with_args, ()), Request::Create));
+    }
+
+    #[test]
+    fn test_environment_cache_failure_retries() {
+        let mut cache = EnvironmentCache::<u32, u8>::new();
+        cache.request(&key("a"), 1);
+        cache.request(&key("a"), 2);
+        assert_eq!(cache.complete(&key("a"), None), vec![1, 2]);
+        assert!(cache.is_empty());
+        assert!(matches!(cache.request(&key("a"), 3), Request::Create));
+
+        // Pending entries survive `remove`; ready ones don't.
+        assert_eq!(cache.remove(&key("a")), None);
+        assert_eq!(cache.complete(&key("a"), Some(5)), vec![3]);
+        assert_eq!(cache.remove(&key("a")), Some(5));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_registry_z_order() {
+        let mut registry = InstanceRegistry::new();
+        registry.insert(1, 100);
+        registry.insert(2, 100);
+        registry.insert(3, 100);
+        assert_eq!(registry.stack(100), &[1, 2, 3]);
+
+        assert!(registry.bring_to_front(1));
+        assert_eq!(registry.stack(100), &[2, 3, 1]);
+        assert!(registry.send_to_back(3));
+        assert_eq!(registry.stack(100), &[3, 2, 1]);
+        assert!(registry.place_above(3, 2));
+        assert_eq!(registry.stack(100), &[2, 3, 1]);
+        assert!(registry.place_above(2, 1));
+        assert_eq!(registry.stack(100), &[3, 1, 2]);
+
+        assert!(!registry.place_above(2, 2));
+        assert!(!registry.bring_to_front(4));
+    }
+
+    #[test]
+    fn test_registry_parents() {
+        let mut registry = InstanceRegistry::new();
+        registry.insert(1, 100);
+        registry.insert(2, 100);
+        registry.insert(3, 200);
+        assert!(!registry.place_above(1, 3));
+
+        assert_eq!(registry.set_parent(1, 200), Some(100));
+        assert_eq!(registry.parent(1), Some(200));
+        assert_eq!(registry.stack(100), &[2]);
+        assert_eq!(registry.stack(200), &[3, 1]);
+        assert_eq!(registry.set_parent(4, 200), None);
+
+        assert_eq!(registry.remove(2), Some(100));
+        assert_eq!(registry.stack(100), &[] as &[i32]);
+        assert_eq!(registry.remove(2), None);
+        assert_eq!(registry.len(), 2);
+    }
+}
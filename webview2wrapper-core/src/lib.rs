@@ -0,0 +1,5 @@
+//! Platform independent parts of `webview2wrapper`, so that they build and can
+//! be tested on any host.
+
+pub mod channel;
+pub mod instances;
@@ -0,0 +1,410 @@
+// Generated by webview2wrapper/build.rs. Do not edit.
+
+using System;
+using System.Runtime.InteropServices;
+
+namespace WebView2Wrapper
+{
+    /// <summary>
+    /// Mouse event type used by SendMouseInput to convey the type of mouse event
+    /// being sent to WebView. The values of this enum align with the matching
+    /// WM_* window messages.
+    /// </summary>
+    public enum MouseEventKind : uint
+    {
+        /// <summary>
+        /// Mouse horizontal wheel scroll event, WM_MOUSEHWHEEL.
+        /// </summary>
+        HorizontalWheel = 0x020E,
+        /// <summary>
+        /// Left button double click mouse event, WM_LBUTTONDBLCLK.
+        /// </summary>
+        LeftButtonDoubleClick = 0x0203,
+        /// <summary>
+        /// Left button down mouse event, WM_LBUTTONDOWN.
+        /// </summary>
+        LeftButtonDown = 0x0201,
+        /// <summary>
+        /// Left button up mouse event, WM_LBUTTONUP.
+        /// </summary>
+        LeftButtonUp = 0x0202,
+        /// <summary>
+        /// Mouse leave event, WM_MOUSELEAVE.
+        /// </summary>
+        Leave = 0x02A3,
+        /// <summary>
+        /// Middle button double click mouse event, WM_MBUTTONDBLCLK.
+        /// </summary>
+        MiddleButtonDoubleClick = 0x0209,
+        /// <summary>
+        /// Middle button down mouse event, WM_MBUTTONDOWN.
+        /// </summary>
+        MiddleButtonDown = 0x0207,
+        /// <summary>
+        /// Middle button up mouse event, WM_MBUTTONUP.
+        /// </summary>
+        MiddleButtonUp = 0x0208,
+        /// <summary>
+        /// Mouse move event, WM_MOUSEMOVE.
+        /// </summary>
+        Move = 0x0200,
+        /// <summary>
+        /// Right button double click mouse event, WM_RBUTTONDBLCLK.
+        /// </summary>
+        RightButtonDoubleClick = 0x0206,
+        /// <summary>
+        /// Right button down mouse event, WM_RBUTTONDOWN.
+        /// </summary>
+        RightButtonDown = 0x0204,
+        /// <summary>
+        /// Right button up mouse event, WM_RBUTTONUP.
+        /// </summary>
+        RightButtonUp = 0x0205,
+        /// <summary>
+        /// Mouse wheel scroll event, WM_MOUSEWHEEL.
+        /// </summary>
+        Wheel = 0x020A,
+        /// <summary>
+        /// First or second X button double click mouse event, WM_XBUTTONDBLCLK.
+        /// </summary>
+        XButtonDoubleClick = 0x020D,
+        /// <summary>
+        /// First or second X button down mouse event, WM_XBUTTONDOWN.
+        /// </summary>
+        XButtonDown = 0x020B,
+        /// <summary>
+        /// First or second X button up mouse event, WM_XBUTTONUP.
+        /// </summary>
+        XButtonUp = 0x020C,
+        /// <summary>
+        /// Mouse Right Button Down event over a nonclient area, WM_NCRBUTTONDOWN.
+        /// </summary>
+        NonClientRightButtonDown = 0x00A4,
+        /// <summary>
+        /// Mouse Right Button up event over a nonclient area, WM_NCRBUTTONUP.
+        /// </summary>
+        NonClientRightButtonUp = 0x00A5,
+    }
+
+    /// <summary>
+    /// Mouse event virtual keys associated with a COREWEBVIEW2_MOUSE_EVENT_KIND for
+    /// SendMouseInput. These values can be combined into a bit flag if more than
+    /// one virtual key is pressed for the event. The values of this enum align
+    /// with the matching MK_* mouse keys.
+    /// </summary>
+    public enum MouseEventVirtualKeys : uint
+    {
+        /// <summary>
+        /// No additional keys pressed.
+        /// </summary>
+        None = 0x0,
+        /// <summary>
+        /// Left mouse button is down, MK_LBUTTON.
+        /// </summary>
+        LeftButton = 0x0001,
+        /// <summary>
+        /// Right mouse button is down, MK_RBUTTON.
+        /// </summary>
+        RightButton = 0x0002,
+        /// <summary>
+        /// SHIFT key is down, MK_SHIFT.
+        /// </summary>
+        Shift = 0x0004,
+        /// <summary>
+        /// CTRL key is down, MK_CONTROL.
+        /// </summary>
+        Control = 0x0008,
+        /// <summary>
+        /// Middle mouse button is down, MK_MBUTTON.
+        /// </summary>
+        MiddleButton = 0x0010,
+        /// <summary>
+        /// First X button is down, MK_XBUTTON1
+        /// </summary>
+        XButton1 = 0x0020,
+        /// <summary>
+        /// Second X button is down, MK_XBUTTON2
+        /// </summary>
+        XButton2 = 0x0040,
+    }
+
+    /// <summary>
+    /// Pointer event type used by SendPointerInput to convey the type of pointer
+    /// event being sent to WebView. The values of this enum align with the
+    /// matching WM_POINTER* window messages.
+    /// </summary>
+    public enum PointerEventKind : uint
+    {
+        /// <summary>
+        /// Corresponds to WM_POINTERACTIVATE.
+        /// </summary>
+        Activate = 0x024B,
+        /// <summary>
+        /// Corresponds to WM_POINTERDOWN.
+        /// </summary>
+        Down = 0x0246,
+        /// <summary>
+        /// Corresponds to WM_POINTERENTER.
+        /// </summary>
+        Enter = 0x0249,
+        /// <summary>
+        /// Corresponds to WM_POINTERLEAVE.
+        /// </summary>
+        Leave = 0x024A,
+        /// <summary>
+        /// Corresponds to WM_POINTERUP.
+        /// </summary>
+        Up = 0x0247,
+        /// <summary>
+        /// Corresponds to WM_POINTERUPDATE.
+        /// </summary>
+        Update = 0x0245,
+    }
+
+    /// <summary>
+    /// How the webview is attached to the parent window.
+    /// </summary>
+    public enum Hosting : uint
+    {
+        /// <summary>
+        /// A regular child window on top of the parent window.
+        /// </summary>
+        Windowed = 0,
+        /// <summary>
+        /// Visual hosting. The host renders the webview through DirectComposition
+        /// and forwards input with the `webview2_send_*` functions.
+        /// </summary>
+        Composition = 1,
+    }
+
+    /// <summary>
+    /// Values of the `policy` argument of `webview2_set_queue_limit`.
+    /// </summary>
+    public enum QueuePolicy : uint
+    {
+        DropOldest = 0,
+        DropNewest = 1,
+        /// <summary>
+        /// Block the page for at most `block_timeout_ms`.
+        /// </summary>
+        Block = 2,
+    }
+
+    /// <summary>
+    /// Kind of a message in a batch.
+    /// </summary>
+    public enum MessageKind : byte
+    {
+        /// <summary>
+        /// UTF-8 text.
+        /// </summary>
+        Text = 0,
+        Binary = 1,
+    }
+
+    /// <summary>
+    /// Touch or pen input, in the coordinate space of the engine viewport.
+    ///
+    /// Fields mirror `POINTER_INFO`/`POINTER_TOUCH_INFO`. See
+    /// `ICoreWebView2PointerInfo` for their meaning.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct WebView2PointerInput
+    {
+        /// <summary>
+        /// `PT_TOUCH` (2) or `PT_PEN` (3).
+        /// </summary>
+        public uint pointer_kind;
+        public uint pointer_id;
+        public uint frame_id;
+        public uint pointer_flags;
+        public int x;
+        public int y;
+        public uint time;
+        public uint touch_flags;
+        public uint touch_mask;
+        /// <summary>
+        /// Radius of the contact area around (`x`, `y`).
+        /// </summary>
+        public int touch_contact_radius;
+        public uint touch_orientation;
+        public uint touch_pressure;
+    }
+
+    public static class Native
+    {
+        public const string Library = "webview2wrapper";
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr webview2_check();
+
+        /// <summary>
+        /// Open a webview in the active window showing `url`. With `host_name` and
+        /// `folder_path`, the folder is mapped to the virtual host `host_name`.
+        /// `defines` is a `;` separated list of names, each a `true` flag of
+        /// `window.webview2Config` and a host object `chrome.webview.hostObjects.&lt;name&gt;`
+        /// in every page. Returns the handle of the webview, which is created
+        /// asynchronously.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr webview2_open([MarshalAs(UnmanagedType.LPWStr)] string url_ptr, uint url_len, [MarshalAs(UnmanagedType.LPWStr)] string host_name_ptr, uint host_name_len, [MarshalAs(UnmanagedType.LPWStr)] string folder_path_ptr, uint folder_path_len, [MarshalAs(UnmanagedType.LPWStr)] string defines_ptr, uint defines_len, [MarshalAs(UnmanagedType.LPWStr)] string user_data_folder_ptr, uint user_data_folder_len);
+
+        /// <summary>
+        /// Like `webview2_open`, but creates a composition controller. The host must
+        /// call `webview2_set_root_visual_target` and forward input to the webview.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr webview2_open_composition([MarshalAs(UnmanagedType.LPWStr)] string url_ptr, uint url_len, [MarshalAs(UnmanagedType.LPWStr)] string host_name_ptr, uint host_name_len, [MarshalAs(UnmanagedType.LPWStr)] string folder_path_ptr, uint folder_path_len, [MarshalAs(UnmanagedType.LPWStr)] string defines_ptr, uint defines_len, [MarshalAs(UnmanagedType.LPWStr)] string user_data_folder_ptr, uint user_data_folder_len);
+
+        /// <summary>
+        /// Like `webview2_open`, with an explicit parent window (0 for the active
+        /// window), `hosting` (a `Hosting`), and environment options. Webviews opened
+        /// with the same user data folder, browser arguments and language share an
+        /// environment. Empty `browser_arguments` selects the defaults.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr webview2_open_in(UIntPtr parent, uint hosting, [MarshalAs(UnmanagedType.LPWStr)] string url_ptr, uint url_len, [MarshalAs(UnmanagedType.LPWStr)] string host_name_ptr, uint host_name_len, [MarshalAs(UnmanagedType.LPWStr)] string folder_path_ptr, uint folder_path_len, [MarshalAs(UnmanagedType.LPWStr)] string defines_ptr, uint defines_len, [MarshalAs(UnmanagedType.LPWStr)] string user_data_folder_ptr, uint user_data_folder_len, [MarshalAs(UnmanagedType.LPWStr)] string browser_arguments_ptr, uint browser_arguments_len, [MarshalAs(UnmanagedType.LPWStr)] string language_ptr, uint language_len);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_set_visible(UIntPtr ptr, int visible);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_open_dev_tools_window(UIntPtr ptr);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_update_position(UIntPtr ptr, int left, int top, int w, int h);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_update_position2(UIntPtr ptr, int left, int top, int w, int h, int ref_width, int ref_height);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_pull(UIntPtr ptr, out IntPtr @out, out uint len);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_pull_free(IntPtr data, uint len);
+
+        /// <summary>
+        /// Take all pending messages at once, laid out as described in the `channel`
+        /// module. The buffer stays valid until the next call. Returns the number of
+        /// messages.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern uint webview2_drain(UIntPtr ptr, out IntPtr @out, out uint len);
+
+        /// <summary>
+        /// Limit the number of pending messages. `capacity` 0 means unbounded.
+        /// `policy` is a `QueuePolicy`.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_set_queue_limit(UIntPtr ptr, uint capacity, uint policy, uint block_timeout_ms);
+
+        /// <summary>
+        /// Number of messages discarded because the queue was full.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern ulong webview2_queue_dropped(UIntPtr ptr);
+
+        /// <summary>
+        /// Send bytes to the page. They arrive at listeners registered with
+        /// `chrome.webview.addBinaryListener`.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_post_binary(UIntPtr ptr, byte[] bytes, uint len);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_post_web_message_as_json(UIntPtr ptr, [MarshalAs(UnmanagedType.LPWStr)] string json_ptr, uint len);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_execute_script(UIntPtr ptr, [MarshalAs(UnmanagedType.LPWStr)] string script_ptr, uint len);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern void webview2_close(UIntPtr ptr);
+
+        /// <summary>
+        /// Move the webview to another parent window, on top of the webviews already
+        /// there. Returns 1 on success.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_set_parent(UIntPtr ptr, UIntPtr parent);
+
+        /// <summary>
+        /// Show the webview above the other webviews in its parent window. Returns 1
+        /// on success.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_bring_to_front(UIntPtr ptr);
+
+        /// <summary>
+        /// Show the webview below the other webviews in its parent window. Returns 1
+        /// on success.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_send_to_back(UIntPtr ptr);
+
+        /// <summary>
+        /// Show the webview directly above `other`, which must have the same parent
+        /// window. Returns 1 on success.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_place_above(UIntPtr ptr, UIntPtr other);
+
+        /// <summary>
+        /// Give the webview keyboard focus. Returns 1 on success.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_focus(UIntPtr ptr);
+
+        /// <summary>
+        /// Number of webviews opened and not yet closed.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern uint webview2_instance_count();
+
+        /// <summary>
+        /// Set the DirectComposition visual or target a composition hosted webview
+        /// renders into. Returns 1 on success.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_set_root_visual_target(UIntPtr ptr, IntPtr target);
+
+        /// <summary>
+        /// Forward a mouse event (`COREWEBVIEW2_MOUSE_EVENT_KIND`) to a composition
+        /// hosted webview. `x` and `y` are in the same space as the rect passed to
+        /// `webview2_update_position`/`webview2_update_position2`. Returns 1 if the
+        /// event was delivered.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_send_mouse_input(UIntPtr ptr, uint kind, uint virtual_keys, uint mouse_data, int x, int y);
+
+        /// <summary>
+        /// Forward a touch or pen event (`COREWEBVIEW2_POINTER_EVENT_KIND`) to a
+        /// composition hosted webview. Returns 1 if the event was delivered.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_send_pointer_input(UIntPtr ptr, uint kind, ref WebView2PointerInput input);
+
+        /// <summary>
+        /// Forward a keyboard message (`WM_KEYDOWN`, `WM_KEYUP`, `WM_CHAR`, ...).
+        ///
+        /// There is no `SendKeyboardInput` in WebView2: once focused, the webview
+        /// receives keyboard input through the window that has the focus, so the
+        /// webview is focused first if needed and the message is posted there.
+        /// Returns 1 if the message was posted.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern int webview2_send_keyboard_input(UIntPtr ptr, uint message, UIntPtr wparam, IntPtr lparam);
+
+        /// <summary>
+        /// Returns the `HCURSOR` a composition hosted webview wants displayed, or 0.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern UIntPtr webview2_get_cursor(UIntPtr ptr);
+
+        /// <summary>
+        /// Returns the system cursor id (`IDC_*`) a composition hosted webview wants
+        /// displayed, or 0 if it uses a custom cursor.
+        /// </summary>
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        public static extern uint webview2_get_system_cursor_id(UIntPtr ptr);
+    }
+}
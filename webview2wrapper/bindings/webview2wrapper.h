@@ -0,0 +1,249 @@
+// Generated by webview2wrapper/build.rs. Do not edit.
+
+#ifndef WEBVIEW2WRAPPER_H
+#define WEBVIEW2WRAPPER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Mouse event type used by SendMouseInput to convey the type of mouse event
+// being sent to WebView. The values of this enum align with the matching
+// WM_* window messages.
+typedef enum WebView2MouseEventKind {
+    // Mouse horizontal wheel scroll event, WM_MOUSEHWHEEL.
+    WEBVIEW2_MOUSE_EVENT_KIND_HORIZONTAL_WHEEL = 0x020E,
+    // Left button double click mouse event, WM_LBUTTONDBLCLK.
+    WEBVIEW2_MOUSE_EVENT_KIND_LEFT_BUTTON_DOUBLE_CLICK = 0x0203,
+    // Left button down mouse event, WM_LBUTTONDOWN.
+    WEBVIEW2_MOUSE_EVENT_KIND_LEFT_BUTTON_DOWN = 0x0201,
+    // Left button up mouse event, WM_LBUTTONUP.
+    WEBVIEW2_MOUSE_EVENT_KIND_LEFT_BUTTON_UP = 0x0202,
+    // Mouse leave event, WM_MOUSELEAVE.
+    WEBVIEW2_MOUSE_EVENT_KIND_LEAVE = 0x02A3,
+    // Middle button double click mouse event, WM_MBUTTONDBLCLK.
+    WEBVIEW2_MOUSE_EVENT_KIND_MIDDLE_BUTTON_DOUBLE_CLICK = 0x0209,
+    // Middle button down mouse event, WM_MBUTTONDOWN.
+    WEBVIEW2_MOUSE_EVENT_KIND_MIDDLE_BUTTON_DOWN = 0x0207,
+    // Middle button up mouse event, WM_MBUTTONUP.
+    WEBVIEW2_MOUSE_EVENT_KIND_MIDDLE_BUTTON_UP = 0x0208,
+    // Mouse move event, WM_MOUSEMOVE.
+    WEBVIEW2_MOUSE_EVENT_KIND_MOVE = 0x0200,
+    // Right button double click mouse event, WM_RBUTTONDBLCLK.
+    WEBVIEW2_MOUSE_EVENT_KIND_RIGHT_BUTTON_DOUBLE_CLICK = 0x0206,
+    // Right button down mouse event, WM_RBUTTONDOWN.
+    WEBVIEW2_MOUSE_EVENT_KIND_RIGHT_BUTTON_DOWN = 0x0204,
+    // Right button up mouse event, WM_RBUTTONUP.
+    WEBVIEW2_MOUSE_EVENT_KIND_RIGHT_BUTTON_UP = 0x0205,
+    // Mouse wheel scroll event, WM_MOUSEWHEEL.
+    WEBVIEW2_MOUSE_EVENT_KIND_WHEEL = 0x020A,
+    // First or second X button double click mouse event, WM_XBUTTONDBLCLK.
+    WEBVIEW2_MOUSE_EVENT_KIND_X_BUTTON_DOUBLE_CLICK = 0x020D,
+    // First or second X button down mouse event, WM_XBUTTONDOWN.
+    WEBVIEW2_MOUSE_EVENT_KIND_X_BUTTON_DOWN = 0x020B,
+    // First or second X button up mouse event, WM_XBUTTONUP.
+    WEBVIEW2_MOUSE_EVENT_KIND_X_BUTTON_UP = 0x020C,
+    // Mouse Right Button Down event over a nonclient area, WM_NCRBUTTONDOWN.
+    WEBVIEW2_MOUSE_EVENT_KIND_NON_CLIENT_RIGHT_BUTTON_DOWN = 0x00A4,
+    // Mouse Right Button up event over a nonclient area, WM_NCRBUTTONUP.
+    WEBVIEW2_MOUSE_EVENT_KIND_NON_CLIENT_RIGHT_BUTTON_UP = 0x00A5,
+} WebView2MouseEventKind;
+
+// Mouse event virtual keys associated with a COREWEBVIEW2_MOUSE_EVENT_KIND for
+// SendMouseInput. These values can be combined into a bit flag if more than
+// one virtual key is pressed for the event. The values of this enum align
+// with the matching MK_* mouse keys.
+typedef enum WebView2MouseEventVirtualKeys {
+    // No additional keys pressed.
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_NONE = 0x0,
+    // Left mouse button is down, MK_LBUTTON.
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_LEFT_BUTTON = 0x0001,
+    // Right mouse button is down, MK_RBUTTON.
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_RIGHT_BUTTON = 0x0002,
+    // SHIFT key is down, MK_SHIFT.
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_SHIFT = 0x0004,
+    // CTRL key is down, MK_CONTROL.
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_CONTROL = 0x0008,
+    // Middle mouse button is down, MK_MBUTTON.
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_MIDDLE_BUTTON = 0x0010,
+    // First X button is down, MK_XBUTTON1
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_X_BUTTON1 = 0x0020,
+    // Second X button is down, MK_XBUTTON2
+    WEBVIEW2_MOUSE_EVENT_VIRTUAL_KEYS_X_BUTTON2 = 0x0040,
+} WebView2MouseEventVirtualKeys;
+
+// Pointer event type used by SendPointerInput to convey the type of pointer
+// event being sent to WebView. The values of this enum align with the
+// matching WM_POINTER* window messages.
+typedef enum WebView2PointerEventKind {
+    // Corresponds to WM_POINTERACTIVATE.
+    WEBVIEW2_POINTER_EVENT_KIND_ACTIVATE = 0x024B,
+    // Corresponds to WM_POINTERDOWN.
+    WEBVIEW2_POINTER_EVENT_KIND_DOWN = 0x0246,
+    // Corresponds to WM_POINTERENTER.
+    WEBVIEW2_POINTER_EVENT_KIND_ENTER = 0x0249,
+    // Corresponds to WM_POINTERLEAVE.
+    WEBVIEW2_POINTER_EVENT_KIND_LEAVE = 0x024A,
+    // Corresponds to WM_POINTERUP.
+    WEBVIEW2_POINTER_EVENT_KIND_UP = 0x0247,
+    // Corresponds to WM_POINTERUPDATE.
+    WEBVIEW2_POINTER_EVENT_KIND_UPDATE = 0x0245,
+} WebView2PointerEventKind;
+
+// How the webview is attached to the parent window.
+typedef enum WebView2Hosting {
+    // A regular child window on top of the parent window.
+    WEBVIEW2_HOSTING_WINDOWED = 0,
+    // Visual hosting. The host renders the webview through DirectComposition
+    // and forwards input with the `webview2_send_*` functions.
+    WEBVIEW2_HOSTING_COMPOSITION = 1,
+} WebView2Hosting;
+
+// Values of the `policy` argument of `webview2_set_queue_limit`.
+typedef enum WebView2QueuePolicy {
+    WEBVIEW2_QUEUE_POLICY_DROP_OLDEST = 0,
+    WEBVIEW2_QUEUE_POLICY_DROP_NEWEST = 1,
+    // Block the page for at most `block_timeout_ms`.
+    WEBVIEW2_QUEUE_POLICY_BLOCK = 2,
+} WebView2QueuePolicy;
+
+// Kind of a message in a batch.
+typedef enum WebView2MessageKind {
+    // UTF-8 text.
+    WEBVIEW2_MESSAGE_KIND_TEXT = 0,
+    WEBVIEW2_MESSAGE_KIND_BINARY = 1,
+} WebView2MessageKind;
+
+// Touch or pen input, in the coordinate space of the engine viewport.
+//
+// Fields mirror `POINTER_INFO`/`POINTER_TOUCH_INFO`. See
+// `ICoreWebView2PointerInfo` for their meaning.
+typedef struct WebView2PointerInput {
+    // `PT_TOUCH` (2) or `PT_PEN` (3).
+    uint32_t pointer_kind;
+    uint32_t pointer_id;
+    uint32_t frame_id;
+    uint32_t pointer_flags;
+    int32_t x;
+    int32_t y;
+    uint32_t time;
+    uint32_t touch_flags;
+    uint32_t touch_mask;
+    // Radius of the contact area around (`x`, `y`).
+    int32_t touch_contact_radius;
+    uint32_t touch_orientation;
+    uint32_t touch_pressure;
+} WebView2PointerInput;
+
+uintptr_t webview2_check(void);
+
+// Open a webview in the active window showing `url`. With `host_name` and
+// `folder_path`, the folder is mapped to the virtual host `host_name`.
+// `defines` is a `;` separated list of names, each a `true` flag of
+// `window.webview2Config` and a host object `chrome.webview.hostObjects.<name>`
+// in every page. Returns the handle of the webview, which is created
+// asynchronously.
+uintptr_t webview2_open(const uint16_t* url_ptr, uint32_t url_len, const uint16_t* host_name_ptr, uint32_t host_name_len, const uint16_t* folder_path_ptr, uint32_t folder_path_len, const uint16_t* defines_ptr, uint32_t defines_len, const uint16_t* user_data_folder_ptr, uint32_t user_data_folder_len);
+
+// Like `webview2_open`, but creates a composition controller. The host must
+// call `webview2_set_root_visual_target` and forward input to the webview.
+uintptr_t webview2_open_composition(const uint16_t* url_ptr, uint32_t url_len, const uint16_t* host_name_ptr, uint32_t host_name_len, const uint16_t* folder_path_ptr, uint32_t folder_path_len, const uint16_t* defines_ptr, uint32_t defines_len, const uint16_t* user_data_folder_ptr, uint32_t user_data_folder_len);
+
+// Like `webview2_open`, with an explicit parent window (0 for the active
+// window), `hosting` (a `Hosting`), and environment options. Webviews opened
+// with the same user data folder, browser arguments and language share an
+// environment. Empty `browser_arguments` selects the defaults.
+uintptr_t webview2_open_in(uintptr_t parent, uint32_t hosting, const uint16_t* url_ptr, uint32_t url_len, const uint16_t* host_name_ptr, uint32_t host_name_len, const uint16_t* folder_path_ptr, uint32_t folder_path_len, const uint16_t* defines_ptr, uint32_t defines_len, const uint16_t* user_data_folder_ptr, uint32_t user_data_folder_len, const uint16_t* browser_arguments_ptr, uint32_t browser_arguments_len, const uint16_t* language_ptr, uint32_t language_len);
+
+void webview2_set_visible(uintptr_t ptr, int32_t visible);
+
+void webview2_open_dev_tools_window(uintptr_t ptr);
+
+void webview2_update_position(uintptr_t ptr, int32_t left, int32_t top, int32_t w, int32_t h);
+
+void webview2_update_position2(uintptr_t ptr, int32_t left, int32_t top, int32_t w, int32_t h, int32_t ref_width, int32_t ref_height);
+
+void webview2_pull(uintptr_t ptr, const uint16_t** out, uint32_t* len);
+
+void webview2_pull_free(uint16_t* data, uint32_t len);
+
+// Take all pending messages at once, laid out as described in the `channel`
+// module. The buffer stays valid until the next call. Returns the number of
+// messages.
+uint32_t webview2_drain(uintptr_t ptr, const uint8_t** out, uint32_t* len);
+
+// Limit the number of pending messages. `capacity` 0 means unbounded.
+// `policy` is a `QueuePolicy`.
+void webview2_set_queue_limit(uintptr_t ptr, uint32_t capacity, uint32_t policy, uint32_t block_timeout_ms);
+
+// Number of messages discarded because the queue was full.
+uint64_t webview2_queue_dropped(uintptr_t ptr);
+
+// Send bytes to the page. They arrive at listeners registered with
+// `chrome.webview.addBinaryListener`.
+void webview2_post_binary(uintptr_t ptr, const uint8_t* bytes, uint32_t len);
+
+void webview2_post_web_message_as_json(uintptr_t ptr, const uint16_t* json_ptr, uint32_t len);
+
+void webview2_execute_script(uintptr_t ptr, const uint16_t* script_ptr, uint32_t len);
+
+void webview2_close(uintptr_t ptr);
+
+// Move the webview to another parent window, on top of the webviews already
+// there. Returns 1 on success.
+int32_t webview2_set_parent(uintptr_t ptr, uintptr_t parent);
+
+// Show the webview above the other webviews in its parent window. Returns 1
+// on success.
+int32_t webview2_bring_to_front(uintptr_t ptr);
+
+// Show the webview below the other webviews in its parent window. Returns 1
+// on success.
+int32_t webview2_send_to_back(uintptr_t ptr);
+
+// Show the webview directly above `other`, which must have the same parent
+// window. Returns 1 on success.
+int32_t webview2_place_above(uintptr_t ptr, uintptr_t other);
+
+// Give the webview keyboard focus. Returns 1 on success.
+int32_t webview2_focus(uintptr_t ptr);
+
+// Number of webviews opened and not yet closed.
+uint32_t webview2_instance_count(void);
+
+// Set the DirectComposition visual or target a composition hosted webview
+// renders into. Returns 1 on success.
+int32_t webview2_set_root_visual_target(uintptr_t ptr, void* target);
+
+// Forward a mouse event (`COREWEBVIEW2_MOUSE_EVENT_KIND`) to a composition
+// hosted webview. `x` and `y` are in the same space as the rect passed to
+// `webview2_update_position`/`webview2_update_position2`. Returns 1 if the
+// event was delivered.
+int32_t webview2_send_mouse_input(uintptr_t ptr, uint32_t kind, uint32_t virtual_keys, uint32_t mouse_data, int32_t x, int32_t y);
+
+// Forward a touch or pen event (`COREWEBVIEW2_POINTER_EVENT_KIND`) to a
+// composition hosted webview. Returns 1 if the event was delivered.
+int32_t webview2_send_pointer_input(uintptr_t ptr, uint32_t kind, const WebView2PointerInput* input);
+
+// Forward a keyboard message (`WM_KEYDOWN`, `WM_KEYUP`, `WM_CHAR`, ...).
+//
+// There is no `SendKeyboardInput` in WebView2: once focused, the webview
+// receives keyboard input through the window that has the focus, so the
+// webview is focused first if needed and the message is posted there.
+// Returns 1 if the message was posted.
+int32_t webview2_send_keyboard_input(uintptr_t ptr, uint32_t message, uintptr_t wparam, intptr_t lparam);
+
+// Returns the `HCURSOR` a composition hosted webview wants displayed, or 0.
+uintptr_t webview2_get_cursor(uintptr_t ptr);
+
+// Returns the system cursor id (`IDC_*`) a composition hosted webview wants
+// displayed, or 0 if it uses a custom cursor.
+uint32_t webview2_get_system_cursor_id(uintptr_t ptr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,535 @@
+//! Generates `webview2wrapper.h` and `WebView2Wrapper.cs` into `OUT_DIR` from
+//! the `#[no_mangle] extern "C"` functions, `#[repr(C)]` structs and
+//! `#[repr(u8/u32)]` enums of this crate and of the `channel` module of
+//! webview2wrapper-core, plus the few webview2-sys enums that appear in the
+//! API.
+//!
+//! The generated files are committed in the `bindings` directory.
+//! `tests/bindings.rs` checks that they are up to date.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const SOURCES: &[&str] = &["src/lib.rs", "../webview2wrapper-core/src/channel.rs"];
+const SYS_SOURCE: &str = "../webview2-sys/src/lib.rs";
+const SYS_ENUMS: &[&str] = &[
+    "MouseEventKind",
+    "MouseEventVirtualKeys",
+    "PointerEventKind",
+];
+const LIBRARY: &str = "webview2wrapper";
+
+#[derive(Debug, Clone)]
+enum Ty {
+    Void,
+    Prim(String),
+    Named(String),
+    Ptr { mutable: bool, pointee: Box<Ty> },
+}
+
+struct Variant {
+    name: String,
+    value: String,
+    doc: Vec<String>,
+}
+
+struct Enum {
+    name: String,
+    repr: String,
+    variants: Vec<Variant>,
+    doc: Vec<String>,
+}
+
+struct Field {
+    name: String,
+    ty: Ty,
+    doc: Vec<String>,
+}
+
+struct Struct {
+    name: String,
+    fields: Vec<Field>,
+    doc: Vec<String>,
+}
+
+struct Function {
+    name: String,
+    args: Vec<(String, Ty)>,
+    ret: Ty,
+    doc: Vec<String>,
+}
+
+#[derive(Default)]
+struct Api {
+    enums: Vec<Enum>,
+    structs: Vec<Struct>,
+    functions: Vec<Function>,
+}
+
+fn main() {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+
+    let mut api = Api::default();
+
+    let sys = parse_file(&manifest_dir.join(SYS_SOURCE));
+    for name in SYS_ENUMS {
+        let e = sys
+            .items
+            .iter()
+            .find_map(|item| match item {
+                syn::Item::Enum(e) if e.ident == name => Some(e),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("enum {} not found in {}", name, SYS_SOURCE));
+        api.enums.push(convert_enum(e).expect("repr"));
+    }
+
+    for source in SOURCES {
+        let file = parse_file(&manifest_dir.join(source));
+        for item in &file.items {
+            match item {
+                syn::Item::Enum(e) if is_pub(&e.vis) => api.enums.extend(convert_enum(e)),
+                syn::Item::Struct(s)
+                    if is_pub(&s.vis) && repr(&s.attrs).as_deref() == Some("C") =>
+                {
+                    api.structs.push(convert_struct(s))
+                }
+                syn::Item::Fn(f) if is_export(f) => api.functions.push(convert_fn(f)),
+                _ => {}
+            }
+        }
+    }
+
+    std::fs::write(out_dir.join("webview2wrapper.h"), c_header(&api)).unwrap();
+    std::fs::write(out_dir.join("WebView2Wrapper.cs"), csharp(&api)).unwrap();
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", SYS_SOURCE);
+    for source in SOURCES {
+        println!("cargo:rerun-if-changed={}", source);
+    }
+}
+
+fn parse_file(path: &Path) -> syn::File {
+    let src =
+        std::fs::read_to_string(path).unwrap_or_else(|e| panic!("read {}: {}", path.display(), e));
+    syn::parse_file(&src).unwrap_or_else(|e| panic!("parse {}: {}", path.display(), e))
+}
+
+fn is_pub(vis: &syn::Visibility) -> bool {
+    matches!(vis, syn::Visibility::Public(_))
+}
+
+fn is_export(f: &syn::ItemFn) -> bool {
+    let no_mangle = f.attrs.iter().any(|a| a.path.is_ident("no_mangle"));
+    let extern_c = matches!(
+        f.sig.abi.as_ref().and_then(|abi| abi.name.as_ref()),
+        Some(name) if name.value() == "C"
+    );
+    no_mangle && extern_c && is_pub(&f.vis)
+}
+
+fn repr(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs
+        .iter()
+        .find(|a| a.path.is_ident("repr"))
+        .and_then(|a| a.parse_args::<syn::Ident>().ok())
+        .map(|i| i.to_string())
+}
+
+fn doc(attrs: &[syn::Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("doc"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                lit: syn::Lit::Str(s),
+                ..
+            })) => {
+                let s = s.value();
+                Some(s.strip_prefix(' ').unwrap_or(&s).to_owned())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn convert_enum(e: &syn::ItemEnum) -> Option<Enum> {
+    let repr = repr(&e.attrs)?;
+    if !["u8", "u16", "u32", "i32"].contains(&repr.as_str()) {
+        return None;
+    }
+    let mut next = 0u64;
+    let variants = e
+        .variants
+        .iter()
+        .map(|v| {
+            let value = match &v.discriminant {
+                Some((
+                    _,
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Int(i),
+                        ..
+                    }),
+                )) => {
+                    next = i.base10_parse::<u64>().unwrap() + 1;
+                    i.to_string()
+                }
+                Some(_) => panic!("unsupported discriminant in {}::{}", e.ident, v.ident),
+                None => {
+                    next += 1;
+                    (next - 1).to_string()
+                }
+            };
+            Variant {
+                name: v.ident.to_string(),
+                value,
+                doc: doc(&v.attrs),
+            }
+        })
+        .collect();
+    Some(Enum {
+        name: e.ident.to_string(),
+        repr,
+        variants,
+        doc: doc(&e.attrs),
+    })
+}
+
+fn convert_struct(s: &syn::ItemStruct) -> Struct {
+    let fields = s
+        .fields
+        .iter()
+        .map(|f| Field {
+            name: f.ident.as_ref().expect("named field").to_string(),
+            ty: convert_ty(&f.ty),
+            doc: doc(&f.attrs),
+        })
+        .collect();
+    Struct {
+        name: s.ident.to_string(),
+        fields,
+        doc: doc(&s.attrs),
+    }
+}
+
+fn convert_fn(f: &syn::ItemFn) -> Function {
+    let args = f
+        .sig
+        .inputs
+        .iter()
+        .map(|arg| match arg {
+            syn::FnArg::Typed(pat) => {
+                let name = match &*pat.pat {
+                    syn::Pat::Ident(i) => i.ident.to_string(),
+                    _ => panic!("unsupported argument pattern in {}", f.sig.ident),
+                };
+                (name.trim_start_matches('_').to_owned(), convert_ty(&pat.ty))
+            }
+            syn::FnArg::Receiver(_) => panic!("unexpected self in {}", f.sig.ident),
+        })
+        .collect();
+    let ret = match &f.sig.output {
+        syn::ReturnType::Default => Ty::Void,
+        syn::ReturnType::Type(_, ty) => convert_ty(ty),
+    };
+    Function {
+        name: f.sig.ident.to_string(),
+        args,
+        ret,
+        doc: doc(&f.attrs),
+    }
+}
+
+fn convert_ty(ty: &syn::Type) -> Ty {
+    match ty {
+        syn::Type::Ptr(p) => Ty::Ptr {
+            mutable: p.mutability.is_some(),
+            pointee: Box::new(convert_ty(&p.elem)),
+        },
+        syn::Type::Path(p) => {
+            let name = p.path.segments.last().unwrap().ident.to_string();
+            match name.as_str() {
+                "c_void" => Ty::Void,
+                "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64" | "usize" | "isize"
+                | "f32" | "f64" => Ty::Prim(name),
+                _ => Ty::Named(name),
+            }
+        }
+        syn::Type::Tuple(t) if t.elems.is_empty() => Ty::Void,
+        _ => panic!("unsupported type in an exported item"),
+    }
+}
+
+/// `MouseEventKind` -> `WebView2MouseEventKind`.
+fn c_type_name(name: &str) -> String {
+    if name.starts_with("WebView2") {
+        name.to_owned()
+    } else {
+        format!("WebView2{}", name)
+    }
+}
+
+/// `MouseEventKind` -> `MOUSE_EVENT_KIND`.
+fn screaming_snake(name: &str) -> String {
+    let mut s = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        let boundary = i > 0
+            && c.is_ascii_uppercase()
+            && (chars[i - 1].is_ascii_lowercase()
+                || matches!(chars.get(i + 1), Some(n) if n.is_ascii_lowercase()));
+        if boundary {
+            s.push('_');
+        }
+        s.push(c.to_ascii_uppercase());
+    }
+    s
+}
+
+fn c_ty(ty: &Ty) -> String {
+    match ty {
+        Ty::Void => "void".to_owned(),
+        Ty::Prim(p) => match p.as_str() {
+            "u8" => "uint8_t",
+            "u16" => "uint16_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "i8" => "int8_t",
+            "i16" => "int16_t",
+            "i32" => "int32_t",
+            "i64" => "int64_t",
+            "usize" => "uintptr_t",
+            "isize" => "intptr_t",
+            "f32" => "float",
+            "f64" => "double",
+            _ => unreachable!(),
+        }
+        .to_owned(),
+        Ty::Named(n) => c_type_name(n),
+        Ty::Ptr { mutable, pointee } => {
+            let pointee = c_ty(pointee);
+            if *mutable {
+                format!("{}*", pointee)
+            } else {
+                format!("const {}*", pointee)
+            }
+        }
+    }
+}
+
+fn c_doc(out: &mut String, indent: &str, doc: &[String]) {
+    for line in doc {
+        if line.is_empty() {
+            writeln!(out, "{}//", indent).unwrap();
+        } else {
+            writeln!(out, "{}// {}", indent, line).unwrap();
+        }
+    }
+}
+
+fn c_header(api: &Api) -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by webview2wrapper/build.rs. Do not edit.\n\n");
+    out.push_str("#ifndef WEBVIEW2WRAPPER_H\n#define WEBVIEW2WRAPPER_H\n\n");
+    out.push_str("#include <stdint.h>\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+
+    for e in &api.enums {
+        let name = c_type_name(&e.name);
+        let prefix = format!("WEBVIEW2_{}", screaming_snake(&name["WebView2".len()..]));
+        c_doc(&mut out, "", &e.doc);
+        writeln!(out, "typedef enum {} {{", name).unwrap();
+        for v in &e.variants {
+            c_doc(&mut out, "    ", &v.doc);
+            writeln!(
+                out,
+                "    {}_{} = {},",
+                prefix,
+                screaming_snake(&v.name),
+                v.value
+            )
+            .unwrap();
+        }
+        writeln!(out, "}} {};\n", name).unwrap();
+    }
+
+    for s in &api.structs {
+        c_doc(&mut out, "", &s.doc);
+        writeln!(out, "typedef struct {} {{", s.name).unwrap();
+        for f in &s.fields {
+            c_doc(&mut out, "    ", &f.doc);
+            writeln!(out, "    {} {};", c_ty(&f.ty), f.name).unwrap();
+        }
+        writeln!(out, "}} {};\n", s.name).unwrap();
+    }
+
+    for f in &api.functions {
+        c_doc(&mut out, "", &f.doc);
+        let args = if f.args.is_empty() {
+            "void".to_owned()
+        } else {
+            f.args
+                .iter()
+                .map(|(name, ty)| format!("{} {}", c_ty(ty), name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(out, "{} {}({});\n", c_ty(&f.ret), f.name, args).unwrap();
+    }
+
+    out.push_str("#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+    out
+}
+
+fn cs_prim(p: &str) -> &'static str {
+    match p {
+        "u8" => "byte",
+        "u16" => "ushort",
+        "u32" => "uint",
+        "u64" => "ulong",
+        "i8" => "sbyte",
+        "i16" => "short",
+        "i32" => "int",
+        "i64" => "long",
+        "usize" => "UIntPtr",
+        "isize" => "IntPtr",
+        "f32" => "float",
+        "f64" => "double",
+        _ => unreachable!(),
+    }
+}
+
+/// Type of a struct field or return value.
+fn cs_ty(ty: &Ty) -> String {
+    match ty {
+        Ty::Void => "void".to_owned(),
+        Ty::Prim(p) => cs_prim(p).to_owned(),
+        Ty::Named(n) => n.clone(),
+        Ty::Ptr { .. } => "IntPtr".to_owned(),
+    }
+}
+
+/// Type of a function argument.
+fn cs_arg_ty(ty: &Ty) -> String {
+    match ty {
+        Ty::Ptr {
+            mutable: false,
+            pointee,
+        } => match &**pointee {
+            // UTF-16 strings are passed with a separate length.
+            Ty::Prim(p) if p == "u16" => "[MarshalAs(UnmanagedType.LPWStr)] string".to_owned(),
+            Ty::Prim(p) if p == "u8" => "byte[]".to_owned(),
+            Ty::Named(n) => format!("ref {}", n),
+            _ => "IntPtr".to_owned(),
+        },
+        Ty::Ptr {
+            mutable: true,
+            pointee,
+        } => match &**pointee {
+            Ty::Ptr { .. } => "out IntPtr".to_owned(),
+            // Buffers.
+            Ty::Prim(p) if p == "u8" || p == "u16" => "IntPtr".to_owned(),
+            Ty::Prim(p) => format!("out {}", cs_prim(p)),
+            _ => "IntPtr".to_owned(),
+        },
+        _ => cs_ty(ty),
+    }
+}
+
+fn cs_ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "base", "checked", "event", "fixed", "in", "lock", "object", "operator", "out", "params",
+        "ref", "string",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("@{}", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+fn cs_doc(out: &mut String, indent: &str, doc: &[String]) {
+    if doc.is_empty() {
+        return;
+    }
+    writeln!(out, "{}/// <summary>", indent).unwrap();
+    for line in doc {
+        let line = line
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        if line.is_empty() {
+            writeln!(out, "{}///", indent).unwrap();
+        } else {
+            writeln!(out, "{}/// {}", indent, line).unwrap();
+        }
+    }
+    writeln!(out, "{}/// </summary>", indent).unwrap();
+}
+
+fn csharp(api: &Api) -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by webview2wrapper/build.rs. Do not edit.\n\n");
+    out.push_str("using System;\nusing System.Runtime.InteropServices;\n\n");
+    out.push_str("namespace WebView2Wrapper\n{\n");
+
+    for e in &api.enums {
+        cs_doc(&mut out, "    ", &e.doc);
+        writeln!(out, "    public enum {} : {}", e.name, cs_prim(&e.repr)).unwrap();
+        out.push_str("    {\n");
+        for v in &e.variants {
+            cs_doc(&mut out, "        ", &v.doc);
+            writeln!(out, "        {} = {},", v.name, v.value).unwrap();
+        }
+        out.push_str("    }\n\n");
+    }
+
+    for s in &api.structs {
+        cs_doc(&mut out, "    ", &s.doc);
+        out.push_str("    [StructLayout(LayoutKind.Sequential)]\n");
+        writeln!(out, "    public struct {}", s.name).unwrap();
+        out.push_str("    {\n");
+        for f in &s.fields {
+            cs_doc(&mut out, "        ", &f.doc);
+            writeln!(
+                out,
+                "        public {} {};",
+                cs_ty(&f.ty),
+                cs_ident(&f.name)
+            )
+            .unwrap();
+        }
+        out.push_str("    }\n\n");
+    }
+
+    out.push_str("    public static class Native\n    {\n");
+    writeln!(
+        out,
+        "        public const string Library = \"{}\";",
+        LIBRARY
+    )
+    .unwrap();
+    for f in &api.functions {
+        out.push('\n');
+        cs_doc(&mut out, "        ", &f.doc);
+        out.push_str("        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]\n");
+        let args = f
+            .args
+            .iter()
+            .map(|(name, ty)| format!("{} {}", cs_arg_ty(ty), cs_ident(name)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            out,
+            "        public static extern {} {}({});",
+            cs_ty(&f.ret),
+            f.name,
+            args
+        )
+        .unwrap();
+    }
+    out.push_str("    }\n}\n");
+    out
+}
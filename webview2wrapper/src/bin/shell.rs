@@ -2,6 +2,8 @@
 
 use std::mem;
 use std::sync::{Arc, RwLock};
+use webview2::dpi::DpiManager;
+use webview2::util::layout::{Layout, Policy};
 use webview2wrapper::*;
 use winapi::shared::windef::*;
 use winapi::um::winuser::*;
@@ -11,18 +13,9 @@ use winit::event_loop::{ControlFlow, EventLoop};
 use winit::platform::windows::WindowExtWindows;
 use winit::window::WindowBuilder;
 
-fn update_bounds(controller: webview2::Controller, rect: RECT) {
-    let dpi = unsafe {
-        winapi::um::winuser::GetDpiForWindow(
-            controller.get_parent_window().expect("get_host_window"),
-        )
-    };
-
-    if let Some((rect, zoom)) = webview2::util::calculate_bounds(rect, 1920, 1080, dpi) {
-        controller
-            .set_bounds_and_zoom_factor(rect, zoom)
-            .expect("set_bonds_and_zoom_factor");
-    }
+fn update_bounds(dpi: &DpiManager, rect: RECT) {
+    dpi.set_layout(Layout::new(Policy::Fit, 1920, 1080), rect.into())
+        .expect("set_layout");
 }
 
 fn main() {
@@ -72,7 +65,7 @@ fn main() {
                         let hbrush = winapi::um::wingdi::CreateSolidBrush(0x00000000);
                         FillRect(hdc, &rect, hbrush);
 
-                        update_bounds(data.controller.clone(), rect);
+                        update_bounds(&data.dpi, rect);
                     }
 
                     if false {
@@ -115,6 +108,14 @@ fn main() {
                     let mut guard = wrapper.write().unwrap();
                     if let Some(data) = guard.as_mut() {
                         let _ = data.controller.notify_parent_window_position_changed();
+                        let _ = data.dpi.refresh();
+                    }
+                }
+                // The layout is re-applied if the effective scale changed.
+                WindowEvent::ScaleFactorChanged { .. } => {
+                    let guard = wrapper.read().unwrap();
+                    if let Some(data) = guard.as_ref() {
+                        let _ = data.dpi.refresh();
                     }
                 }
                 // Update webview bounds when the parent window is resized.
@@ -127,7 +128,7 @@ fn main() {
                             right: new_size.width as i32,
                             bottom: new_size.height as i32,
                         };
-                        update_bounds(data.controller.clone(), r);
+                        update_bounds(&data.dpi, r);
                     }
                 }
                 _ => {}
@@ -1,25 +1,135 @@
+pub use webview2wrapper_core::{channel, instances};
+
+use channel::{BoundedQueue, Message, OverflowPolicy};
+use instances::{EnvironmentCache, EnvironmentKey, InstanceRegistry, Request};
+use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
 use std::mem::ManuallyDrop;
-use std::sync::mpsc;
-use std::sync::{Arc, RwLock};
-use webview2::host_object::IDispatch;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Once, RwLock};
+use webview2::config::PageConfig;
+use webview2::dpi::DpiManager;
+use webview2::geometry::{Point, Rect, Size};
+use webview2::host_object::{binding, HostObjectBuilder};
+use webview2::util::layout::{Layout, Policy};
 use webview2::*;
 
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
 use winapi::shared::windef::*;
-use winapi::shared::winerror::S_OK;
-use winapi::um::shellscalingapi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
-use winapi::um::winuser::{MonitorFromWindow, MONITOR_DEFAULTTONEAREST};
-
-use winreg::enums::*;
-use winreg::RegKey;
 
 pub type WebView2DataWrapper = Arc<RwLock<Option<WebView2Data>>>;
 
+pub struct CompositionHost {
+    pub controller: CompositionController,
+    // For creating `PointerInfo`s.
+    environment: Environment3,
+}
+
 pub struct WebView2Data {
     pub controller: Controller,
-
-    // Callbacks
-    queue: mpsc::Receiver<String>,
+    /// Child window of the parent that holds a windowed webview. Moving it
+    /// in the z-order moves the webview.
+    pub container: Option<HWND>,
+    /// Set when the webview was opened with `webview2_open_composition`.
+    pub composition: Option<CompositionHost>,
+    /// Last bounds passed to the controller, in the coordinate space of the
+    /// engine viewport. Used to translate input coordinates.
+    bounds: Rect,
+    /// Keeps the layout set by `webview2_update_position2` in sync with the
+    /// effective scale.
+    pub dpi: DpiManager,
+    mouse_inside: bool,
+    /// Whether the webview has keyboard focus.
+    focused: Rc<Cell<bool>>,
+
+    // Callbacks. Web messages and calls to the `functioncall` host object.
+    queue: Arc<BoundedQueue<Message>>,
     pull_scratch: Vec<u16>,
+    drain_scratch: Vec<u8>,
+}
+
+/// Place the webview at `rect` in the parent's client area.
+fn place(
+    controller: &Controller,
+    container: Option<HWND>,
+    rect: Rect,
+    zoom: Option<f64>,
+) -> Result<()> {
+    use winapi::um::winuser::{SetWindowPos, SWP_NOACTIVATE, SWP_NOZORDER};
+
+    let bounds = match container {
+        Some(container) => {
+            unsafe {
+                SetWindowPos(
+                    container,
+                    ptr::null_mut(),
+                    rect.left,
+                    rect.top,
+                    rect.width(),
+                    rect.height(),
+                    SWP_NOZORDER | SWP_NOACTIVATE,
+                )
+            };
+            Rect::from_origin_size(Point::default(), rect.size())
+        }
+        None => rect,
+    };
+    match zoom {
+        Some(zoom) => controller.set_bounds_and_zoom_factor(bounds.into(), zoom),
+        None => controller.put_bounds(bounds.into()),
+    }
+}
+
+impl WebView2Data {
+    fn set_bounds(&mut self, rect: Rect, zoom: Option<f64>) -> Result<()> {
+        self.dpi.clear_layout();
+        place(&self.controller, self.container, rect, zoom)?;
+        self.bounds = rect;
+        Ok(())
+    }
+
+    /// Host the webview in `container`. Placements made by the DPI manager,
+    /// including those after a scale change, move the container too.
+    fn set_container(&mut self, container: Option<HWND>) {
+        self.container = container;
+        let controller = self.controller.clone();
+        self.dpi.set_apply(move |placement| {
+            place(
+                &controller,
+                container,
+                placement.bounds,
+                Some(placement.zoom),
+            )
+        });
+    }
+
+    /// Where the engine viewport currently is, for translating input.
+    fn viewport(&self) -> Rect {
+        self.dpi
+            .placement()
+            .map_or(self.bounds, |placement| placement.bounds)
+    }
+
+    fn next_message(&self) -> Option<Message> {
+        self.queue.pop()
+    }
+}
+
+/// Keep track of whether the webview has keyboard focus.
+fn track_focus(controller: &Controller) -> Result<Rc<Cell<bool>>> {
+    let focused = Rc::new(Cell::new(false));
+    let f = focused.clone();
+    controller.add_got_focus(move |_| {
+        f.set(true);
+        Ok(())
+    })?;
+    let f = focused.clone();
+    controller.add_lost_focus(move |_| {
+        f.set(false);
+        Ok(())
+    })?;
+    Ok(focused)
 }
 
 fn from_utf16(ptr: *const u16, len: u32) -> Option<String> {
@@ -30,12 +140,144 @@ fn from_utf16(ptr: *const u16, len: u32) -> Option<String> {
     String::from_utf16(data).ok()
 }
 
+/// Browser arguments used when the host does not pass any.
+pub const BROWSER_ARGUMENTS: &str =
+    "--autoplay-policy=no-user-gesture-required --unlimited-storage";
+
 pub fn init_env() {
     unsafe {
-        std::env::set_var(
-            "WEBVIEW2_ADDITIONAL_BROWSER_ARGUMENTS",
-            "--autoplay-policy=no-user-gesture-required --unlimited-storage",
-        );
+        std::env::set_var("WEBVIEW2_ADDITIONAL_BROWSER_ARGUMENTS", BROWSER_ARGUMENTS);
+    }
+}
+
+type EnvironmentWaiter = Box<dyn FnOnce(Result<Environment>)>;
+
+thread_local! {
+    // Environments are bound to the thread that created them.
+    static ENVIRONMENTS: RefCell<EnvironmentCache<Environment, EnvironmentWaiter>> =
+        RefCell::new(EnvironmentCache::new());
+}
+
+static INSTANCES: Lazy<Mutex<InstanceRegistry<usize, usize>>> =
+    Lazy::new(|| Mutex::new(InstanceRegistry::new()));
+
+/// Call `f` with the environment for `key`, creating it on first use.
+pub fn with_environment(
+    key: EnvironmentKey,
+    f: impl FnOnce(Result<Environment>) + 'static,
+) -> Result<()> {
+    let request = ENVIRONMENTS.with(|cache| cache.borrow_mut().request(&key, Box::new(f)));
+    match request {
+        Request::Ready(env, f) => {
+            f(Ok(env));
+            return Ok(());
+        }
+        Request::Queued => return Ok(()),
+        Request::Create => {}
+    }
+
+    let mut builder =
+        Environment::builder().with_additional_browser_arguments(&key.browser_arguments);
+    if let Some(ref user_data_folder) = key.user_data_folder {
+        builder = builder.with_user_data_folder(std::path::Path::new(user_data_folder));
+    }
+    if let Some(ref language) = key.language {
+        builder = builder.with_language(language);
+    }
+
+    let completed_key = key.clone();
+    let res = builder.build(move |env| {
+        let waiters = ENVIRONMENTS.with(|cache| {
+            cache
+                .borrow_mut()
+                .complete(&completed_key, env.as_ref().ok().cloned())
+        });
+        for f in waiters {
+            f(match env {
+                Ok(ref env) => Ok(env.clone()),
+                Err(ref e) => Err(Error::new(e.hresult())),
+            });
+        }
+        Ok(())
+    });
+    if res.is_err() {
+        ENVIRONMENTS.with(|cache| cache.borrow_mut().complete(&key, None));
+    }
+    res
+}
+
+const CONTAINER_CLASS: &str = "WebView2WrapperContainer";
+
+unsafe extern "system" fn container_proc(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    use winapi::um::winuser::{DefWindowProcW, HTTRANSPARENT, WM_NCHITTEST};
+
+    // Let clicks outside the webview through to the parent.
+    if msg == WM_NCHITTEST {
+        return HTTRANSPARENT as LRESULT;
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+fn create_container(parent: HWND) -> HWND {
+    use winapi::um::libloaderapi::GetModuleHandleW;
+    use winapi::um::winuser::*;
+
+    static REGISTER: Once = Once::new();
+    let class: Vec<u16> = CONTAINER_CLASS.encode_utf16().chain(Some(0)).collect();
+    unsafe {
+        let instance = GetModuleHandleW(ptr::null());
+        REGISTER.call_once(|| {
+            let wc = WNDCLASSW {
+                lpfnWndProc: Some(container_proc),
+                hInstance: instance,
+                lpszClassName: class.as_ptr(),
+                ..std::mem::zeroed()
+            };
+            RegisterClassW(&wc);
+        });
+        CreateWindowExW(
+            0,
+            class.as_ptr(),
+            ptr::null(),
+            WS_CHILD | WS_VISIBLE | WS_CLIPCHILDREN | WS_CLIPSIBLINGS,
+            0,
+            0,
+            0,
+            0,
+            parent,
+            ptr::null_mut(),
+            instance,
+            ptr::null_mut(),
+        )
+    }
+}
+
+/// Restack the windowed instances in `parent` to match the registry.
+fn apply_z_order(parent: usize) {
+    use winapi::um::winuser::{SetWindowPos, HWND_TOP, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE};
+
+    let stack = INSTANCES.lock().unwrap().stack(parent).to_vec();
+    for handle in stack {
+        with_wrapper(handle, |data| {
+            if let Some(container) = data.container {
+                unsafe {
+                    SetWindowPos(
+                        container,
+                        HWND_TOP,
+                        0,
+                        0,
+                        0,
+                        0,
+                        SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+                    )
+                };
+            }
+        });
     }
 }
 
@@ -44,7 +286,7 @@ pub struct InitializeState {
     pub host_name: Option<String>,
     pub folder_path: Option<String>,
 
-    pub defines: Vec<String>,
+    pub defines: String,
 }
 
 pub fn setup_controller(controller: Controller) {
@@ -114,7 +356,25 @@ pub fn setup_controller(controller: Controller) {
     .ok();
 }
 
-pub fn inject_defines<Fn>(w: WebView, mut names: Vec<String>, cb: Fn)
+/// Install the `;` separated `defines` as `true` flags of
+/// `window.webview2Config` in every document, and bind each as a host object
+/// holding 1 like earlier versions did, then call `cb`.
+pub fn inject_defines<Fn>(w: WebView, defines: &str, cb: Fn)
+where
+    Fn: FnOnce() + 'static,
+{
+    let config = PageConfig::from_defines(defines);
+    let names = config.values().keys().cloned().collect();
+    let w1 = w.clone();
+    w.add_script_to_execute_on_document_created(&config.document_script(), move |_| {
+        bind_defines(w1, names, cb);
+        Ok(())
+    })
+    .expect("add_script_to_execute_on_document_created");
+}
+
+/// Bind `names` as `chrome.webview.hostObjects.<name>` one after the other.
+fn bind_defines<Fn>(w: WebView, mut names: Vec<String>, cb: Fn)
 where
     Fn: FnOnce() + 'static,
 {
@@ -125,10 +385,13 @@ where
             return;
         }
     };
-
-    let obj = Box::new(host_object::Variant::from(1));
-    host_object::ensure_bind(w.clone(), name, obj, move |w| {
-        inject_defines(w, names, cb);
+    let obj = host_object::Variant::from(1);
+    host_object::ensure_bind(w, name.clone(), obj, move |w, res| {
+        // The flag in `webview2Config` is still there.
+        if let Err(e) = res {
+            eprintln!("webview2_open: binding define {}: {}", name, e);
+        }
+        bind_defines(w, names, cb);
     });
 }
 
@@ -144,7 +407,7 @@ pub fn initialize_controller_nobind(
     } = state;
 
     let w = controller.get_webview().expect("get_webview");
-    inject_defines(w.clone(), defines, move || {
+    inject_defines(w.clone(), &defines, move || {
         if let (Some(host_name), Some(folder_path)) = (host_name.as_ref(), folder_path.as_ref()) {
             w.get_webview_3()
                 .expect("get_webview_3")
@@ -158,12 +421,18 @@ pub fn initialize_controller_nobind(
         w.navigate(&url_str).expect("navigate");
     });
 
-    let (_sender, receiver) = mpsc::channel();
     Ok(WebView2Data {
+        dpi: DpiManager::new(controller.clone())?,
+        focused: track_focus(&controller)?,
         controller,
+        container: None,
+        composition: None,
+        bounds: Rect::default(),
+        mouse_inside: false,
 
-        queue: receiver,
+        queue: Arc::new(BoundedQueue::default()),
         pull_scratch: Vec::new(),
+        drain_scratch: Vec::new(),
     })
 }
 
@@ -190,32 +459,52 @@ pub fn initialize_controller(
 
     controller.put_bounds(r).expect("put_bounds");
 
-    let (sender, receiver) = mpsc::channel();
-
-    let obj = host_object::FunctionWithStringArgument {
-        sender: sender.clone(),
-    };
-    let message_obj = Box::new(host_object::Variant::from(ManuallyDrop::new(Some(
-        IDispatch::from(obj),
-    ))));
+    let queue = Arc::new(BoundedQueue::default());
+
+    // Calls share the queue, and so its limit, with web messages.
+    let queue1 = queue.clone();
+    let obj = HostObjectBuilder::new()
+        .with_default_method(move |s: String| {
+            let reply = format!(
+                r#"Successful sync call functionWithStringArgument, and the argument is "{}"."#,
+                s
+            );
+            queue1.push(Message::Text(s));
+            reply
+        })
+        .build();
+    let message_obj = host_object::Variant::from(ManuallyDrop::new(Some(obj.to_dispatch())));
 
-    let sender0 = sender.clone();
+    let queue0 = queue.clone();
     w.add_web_message_received(move |_w, args| {
+        // Reports about host objects are for `ensure_bind`, not the app.
+        if let Ok(json) = args.get_web_message_as_json() {
+            if binding::Probe::parse(&json).is_some() {
+                return Ok(());
+            }
+        }
         let msg = args.try_get_web_message_as_string();
         if let Ok(msg) = msg {
-            sender0.send(msg).expect("mpsc::Sender::send");
+            queue0.push(Message::decode(msg));
         }
         Ok(())
     })
     .expect("add_web_message_received");
 
-    inject_defines(w.clone(), defines, move || {
+    w.add_script_to_execute_on_document_created(channel::BOOTSTRAP_SCRIPT, |_| Ok(()))
+        .expect("add_script_to_execute_on_document_created");
+
+    inject_defines(w.clone(), &defines, move || {
         let url_str = url_str.clone();
         host_object::ensure_bind(
             w.clone(),
             "functioncall".to_owned(),
             message_obj,
-            move |w| {
+            move |w, res| {
+                // The page still loads, it just cannot call the host.
+                if let Err(e) = res {
+                    eprintln!("webview2_open: binding functioncall: {}", e);
+                }
                 if let (Some(host_name), Some(folder_path)) =
                     (host_name.as_ref(), folder_path.as_ref())
                 {
@@ -234,10 +523,17 @@ pub fn initialize_controller(
     });
 
     Ok(WebView2Data {
+        dpi: DpiManager::new(controller.clone())?,
+        focused: track_focus(&controller)?,
         controller,
+        container: None,
+        composition: None,
+        bounds: Rect::default(),
+        mouse_inside: false,
 
-        queue: receiver,
+        queue,
         pull_scratch: Vec::new(),
+        drain_scratch: Vec::new(),
     })
 }
 
@@ -250,6 +546,23 @@ pub unsafe extern "C" fn webview2_check() -> usize {
     }
 }
 
+/// How the webview is attached to the parent window.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Hosting {
+    /// A regular child window on top of the parent window.
+    Windowed = 0,
+    /// Visual hosting. The host renders the webview through DirectComposition
+    /// and forwards input with the `webview2_send_*` functions.
+    Composition = 1,
+}
+
+/// Open a webview in the active window showing `url`. With `host_name` and
+/// `folder_path`, the folder is mapped to the virtual host `host_name`.
+/// `defines` is a `;` separated list of names, each a `true` flag of
+/// `window.webview2Config` and a host object `chrome.webview.hostObjects.<name>`
+/// in every page. Returns the handle of the webview, which is created
+/// asynchronously.
 #[no_mangle]
 pub unsafe extern "C" fn webview2_open(
     url_ptr: *const u16,
@@ -263,63 +576,232 @@ pub unsafe extern "C" fn webview2_open(
     user_data_folder_ptr: *const u16,
     user_data_folder_len: u32,
 ) -> usize {
-    init_env();
+    use winapi::um::winuser::GetActiveWindow;
+
+    let state = initialize_state(
+        url_ptr,
+        url_len,
+        host_name_ptr,
+        host_name_len,
+        folder_path_ptr,
+        folder_path_len,
+        defines_ptr,
+        defines_len,
+    );
+    let environment = EnvironmentKey {
+        user_data_folder: from_utf16(user_data_folder_ptr, user_data_folder_len),
+        browser_arguments: BROWSER_ARGUMENTS.to_owned(),
+        language: None,
+    };
+    open(GetActiveWindow(), Hosting::Windowed, environment, state)
+}
+
+/// Like `webview2_open`, but creates a composition controller. The host must
+/// call `webview2_set_root_visual_target` and forward input to the webview.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_open_composition(
+    url_ptr: *const u16,
+    url_len: u32,
+    host_name_ptr: *const u16,
+    host_name_len: u32,
+    folder_path_ptr: *const u16,
+    folder_path_len: u32,
+    defines_ptr: *const u16,
+    defines_len: u32,
+    user_data_folder_ptr: *const u16,
+    user_data_folder_len: u32,
+) -> usize {
+    use winapi::um::winuser::GetActiveWindow;
+
+    let state = initialize_state(
+        url_ptr,
+        url_len,
+        host_name_ptr,
+        host_name_len,
+        folder_path_ptr,
+        folder_path_len,
+        defines_ptr,
+        defines_len,
+    );
+    let environment = EnvironmentKey {
+        user_data_folder: from_utf16(user_data_folder_ptr, user_data_folder_len),
+        browser_arguments: BROWSER_ARGUMENTS.to_owned(),
+        language: None,
+    };
+    open(GetActiveWindow(), Hosting::Composition, environment, state)
+}
+
+/// Like `webview2_open`, with an explicit parent window (0 for the active
+/// window), `hosting` (a `Hosting`), and environment options. Webviews opened
+/// with the same user data folder, browser arguments and language share an
+/// environment. Empty `browser_arguments` selects the defaults.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_open_in(
+    parent: usize,
+    hosting: u32,
+    url_ptr: *const u16,
+    url_len: u32,
+    host_name_ptr: *const u16,
+    host_name_len: u32,
+    folder_path_ptr: *const u16,
+    folder_path_len: u32,
+    defines_ptr: *const u16,
+    defines_len: u32,
+    user_data_folder_ptr: *const u16,
+    user_data_folder_len: u32,
+    browser_arguments_ptr: *const u16,
+    browser_arguments_len: u32,
+    language_ptr: *const u16,
+    language_len: u32,
+) -> usize {
+    use winapi::um::winuser::GetActiveWindow;
+
+    let hosting = match hosting {
+        h if h == Hosting::Windowed as u32 => Hosting::Windowed,
+        h if h == Hosting::Composition as u32 => Hosting::Composition,
+        _ => return 0,
+    };
+    let parent = if parent == 0 {
+        GetActiveWindow()
+    } else {
+        parent as HWND
+    };
+
+    let state = initialize_state(
+        url_ptr,
+        url_len,
+        host_name_ptr,
+        host_name_len,
+        folder_path_ptr,
+        folder_path_len,
+        defines_ptr,
+        defines_len,
+    );
+    let environment = EnvironmentKey {
+        user_data_folder: from_utf16(user_data_folder_ptr, user_data_folder_len),
+        browser_arguments: from_utf16(browser_arguments_ptr, browser_arguments_len)
+            .unwrap_or_else(|| BROWSER_ARGUMENTS.to_owned()),
+        language: from_utf16(language_ptr, language_len),
+    };
+    open(parent, hosting, environment, state)
+}
 
+#[allow(clippy::too_many_arguments)]
+fn initialize_state(
+    url_ptr: *const u16,
+    url_len: u32,
+    host_name_ptr: *const u16,
+    host_name_len: u32,
+    folder_path_ptr: *const u16,
+    folder_path_len: u32,
+    defines_ptr: *const u16,
+    defines_len: u32,
+) -> InitializeState {
     let url_str = from_utf16(url_ptr, url_len).expect("url_str.from_utf16");
     let host_name = from_utf16(host_name_ptr, host_name_len);
     let folder_path = from_utf16(folder_path_ptr, folder_path_len);
-    let user_data_folder = from_utf16(user_data_folder_ptr, user_data_folder_len);
 
-    let defines = from_utf16(defines_ptr, defines_len)
-        .unwrap_or("".to_owned())
-        .split(';')
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_owned())
-        .collect();
+    let defines = from_utf16(defines_ptr, defines_len).unwrap_or_default();
 
-    let state = InitializeState {
+    InitializeState {
         url_str,
         host_name,
         folder_path,
         defines,
-    };
-
-    use winapi::um::winuser::*;
-
-    let hwnd = unsafe { GetActiveWindow() };
-
-    let wrapper: WebView2DataWrapper = Arc::new(RwLock::new(None));
-    let ptr = WebView2DataWrapper::into_raw(wrapper.clone());
-
-    let mut builder = Environment::builder();
-    if let Some(ref user_data_folder) = user_data_folder {
-        let path = std::path::Path::new(user_data_folder);
-        builder = builder.with_user_data_folder(&path);
     }
+}
 
-    let res = builder.build(move |env| {
-        let env = env.expect("env");
+fn open(
+    parent: HWND,
+    hosting: Hosting,
+    environment: EnvironmentKey,
+    state: InitializeState,
+) -> usize {
+    let wrapper: WebView2DataWrapper = Arc::new(RwLock::new(None));
+    let ptr = WebView2DataWrapper::into_raw(wrapper.clone()) as usize;
 
-        env.create_controller(hwnd, move |controller| {
-            let controller = controller.expect("create host");
-            let data = initialize_controller(controller, state).expect("initialize_controller");
+    let container = match hosting {
+        Hosting::Windowed => Some(create_container(parent)),
+        Hosting::Composition => None,
+    };
+    INSTANCES.lock().unwrap().insert(ptr, parent as usize);
+
+    let res = with_environment(environment, move |env| {
+        let env = match env {
+            Ok(env) => env,
+            Err(e) => {
+                eprintln!("webview2_open: {:?}", e);
+                abandon(ptr, container);
+                return;
+            }
+        };
 
+        let store = move |res: Result<WebView2Data>| {
+            let mut data = match res {
+                Ok(data) => data,
+                Err(e) => {
+                    eprintln!("webview2_open: {:?}", e);
+                    abandon(ptr, container);
+                    return;
+                }
+            };
+            data.set_container(container);
             {
                 let mut guard = wrapper.write().unwrap();
                 *guard = Some(data);
             }
 
             std::mem::forget(wrapper);
+            if let Some(parent) = INSTANCES.lock().unwrap().parent(ptr) {
+                apply_z_order(parent);
+            }
+        };
 
-            Ok(())
-        })
+        let res = match hosting {
+            Hosting::Windowed => env.create_controller(container.unwrap(), move |controller| {
+                store(controller.and_then(|controller| initialize_controller(controller, state)));
+
+                Ok(())
+            }),
+            Hosting::Composition => env.environment3().and_then(|environment| {
+                environment
+                    .clone()
+                    .create_composition_controller(parent, move |composition| {
+                        store(composition.and_then(|composition| {
+                            let controller = composition.get_controller()?;
+                            let mut data = initialize_controller(controller, state)?;
+                            data.composition = Some(CompositionHost {
+                                controller: composition,
+                                environment,
+                            });
+                            Ok(data)
+                        }));
+
+                        Ok(())
+                    })
+            }),
+        };
+        if let Err(e) = res {
+            eprintln!("webview2_open: {:?}", e);
+            abandon(ptr, container);
+        }
     });
 
     if let Err(e) = res {
         eprintln!("webview2_open: {:?}", e);
+        abandon(ptr, container);
         0
     } else {
-        ptr as usize
+        ptr
+    }
+}
+
+/// Forget an instance whose webview could not be created. The handle stays
+/// valid for `webview2_close`, which then does nothing.
+fn abandon(ptr: usize, container: Option<HWND>) {
+    INSTANCES.lock().unwrap().remove(ptr);
+    if let Some(container) = container {
+        unsafe { winapi::um::winuser::DestroyWindow(container) };
     }
 }
 
@@ -339,7 +821,12 @@ where
 
 #[no_mangle]
 pub unsafe extern "C" fn webview2_set_visible(ptr: usize, visible: i32) {
+    use winapi::um::winuser::{ShowWindow, SW_HIDE, SW_SHOWNA};
+
     with_wrapper(ptr, |data| {
+        if let Some(container) = data.container {
+            ShowWindow(container, if visible != 0 { SW_SHOWNA } else { SW_HIDE });
+        }
         data.controller
             .put_is_visible(visible != 0)
             .expect("put_is_visible");
@@ -356,25 +843,13 @@ pub unsafe extern "C" fn webview2_open_dev_tools_window(ptr: usize) {
 
 #[no_mangle]
 pub unsafe extern "C" fn webview2_update_position(ptr: usize, left: i32, top: i32, w: i32, h: i32) {
-    let r = RECT {
-        left,
-        top,
-        right: w + left,
-        bottom: h + top,
-    };
+    let r = Rect::from_origin_size(Point::new(left, top), Size::new(w, h));
 
     with_wrapper(ptr, |data| {
-        data.controller.put_bounds(r).expect("put_bounds");
+        data.set_bounds(r, None).expect("put_bounds");
     });
 }
 
-fn get_text_scale_factor() -> Result<u32> {
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let accessibility = hkcu.open_subkey("Software\\Microsoft\\Accessibility")?;
-    let scale: u32 = accessibility.get_value("TextScaleFactor")?;
-    Ok(scale)
-}
-
 #[no_mangle]
 pub unsafe extern "C" fn webview2_update_position2(
     ptr: usize,
@@ -385,41 +860,17 @@ pub unsafe extern "C" fn webview2_update_position2(
     ref_width: i32,
     ref_height: i32,
 ) {
-    let r = RECT {
-        left,
-        top,
-        right: w + left,
-        bottom: h + top,
-    };
+    let r = Rect::from_origin_size(Point::new(left, top), Size::new(w, h));
 
     with_wrapper(ptr, |data| {
         if !data.controller.get_is_visible().unwrap() {
             return;
         }
 
-        let hmonitor = unsafe {
-            MonitorFromWindow(
-                data.controller
-                    .get_parent_window()
-                    .expect("get_host_window"),
-                MONITOR_DEFAULTTONEAREST,
-            )
-        };
-        let mut dpi_x = 0u32;
-        let mut dpi_y = 0u32;
-        let hr = unsafe { GetDpiForMonitor(hmonitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) };
-
-        let scale_factor = get_text_scale_factor().unwrap_or(100);
-        let dpi = (dpi_x as f64 * scale_factor as f64 / 100.0).round() as u32;
-
-        if hr != S_OK {
-            return;
-        }
-
-        if let Some((rect, zoom)) = util::calculate_bounds(r, ref_width, ref_height, dpi) {
-            data.controller
-                .set_bounds_and_zoom_factor(rect, zoom)
-                .expect("set_bonds_and_zoom_factor");
+        data.dpi.refresh().expect("refresh");
+        let layout = Layout::new(Policy::Fit, ref_width, ref_height);
+        if let Some(placement) = data.dpi.set_layout(layout, r).expect("set_layout") {
+            data.bounds = placement.bounds;
         }
     });
 }
@@ -427,10 +878,10 @@ pub unsafe extern "C" fn webview2_update_position2(
 #[no_mangle]
 pub unsafe extern "C" fn webview2_pull(ptr: usize, out: *mut *const u16, len: *mut u32) {
     with_wrapper(ptr, |data| {
-        if let Ok(s) = data.queue.try_recv() {
+        if let Some(m) = data.next_message() {
             let data = &mut data.pull_scratch;
             data.clear();
-            for v in s.encode_utf16() {
+            for v in m.encode().encode_utf16() {
                 data.push(v);
             }
             data.push(0);
@@ -446,6 +897,91 @@ pub unsafe extern "C" fn webview2_pull_free(_data: *mut u16, _len: u32) {
     // noop
 }
 
+/// Take all pending messages at once, laid out as described in the `channel`
+/// module. The buffer stays valid until the next call. Returns the number of
+/// messages.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_drain(ptr: usize, out: *mut *const u8, len: *mut u32) -> u32 {
+    let mut count = 0;
+    with_wrapper(ptr, |data| {
+        let messages = data.queue.drain();
+        count = messages.len() as u32;
+
+        data.drain_scratch.clear();
+        channel::encode_batch(&messages, &mut data.drain_scratch);
+
+        *out = data.drain_scratch.as_ptr();
+        *len = data.drain_scratch.len() as u32;
+    });
+    count
+}
+
+/// Values of the `policy` argument of `webview2_set_queue_limit`.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum QueuePolicy {
+    DropOldest = 0,
+    DropNewest = 1,
+    /// Block the page for at most `block_timeout_ms`.
+    Block = 2,
+}
+
+/// Limit the number of pending messages. `capacity` 0 means unbounded.
+/// `policy` is a `QueuePolicy`.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_set_queue_limit(
+    ptr: usize,
+    capacity: u32,
+    policy: u32,
+    block_timeout_ms: u32,
+) {
+    let capacity = if capacity == 0 {
+        None
+    } else {
+        Some(capacity as usize)
+    };
+    let policy = match policy {
+        p if p == QueuePolicy::DropNewest as u32 => OverflowPolicy::DropNewest,
+        p if p == QueuePolicy::Block as u32 => {
+            OverflowPolicy::Block(std::time::Duration::from_millis(block_timeout_ms as u64))
+        }
+        _ => OverflowPolicy::DropOldest,
+    };
+    with_wrapper(ptr, |data| {
+        data.queue.set_limit(capacity, policy);
+    });
+}
+
+/// Number of messages discarded because the queue was full.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_queue_dropped(ptr: usize) -> u64 {
+    let mut dropped = 0;
+    with_wrapper(ptr, |data| {
+        dropped = data.queue.dropped();
+    });
+    dropped
+}
+
+/// Send bytes to the page. They arrive at listeners registered with
+/// `chrome.webview.addBinaryListener`.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_post_binary(ptr: usize, bytes: *const u8, len: u32) {
+    let bytes = if bytes.is_null() || len == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(bytes, len as usize)
+    };
+    let message = channel::encode_binary(bytes);
+
+    with_wrapper(ptr, |data| {
+        if let Ok(webview) = data.controller.get_webview() {
+            webview
+                .post_web_message_as_string(&message)
+                .expect("post_web_message_as_string");
+        }
+    });
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn webview2_post_web_message_as_json(
     ptr: usize,
@@ -478,5 +1014,375 @@ pub unsafe extern "C" fn webview2_execute_script(ptr: usize, script_ptr: *const
 pub unsafe extern "C" fn webview2_close(ptr: usize) {
     with_wrapper(ptr, |data| {
         data.controller.close().expect("close");
+        if let Some(container) = data.container.take() {
+            winapi::um::winuser::DestroyWindow(container);
+        }
+    });
+    INSTANCES.lock().unwrap().remove(ptr);
+}
+
+/// Move the webview to another parent window, on top of the webviews already
+/// there. Returns 1 on success.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_set_parent(ptr: usize, parent: usize) -> i32 {
+    use winapi::um::winuser::SetParent;
+
+    let mut ok = 0;
+    with_wrapper(ptr, |data| {
+        let res = match data.container {
+            Some(container) if !SetParent(container, parent as HWND).is_null() => Ok(()),
+            Some(_) => return,
+            None => data.controller.put_parent_window(parent as HWND),
+        };
+        if res
+            .and_then(|_| data.controller.notify_parent_window_position_changed())
+            .is_ok()
+        {
+            ok = 1;
+        }
+    });
+    if ok == 1 && INSTANCES.lock().unwrap().set_parent(ptr, parent).is_some() {
+        apply_z_order(parent);
+    }
+    ok
+}
+
+/// Show the webview above the other webviews in its parent window. Returns 1
+/// on success.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_bring_to_front(ptr: usize) -> i32 {
+    restack(ptr, |instances| instances.bring_to_front(ptr))
+}
+
+/// Show the webview below the other webviews in its parent window. Returns 1
+/// on success.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_send_to_back(ptr: usize) -> i32 {
+    restack(ptr, |instances| instances.send_to_back(ptr))
+}
+
+/// Show the webview directly above `other`, which must have the same parent
+/// window. Returns 1 on success.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_place_above(ptr: usize, other: usize) -> i32 {
+    restack(ptr, |instances| instances.place_above(ptr, other))
+}
+
+fn restack<F>(ptr: usize, f: F) -> i32
+where
+    F: FnOnce(&mut InstanceRegistry<usize, usize>) -> bool,
+{
+    let parent = {
+        let mut instances = INSTANCES.lock().unwrap();
+        if !f(&mut instances) {
+            return 0;
+        }
+        instances.parent(ptr)
+    };
+    if let Some(parent) = parent {
+        apply_z_order(parent);
+    }
+    1
+}
+
+/// Give the webview keyboard focus. Returns 1 on success.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_focus(ptr: usize) -> i32 {
+    let mut ok = 0;
+    with_wrapper(ptr, |data| {
+        if data
+            .controller
+            .move_focus(MoveFocusReason::Programmatic)
+            .is_ok()
+        {
+            ok = 1;
+        }
+    });
+    ok
+}
+
+/// Number of webviews opened and not yet closed.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_instance_count() -> u32 {
+    INSTANCES.lock().unwrap().len() as u32
+}
+
+fn mouse_event_kind(kind: u32) -> Option<MouseEventKind> {
+    use MouseEventKind::*;
+
+    [
+        HorizontalWheel,
+        LeftButtonDoubleClick,
+        LeftButtonDown,
+        LeftButtonUp,
+        Leave,
+        MiddleButtonDoubleClick,
+        MiddleButtonDown,
+        MiddleButtonUp,
+        Move,
+        NonClientRightButtonDown,
+        NonClientRightButtonUp,
+        RightButtonDoubleClick,
+        RightButtonDown,
+        RightButtonUp,
+        Wheel,
+        XButtonDoubleClick,
+        XButtonDown,
+        XButtonUp,
+    ]
+    .iter()
+    .copied()
+    .find(|k| *k as u32 == kind)
+}
+
+/// Keep the `MK_*` bits WebView2 knows about.
+fn mouse_virtual_keys(keys: u32) -> u32 {
+    use MouseEventVirtualKeys::*;
+
+    [
+        LeftButton,
+        RightButton,
+        MiddleButton,
+        XButton1,
+        XButton2,
+        Shift,
+        Control,
+    ]
+    .iter()
+    .fold(0, |bits, k| bits | (keys & *k as u32))
+}
+
+fn pointer_event_kind(kind: u32) -> Option<PointerEventKind> {
+    use PointerEventKind::*;
+
+    [Activate, Down, Enter, Leave, Up, Update]
+        .iter()
+        .copied()
+        .find(|k| *k as u32 == kind)
+}
+
+/// Touch or pen input, in the coordinate space of the engine viewport.
+///
+/// Fields mirror `POINTER_INFO`/`POINTER_TOUCH_INFO`. See
+/// `ICoreWebView2PointerInfo` for their meaning.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct WebView2PointerInput {
+    /// `PT_TOUCH` (2) or `PT_PEN` (3).
+    pub pointer_kind: u32,
+    pub pointer_id: u32,
+    pub frame_id: u32,
+    pub pointer_flags: u32,
+    pub x: i32,
+    pub y: i32,
+    pub time: u32,
+    pub touch_flags: u32,
+    pub touch_mask: u32,
+    /// Radius of the contact area around (`x`, `y`).
+    pub touch_contact_radius: i32,
+    pub touch_orientation: u32,
+    pub touch_pressure: u32,
+}
+
+/// Set the DirectComposition visual or target a composition hosted webview
+/// renders into. Returns 1 on success.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_set_root_visual_target(
+    ptr: usize,
+    target: *mut std::ffi::c_void,
+) -> i32 {
+    let mut ok = 0;
+    with_wrapper(ptr, |data| {
+        if let Some(composition) = data.composition.as_ref() {
+            if unsafe { composition.controller.put_root_visual_target(target as _) }.is_ok() {
+                ok = 1;
+            }
+        }
+    });
+    ok
+}
+
+/// Forward a mouse event (`COREWEBVIEW2_MOUSE_EVENT_KIND`) to a composition
+/// hosted webview. `x` and `y` are in the same space as the rect passed to
+/// `webview2_update_position`/`webview2_update_position2`. Returns 1 if the
+/// event was delivered.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_send_mouse_input(
+    ptr: usize,
+    kind: u32,
+    virtual_keys: u32,
+    mouse_data: u32,
+    x: i32,
+    y: i32,
+) -> i32 {
+    let kind = match mouse_event_kind(kind) {
+        Some(kind) => kind,
+        None => return 0,
+    };
+    let virtual_keys = mouse_virtual_keys(virtual_keys);
+
+    let mut ok = 0;
+    with_wrapper(ptr, |data| {
+        let composition = match data.composition.as_ref() {
+            Some(composition) => composition,
+            None => return,
+        };
+
+        let point = util::translate_point(data.viewport(), Point::new(x, y)).map(POINT::from);
+        let result = match (point, kind) {
+            (_, MouseEventKind::Leave) => {
+                data.mouse_inside = false;
+                composition.controller.send_mouse_input_with_keys(
+                    kind,
+                    virtual_keys,
+                    mouse_data,
+                    POINT { x: 0, y: 0 },
+                )
+            }
+            (Some(point), _) => {
+                data.mouse_inside = true;
+                composition.controller.send_mouse_input_with_keys(
+                    kind,
+                    virtual_keys,
+                    mouse_data,
+                    point,
+                )
+            }
+            // Moving onto the letterbox margins leaves the webview.
+            (None, MouseEventKind::Move) if data.mouse_inside => {
+                data.mouse_inside = false;
+                composition.controller.send_mouse_input(
+                    MouseEventKind::Leave,
+                    MouseEventVirtualKeys::None,
+                    0,
+                    POINT { x: 0, y: 0 },
+                )
+            }
+            (None, _) => return,
+        };
+        if result.is_ok() {
+            ok = 1;
+        }
+    });
+    ok
+}
+
+/// Forward a touch or pen event (`COREWEBVIEW2_POINTER_EVENT_KIND`) to a
+/// composition hosted webview. Returns 1 if the event was delivered.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_send_pointer_input(
+    ptr: usize,
+    kind: u32,
+    input: *const WebView2PointerInput,
+) -> i32 {
+    let kind = match pointer_event_kind(kind) {
+        Some(kind) => kind,
+        None => return 0,
+    };
+    if input.is_null() {
+        return 0;
+    }
+    let input = *input;
+
+    let mut ok = 0;
+    with_wrapper(ptr, |data| {
+        let composition = match data.composition.as_ref() {
+            Some(composition) => composition,
+            None => return,
+        };
+        let point = match util::translate_point(data.viewport(), Point::new(input.x, input.y)) {
+            Some(point) => POINT::from(point),
+            None => return,
+        };
+
+        let result = (|| -> Result<()> {
+            let info = composition.environment.create_pointer_info()?;
+
+            let r = input.touch_contact_radius;
+            info.put_pointer_kind(input.pointer_kind)?;
+            info.put_pointer_id(input.pointer_id)?;
+            info.put_frame_id(input.frame_id)?;
+            info.put_pointer_flags(input.pointer_flags)?;
+            info.put_pixel_location(point)?;
+            info.put_pixel_location_raw(point)?;
+            info.put_time(input.time)?;
+            info.put_touch_flags(input.touch_flags)?;
+            info.put_touch_mask(input.touch_mask)?;
+            info.put_touch_contact(RECT {
+                left: point.x - r,
+                top: point.y - r,
+                right: point.x + r,
+                bottom: point.y + r,
+            })?;
+            info.put_touch_orientation(input.touch_orientation)?;
+            info.put_touch_pressure(input.touch_pressure)?;
+
+            composition.controller.send_pointer_input(kind, &info)
+        })();
+        if result.is_ok() {
+            ok = 1;
+        }
+    });
+    ok
+}
+
+/// Forward a keyboard message (`WM_KEYDOWN`, `WM_KEYUP`, `WM_CHAR`, ...).
+///
+/// There is no `SendKeyboardInput` in WebView2: once focused, the webview
+/// receives keyboard input through the window that has the focus, so the
+/// webview is focused first if needed and the message is posted there.
+/// Returns 1 if the message was posted.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_send_keyboard_input(
+    ptr: usize,
+    message: u32,
+    wparam: usize,
+    lparam: isize,
+) -> i32 {
+    use winapi::um::winuser::{GetFocus, PostMessageW};
+
+    let mut ok = 0;
+    with_wrapper(ptr, |data| {
+        if !data.focused.get()
+            && data
+                .controller
+                .move_focus(MoveFocusReason::Programmatic)
+                .is_err()
+        {
+            return;
+        }
+        let hwnd = unsafe { GetFocus() };
+        if !hwnd.is_null() && unsafe { PostMessageW(hwnd, message, wparam, lparam) } != 0 {
+            ok = 1;
+        }
+    });
+    ok
+}
+
+/// Returns the `HCURSOR` a composition hosted webview wants displayed, or 0.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_get_cursor(ptr: usize) -> usize {
+    let mut cursor = 0;
+    with_wrapper(ptr, |data| {
+        if let Some(composition) = data.composition.as_ref() {
+            cursor = composition
+                .controller
+                .get_cursor()
+                .map_or(0, |c| c as usize);
+        }
+    });
+    cursor
+}
+
+/// Returns the system cursor id (`IDC_*`) a composition hosted webview wants
+/// displayed, or 0 if it uses a custom cursor.
+#[no_mangle]
+pub unsafe extern "C" fn webview2_get_system_cursor_id(ptr: usize) -> u32 {
+    let mut id = 0;
+    with_wrapper(ptr, |data| {
+        if let Some(composition) = data.composition.as_ref() {
+            id = composition.controller.get_system_cursor_id().unwrap_or(0);
+        }
     });
+    id
 }
@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::Path;
+
+// The committed bindings must match what build.rs generates from the current
+// exports. Run with `UPDATE_BINDINGS=1` to refresh them.
+fn check(name: &str, generated: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("bindings")
+        .join(name);
+    if std::env::var_os("UPDATE_BINDINGS").is_some() {
+        fs::write(&path, generated).unwrap();
+        return;
+    }
+    let committed = fs::read_to_string(&path).unwrap().replace("\r\n", "\n");
+    assert!(
+        committed == generated,
+        "{} is out of date, run `UPDATE_BINDINGS=1 cargo test -p webview2wrapper --test bindings`",
+        path.display()
+    );
+}
+
+#[test]
+fn test_c_header() {
+    check(
+        "webview2wrapper.h",
+        include_str!(concat!(env!("OUT_DIR"), "/webview2wrapper.h")),
+    );
+}
+
+#[test]
+fn test_csharp_bindings() {
+    check(
+        "WebView2Wrapper.cs",
+        include_str!(concat!(env!("OUT_DIR"), "/WebView2Wrapper.cs")),
+    );
+}